@@ -152,6 +152,10 @@ where
     async fn flush(&mut self) -> Result<(), Self::Error> {
         self.device.sync().await
     }
+
+    async fn discard_blocks(&mut self, start: BlockAddress, count: u32) -> Result<(), Self::Error> {
+        self.device.discard(start.value(), count).await
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +223,20 @@ pub(crate) mod tests {
         async fn sync(&mut self) -> Result<(), Self::Error> {
             Ok(())
         }
+
+        async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+            for addr in block_address..block_address + count {
+                self.data.remove(&addr);
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    impl<const BLOCK_SIZE: usize> MockBlockDevice<BLOCK_SIZE> {
+        pub(crate) fn is_written(&self, block_address: u32) -> bool {
+            self.data.contains_key(&block_address)
+        }
     }
 
     #[tokio::test]
@@ -276,4 +294,26 @@ pub(crate) mod tests {
         assert_eq!(&read_data[..256], &vec![99u8; 256][..]);
         assert_eq!(&read_data[256..], &vec![0u8; BLOCK_SIZE - 256][..]);
     }
+
+    #[cfg(feature = "alloc")]
+    #[tokio::test]
+    async fn test_adapter_discard() {
+        let device = MockBlockDevice::<BLOCK_SIZE>::new(1024 * 1024);
+        let mut adapter = BlockDeviceAdapter::new(device);
+
+        adapter
+            .write_blocks(BlockAddress::new(0), &vec![7u8; 4 * BLOCK_SIZE])
+            .await
+            .unwrap();
+        adapter
+            .discard_blocks(BlockAddress::new(1), 2)
+            .await
+            .unwrap();
+
+        let device = adapter.device();
+        assert!(device.is_written(0));
+        assert!(!device.is_written(1));
+        assert!(!device.is_written(2));
+        assert!(device.is_written(3));
+    }
 }
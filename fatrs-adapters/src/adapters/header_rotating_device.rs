@@ -346,6 +346,21 @@ where
     async fn sync(&mut self) -> Result<(), Self::Error> {
         self.inner_mut().sync().await
     }
+
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        // The header slots always hold a header, only data pages are discarded
+        let (block_address, count) = if block_address == 0 {
+            (1, count.saturating_sub(1))
+        } else {
+            (block_address, count)
+        };
+        if count == 0 {
+            return Ok(());
+        }
+        // Data pages are contiguous on the physical device
+        let physical_page = self.logical_to_physical(block_address);
+        self.inner_mut().discard(physical_page, count).await
+    }
 }
 
 #[cfg(test)]
@@ -355,12 +370,14 @@ mod tests {
     /// Mock block device for testing
     struct MockDevice {
         pages: [[u8; HEADER_ROTATION_BLOCK_SIZE]; 16],
+        discarded: Option<(u32, u32)>,
     }
 
     impl MockDevice {
         fn new() -> Self {
             Self {
                 pages: [[0xFF; HEADER_ROTATION_BLOCK_SIZE]; 16],
+                discarded: None,
             }
         }
     }
@@ -404,6 +421,11 @@ mod tests {
         async fn sync(&mut self) -> Result<(), Self::Error> {
             Ok(())
         }
+
+        async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+            self.discarded = Some((block_address, count));
+            Ok(())
+        }
     }
 
     fn block_on<F: core::future::Future>(f: F) -> F::Output {
@@ -483,6 +505,26 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_discard_skips_header() {
+        block_on(async {
+            let device = MockDevice::new();
+            let config = HeaderRotationConfig::new(4);
+            let mut rotating = HeaderRotatingDevice::new(device, config);
+
+            // Data pages are translated like writes
+            rotating.discard(2, 3).await.unwrap();
+            assert_eq!(rotating.inner_mut().discarded, Some((5, 3)));
+
+            // The header page is never discarded
+            rotating.discard(0, 2).await.unwrap();
+            assert_eq!(rotating.inner_mut().discarded, Some((4, 1)));
+            rotating.inner_mut().discarded = None;
+            rotating.discard(0, 1).await.unwrap();
+            assert_eq!(rotating.inner_mut().discarded, None);
+        });
+    }
+
     #[test]
     fn test_size_reports_logical_size() {
         block_on(async {
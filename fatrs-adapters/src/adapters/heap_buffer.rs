@@ -3,7 +3,7 @@
 #[cfg(feature = "alloc")]
 use crate::{
     adapters::{BlockDeviceAdapter, error::HeapAdapterError},
-    domain::{PageBuffer, PageConfig, PageNumber},
+    domain::{BlockAddress, PageBuffer, PageConfig, PageNumber},
 };
 
 #[cfg(feature = "alloc")]
@@ -115,6 +115,18 @@ where
         self.inner.flush().await.map_err(HeapAdapterError::from_domain)
     }
 
+    /// Discard `count` blocks starting at `start_block` on the device (writes a dirty page first).
+    pub async fn discard(
+        &mut self,
+        start_block: u32,
+        count: u32,
+    ) -> Result<(), HeapAdapterError<D::Error>> {
+        self.inner
+            .discard_blocks(BlockAddress::new(start_block), count)
+            .await
+            .map_err(HeapAdapterError::from_domain)
+    }
+
     /// Clear the buffer (discards loaded page).
     pub fn clear(&mut self) {
         self.inner.clear();
@@ -208,4 +220,24 @@ mod tests {
         let buffer = HeapBuffer::new(device, 4096);
         assert!(buffer.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_heap_buffer_discard_writes_dirty_page_first() {
+        let device = MockBlockDevice::<512>::new(1024 * 1024);
+        let mut buffer = HeapBuffer::new(device, presets::PAGE_4K).unwrap();
+
+        buffer.load(0).await.unwrap();
+        buffer.modify(|data| data.fill(1)).unwrap();
+        buffer.discard(2, 4).await.unwrap();
+        assert!(!buffer.is_dirty());
+
+        // Discarded blocks read back as zeros from the mock device
+        buffer.clear();
+        buffer.load(0).await.unwrap();
+        let data = buffer.data().unwrap();
+        assert_eq!(data[2 * 512 - 1], 1);
+        assert_eq!(data[2 * 512], 0);
+        assert_eq!(data[6 * 512 - 1], 0);
+        assert_eq!(data[6 * 512], 1);
+    }
 }
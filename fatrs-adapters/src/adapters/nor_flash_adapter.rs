@@ -18,6 +18,7 @@
 //! ```
 
 use core::cell::UnsafeCell;
+use core::ops::Range;
 use aligned::Aligned;
 use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
 use fatrs_block_device::BlockDevice;
@@ -121,6 +122,8 @@ impl std::error::Error for NorFlashError {}
 pub struct NorFlashAdapter<F> {
     flash: UnsafeCell<F>,
     config: NorFlashConfig,
+    /// Blocks erased by the last discard and not written since
+    erased: Range<u32>,
 }
 
 // SAFETY: NorFlashAdapter is Send if F is Send
@@ -141,6 +144,7 @@ impl<F> NorFlashAdapter<F> {
         Self {
             flash: UnsafeCell::new(flash),
             config,
+            erased: 0..0,
         }
     }
 
@@ -166,6 +170,22 @@ impl<F> NorFlashAdapter<F> {
     fn block_to_offset(&self, block: u32) -> u32 {
         self.config.start_offset + block * NOR_FLASH_BLOCK_SIZE as u32
     }
+
+    /// Take `block` out of the erased range, returning whether it was still erased
+    ///
+    /// Only a single range is tracked, so blocks after `block` are forgotten when it is
+    /// in the middle of the range. They are erased again on their next write.
+    fn take_erased(&mut self, block: u32) -> bool {
+        if !self.erased.contains(&block) {
+            return false;
+        }
+        if block == self.erased.start {
+            self.erased.start += 1;
+        } else {
+            self.erased.end = block;
+        }
+        true
+    }
 }
 
 impl<F> BlockDevice<NOR_FLASH_BLOCK_SIZE> for NorFlashAdapter<F>
@@ -195,12 +215,15 @@ where
         data: &[Aligned<Self::Align, [u8; NOR_FLASH_BLOCK_SIZE]>],
     ) -> Result<(), Self::Error> {
         for (i, block) in data.iter().enumerate() {
-            let offset = self.block_to_offset(block_address + i as u32);
-
-            // Erase before write (required for NOR flash)
-            self.flash_mut()
-                .erase(offset, offset + NOR_FLASH_BLOCK_SIZE as u32)
-                .map_err(|_| NorFlashError)?;
+            let block_address = block_address + i as u32;
+            let offset = self.block_to_offset(block_address);
+
+            // Erase before write (required for NOR flash), unless a discard erased it already
+            if !self.take_erased(block_address) {
+                self.flash_mut()
+                    .erase(offset, offset + NOR_FLASH_BLOCK_SIZE as u32)
+                    .map_err(|_| NorFlashError)?;
+            }
 
             // Write the data
             self.flash_mut()
@@ -218,6 +241,30 @@ where
         // NOR flash writes are typically synchronous
         Ok(())
     }
+
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        // Erase the sectors now, so the next write of a discarded block does not have to
+        if block_address
+            .checked_add(count)
+            .is_none_or(|end| end > self.config.page_count)
+        {
+            return Err(NorFlashError);
+        }
+        if count == 0 {
+            return Ok(());
+        }
+        let from = self.block_to_offset(block_address);
+        let to = self.block_to_offset(block_address + count);
+        self.flash_mut().erase(from, to).map_err(|_| NorFlashError)?;
+        let discarded = block_address..block_address + count;
+        self.erased = if discarded.start <= self.erased.end && self.erased.start <= discarded.end {
+            // overlapping or adjacent to the blocks erased before
+            discarded.start.min(self.erased.start)..discarded.end.max(self.erased.end)
+        } else {
+            discarded
+        };
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -227,12 +274,14 @@ mod tests {
     /// Mock NOR flash for testing
     struct MockFlash {
         data: [[u8; NOR_FLASH_BLOCK_SIZE]; 16],
+        erases: usize,
     }
 
     impl MockFlash {
         fn new() -> Self {
             Self {
                 data: [[0xFF; NOR_FLASH_BLOCK_SIZE]; 16],
+                erases: 0,
             }
         }
     }
@@ -279,6 +328,7 @@ mod tests {
             for page in start_page..end_page.min(self.data.len()) {
                 self.data[page] = [0xFF; NOR_FLASH_BLOCK_SIZE];
             }
+            self.erases += 1;
             Ok(())
         }
 
@@ -338,6 +388,49 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_nor_flash_adapter_discard_erases() {
+        block_on(async {
+            let flash = MockFlash::new();
+            let config = NorFlashConfig::new(0, 16);
+            let mut adapter = NorFlashAdapter::new(flash, config);
+
+            let write_buf: Aligned<aligned::A4, [u8; NOR_FLASH_BLOCK_SIZE]> =
+                Aligned([42u8; NOR_FLASH_BLOCK_SIZE]);
+            for block in 1..4 {
+                adapter
+                    .write(block, core::slice::from_ref(&write_buf))
+                    .await
+                    .unwrap();
+            }
+
+            adapter.discard(1, 2).await.unwrap();
+            let flash = adapter.flash_mut();
+            assert!(flash.data[1].iter().all(|&b| b == 0xFF));
+            assert!(flash.data[2].iter().all(|&b| b == 0xFF));
+            assert_eq!(flash.data[3][0], 42);
+            assert_eq!(flash.erases, 4);
+
+            // Discarded blocks are written without another erase, but only once
+            let write_buf: Aligned<aligned::A4, [u8; NOR_FLASH_BLOCK_SIZE]> =
+                Aligned([7u8; NOR_FLASH_BLOCK_SIZE]);
+            adapter
+                .write(1, &[write_buf, write_buf])
+                .await
+                .unwrap();
+            assert_eq!(adapter.flash_mut().erases, 4);
+            adapter
+                .write(1, core::slice::from_ref(&write_buf))
+                .await
+                .unwrap();
+            assert_eq!(adapter.flash_mut().erases, 5);
+            assert_eq!(adapter.flash_mut().data[2][0], 7);
+
+            // Discards outside of the configured region are rejected
+            assert!(adapter.discard(15, 2).await.is_err());
+        });
+    }
+
     #[test]
     fn test_nor_flash_adapter_size() {
         block_on(async {
@@ -2,7 +2,7 @@
 
 use crate::{
     adapters::{BlockDeviceAdapter, error::AdapterError},
-    domain::{PageBuffer, PageConfig, PageNumber},
+    domain::{BlockAddress, PageBuffer, PageConfig, PageNumber},
 };
 use fatrs_block_device::BlockDevice;
 
@@ -93,6 +93,18 @@ where
         self.inner.flush().await.map_err(AdapterError::from_domain)
     }
 
+    /// Discard `count` blocks starting at `start_block` on the device (writes a dirty page first).
+    pub async fn discard(
+        &mut self,
+        start_block: u32,
+        count: u32,
+    ) -> Result<(), AdapterError<D::Error>> {
+        self.inner
+            .discard_blocks(BlockAddress::new(start_block), count)
+            .await
+            .map_err(AdapterError::from_domain)
+    }
+
     /// Clear the buffer (discards loaded page).
     pub fn clear(&mut self) {
         self.inner.clear();
@@ -7,7 +7,7 @@ use crate::domain::{
     entities::{Page, PageState},
     error::DomainError,
     ports::BlockStorage,
-    value_objects::{PageConfig, PageConfigError, PageNumber},
+    value_objects::{BlockAddress, PageConfig, PageConfigError, PageNumber},
 };
 
 #[cfg(feature = "alloc")]
@@ -315,6 +315,26 @@ impl<S: BlockStorage, const BLOCK_SIZE: usize> PageBuffer<S, Vec<u8>, BLOCK_SIZE
 
         Ok(())
     }
+
+    /// Discard `count` blocks starting at `start` in storage.
+    ///
+    /// A dirty page is written first so that it cannot overwrite the discarded blocks
+    /// later. The loaded page is kept, the filesystem does not read discarded data back.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing the dirty page or the storage discard fails.
+    pub async fn discard_blocks(
+        &mut self,
+        start: BlockAddress,
+        count: u32,
+    ) -> Result<(), DomainError<S::Error>> {
+        self.flush().await?;
+        self.storage
+            .discard_blocks(start, count)
+            .await
+            .map_err(DomainError::Storage)
+    }
 }
 
 // Implementation for stack-allocated page buffers
@@ -503,6 +523,19 @@ impl<S: BlockStorage, const N: usize, const BLOCK_SIZE: usize> PageBuffer<S, [u8
 
         Ok(())
     }
+
+    /// Discard `count` blocks starting at `start` in storage.
+    pub async fn discard_blocks(
+        &mut self,
+        start: BlockAddress,
+        count: u32,
+    ) -> Result<(), DomainError<S::Error>> {
+        self.flush().await?;
+        self.storage
+            .discard_blocks(start, count)
+            .await
+            .map_err(DomainError::Storage)
+    }
 }
 
 #[cfg(test)]
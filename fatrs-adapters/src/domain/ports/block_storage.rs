@@ -132,6 +132,19 @@ pub trait BlockStorage: Send + Sync {
     async fn flush(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    /// Discard `count` blocks starting at the given block address.
+    ///
+    /// Tells the storage that the blocks no longer hold data (TRIM), see
+    /// `BlockDevice::discard`. The default implementation is a no-op.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the discard operation fails.
+    async fn discard_blocks(&mut self, start: BlockAddress, count: u32) -> Result<(), Self::Error> {
+        let _ = (start, count);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -5,7 +5,7 @@
 //! bounds needed!
 
 use crate::infrastructure::streaming::StreamError;
-use fatrs_block_device::BlockDevice;
+use fatrs_block_device::{BlockDevice, Discard};
 
 #[cfg(feature = "alloc")]
 use crate::infrastructure::streaming::HeapPageStream;
@@ -68,6 +68,18 @@ where
     }
 }
 
+impl<D, const N: usize, const BLOCK_SIZE: usize> Discard for StackPageStream<D, N, BLOCK_SIZE>
+where
+    D: BlockDevice<BLOCK_SIZE> + Send + Sync,
+    D::Error: core::error::Error + Send + Sync + 'static,
+{
+    type Error = StreamError<D::Error>;
+
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), StreamError<D::Error>> {
+        StackPageStream::discard(self, offset, len).await
+    }
+}
+
 // Implement for HeapPageStream
 #[cfg(feature = "alloc")]
 impl<D, const BLOCK_SIZE: usize> ErrorType for HeapPageStream<D, BLOCK_SIZE>
@@ -114,3 +126,16 @@ where
         HeapPageStream::seek(self, convert_seek_from(pos)).await
     }
 }
+
+#[cfg(feature = "alloc")]
+impl<D, const BLOCK_SIZE: usize> Discard for HeapPageStream<D, BLOCK_SIZE>
+where
+    D: BlockDevice<BLOCK_SIZE> + Send + Sync,
+    D::Error: core::error::Error + Send + Sync + 'static,
+{
+    type Error = StreamError<D::Error>;
+
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), StreamError<D::Error>> {
+        HeapPageStream::discard(self, offset, len).await
+    }
+}
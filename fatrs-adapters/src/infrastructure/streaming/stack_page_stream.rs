@@ -2,9 +2,11 @@
 
 use crate::{
     adapters::{StackBuffer, AdapterError},
-    infrastructure::streaming::{StreamError, SeekFrom},
+    infrastructure::streaming::StreamError,
 };
-use fatrs_block_device::BlockDevice;
+#[cfg(feature = "alloc")]
+use crate::infrastructure::streaming::SeekFrom;
+use fatrs_block_device::{BlockDevice, discard_block_range};
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -160,6 +162,26 @@ where
         })
     }
 
+    /// Discard the whole blocks inside `len` bytes starting at byte `offset`.
+    ///
+    /// Note: This method is internal. Users should use the `fatrs_block_device::Discard` trait.
+    pub(crate) async fn discard(
+        &mut self,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), StreamError<D::Error>> {
+        let (first, count) = discard_block_range(offset, len, BLOCK_SIZE as u64);
+        if count == 0 {
+            return Ok(());
+        }
+        let first = u32::try_from(first).map_err(|_| StreamError::OutOfBounds)?;
+        let count = u32::try_from(count).map_err(|_| StreamError::OutOfBounds)?;
+        self.buffer.discard(first, count).await.map_err(|e| match e {
+            AdapterError::Storage(s) => StreamError::Storage(s),
+            _ => StreamError::OutOfBounds,
+        })
+    }
+
     /// Seek to a new position in the stream.
     ///
     /// Returns the new position from the start of the stream.
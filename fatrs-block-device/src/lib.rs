@@ -9,6 +9,7 @@
 //! - Async-first design using native async fn in traits
 //! - Alignment-aware buffer handling for DMA compatibility
 //! - Two trait variants: [`BlockDevice`] (single-threaded) and [`SendBlockDevice`] (multi-threaded)
+//! - Optional discard (TRIM) of unused blocks, see [`BlockDevice::discard`] and [`Discard`]
 //!
 //! # Example
 //!
@@ -111,6 +112,44 @@ pub trait BlockDevice<const SIZE: usize> {
     /// This operation flushes any cached writes to the underlying storage medium.
     /// Implementations should override this if the device has write caching.
     async fn sync(&mut self) -> Result<(), Self::Error>;
+
+    /// Tell the device that `count` blocks starting at `block_address` no longer hold data.
+    ///
+    /// Flash based devices can erase or unmap the blocks ahead of time (TRIM), which avoids
+    /// copying stale data during garbage collection. The contents of discarded blocks are
+    /// undefined until they are written again.
+    ///
+    /// The default implementation does nothing, which is always correct.
+    fn discard(
+        &mut self,
+        block_address: u32,
+        count: u32,
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        let _ = (block_address, count);
+        async { Ok(()) }
+    }
+}
+
+/// A byte stream that can pass discards (TRIM) on to the storage below it.
+///
+/// Implemented by the stream adapters which expose a [`BlockDevice`] as a `Read + Write + Seek`
+/// stream, so that a filesystem mounted on the stream can release the storage behind freed
+/// clusters. Only whole blocks inside the range are discarded.
+pub trait Discard {
+    /// The error type for the discard operation.
+    type Error: core::fmt::Debug;
+
+    /// Discard `len` bytes starting at byte `offset` of the stream.
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), Self::Error>;
+}
+
+/// Returns the range of whole `block_size` blocks inside `len` bytes starting at byte `offset`,
+/// as the first block address and the number of blocks.
+#[must_use]
+pub fn discard_block_range(offset: u64, len: u64, block_size: u64) -> (u64, u64) {
+    let first = offset.div_ceil(block_size);
+    let end = (offset + len) / block_size;
+    (first, end.saturating_sub(first))
 }
 
 /// Cast a byte slice to an aligned slice of blocks.
@@ -214,4 +253,12 @@ mod test {
         let blocks: &mut [Aligned<aligned::A4, [u8; 512]>] = slice_to_blocks_mut(slice);
         assert!(blocks.len() == 2);
     }
+
+    #[test]
+    fn test_discard_block_range() {
+        assert_eq!(discard_block_range(0, 1024, 512), (0, 2));
+        assert_eq!(discard_block_range(100, 1024, 512), (1, 1));
+        assert_eq!(discard_block_range(512, 511, 512), (1, 0));
+        assert_eq!(discard_block_range(600, 100, 512), (2, 0));
+    }
 }
@@ -62,7 +62,7 @@ pub use fatrs_block_device::{BlockDevice, SendBlockDevice};
 #[cfg(feature = "embedded-io-async")]
 pub mod stream;
 #[cfg(feature = "embedded-io-async")]
-pub use stream::StreamBlockDevice;
+pub use stream::{DiscardPolicy, ForwardDiscards, IgnoreDiscards, StreamBlockDevice};
 
 // Embedded SPI module
 #[cfg(feature = "sdspi")]
@@ -4,7 +4,7 @@
 
 use aligned::{A4, Aligned};
 use embedded_io_async::{ErrorType, Read, Seek, SeekFrom, Write};
-use fatrs_block_device::BlockDevice;
+use fatrs_block_device::{BlockDevice, Discard, discard_block_range};
 use std::io;
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
@@ -14,6 +14,19 @@ const BLOCK_SIZE: usize = 512;
 /// Linux block device wrapper for async block I/O
 ///
 /// Provides direct access to Linux block devices like /dev/sdb, /dev/mmcblk0, etc.
+///
+/// Whole-disk devices usually carry a partition table (MBR or GPT). Instead of opening the
+/// partition node (e.g. /dev/sdb1) the whole device can be mounted with
+/// `fatrs::FileSystem::open_partition` or `fatrs::FileSystem::open_first_fat_partition`, which
+/// locate the FAT volume themselves:
+///
+/// ```ignore
+/// use fatrs_adapters::HeapPageStream;
+///
+/// let dev = LinuxBlockDevice::open("/dev/sdb", false).await?;
+/// let stream = HeapPageStream::new(dev, 4096)?;
+/// let fs = fatrs::FileSystem::open_first_fat_partition(stream, fatrs::FsOptions::new()).await?;
+/// ```
 pub struct LinuxBlockDevice {
     inner: std::sync::Arc<std::sync::Mutex<std::fs::File>>,
     size: u64,
@@ -264,6 +277,48 @@ impl BlockDevice<BLOCK_SIZE> for LinuxBlockDevice {
         .await
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
     }
+
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        if count == 0 {
+            return Ok(());
+        }
+        let inner = self.inner.clone();
+        // Byte offset and length of the range
+        let range: [u64; 2] = [
+            (block_address as u64) * BLOCK_SIZE as u64,
+            (count as u64) * BLOCK_SIZE as u64,
+        ];
+
+        tokio::task::spawn_blocking(move || {
+            use std::os::unix::io::AsRawFd;
+            let file = inner.lock().unwrap();
+            unsafe {
+                // BLKDISCARD = _IO(0x12, 119)
+                if libc::ioctl(file.as_raw_fd(), 0x1277, range.as_ptr()) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+}
+
+impl Discard for LinuxBlockDevice {
+    type Error = io::Error;
+
+    /// Discards the whole blocks inside the byte range with `BLKDISCARD`.
+    ///
+    /// Devices without discard support fail with `EOPNOTSUPP`.
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), io::Error> {
+        let (first, count) = discard_block_range(offset, len, BLOCK_SIZE as u64);
+        let first =
+            u32::try_from(first).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let count =
+            u32::try_from(count).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        BlockDevice::discard(self, first, count).await
+    }
 }
 
 /// Information about a block device
@@ -4,8 +4,9 @@
 
 use aligned::{A4, Aligned};
 use core::cell::RefCell;
+use core::marker::PhantomData;
 use embedded_io_async::{ErrorType, Read, Seek, SeekFrom, Write};
-use fatrs_block_device::BlockDevice;
+use fatrs_block_device::{BlockDevice, Discard};
 
 const BLOCK_SIZE: usize = 512;
 
@@ -19,6 +20,9 @@ const BLOCK_SIZE: usize = 512;
 /// This is useful for wrapping file handles, in-memory buffers, or any other
 /// stream-like interface to provide block device access.
 ///
+/// Discards are ignored by default. Streams implementing [`Discard`] can be wrapped with
+/// [`StreamBlockDevice::with_discard`] to forward them.
+///
 /// # Example
 ///
 /// ```ignore
@@ -29,14 +33,54 @@ const BLOCK_SIZE: usize = 512;
 /// let stream = FromTokio::new(file);
 /// let block_dev = StreamBlockDevice::new(stream);
 /// ```
-pub struct StreamBlockDevice<T>(RefCell<T>);
+pub struct StreamBlockDevice<T, D = IgnoreDiscards>(RefCell<T>, PhantomData<D>);
+
+/// How a [`StreamBlockDevice`] handles [`BlockDevice::discard`]
+#[allow(async_fn_in_trait)]
+pub trait DiscardPolicy<T: ErrorType> {
+    /// Discard `len` bytes starting at byte `offset` of the stream.
+    async fn discard(stream: &mut T, offset: u64, len: u64) -> Result<(), T::Error>;
+}
+
+/// Discards are dropped, works with any stream
+pub struct IgnoreDiscards;
+
+/// Discards are forwarded to the stream's [`Discard`] implementation
+pub struct ForwardDiscards;
+
+impl<T: ErrorType> DiscardPolicy<T> for IgnoreDiscards {
+    async fn discard(_stream: &mut T, _offset: u64, _len: u64) -> Result<(), T::Error> {
+        Ok(())
+    }
+}
+
+impl<T> DiscardPolicy<T> for ForwardDiscards
+where
+    T: ErrorType + Discard<Error = <T as ErrorType>::Error>,
+{
+    async fn discard(stream: &mut T, offset: u64, len: u64) -> Result<(), <T as ErrorType>::Error> {
+        Discard::discard(stream, offset, len).await
+    }
+}
 
 impl<T> StreamBlockDevice<T> {
     /// Create a new StreamBlockDevice wrapping the given stream.
     pub fn new(inner: T) -> Self {
-        Self(RefCell::new(inner))
+        Self(RefCell::new(inner), PhantomData)
     }
+}
 
+impl<T> StreamBlockDevice<T, ForwardDiscards>
+where
+    T: ErrorType + Discard<Error = <T as ErrorType>::Error>,
+{
+    /// Create a new StreamBlockDevice which forwards discards to the stream.
+    pub fn with_discard(inner: T) -> Self {
+        Self(RefCell::new(inner), PhantomData)
+    }
+}
+
+impl<T, D> StreamBlockDevice<T, D> {
     /// Get a reference to the inner stream.
     ///
     /// # Panics
@@ -59,13 +103,14 @@ impl<T> StreamBlockDevice<T> {
     }
 }
 
-impl<T: ErrorType> ErrorType for StreamBlockDevice<T> {
+impl<T: ErrorType, D> ErrorType for StreamBlockDevice<T, D> {
     type Error = T::Error;
 }
 
-impl<T> BlockDevice<BLOCK_SIZE> for StreamBlockDevice<T>
+impl<T, D> BlockDevice<BLOCK_SIZE> for StreamBlockDevice<T, D>
 where
     T: Read + Write + Seek,
+    D: DiscardPolicy<T>,
 {
     type Error = T::Error;
     type Align = A4;
@@ -128,4 +173,14 @@ where
         let mut inner = self.0.borrow_mut();
         inner.flush().await
     }
+
+    async fn discard(&mut self, block_address: u32, count: u32) -> Result<(), Self::Error> {
+        let mut inner = self.0.borrow_mut();
+        D::discard(
+            &mut inner,
+            (block_address as u64) * BLOCK_SIZE as u64,
+            (count as u64) * BLOCK_SIZE as u64,
+        )
+        .await
+    }
 }
@@ -7,7 +7,10 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand, ValueEnum};
 use embedded_io_adapters::tokio_1::FromTokio;
-use fatrs::{FatType, FormatVolumeOptions, FsOptions};
+use fatrs::{
+    FatType, FormatDiskOptions, FormatVolumeOptions, FsOptions, PartitionOptions,
+    PartitionTableType,
+};
 use fatrs_adapters::{HeapPageStream, presets};
 use fatrs_block_platform::StreamBlockDevice;
 use fatrs_cli::path_parser::{PathSpec, parse_copy_operation};
@@ -42,6 +45,18 @@ pub enum PageSize {
     Page1M,
 }
 
+/// Partition table written by the `create` command
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum PartitionTable {
+    /// No partition table, the FAT volume starts at sector 0 ("superfloppy")
+    None,
+    /// Master Boot Record with a single FAT partition (like SD card formatters)
+    #[default]
+    Mbr,
+    /// GUID Partition Table with a single Basic Data partition
+    Gpt,
+}
+
 impl PageSize {
     /// Convert to byte size
     pub fn to_bytes(self) -> Option<usize> {
@@ -116,6 +131,12 @@ pub enum Command {
         image: PathBuf,
     },
 
+    /// List the partitions of a partitioned disk image (MBR or GPT)
+    Partitions {
+        /// Path to disk image
+        image: PathBuf,
+    },
+
     /// Display contents of a file
     #[command(long_about = "Display contents of a file from a FAT image\n\n\
         EXAMPLES:\n  \
@@ -203,6 +224,10 @@ pub enum Command {
         #[arg(short, long)]
         label: Option<String>,
 
+        /// Partition table to write (the FAT partition is aligned to 1 MiB)
+        #[arg(long, value_enum, default_value = "mbr")]
+        partition_table: PartitionTable,
+
         /// Source directory to copy into the image
         #[arg(short, long)]
         from: Option<PathBuf>,
@@ -217,9 +242,10 @@ pub enum Command {
         #[arg(long, requires = "transaction_log")]
         log_sector: Option<u32>,
 
-        /// Number of sectors for transaction log (default: 4)
+        /// Number of sectors for transaction log: 4 entries plus one before-image per sector a transaction
+        /// may modify (default: 68)
         #[cfg(feature = "transaction-safe")]
-        #[arg(long, requires = "transaction_log", default_value = "4")]
+        #[arg(long, requires = "transaction_log", default_value = "68")]
         log_count: u32,
     },
 
@@ -347,13 +373,15 @@ fn effective_page_size(page_size: PageSize) -> usize {
 }
 
 /// Open a FAT filesystem image with large page buffering
+///
+/// Partitioned images (MBR or GPT) are supported, the first FAT partition is opened.
 async fn open_fs_buffered(
     image: &Path,
     writable: bool,
     page_size: usize,
 ) -> Result<(
     fatrs::FileSystem<
-        HeapPageStream<StreamBlockDevice<FromTokio<tokio::fs::File>>, 512>,
+        fatrs::StreamSlice<HeapPageStream<StreamBlockDevice<FromTokio<tokio::fs::File>>, 512>>,
         fatrs::DefaultTimeProvider,
         fatrs::LossyOemCpConverter,
     >,
@@ -370,7 +398,7 @@ async fn open_fs_buffered(
     let stream = HeapPageStream::new(block_dev, page_size)
         .map_err(|e| anyhow::anyhow!("Failed to create page stream: {:?}", e))?;
 
-    let fs = fatrs::FileSystem::new(stream, FsOptions::new())
+    let fs = fatrs::FileSystem::open_first_fat_partition(stream, FsOptions::new())
         .await
         .context("Failed to mount FAT filesystem")?;
 
@@ -388,6 +416,7 @@ pub async fn run(cli: Cli) -> Result<()> {
             recursive,
         } => cmd_ls(&image, &path, long, recursive, page_size).await,
         Command::Info { image } => cmd_info(&image, page_size).await,
+        Command::Partitions { image } => cmd_partitions(&image).await,
         Command::Cat { path } => {
             let path_spec = PathSpec::parse(&path)?;
             match path_spec {
@@ -449,6 +478,7 @@ pub async fn run(cli: Cli) -> Result<()> {
             size,
             fat_type,
             label,
+            partition_table,
             from,
             #[cfg(feature = "transaction-safe")]
             transaction_log,
@@ -462,6 +492,7 @@ pub async fn run(cli: Cli) -> Result<()> {
                 &size,
                 fat_type,
                 label.as_deref(),
+                partition_table,
                 from.as_deref(),
                 page_size,
                 #[cfg(feature = "transaction-safe")]
@@ -637,6 +668,44 @@ async fn cmd_info(image: &Path, page_size: usize) -> Result<()> {
     Ok(())
 }
 
+async fn cmd_partitions(image: &Path) -> Result<()> {
+    let file = tokio::fs::File::open(image)
+        .await
+        .with_context(|| format!("Failed to open image: {}", image.display()))?;
+    let mut stream = FromTokio::new(file);
+
+    let partitions = fatrs::partitions(&mut stream)
+        .await
+        .context("Failed to read partition table")?;
+
+    if partitions.is_empty() {
+        println!("No partition table (the image is a single FAT volume)");
+        return Ok(());
+    }
+
+    println!("{:<6} {:>14} {:>10}  {}", "Index", "Offset", "Size", "Type");
+    for partition in partitions {
+        let kind = match &partition {
+            fatrs::Partition::Mbr(p) => format!(
+                "MBR type {:#04x}{}",
+                p.partition_type(),
+                if p.is_bootable() { " (bootable)" } else { "" }
+            ),
+            fatrs::Partition::Gpt(p) => format!("{} \"{}\"", p.type_guid(), p.name()),
+        };
+        println!(
+            "{:<6} {:>14} {:>10}  {}{}",
+            partition.index(),
+            partition.byte_offset(),
+            format_size(partition.byte_len()),
+            kind,
+            if partition.is_fat() { " [FAT]" } else { "" }
+        );
+    }
+
+    Ok(())
+}
+
 async fn cmd_cat(image: &Path, path: &str, page_size: usize) -> Result<()> {
     let (fs, _) = open_fs_buffered(image, false, page_size).await?;
 
@@ -1005,6 +1074,7 @@ async fn cmd_create(
     size: &str,
     fat_type: u8,
     label: Option<&str>,
+    partition_table: PartitionTable,
     from: Option<&Path>,
     page_size: usize,
     #[cfg(feature = "transaction-safe")]
@@ -1041,6 +1111,15 @@ async fn cmd_create(
         _ => anyhow::bail!("Invalid FAT type: {}. Must be 12, 16, or 32", fat_type),
     };
 
+    // Automatic placement puts the transaction log at the end of the reserved sectors
+    #[cfg(feature = "transaction-safe")]
+    if transaction_log && log_sector.is_none() {
+        let base_reserved: u32 = if fat_type == 32 { 8 } else { 1 };
+        let reserved = u16::try_from(base_reserved.saturating_add(log_count))
+            .context("Transaction log is too large")?;
+        options = options.reserved_sectors(reserved);
+    }
+
     if let Some(label) = label {
         let mut label_bytes = [0x20u8; 11]; // Space-padded
         let label_upper = label.to_uppercase();
@@ -1049,10 +1128,29 @@ async fn cmd_create(
         options = options.volume_label(label_bytes);
     }
 
-    // Format the volume
-    fatrs::format_volume(&mut io, options)
+    // Format the volume, inside a partition unless a bare volume was requested
+    let table_type = match partition_table {
+        PartitionTable::None => None,
+        PartitionTable::Mbr => Some(PartitionTableType::Mbr),
+        PartitionTable::Gpt => Some(PartitionTableType::Gpt),
+    };
+    if let Some(table_type) = table_type {
+        let mut partition = PartitionOptions::new().volume_options(options);
+        if let Some(label) = label {
+            partition = partition.name(label);
+        }
+        fatrs::format_disk(
+            &mut io,
+            FormatDiskOptions::new().partition_table(table_type),
+            &[partition],
+        )
         .await
-        .context("Failed to format volume")?;
+        .context("Failed to format disk")?;
+    } else {
+        fatrs::format_volume(&mut io, options)
+            .await
+            .context("Failed to format volume")?;
+    }
 
     #[cfg(feature = "transaction-safe")]
     if transaction_log {
@@ -1085,7 +1183,7 @@ async fn cmd_create(
             if let Some(sector) = log_sector {
                 FsOptions::new().with_transaction_log_at(sector, log_count)
             } else {
-                FsOptions::new().with_transaction_log()
+                FsOptions::new().with_transaction_log_at(0, log_count)
             }
         } else {
             FsOptions::new()
@@ -1094,7 +1192,7 @@ async fn cmd_create(
         #[cfg(not(feature = "transaction-safe"))]
         let fs_options = FsOptions::new();
 
-        let fs = fatrs::FileSystem::new(stream, fs_options)
+        let fs = fatrs::FileSystem::open_first_fat_partition(stream, fs_options)
             .await
             .context("Failed to mount newly created filesystem")?;
 
@@ -1308,7 +1406,7 @@ async fn open_flash_device(
     page_size: usize,
 ) -> Result<(
     fatrs::FileSystem<
-        HeapPageStream<StreamBlockDevice<fatrs_cli::AsyncWindowsDevice>, 512>,
+        fatrs::StreamSlice<HeapPageStream<StreamBlockDevice<fatrs_cli::AsyncWindowsDevice>, 512>>,
         fatrs::DefaultTimeProvider,
         fatrs::LossyOemCpConverter,
     >,
@@ -1324,7 +1422,7 @@ async fn open_flash_device(
     let stream = HeapPageStream::new(block_dev, page_size)
         .map_err(|e| anyhow::anyhow!("Failed to create page stream: {:?}", e))?;
 
-    let fs = fatrs::FileSystem::new(stream, FsOptions::new())
+    let fs = fatrs::FileSystem::open_first_fat_partition(stream, FsOptions::new())
         .await
         .context("Failed to mount FAT filesystem")?;
 
@@ -1343,7 +1441,8 @@ async fn cmd_txlog(image: &Path, page_size: usize) -> Result<()> {
 
     println!("Total slots:    {}", stats.total_slots);
     println!("Used slots:     {}", stats.used_slots);
-    println!("Sequence number: {}\n", stats.sequence_number);
+    println!("Sequence number: {}", stats.sequence_number);
+    println!("Sector capacity: {}\n", stats.sector_capacity);
 
     // Get detailed transaction list
     let transactions = fs.transaction_list().await;
@@ -112,7 +112,7 @@ fn main() -> Result<()> {
         // Create filesystem
         let fs = fatrs::FileSystem::new(
             embedded_io_adapters::tokio_1::FromTokio::new(file),
-            fatrs::FsOptions::new(),
+            fatrs::FsOptions::new().read_only(args.read_only),
         )
         .await
         .context("Failed to mount FAT filesystem")?;
@@ -146,7 +146,7 @@ async fn open_image_file(
 
             let io = UnifiedIO::Device(stream);
 
-            let fs = fatrs::FileSystem::new(io, fatrs::FsOptions::new())
+            let fs = fatrs::FileSystem::new(io, fatrs::FsOptions::new().read_only(true))
                 .await
                 .context("Failed to mount FAT filesystem from device")?;
 
@@ -174,7 +174,7 @@ async fn open_image_file(
 
         let io = UnifiedIO::File(embedded_io_adapters::tokio_1::FromTokio::new(file));
 
-        let fs = fatrs::FileSystem::new(io, fatrs::FsOptions::new())
+        let fs = fatrs::FileSystem::new(io, fatrs::FsOptions::new().read_only(read_only))
             .await
             .context("Failed to mount FAT filesystem")?;
 
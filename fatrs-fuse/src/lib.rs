@@ -1003,23 +1003,17 @@ where
             }
         };
 
-        // Handle truncate (size change)
+        // Handle size change (shrink or zero-filled extension)
         if let Some(new_size) = size {
             let result = self.block_on(async {
-                use embedded_io_async::Seek as _;
-
                 let root = self.fs.root_dir();
 
                 // Open the file
                 let path_str = file_path.to_str().ok_or(fatrs::Error::InvalidInput)?;
                 let mut file = root.open_file(path_str.trim_start_matches('/')).await?;
 
-                // Seek to the new size position
-                file.seek(embedded_io_async::SeekFrom::Start(new_size))
-                    .await?;
-
-                // Truncate at this position
-                file.truncate().await?;
+                // Resize the file
+                file.set_len(new_size).await?;
 
                 // Flush changes
                 embedded_io_async::Write::flush(&mut file).await?;
@@ -1031,11 +1025,11 @@ where
             match result {
                 Ok(entry) => {
                     let attr = self.fat_to_fuse_attr(ino, &entry);
-                    debug!("setattr: truncated {:?} to {} bytes", file_path, new_size);
+                    debug!("setattr: resized {:?} to {} bytes", file_path, new_size);
                     reply.attr(&TTL, &attr);
                 }
                 Err(e) => {
-                    debug!("setattr: failed to truncate {:?}: {:?}", file_path, e);
+                    debug!("setattr: failed to resize {:?}: {:?}", file_path, e);
                     reply.error(libc::EIO);
                 }
             }
@@ -34,6 +34,7 @@ async fn main() {
     }
 
     benchmark_random_access().await;
+    benchmark_backward_seek().await;
 
     // Cleanup
     let _ = fs::remove_file("target/bench_random.img").await;
@@ -93,3 +94,69 @@ async fn benchmark_random_access() {
     );
     println!();
 }
+
+/// Seeks backwards in a fragmented file, which walks the cluster chain from the start of the file
+/// unless cluster checkpoints are enabled. Compare runs with and without `--features cluster-checkpoints`.
+async fn benchmark_backward_seek() {
+    println!("--- Backward Seek Benchmark ---");
+    println!(
+        "  Checkpoints: {}",
+        if cfg!(feature = "cluster-checkpoints") {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    let img_file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("target/bench_random.img")
+        .await
+        .unwrap();
+
+    let fs = fatrs::FileSystem::new(img_file, fatrs::FsOptions::new())
+        .await
+        .unwrap();
+    let root = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+
+    // Interleave two files so the chain cannot be followed without reading the FAT
+    let chunk = vec![0xAB; cluster_size];
+    let mut file = root.create_file("fragmented.bin").await.unwrap();
+    let mut filler = root.create_file("filler.bin").await.unwrap();
+    let clusters = 4096u64;
+    for _ in 0..clusters {
+        file.write_all(&chunk).await.unwrap();
+        filler.write_all(&chunk).await.unwrap();
+    }
+    file.flush().await.unwrap();
+    filler.flush().await.unwrap();
+    drop(filler);
+
+    // Checkpoints are recorded while the file is read sequentially
+    let mut file = root.open_file("fragmented.bin").await.unwrap();
+    let mut buf = vec![0u8; cluster_size];
+    let start = Instant::now();
+    while file.read(&mut buf).await.unwrap() > 0 {}
+    println!("  Sequential read: {:.3}s", start.elapsed().as_secs_f64());
+
+    // Seek from the end of the file to positions in its last quarter
+    let iterations = 200u64;
+    let start = Instant::now();
+    for i in 0..iterations {
+        let cluster = clusters * 3 / 4 + (i * 7919) % (clusters / 4);
+        file.seek(SeekFrom::End(0)).await.unwrap();
+        file.seek(SeekFrom::Start(cluster * cluster_size as u64))
+            .await
+            .unwrap();
+        file.read(&mut buf[..512]).await.unwrap();
+    }
+    let elapsed = start.elapsed();
+    let avg_latency = elapsed / iterations as u32;
+
+    println!("  Iterations: {}", iterations);
+    println!("  Total time: {:.3}s", elapsed.as_secs_f64());
+    println!("  Avg latency: {:.2}ms", avg_latency.as_secs_f64() * 1000.0);
+    println!();
+}
@@ -3,10 +3,14 @@ use core::cmp;
 use crate::dir_entry::DIR_ENTRY_SIZE;
 use crate::error::{Error, IoError};
 use crate::fs::{FatType, FormatVolumeOptions, FsStatusFlags};
-use crate::io::{Read, ReadLeExt, Write, WriteLeExt};
+use crate::io::{Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
 use crate::table::RESERVED_FAT_ENTRIES;
 
 const BITS_PER_BYTE: u32 = 8;
+/// Bit of the FAT32 extended flags disabling FAT mirroring, the low 4 bits select the active FAT
+pub(crate) const EXT_FLAG_NO_MIRRORING: u16 = 0x80;
+/// Sector of the backup boot sector written by `format_volume` and recommended by the FAT specification
+const BACKUP_BOOT_SECTOR: u16 = 6;
 const KB_32: u32 = 1024;
 const KB_64: u64 = 1024;
 const MB_64: u64 = KB_64 * 1024;
@@ -220,6 +224,15 @@ impl BiosParameterBlock {
                 self.fats
             );
         }
+        if self.is_fat32() && !self.mirroring_enabled() && self.active_fat() >= u16::from(self.fats)
+        {
+            error!(
+                "invalid active FAT in BPB: {} but the volume has {} FATs",
+                self.active_fat(),
+                self.fats
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
         Ok(())
     }
 
@@ -330,7 +343,7 @@ impl BiosParameterBlock {
     }
 
     pub(crate) fn mirroring_enabled(&self) -> bool {
-        self.extended_flags & 0x80 == 0
+        self.extended_flags & EXT_FLAG_NO_MIRRORING == 0
     }
 
     pub(crate) fn active_fat(&self) -> u16 {
@@ -481,6 +494,35 @@ impl BootSector {
     }
 }
 
+/// Reads the FAT32 backup boot sector when the primary one cannot be used.
+///
+/// The sector size is unknown without a valid primary boot sector, so every sector size is tried and a
+/// candidate is only accepted if it is a valid FAT32 boot sector describing itself as the backup at that
+/// position.
+pub(crate) async fn read_backup_boot_sector<S: Read + Seek>(
+    storage: &mut S,
+) -> Result<BootSector, Error<S::Error>> {
+    for bytes_per_sector in [512_u16, 1024, 2048, 4096] {
+        let offset = u64::from(BACKUP_BOOT_SECTOR) * u64::from(bytes_per_sector);
+        storage.seek(SeekFrom::Start(offset)).await?;
+        let boot = match BootSector::deserialize(storage).await {
+            Ok(boot) => boot,
+            Err(Error::UnexpectedEof) => break,
+            Err(err) => return Err(err),
+        };
+        let bpb = &boot.bpb;
+        if bpb.bytes_per_sector == bytes_per_sector
+            && bpb.backup_boot_sector == BACKUP_BOOT_SECTOR
+            && bpb.is_fat32()
+            && boot.validate::<S::Error>().is_ok()
+        {
+            return Ok(boot);
+        }
+    }
+    error!("No valid backup boot sector found");
+    Err(Error::CorruptedFileSystem)
+}
+
 impl Default for BootSector {
     fn default() -> Self {
         Self {
@@ -504,7 +546,7 @@ fn estimate_fat_type(total_bytes: u64) -> FatType {
     }
 }
 
-fn determine_bytes_per_cluster(
+pub(crate) fn determine_bytes_per_cluster(
     total_bytes: u64,
     bytes_per_sector: u16,
     fat_type: Option<FatType>,
@@ -532,6 +574,14 @@ fn determine_bytes_per_cluster(
                 ((total_bytes.next_power_of_two() / (2 * GB_64)) as u32) * KB_32
             }
         }
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => {
+            if total_bytes <= 256 * MB_64 {
+                4 * KB_32
+            } else {
+                32 * KB_32
+            }
+        }
     };
     let bytes_per_cluster_clamped = cmp::min(
         cmp::max(bytes_per_cluster, u32::from(bytes_per_sector)),
@@ -726,6 +776,21 @@ fn format_bpb<E: IoError>(
     // reserved_0 is always zero
     let reserved_0 = [0_u8; 12];
 
+    // FAT mirroring is disabled by selecting the active FAT, which is only supported by FAT32
+    let extended_flags = match options.active_fat {
+        None => 0,
+        Some(active_fat) if fat_type == FatType::Fat32 && active_fat < fats => {
+            EXT_FLAG_NO_MIRRORING | u16::from(active_fat)
+        }
+        Some(active_fat) => {
+            error!(
+                "Active FAT {} cannot be selected on a {:?} volume with {} FATs",
+                active_fat, fat_type, fats
+            );
+            return Err(Error::InvalidInput);
+        }
+    };
+
     // setup volume label
     let mut volume_label = [0_u8; 11];
     if let Some(volume_label_from_opts) = options.volume_label {
@@ -740,6 +805,8 @@ fn format_bpb<E: IoError>(
         FatType::Fat12 => b"FAT12   ",
         FatType::Fat16 => b"FAT16   ",
         FatType::Fat32 => b"FAT32   ",
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => b"EXFAT   ",
     };
     fs_type_label.copy_from_slice(fs_type_label_str);
 
@@ -766,7 +833,7 @@ fn format_bpb<E: IoError>(
         sectors_per_fat_16,
         sectors_per_track: options.sectors_per_track.unwrap_or(0x20),
         heads: options.heads.unwrap_or(0x40),
-        hidden_sectors: 0,
+        hidden_sectors: options.hidden_sectors.unwrap_or(0),
         total_sectors_32: if total_sectors >= 0x10000 {
             total_sectors
         } else {
@@ -774,11 +841,11 @@ fn format_bpb<E: IoError>(
         },
         // FAT32 fields start
         sectors_per_fat_32: if is_fat32 { sectors_per_fat } else { 0 },
-        extended_flags: 0, // mirroring enabled
+        extended_flags,
         fs_version: 0,
         root_dir_first_cluster: if is_fat32 { 2 } else { 0 },
         fs_info_sector: if is_fat32 { 1 } else { 0 },
-        backup_boot_sector: if is_fat32 { 6 } else { 0 },
+        backup_boot_sector: if is_fat32 { BACKUP_BOOT_SECTOR } else { 0 },
         reserved_0,
         // FAT32 fields end
         drive_num,
@@ -0,0 +1,1437 @@
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::dir::{Dir, MAX_LONG_NAME_LEN, lfn_checksum};
+use crate::dir_entry::{
+    DIR_ENTRY_DELETED_FLAG, DIR_ENTRY_SIZE, DirEntryData, DirFileEntryData, DirLfnEntryData,
+    LFN_ENTRY_LAST_FLAG, LFN_PART_LEN, SFN_SIZE,
+};
+use crate::error::Error;
+use crate::fs::{DiskSlice, FatType, FileSystem, FsIoAdapter, OemCpConverter, ReadWriteSeek};
+use crate::io::{Read, Seek, SeekFrom, Write, WriteLeExt};
+use crate::table::{FatValue, RESERVED_FAT_ENTRIES, read_fat, write_fat};
+use crate::time::TimeProvider;
+
+const DOT_NAME: [u8; SFN_SIZE] = *b".          ";
+const DOTDOT_NAME: [u8; SFN_SIZE] = *b"..         ";
+const MAX_LONG_DIR_ENTRIES: u8 = MAX_LONG_NAME_LEN.div_ceil(LFN_PART_LEN) as u8;
+// Number of bytes compared or copied at once
+const CHUNK_LEN: usize = 512;
+
+/// A problem found by [`FileSystem::check`].
+///
+/// Paths are absolute and use `/` as a separator. Long names are used when they are valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Problem {
+    /// A chain of allocated clusters that is not referenced by any directory entry.
+    LostChain { first_cluster: u32, clusters: u32 },
+    /// A cluster that was already used by another chain (or earlier in the same chain) when following the
+    /// chain of the entry at `path`.
+    CrossLinkedCluster { path: String, cluster: u32 },
+    /// The number of clusters in the chain of a file does not match the size in its directory entry.
+    ChainLengthMismatch {
+        path: String,
+        size: u32,
+        clusters: u32,
+    },
+    /// The chain of the entry at `path` references a cluster outside of the data area, or contains a cluster
+    /// marked as free or bad in the FAT. A directory without a first cluster is reported with cluster 0.
+    InvalidClusterReference { path: String, cluster: u32 },
+    /// The long name stored before the entry at `path` was created for a different short name.
+    LfnChecksumMismatch { path: String },
+    /// Long name entries in the directory at `path` that are not followed by their short name entry.
+    OrphanedLongName { path: String },
+    /// The `.` entry of the directory at `path` is missing or does not point to the directory itself.
+    BrokenDotEntry { path: String },
+    /// The `..` entry of the directory at `path` is missing or does not point to the parent directory.
+    BrokenDotDotEntry { path: String },
+    /// FAT copy number `fat` holds a different value for `cluster` than the first FAT.
+    FatMismatch { fat: u8, cluster: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::LostChain {
+                first_cluster,
+                clusters,
+            } => write!(
+                f,
+                "Lost chain of {} clusters starting at cluster {}",
+                clusters, first_cluster
+            ),
+            Problem::CrossLinkedCluster { path, cluster } => {
+                write!(f, "{}: cluster {} is cross-linked", path, cluster)
+            }
+            Problem::ChainLengthMismatch {
+                path,
+                size,
+                clusters,
+            } => write!(
+                f,
+                "{}: size {} does not match chain of {} clusters",
+                path, size, clusters
+            ),
+            Problem::InvalidClusterReference { path, cluster } => {
+                write!(f, "{}: invalid cluster reference {}", path, cluster)
+            }
+            Problem::LfnChecksumMismatch { path } => {
+                write!(f, "{}: long name checksum mismatch", path)
+            }
+            Problem::OrphanedLongName { path } => write!(f, "{}: orphaned long name entries", path),
+            Problem::BrokenDotEntry { path } => write!(f, "{}: broken '.' entry", path),
+            Problem::BrokenDotDotEntry { path } => write!(f, "{}: broken '..' entry", path),
+            Problem::FatMismatch { fat, cluster } => {
+                write!(f, "FAT copy {} differs for cluster {}", fat, cluster)
+            }
+        }
+    }
+}
+
+/// Result of a file system consistency check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    problems: Vec<Problem>,
+    files: u32,
+    dirs: u32,
+    used_clusters: u32,
+}
+
+impl CheckReport {
+    /// Problems found, in the order they were detected.
+    #[must_use]
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns `true` if no problems were found.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Number of files found (excluding directories)
+    #[must_use]
+    pub fn files(&self) -> u32 {
+        self.files
+    }
+
+    /// Number of directories found (excluding the root directory)
+    #[must_use]
+    pub fn dirs(&self) -> u32 {
+        self.dirs
+    }
+
+    /// Number of clusters referenced by directory entries
+    #[must_use]
+    pub fn used_clusters(&self) -> u32 {
+        self.used_clusters
+    }
+}
+
+/// What [`FileSystem::repair`] does with lost cluster chains.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LostChainAction {
+    /// Marks the clusters as free.
+    Free,
+    /// Saves every chain as a file, like `chkdsk` does.
+    #[default]
+    Reclaim,
+}
+
+/// Options for [`FileSystem::repair`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RepairOptions {
+    lost_chains: LostChainAction,
+}
+
+impl RepairOptions {
+    /// Creates the default options: lost chains are reclaimed.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what is done with lost cluster chains.
+    #[must_use]
+    pub fn lost_chains(mut self, action: LostChainAction) -> Self {
+        self.lost_chains = action;
+        self
+    }
+}
+
+/// Set of cluster numbers backed by a bitmap.
+struct ClusterSet {
+    bits: Vec<u32>,
+}
+
+impl ClusterSet {
+    fn new(end_cluster: u32) -> Self {
+        Self {
+            bits: vec![0; end_cluster.div_ceil(32) as usize],
+        }
+    }
+
+    fn contains(&self, cluster: u32) -> bool {
+        self.bits
+            .get((cluster / 32) as usize)
+            .is_some_and(|word| word & (1 << (cluster % 32)) != 0)
+    }
+
+    /// Adds a cluster. Returns `false` if it was already present.
+    fn insert(&mut self, cluster: u32) -> bool {
+        let word = &mut self.bits[(cluster / 32) as usize];
+        let mask = 1 << (cluster % 32);
+        let inserted = *word & mask == 0;
+        *word |= mask;
+        inserted
+    }
+
+    /// Removes a cluster. Returns `false` if it was not present.
+    fn remove(&mut self, cluster: u32) -> bool {
+        let removed = self.contains(cluster);
+        if removed {
+            self.bits[(cluster / 32) as usize] &= !(1 << (cluster % 32));
+        }
+        removed
+    }
+}
+
+/// A directory waiting to be scanned.
+struct PendingDir {
+    path: String,
+    /// First cluster, `None` for the fixed FAT12/FAT16 root directory
+    cluster: Option<u32>,
+    /// Value expected in the `..` entry (0 for the root directory), `None` for the root directory itself
+    parent: Option<u32>,
+    clusters: Vec<u32>,
+}
+
+/// Long name entries collected while scanning a directory.
+struct PendingLfn {
+    checksum: u8,
+    /// Order number of the next expected entry, 0 when the name is complete
+    next: u8,
+    name: Vec<u16>,
+    /// Positions of the entries, used to delete them when repairing
+    positions: Vec<u64>,
+}
+
+impl PendingLfn {
+    fn decode(&self) -> String {
+        let len = self
+            .name
+            .iter()
+            .position(|unit| *unit == 0 || *unit == 0xFFFF)
+            .unwrap_or(self.name.len());
+        String::from_utf16_lossy(&self.name[..len])
+    }
+}
+
+/// State of a directory scan spanning multiple clusters.
+struct DirScan {
+    /// Number of short name entries seen so far
+    entries: u32,
+    lfn: Option<PendingLfn>,
+}
+
+struct Checker<'a, IO: ReadWriteSeek, TP, OCC>
+where
+    IO::Error: 'static,
+{
+    fs: &'a FileSystem<IO, TP, OCC>,
+    used: ClusterSet,
+    report: CheckReport,
+    /// Problems are fixed as they are found when set
+    repair: Option<RepairOptions>,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Checker<'a, IO, TP, OCC>
+where
+    IO::Error: 'static,
+{
+    fn end_cluster(&self) -> u32 {
+        self.fs.total_clusters() + RESERVED_FAT_ENTRIES
+    }
+
+    fn repairing(&self) -> bool {
+        self.repair.is_some()
+    }
+
+    fn problem(&mut self, problem: Problem) {
+        self.report.problems.push(problem);
+    }
+
+    async fn write_fat(&self, cluster: u32, value: FatValue) -> Result<(), Error<IO::Error>> {
+        write_fat(&mut self.fs.fat_slice(), self.fs.fat_type(), cluster, value).await
+    }
+
+    async fn write_entry(&self, pos: u64, data: &DirFileEntryData) -> Result<(), Error<IO::Error>> {
+        data.serialize(&mut self.fs.dir_entry_slice(pos)).await
+    }
+
+    /// Marks directory entries as deleted when repairing.
+    async fn delete_entries(&self, positions: &[u64]) -> Result<(), Error<IO::Error>> {
+        if self.repairing() {
+            for &pos in positions {
+                let mut entry = self.fs.dir_entry_slice(pos);
+                entry.write_u8(DIR_ENTRY_DELETED_FLAG).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows a cluster chain, marking its clusters as used. Clusters are appended to `clusters` if given.
+    ///
+    /// Returns the chain length, or `None` if the chain is broken. When repairing, a broken chain is fixed and its
+    /// new length is returned. The first cluster is updated if the chain had to be dropped or replaced.
+    async fn walk_chain(
+        &mut self,
+        path: &str,
+        first_cluster: &mut Option<u32>,
+        mut clusters: Option<&mut Vec<u32>>,
+    ) -> Result<Option<u32>, Error<IO::Error>> {
+        let Some(first) = *first_cluster else {
+            return Ok(Some(0));
+        };
+        let end_cluster = self.end_cluster();
+        let fs = self.fs;
+        let mut fat = fs.fat_slice();
+        let mut cluster = first;
+        let mut prev = None;
+        let mut len = 0;
+        loop {
+            if !(RESERVED_FAT_ENTRIES..end_cluster).contains(&cluster) {
+                self.problem(Problem::InvalidClusterReference {
+                    path: path.into(),
+                    cluster,
+                });
+                return self.cut_chain(first_cluster, prev, len).await;
+            }
+            if !self.used.insert(cluster) {
+                self.problem(Problem::CrossLinkedCluster {
+                    path: path.into(),
+                    cluster,
+                });
+                if !self.repairing() {
+                    return Ok(None);
+                }
+                if self.chain_contains(first, len, cluster).await? {
+                    // the chain loops back onto itself
+                    return self.cut_chain(first_cluster, prev, len).await;
+                }
+                return self
+                    .copy_shared_tail(first_cluster, prev, len, cluster, clusters)
+                    .await
+                    .map(Some);
+            }
+            len += 1;
+            if let Some(clusters) = clusters.as_mut() {
+                clusters.push(cluster);
+            }
+            prev = Some(cluster);
+            match read_fat(&mut fat, fs.fat_type(), cluster).await? {
+                FatValue::Data(next) => cluster = next,
+                FatValue::EndOfChain => return Ok(Some(len)),
+                FatValue::Free | FatValue::Bad => {
+                    // the chain is not properly terminated
+                    self.problem(Problem::InvalidClusterReference {
+                        path: path.into(),
+                        cluster,
+                    });
+                    if !self.repairing() {
+                        return Ok(None);
+                    }
+                    self.write_fat(cluster, FatValue::EndOfChain).await?;
+                    return Ok(Some(len));
+                }
+            }
+        }
+    }
+
+    /// Ends a broken chain after `last`, or drops the chain if it has no valid cluster. Does nothing unless
+    /// repairing.
+    async fn cut_chain(
+        &self,
+        first_cluster: &mut Option<u32>,
+        last: Option<u32>,
+        len: u32,
+    ) -> Result<Option<u32>, Error<IO::Error>> {
+        if !self.repairing() {
+            return Ok(None);
+        }
+        match last {
+            Some(cluster) => self.write_fat(cluster, FatValue::EndOfChain).await?,
+            None => *first_cluster = None,
+        }
+        Ok(Some(len))
+    }
+
+    /// Returns `true` if `cluster` is one of the first `len` clusters of the chain starting at `first_cluster`.
+    async fn chain_contains(
+        &self,
+        first_cluster: u32,
+        len: u32,
+        cluster: u32,
+    ) -> Result<bool, Error<IO::Error>> {
+        let mut iter = self.fs.cluster_iter(first_cluster);
+        let mut current = first_cluster;
+        for _ in 0..len {
+            if current == cluster {
+                return Ok(true);
+            }
+            match iter.next().await {
+                Some(next) => current = next?,
+                None => break,
+            }
+        }
+        Ok(false)
+    }
+
+    /// Replaces the part of a chain that is shared with another chain by a copy of it, starting with the
+    /// `shared` cluster that follows `last`.
+    ///
+    /// Returns the new chain length. The chain is cut if the volume runs out of space.
+    async fn copy_shared_tail(
+        &mut self,
+        first_cluster: &mut Option<u32>,
+        last: Option<u32>,
+        mut len: u32,
+        shared: u32,
+        mut clusters: Option<&mut Vec<u32>>,
+    ) -> Result<u32, Error<IO::Error>> {
+        let end_cluster = self.end_cluster();
+        let fs = self.fs;
+        let mut fat = fs.fat_slice();
+        let mut src = shared;
+        let mut prev = last;
+        loop {
+            let cluster = match fs.alloc_cluster(prev, false).await {
+                Ok(cluster) => cluster,
+                Err(Error::NotEnoughSpace) => {
+                    warn!("Not enough space to copy cross-linked clusters");
+                    return Ok(self
+                        .cut_chain(first_cluster, prev, len)
+                        .await?
+                        .unwrap_or(len));
+                }
+                Err(err) => return Err(err),
+            };
+            if prev.is_none() {
+                *first_cluster = Some(cluster);
+            }
+            self.used.insert(cluster);
+            if let Some(clusters) = clusters.as_mut() {
+                clusters.push(cluster);
+            }
+            self.copy_cluster(src, cluster).await?;
+            len += 1;
+            prev = Some(cluster);
+            // the other chain has already been checked so it is properly terminated
+            match read_fat(&mut fat, fs.fat_type(), src).await? {
+                FatValue::Data(next) if (RESERVED_FAT_ENTRIES..end_cluster).contains(&next) => {
+                    src = next;
+                }
+                _ => return Ok(len),
+            }
+        }
+    }
+
+    async fn copy_cluster(&self, src: u32, dst: u32) -> Result<(), Error<IO::Error>> {
+        let mut src = self.fs.cluster_slice(src);
+        let mut dst = self.fs.cluster_slice(dst);
+        let mut buf = [0_u8; CHUNK_LEN];
+        for _ in 0..self.fs.cluster_size() as usize / CHUNK_LEN {
+            src.read_exact(&mut buf).await?;
+            dst.write_all(&buf).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the clusters of a chain that is about to be freed from the used set.
+    async fn unmark_chain(&mut self, first_cluster: u32) -> Result<(), Error<IO::Error>> {
+        let fs = self.fs;
+        let mut iter = fs.cluster_iter(first_cluster);
+        self.used.remove(first_cluster);
+        while let Some(cluster) = iter.next().await {
+            self.used.remove(cluster?);
+        }
+        Ok(())
+    }
+
+    async fn check_dir(
+        &mut self,
+        dir: PendingDir,
+        pending: &mut Vec<PendingDir>,
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("check_dir {}", dir.path.as_str());
+        let mut scan = DirScan {
+            entries: 0,
+            lfn: None,
+        };
+        let mut finished = false;
+        if dir.cluster.is_none() {
+            let slice = self.fs.fixed_root_dir_slice();
+            let len = self.fs.bpb().root_dir_sectors();
+            let len = self.fs.bpb().bytes_from_sectors(len);
+            finished = self
+                .check_dir_region(&dir, &mut scan, slice, len, pending)
+                .await?;
+        }
+        for &cluster in &dir.clusters {
+            if finished {
+                break;
+            }
+            let slice = self.fs.cluster_slice(cluster);
+            let len = u64::from(self.fs.cluster_size());
+            finished = self
+                .check_dir_region(&dir, &mut scan, slice, len, pending)
+                .await?;
+        }
+        if let Some(lfn) = scan.lfn.take() {
+            self.orphaned_long_name(&dir, &lfn.positions).await?;
+        }
+        // an empty directory still has to contain the dot entries
+        if dir.parent.is_some() && scan.entries < 2 {
+            if scan.entries == 0 {
+                self.problem(Problem::BrokenDotEntry {
+                    path: dir.path.clone(),
+                });
+            }
+            self.problem(Problem::BrokenDotDotEntry { path: dir.path });
+        }
+        Ok(())
+    }
+
+    /// Checks entries stored in a single region of a directory. Returns `true` if the end of the directory has
+    /// been reached.
+    async fn check_dir_region(
+        &mut self,
+        dir: &PendingDir,
+        scan: &mut DirScan,
+        mut slice: DiskSlice<FsIoAdapter<'a, IO, TP, OCC>>,
+        len: u64,
+        pending: &mut Vec<PendingDir>,
+    ) -> Result<bool, Error<IO::Error>> {
+        for _ in 0..len / u64::from(DIR_ENTRY_SIZE) {
+            let pos = slice.abs_pos();
+            match DirEntryData::deserialize(&mut slice).await? {
+                DirEntryData::Lfn(data) => self.check_lfn_entry(dir, scan, pos, &data).await?,
+                DirEntryData::File(data) if data.is_end() => return Ok(true),
+                DirEntryData::File(data) => {
+                    self.check_file_entry(dir, scan, pos, data, pending).await?;
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Reports long name entries that do not belong to any short name entry and deletes them when repairing.
+    async fn orphaned_long_name(
+        &mut self,
+        dir: &PendingDir,
+        positions: &[u64],
+    ) -> Result<(), Error<IO::Error>> {
+        self.problem(Problem::OrphanedLongName {
+            path: dir.path.clone(),
+        });
+        self.delete_entries(positions).await
+    }
+
+    async fn check_lfn_entry(
+        &mut self,
+        dir: &PendingDir,
+        scan: &mut DirScan,
+        pos: u64,
+        data: &DirLfnEntryData,
+    ) -> Result<(), Error<IO::Error>> {
+        if data.is_deleted() {
+            if let Some(lfn) = scan.lfn.take() {
+                self.orphaned_long_name(dir, &lfn.positions).await?;
+            }
+            return Ok(());
+        }
+        let order = data.order() & !LFN_ENTRY_LAST_FLAG;
+        let valid_order = order > 0 && order <= MAX_LONG_DIR_ENTRIES;
+        if valid_order && data.order() & LFN_ENTRY_LAST_FLAG != 0 {
+            // the last part of the name is stored first
+            if let Some(lfn) = scan.lfn.take() {
+                self.orphaned_long_name(dir, &lfn.positions).await?;
+            }
+            scan.lfn = Some(PendingLfn {
+                checksum: data.checksum(),
+                next: order,
+                name: vec![0; usize::from(order) * LFN_PART_LEN],
+                positions: Vec::new(),
+            });
+        }
+        match scan.lfn.as_mut() {
+            Some(lfn) if valid_order && order == lfn.next && data.checksum() == lfn.checksum => {
+                let name_pos = usize::from(order - 1) * LFN_PART_LEN;
+                data.copy_name_to_slice(&mut lfn.name[name_pos..name_pos + LFN_PART_LEN]);
+                lfn.next -= 1;
+                lfn.positions.push(pos);
+            }
+            _ => {
+                let mut positions = scan.lfn.take().map(|lfn| lfn.positions).unwrap_or_default();
+                positions.push(pos);
+                self.orphaned_long_name(dir, &positions).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn check_file_entry(
+        &mut self,
+        dir: &PendingDir,
+        scan: &mut DirScan,
+        pos: u64,
+        mut data: DirFileEntryData,
+        pending: &mut Vec<PendingDir>,
+    ) -> Result<(), Error<IO::Error>> {
+        let lfn = scan.lfn.take();
+        if data.is_deleted() || data.is_volume() {
+            if let Some(lfn) = lfn {
+                self.orphaned_long_name(dir, &lfn.positions).await?;
+            }
+            return Ok(());
+        }
+        let fat_type = self.fs.fat_type();
+        let first_cluster = data.first_cluster(fat_type);
+        let index = scan.entries;
+        scan.entries += 1;
+
+        if *data.name() == DOT_NAME || *data.name() == DOTDOT_NAME {
+            let is_dot = *data.name() == DOT_NAME;
+            let expected = if is_dot { dir.cluster } else { dir.parent };
+            let expected = expected.unwrap_or(0);
+            // the root directory has no dot entries, `..` may point to the FAT32 root cluster instead of 0
+            let in_place = data.is_dir() && index == u32::from(!is_dot);
+            let valid = dir.parent.is_none()
+                || (in_place
+                    && (first_cluster.unwrap_or(0) == expected
+                        || (!is_dot && expected == 0 && first_cluster == self.root_dir_cluster())));
+            if !valid {
+                let path = dir.path.clone();
+                self.problem(if is_dot {
+                    Problem::BrokenDotEntry { path }
+                } else {
+                    Problem::BrokenDotDotEntry { path }
+                });
+                // only the cluster of an entry in the right place can be fixed
+                if self.repairing() && in_place {
+                    data.set_first_cluster(Some(expected), fat_type);
+                    self.write_entry(pos, &data).await?;
+                }
+            }
+            return Ok(());
+        }
+        if dir.parent.is_some() && index < 2 {
+            let path = dir.path.clone();
+            self.problem(if index == 0 {
+                Problem::BrokenDotEntry { path }
+            } else {
+                Problem::BrokenDotDotEntry { path }
+            });
+        }
+
+        let short_name = data
+            .lowercase_name()
+            .to_string(&self.fs.options.oem_cp_converter);
+        // positions of the entries making up this file, used to delete it when repairing
+        let mut positions = Vec::new();
+        let name = match lfn {
+            Some(lfn) if lfn.next == 0 && lfn.checksum == lfn_checksum(data.name()) => {
+                let name = lfn.decode();
+                positions = lfn.positions;
+                name
+            }
+            Some(lfn) if lfn.next == 0 => {
+                self.problem(Problem::LfnChecksumMismatch {
+                    path: join_path(&dir.path, &short_name),
+                });
+                self.delete_entries(&lfn.positions).await?;
+                short_name
+            }
+            Some(lfn) => {
+                self.orphaned_long_name(dir, &lfn.positions).await?;
+                short_name
+            }
+            None => short_name,
+        };
+        positions.push(pos);
+        let path = join_path(&dir.path, &name);
+
+        let mut cluster = first_cluster;
+        if data.is_dir() {
+            self.report.dirs += 1;
+            let mut clusters = Vec::new();
+            if cluster.is_some() {
+                self.walk_chain(&path, &mut cluster, Some(&mut clusters))
+                    .await?;
+            } else {
+                self.problem(Problem::InvalidClusterReference {
+                    path: path.clone(),
+                    cluster: 0,
+                });
+            }
+            let Some(cluster) = cluster else {
+                // nothing is left of the directory
+                return self.delete_entries(&positions).await;
+            };
+            if Some(cluster) != first_cluster {
+                data.set_first_cluster(Some(cluster), fat_type);
+                self.write_entry(pos, &data).await?;
+            }
+            // a directory starting in an already used cluster is not descended into to avoid cycles
+            if !clusters.is_empty() {
+                pending.push(PendingDir {
+                    path,
+                    cluster: Some(cluster),
+                    parent: Some(if dir.parent.is_some() {
+                        dir.cluster.unwrap_or(0)
+                    } else {
+                        0
+                    }),
+                    clusters,
+                });
+            }
+        } else {
+            self.report.files += 1;
+            let size = data.size().unwrap_or(0);
+            let problems = self.report.problems.len();
+            let clusters = self.walk_chain(&path, &mut cluster, None).await?;
+            let mut modified = cluster != first_cluster;
+            if modified {
+                data.set_first_cluster(cluster, fat_type);
+            }
+            if let Some(clusters) = clusters {
+                let expected = self.fs.clusters_from_bytes(u64::from(size));
+                if clusters != expected {
+                    // a repaired chain has already been reported
+                    if self.report.problems.len() == problems {
+                        self.problem(Problem::ChainLengthMismatch {
+                            path,
+                            size,
+                            clusters,
+                        });
+                    }
+                    if self.repairing() {
+                        self.fix_file_size(&mut data, clusters, expected).await?;
+                        modified = true;
+                    }
+                }
+            }
+            if modified {
+                self.write_entry(pos, &data).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the size of a file agree with its cluster chain. Clusters past the end of the file are freed, a file
+    /// larger than its chain is shortened.
+    async fn fix_file_size(
+        &mut self,
+        data: &mut DirFileEntryData,
+        clusters: u32,
+        expected: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        let fat_type = self.fs.fat_type();
+        let first_cluster = data.first_cluster(fat_type);
+        match first_cluster {
+            Some(first_cluster) if clusters > expected => {
+                if expected == 0 {
+                    self.unmark_chain(first_cluster).await?;
+                    self.fs.free_cluster_chain(first_cluster).await?;
+                    data.set_first_cluster(None, fat_type);
+                    return Ok(());
+                }
+                let fs = self.fs;
+                let mut iter = fs.cluster_iter(first_cluster);
+                let mut last = first_cluster;
+                for _ in 1..expected {
+                    if let Some(next) = iter.next().await {
+                        last = next?;
+                    }
+                }
+                if let Some(next) = iter.next().await {
+                    self.unmark_chain(next?).await?;
+                }
+                fs.truncate_cluster_chain(last).await?;
+            }
+            _ => {
+                let size = self.fs.bytes_from_clusters(clusters);
+                data.set_size(u32::try_from(size).unwrap_or(u32::MAX));
+            }
+        }
+        Ok(())
+    }
+
+    fn root_dir_cluster(&self) -> Option<u32> {
+        match self.fs.fat_type() {
+            FatType::Fat32 => Some(self.fs.bpb().root_dir_first_cluster),
+            _ => None,
+        }
+    }
+
+    /// Reports allocated clusters that do not belong to any chain reachable from the root directory.
+    async fn check_lost_clusters(&mut self) -> Result<(), Error<IO::Error>> {
+        let end_cluster = self.end_cluster();
+        let fat_type = self.fs.fat_type();
+        let mut lost = ClusterSet::new(end_cluster);
+        let mut referenced = ClusterSet::new(end_cluster);
+        {
+            let mut fat = self.fs.fat_slice();
+            for cluster in RESERVED_FAT_ENTRIES..end_cluster {
+                let value = read_fat(&mut fat, fat_type, cluster).await?;
+                if matches!(value, FatValue::Free | FatValue::Bad) || self.used.contains(cluster) {
+                    continue;
+                }
+                lost.insert(cluster);
+                if let FatValue::Data(next) = value {
+                    if next < end_cluster {
+                        referenced.insert(next);
+                    }
+                }
+            }
+        }
+        let mut found = None;
+        // Chain heads first, then whatever is left (chains forming a cycle have no head)
+        for heads_only in [true, false] {
+            for cluster in RESERVED_FAT_ENTRIES..end_cluster {
+                if lost.contains(cluster) && !(heads_only && referenced.contains(cluster)) {
+                    let mut clusters = Vec::new();
+                    let chain = self.repairing().then_some(&mut clusters);
+                    let len = self.collect_lost_chain(&mut lost, cluster, chain).await?;
+                    self.problem(Problem::LostChain {
+                        first_cluster: cluster,
+                        clusters: len,
+                    });
+                    match self.repair.map(|options| options.lost_chains) {
+                        Some(LostChainAction::Free) => {
+                            self.fs.free_clusters(&clusters).await?;
+                        }
+                        Some(LostChainAction::Reclaim) => {
+                            self.reclaim_lost_chain(&mut found, &clusters).await?;
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn collect_lost_chain(
+        &self,
+        lost: &mut ClusterSet,
+        first_cluster: u32,
+        mut clusters: Option<&mut Vec<u32>>,
+    ) -> Result<u32, Error<IO::Error>> {
+        let mut iter = self.fs.cluster_iter(first_cluster);
+        let mut cluster = first_cluster;
+        let mut len = 0;
+        while lost.remove(cluster) {
+            len += 1;
+            if let Some(clusters) = clusters.as_mut() {
+                clusters.push(cluster);
+            }
+            match iter.next().await {
+                Some(next) => cluster = next?,
+                None => break,
+            }
+        }
+        Ok(len)
+    }
+
+    /// Turns a lost chain into a file of the `FOUND.nnn` directory, which is created on first use.
+    async fn reclaim_lost_chain(
+        &self,
+        found: &mut Option<Dir<'a, IO, TP, OCC>>,
+        clusters: &[u32],
+    ) -> Result<(), Error<IO::Error>> {
+        let (Some(&first_cluster), Some(&last_cluster)) = (clusters.first(), clusters.last())
+        else {
+            return Ok(());
+        };
+        // the chain may continue into another chain or loop back onto itself
+        self.write_fat(last_cluster, FatValue::EndOfChain).await?;
+        let dir = match found {
+            Some(dir) => dir,
+            None => found.insert(self.create_found_dir().await?),
+        };
+        let mut index = 0;
+        let name = loop {
+            let name = format!("FILE{:04}.CHK", index);
+            if !dir.exists(&name).await? {
+                break name;
+            }
+            index += 1;
+        };
+        let mut file = dir.create_file(&name).await?;
+        let size = self.fs.bytes_from_clusters(clusters.len() as u32);
+        file.attach_cluster_chain(first_cluster, size.min(u64::from(u32::MAX)));
+        file.flush().await
+    }
+
+    async fn create_found_dir(&self) -> Result<Dir<'a, IO, TP, OCC>, Error<IO::Error>> {
+        let root = self.fs.root_dir();
+        for index in 0..1000 {
+            let name = format!("FOUND.{:03}", index);
+            if !root.exists(&name).await? {
+                return root.create_dir(&name).await;
+            }
+        }
+        Err(Error::AlreadyExists)
+    }
+
+    /// Compares every FAT copy with the first one. Copies are made identical to the first one when repairing.
+    async fn check_fat_copies(&mut self) -> Result<(), Error<IO::Error>> {
+        let bpb = self.fs.bpb();
+        if !bpb.mirroring_enabled() {
+            // only the active FAT is maintained
+            return Ok(());
+        }
+        let fat_type = self.fs.fat_type();
+        let fat_len = bpb.bytes_from_sectors(bpb.sectors_per_fat());
+        let bits_per_entry = u64::from(fat_type.bits_per_fat_entry());
+        let end_cluster = self.end_cluster();
+        let mut primary = self.fs.fat_copy_slice(0);
+        let mut primary_buf = [0_u8; CHUNK_LEN];
+        let mut mirror_buf = [0_u8; CHUNK_LEN];
+        for fat in 1..bpb.fats {
+            let mut mirror = self.fs.fat_copy_slice(fat);
+            // FAT12 entries can span two chunks, do not report them twice
+            let mut next_cluster = RESERVED_FAT_ENTRIES;
+            let mut offset = 0;
+            while offset < fat_len {
+                let len = (fat_len - offset).min(CHUNK_LEN as u64);
+                let chunk = len as usize;
+                primary.seek(SeekFrom::Start(offset)).await?;
+                primary.read_exact(&mut primary_buf[..chunk]).await?;
+                mirror.seek(SeekFrom::Start(offset)).await?;
+                mirror.read_exact(&mut mirror_buf[..chunk]).await?;
+                // Copies of FAT changes deferred by lazy mirroring are made on the next flush
+                if primary_buf[..chunk] != mirror_buf[..chunk]
+                    && !self.fs.fat_mirror_pending(offset, len).await
+                {
+                    let first = (offset * 8 / bits_per_entry) as u32;
+                    let last = ((offset + len) * 8).div_ceil(bits_per_entry) as u32;
+                    for cluster in first.max(next_cluster)..last.min(end_cluster) {
+                        let expected = read_fat(&mut primary, fat_type, cluster).await?;
+                        if read_fat(&mut mirror, fat_type, cluster).await? != expected {
+                            self.problem(Problem::FatMismatch { fat, cluster });
+                        }
+                        next_cluster = cluster + 1;
+                    }
+                    if self.repairing() {
+                        mirror.seek(SeekFrom::Start(offset)).await?;
+                        mirror.write_all(&primary_buf[..chunk]).await?;
+                    }
+                }
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(mut self) -> Result<CheckReport, Error<IO::Error>> {
+        if self.repairing() {
+            // FAT copies are compared on the storage, later fixes go to every copy through the FAT cache
+            self.fs.flush().await?;
+        }
+        self.check_fat_copies().await?;
+        let mut root = PendingDir {
+            path: "/".into(),
+            cluster: self.root_dir_cluster(),
+            parent: None,
+            clusters: Vec::new(),
+        };
+        if let Some(cluster) = root.cluster {
+            let mut first_cluster = Some(cluster);
+            self.walk_chain(&root.path, &mut first_cluster, Some(&mut root.clusters))
+                .await?;
+            if root.clusters.first() != Some(&cluster) {
+                error!("Root directory cluster {} is invalid", cluster);
+                return Err(Error::CorruptedFileSystem);
+            }
+        }
+        let mut pending = vec![root];
+        while let Some(dir) = pending.pop() {
+            self.check_dir(dir, &mut pending).await?;
+        }
+        self.report.used_clusters = self.used.bits.iter().map(|word| word.count_ones()).sum();
+        self.check_lost_clusters().await?;
+        if self.repairing() {
+            self.fs.rebuild_free_cluster_info().await?;
+            self.fs.clear_mount_dirty_flag().await?;
+            self.fs.flush().await?;
+        }
+        Ok(self.report)
+    }
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
+    /// Checks the consistency of the file system.
+    ///
+    /// Every directory reachable from the root directory is scanned and every cluster chain is followed. Problems
+    /// are collected in the returned [`CheckReport`] instead of failing the check. Nothing is written to the
+    /// storage.
+    ///
+    /// Detected problems: lost cluster chains, cross-linked clusters, chains not matching the file size, invalid
+    /// cluster references, long name checksum mismatches and orphaned long name entries, broken `.`/`..` entries
+    /// and FAT copies that disagree with the first FAT.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the root directory cannot be read.
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn check(&self) -> Result<CheckReport, Error<IO::Error>> {
+        trace!("FileSystem::check");
+        self.checker(None)?.run().await
+    }
+
+    /// Checks the consistency of the file system and fixes the problems found.
+    ///
+    /// The returned report lists the problems found, as [`FileSystem::check`] would. They are fixed as follows:
+    ///
+    /// * lost chains are freed or saved as `FILEnnnn.CHK` files in a new `FOUND.nnn` directory, see
+    ///   [`RepairOptions::lost_chains`],
+    /// * a cross-linked chain gets a copy of the clusters it shares, a chain looping back onto itself is cut,
+    /// * a chain referencing an invalid cluster is cut before it, a directory left without clusters is removed,
+    /// * clusters past the end of a file are freed and a file larger than its chain is shortened,
+    /// * long name entries with a wrong checksum or without their short name entry are deleted,
+    /// * `.` and `..` entries pointing to the wrong cluster are updated (missing ones are not recreated),
+    /// * FAT copies are overwritten with the first FAT.
+    ///
+    /// Afterwards the free cluster count is recomputed, the dirty flag found on mount is cleared and everything is
+    /// flushed. This allows a device to heal itself after an unclean shutdown:
+    ///
+    /// ```ignore
+    /// let fs = FileSystem::new(disk, FsOptions::new()).await?;
+    /// if fs.read_status_flags().await?.dirty() {
+    ///     fs.repair(RepairOptions::new()).await?;
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the root directory cannot be read.
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::NotEnoughSpace` will be returned if there is no space left to reclaim lost chains.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn repair(&self, options: RepairOptions) -> Result<CheckReport, Error<IO::Error>> {
+        trace!("FileSystem::repair");
+        self.check_writable()?;
+        self.checker(Some(options))?.run().await
+    }
+
+    fn checker(
+        &self,
+        repair: Option<RepairOptions>,
+    ) -> Result<Checker<'_, IO, TP, OCC>, Error<IO::Error>> {
+        if self.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        Ok(Checker {
+            fs: self,
+            used: ClusterSet::new(self.total_clusters() + RESERVED_FAT_ENTRIES),
+            report: CheckReport::default(),
+            repair,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FsOptions, LossyOemCpConverter};
+    use crate::table::{Fat16, FatTrait, count_free_clusters};
+    use crate::test_support::{MB, image};
+    use crate::time::DefaultTimeProvider;
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    type TestFs<'a> =
+        FileSystem<FromTokio<Cursor<&'a mut Vec<u8>>>, DefaultTimeProvider, LossyOemCpConverter>;
+
+    fn problems(report: &CheckReport) -> Vec<Problem> {
+        let mut problems = report.problems().to_vec();
+        problems.sort_by_key(Problem::to_string);
+        problems
+    }
+
+    #[tokio::test]
+    async fn test_clean_volumes() {
+        for (size, fat_type) in [
+            (MB, FatType::Fat12),
+            (8 * MB, FatType::Fat16),
+            (40 * MB, FatType::Fat32),
+        ] {
+            let mut image = image(size, fat_type).await;
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            let root = fs.root_dir();
+            let dir = root.create_dir("A directory").await.unwrap();
+            let nested = dir.create_dir("nested").await.unwrap();
+            let mut file = nested.create_file("a long file name.txt").await.unwrap();
+            file.write_all(&[0xAB; 1500]).await.unwrap();
+            file.flush().await.unwrap();
+            root.create_file("EMPTY.TXT").await.unwrap();
+
+            let report = fs.check().await.unwrap();
+            assert_eq!(report.problems(), &[], "{:?}", fat_type);
+            assert_eq!(report.files(), 2);
+            assert_eq!(report.dirs(), 2);
+            // two directory clusters and three file clusters (plus the FAT32 root directory)
+            let root_clusters = u32::from(fat_type == FatType::Fat32);
+            assert_eq!(report.used_clusters(), 5 + root_clusters);
+        }
+    }
+
+    /// Creates four files and damages their chains. Returns the expected problems.
+    async fn damage_chains(fs: &TestFs<'_>) -> Vec<Problem> {
+        let root = fs.root_dir();
+        for (i, (name, len)) in [
+            ("A.BIN", 1536),
+            ("B.BIN", 1024),
+            ("C.BIN", 1024),
+            ("D.BIN", 512),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut file = root.create_file(name).await.unwrap();
+            file.write_all(&vec![i as u8; len]).await.unwrap();
+            file.flush().await.unwrap();
+        }
+        let mut chains = Vec::new();
+        let mut iter = root.iter();
+        while let Some(entry) = iter.next().await {
+            let first = entry.unwrap().first_cluster().unwrap();
+            let mut chain = vec![first];
+            let mut clusters = fs.cluster_iter(first);
+            while let Some(cluster) = clusters.next().await {
+                chain.push(cluster.unwrap());
+            }
+            chains.push(chain);
+        }
+        let (a, b, c, d) = (&chains[0], &chains[1], &chains[2], &chains[3]);
+        let lost = d[0] + 10;
+        {
+            let mut fat = fs.fat_slice();
+            let ft = fs.fat_type();
+            let result: Result<(), Error<std::io::Error>> = async {
+                // B continues in the middle of A, its second cluster is lost
+                write_fat(&mut fat, ft, b[0], FatValue::Data(a[1])).await?;
+                // C is one cluster short, its second cluster is lost
+                write_fat(&mut fat, ft, c[0], FatValue::EndOfChain).await?;
+                // D points to a free cluster
+                write_fat(&mut fat, ft, d[0], FatValue::Free).await?;
+                // allocated but not referenced
+                write_fat(&mut fat, ft, lost, FatValue::EndOfChain).await
+            }
+            .await;
+            result.unwrap();
+        }
+        let mut expected = vec![
+            Problem::CrossLinkedCluster {
+                path: "/B.BIN".into(),
+                cluster: a[1],
+            },
+            Problem::ChainLengthMismatch {
+                path: "/C.BIN".into(),
+                size: 1024,
+                clusters: 1,
+            },
+            Problem::InvalidClusterReference {
+                path: "/D.BIN".into(),
+                cluster: d[0],
+            },
+            Problem::LostChain {
+                first_cluster: b[1],
+                clusters: 1,
+            },
+            Problem::LostChain {
+                first_cluster: c[1],
+                clusters: 1,
+            },
+            Problem::LostChain {
+                first_cluster: lost,
+                clusters: 1,
+            },
+        ];
+        expected.sort_by_key(Problem::to_string);
+        expected
+    }
+
+    async fn read_file(fs: &TestFs<'_>, path: &str) -> Vec<u8> {
+        let mut file = fs.root_dir().open_file(path).await.unwrap();
+        let mut data = Vec::new();
+        let mut buf = [0; 512];
+        loop {
+            let n = file.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break data;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_problems() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let expected = damage_chains(&fs).await;
+        let report = fs.check().await.unwrap();
+        assert_eq!(problems(&report), expected);
+    }
+
+    #[tokio::test]
+    async fn test_repair_chain_problems() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let expected = damage_chains(&fs).await;
+        let report = fs.repair(RepairOptions::new()).await.unwrap();
+        assert_eq!(problems(&report), expected);
+        let report = fs.check().await.unwrap();
+        assert_eq!(report.problems(), &[]);
+        assert_eq!(report.files(), 7);
+
+        assert_eq!(read_file(&fs, "A.BIN").await, vec![0; 1536]);
+        // B got its own copy of the clusters it shared with A
+        let mut b = vec![1; 512];
+        b.extend_from_slice(&[0; 512]);
+        assert_eq!(read_file(&fs, "B.BIN").await, b);
+        assert_eq!(read_file(&fs, "C.BIN").await, vec![2; 512]);
+        assert_eq!(read_file(&fs, "D.BIN").await, vec![3; 512]);
+        assert_eq!(read_file(&fs, "FOUND.000/FILE0000.CHK").await, vec![1; 512]);
+        assert_eq!(read_file(&fs, "FOUND.000/FILE0001.CHK").await, vec![2; 512]);
+        assert_eq!(read_file(&fs, "FOUND.000/FILE0002.CHK").await.len(), 512);
+
+        // the free cluster count matches the FAT again
+        let free = fs.stats().await.unwrap().free_clusters();
+        let mut fat = fs.fat_slice();
+        let result: Result<u32, Error<std::io::Error>> =
+            count_free_clusters(&mut fat, fs.fat_type(), fs.total_clusters()).await;
+        assert_eq!(free, result.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_repair_free_lost_chains() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let free = fs.stats().await.unwrap().free_clusters();
+        {
+            let mut fat = fs.fat_slice();
+            let ft = fs.fat_type();
+            let result: Result<(), Error<std::io::Error>> = async {
+                // a lost chain of three clusters and a lost cycle of two
+                write_fat(&mut fat, ft, 100, FatValue::Data(101)).await?;
+                write_fat(&mut fat, ft, 101, FatValue::Data(102)).await?;
+                write_fat(&mut fat, ft, 102, FatValue::EndOfChain).await?;
+                write_fat(&mut fat, ft, 200, FatValue::Data(201)).await?;
+                write_fat(&mut fat, ft, 201, FatValue::Data(200)).await
+            }
+            .await;
+            result.unwrap();
+        }
+        let options = RepairOptions::new().lost_chains(LostChainAction::Free);
+        let report = fs.repair(options).await.unwrap();
+        assert_eq!(
+            report.problems(),
+            &[
+                Problem::LostChain {
+                    first_cluster: 100,
+                    clusters: 3,
+                },
+                Problem::LostChain {
+                    first_cluster: 200,
+                    clusters: 2,
+                },
+            ]
+        );
+        assert!(fs.check().await.unwrap().is_clean());
+        assert_eq!(fs.stats().await.unwrap().free_clusters(), free);
+        assert!(!fs.root_dir().exists("FOUND.000").await.unwrap());
+    }
+
+    #[cfg(feature = "transaction-safe")]
+    #[tokio::test]
+    async fn test_rolled_back_repair_keeps_lost_chain() {
+        use crate::fs::{FormatVolumeOptions, format_volume};
+
+        let mut image = vec![0_u8; 8 * MB];
+        let options = FormatVolumeOptions::new()
+            .fat_type(FatType::Fat16)
+            .bytes_per_cluster(512)
+            .with_transaction_log();
+        format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+            .await
+            .unwrap();
+        let lost_data = vec![0xAB_u8; 3 * 512];
+        let offset = {
+            let fs = FileSystem::new(
+                FromTokio::new(Cursor::new(&mut image)),
+                FsOptions::new().with_transaction_log(),
+            )
+            .await
+            .unwrap();
+            // a lost chain in the first free clusters, reused by the file written below
+            {
+                let mut fat = fs.fat_slice();
+                let ft = fs.fat_type();
+                let result: Result<(), Error<std::io::Error>> = async {
+                    write_fat(&mut fat, ft, 2, FatValue::Data(3)).await?;
+                    write_fat(&mut fat, ft, 3, FatValue::Data(4)).await?;
+                    write_fat(&mut fat, ft, 4, FatValue::EndOfChain).await
+                }
+                .await;
+                result.unwrap();
+            }
+            for cluster in 2..5 {
+                let mut data = fs.cluster_slice(cluster);
+                data.write_all(&lost_data[..512]).await.unwrap();
+            }
+            let offset = usize::try_from(fs.offset_from_cluster(2)).unwrap();
+
+            let result = fs
+                .atomic(|| async {
+                    let options = RepairOptions::new().lost_chains(LostChainAction::Free);
+                    fs.repair(options).await?;
+                    let mut file = fs.root_dir().create_file("new.bin").await?;
+                    file.write_all(&[0xCD; 3 * 512]).await?;
+                    file.flush().await?;
+                    Err(Error::InvalidInput)
+                })
+                .await;
+            assert!(matches!(result, Err(Error::InvalidInput)));
+            assert!(!fs.root_dir().exists("new.bin").await.unwrap());
+            fs.unmount().await.unwrap();
+            offset
+        };
+        assert_eq!(&image[offset..offset + lost_data.len()], &lost_data[..]);
+    }
+
+    /// Creates a directory with a long named file and damages its entries and the second FAT.
+    async fn damaged_dir_image() -> Vec<u8> {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let (lfn_pos, dot_pos) = {
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            let root = fs.root_dir();
+            let dir = root.create_dir("sub").await.unwrap();
+            dir.create_file("long file name.txt").await.unwrap();
+            let sub = root.iter().next().await.unwrap().unwrap();
+            let mut iter = dir.iter();
+            let mut file = None;
+            while let Some(entry) = iter.next().await {
+                let entry = entry.unwrap();
+                if entry.is_file() {
+                    file = Some(entry);
+                }
+            }
+            let file = file.unwrap();
+            let dot_pos = fs.offset_from_cluster(sub.first_cluster().unwrap());
+            fs.flush().await.unwrap();
+            // bypass the FAT cache, a flush writes cached sectors to every copy
+            let mut fat = fs.fat_copy_slice(1);
+            let result: Result<(), Error<std::io::Error>> =
+                write_fat(&mut fat, fs.fat_type(), 100, FatValue::EndOfChain).await;
+            result.unwrap();
+            (file.entry_pos, dot_pos)
+        };
+        // corrupt the checksum of both LFN entries and make `.` point to the parent directory
+        for n in 1..=2 {
+            image[(lfn_pos - n * u64::from(DIR_ENTRY_SIZE)) as usize + 13] ^= 0xFF;
+        }
+        // `.` is preceded by a long name entry
+        let dot_entry = dot_pos as usize + DIR_ENTRY_SIZE as usize;
+        image[dot_entry + 26..][..2].copy_from_slice(&[0, 0]);
+        image
+    }
+
+    fn directory_problems() -> Vec<Problem> {
+        let mut expected = vec![
+            Problem::FatMismatch {
+                fat: 1,
+                cluster: 100,
+            },
+            Problem::BrokenDotEntry {
+                path: "/sub".into(),
+            },
+            Problem::LfnChecksumMismatch {
+                path: "/sub/LONGFI~1.TXT".into(),
+            },
+        ];
+        expected.sort_by_key(Problem::to_string);
+        expected
+    }
+
+    #[tokio::test]
+    async fn test_directory_problems() {
+        let mut image = damaged_dir_image().await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let report = fs.check().await.unwrap();
+        assert_eq!(problems(&report), directory_problems());
+    }
+
+    #[tokio::test]
+    async fn test_repair_after_unclean_shutdown() {
+        let mut image = damaged_dir_image().await;
+        // dirty flag in the boot sector and in the second FAT entry
+        image[0x25] |= 1;
+        let fat_len = {
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            let mut fat = fs.fat_copy_slice(0);
+            let result: Result<(), Error<std::io::Error>> =
+                Fat16::set_raw(&mut fat, 1, 0x7FFF).await;
+            result.unwrap();
+            assert!(fs.read_status_flags().await.unwrap().dirty());
+
+            let report = fs.repair(RepairOptions::new()).await.unwrap();
+            assert_eq!(problems(&report), directory_problems());
+            assert!(fs.check().await.unwrap().is_clean());
+            assert!(!fs.read_status_flags().await.unwrap().dirty());
+            assert!(fs.root_dir().file_exists("sub/LONGFI~1.TXT").await.unwrap());
+            let bpb = fs.bpb();
+            bpb.bytes_from_sectors(bpb.sectors_per_fat()) as usize
+        };
+        let fat_start = 512 * usize::from(u16::from_le_bytes([image[14], image[15]]));
+        assert_eq!(
+            image[fat_start..][..fat_len],
+            image[fat_start + fat_len..][..fat_len]
+        );
+
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert!(!fs.read_status_flags().await.unwrap().dirty());
+    }
+}
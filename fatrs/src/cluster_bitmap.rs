@@ -394,6 +394,8 @@ impl ClusterBitmap {
                 crate::FatType::Fat12 => Fat12::get(fat, cluster).await?,
                 crate::FatType::Fat16 => Fat16::get(fat, cluster).await?,
                 crate::FatType::Fat32 => Fat32::get(fat, cluster).await?,
+                #[cfg(feature = "exfat")]
+                crate::FatType::ExFat => crate::table::ExFat::get(fat, cluster).await?,
             };
 
             match value {
@@ -0,0 +1,344 @@
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use core::sync::atomic::Ordering;
+
+use crate::error::Error;
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::io::{Read, Write};
+use crate::time::TimeProvider;
+
+/// Progress of a defragmentation, passed to the progress callback.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DefragProgress {
+    files_done: u32,
+    files_total: u32,
+    clusters_moved: u32,
+}
+
+impl DefragProgress {
+    pub(crate) fn new(files_done: u32, files_total: u32, clusters_moved: u32) -> Self {
+        Self {
+            files_done,
+            files_total,
+            clusters_moved,
+        }
+    }
+
+    /// Number of files processed so far
+    #[must_use]
+    pub fn files_done(&self) -> u32 {
+        self.files_done
+    }
+
+    /// Number of files to process
+    #[must_use]
+    pub fn files_total(&self) -> u32 {
+        self.files_total
+    }
+
+    /// Number of clusters copied so far
+    #[must_use]
+    pub fn clusters_moved(&self) -> u32 {
+        self.clusters_moved
+    }
+}
+
+/// Result of [`FileSystem::defragment`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DefragReport {
+    files: u32,
+    files_moved: u32,
+    files_skipped: u32,
+    clusters_moved: u32,
+}
+
+impl DefragReport {
+    /// Number of files processed
+    #[must_use]
+    pub fn files(&self) -> u32 {
+        self.files
+    }
+
+    /// Number of files moved to a new location
+    #[must_use]
+    pub fn files_moved(&self) -> u32 {
+        self.files_moved
+    }
+
+    /// Number of fragmented files left in place because no free run of clusters was large enough
+    #[must_use]
+    pub fn files_skipped(&self) -> u32 {
+        self.files_skipped
+    }
+
+    /// Number of clusters moved
+    #[must_use]
+    pub fn clusters_moved(&self) -> u32 {
+        self.clusters_moved
+    }
+}
+
+/// Layout of a cluster chain.
+pub(crate) struct Chain {
+    pub(crate) first_cluster: u32,
+    pub(crate) len: u32,
+    pub(crate) contiguous: bool,
+}
+
+pub(crate) async fn read_chain<IO: ReadWriteSeek, TP, OCC>(
+    fs: &FileSystem<IO, TP, OCC>,
+    first_cluster: u32,
+) -> Result<Chain, Error<IO::Error>> {
+    let mut iter = fs.cluster_iter(first_cluster);
+    let mut chain = Chain {
+        first_cluster,
+        len: 1,
+        contiguous: true,
+    };
+    let mut prev = first_cluster;
+    while let Some(cluster) = iter.next().await {
+        let cluster = cluster?;
+        chain.contiguous &= cluster == prev + 1;
+        chain.len += 1;
+        prev = cluster;
+    }
+    Ok(chain)
+}
+
+/// Copies a chain into a free contiguous run of clusters starting below `limit`. The old chain is left untouched.
+///
+/// `progress` is called with the number of clusters copied so far. Returns the first cluster of the copy, or `None`
+/// if there is no suitable run.
+pub(crate) async fn copy_chain<IO: ReadWriteSeek, TP, OCC>(
+    fs: &FileSystem<IO, TP, OCC>,
+    chain: &Chain,
+    limit: u32,
+    progress: &mut impl FnMut(u32),
+) -> Result<Option<u32>, Error<IO::Error>> {
+    let Some(new_first_cluster) = fs.find_free_run(chain.len, limit).await? else {
+        return Ok(None);
+    };
+    trace!(
+        "moving {} clusters from {} to {}",
+        chain.len, chain.first_cluster, new_first_cluster
+    );
+    fs.alloc_contiguous_chain(new_first_cluster, chain.len)
+        .await?;
+    let mut buf = vec![0_u8; fs.cluster_size() as usize];
+    let mut iter = fs.cluster_iter(chain.first_cluster);
+    let mut src = Some(chain.first_cluster);
+    for (index, dst) in (new_first_cluster..new_first_cluster + chain.len).enumerate() {
+        let Some(cluster) = src else {
+            break;
+        };
+        fs.cluster_slice(cluster).read_exact(&mut buf).await?;
+        fs.cluster_slice(dst).write_all(&buf).await?;
+        progress(index as u32 + 1);
+        src = iter.next().await.transpose()?;
+    }
+    Ok(Some(new_first_cluster))
+}
+
+impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
+    /// Defragments every file of the volume.
+    ///
+    /// Files are processed in directory order. Every file is moved into the first free run of clusters large
+    /// enough to hold it, which makes fragmented files contiguous and compacts files towards the start of the
+    /// volume as space frees up below them. See [`File::defragment`] for how each file is moved.
+    ///
+    /// Directories are not moved, a fragmented directory stays fragmented and the free runs between directory
+    /// clusters are only used for files that fit into them. No `File` of the volume may be open, because open files
+    /// keep referring to the clusters that are freed and reused here. `Dir` objects may stay open.
+    ///
+    /// `progress` is called after every copied cluster and after every processed file.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::FilesOpen` will be returned if a `File` of the volume is open.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// [`File::defragment`]: crate::File::defragment
+    pub async fn defragment(
+        &self,
+        mut progress: impl FnMut(&DefragProgress),
+    ) -> Result<DefragReport, Error<IO::Error>> {
+        trace!("FileSystem::defragment");
+        if self.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        self.check_writable()?;
+        if self.open_files.load(Ordering::Acquire) != 0 {
+            return Err(Error::FilesOpen);
+        }
+        // collect the files first so that the total is known
+        let mut files = Vec::new();
+        let mut dirs = vec![self.root_dir()];
+        while let Some(dir) = dirs.pop() {
+            let mut iter = dir.iter();
+            while let Some(entry) = iter.next().await {
+                let entry = entry?;
+                let name = entry.short_file_name_as_bytes();
+                if !entry.is_dir() {
+                    files.push(entry);
+                } else if name != b"." && name != b".." {
+                    dirs.push(entry.to_dir());
+                }
+            }
+        }
+
+        let mut state = DefragProgress::new(0, files.len() as u32, 0);
+        let mut report = DefragReport {
+            files: state.files_total,
+            ..DefragReport::default()
+        };
+        for entry in &files {
+            let mut file = entry.to_file();
+            let moved = file
+                .relocate(true, &mut |clusters| {
+                    progress(&DefragProgress {
+                        clusters_moved: state.clusters_moved + clusters,
+                        ..state
+                    });
+                })
+                .await?;
+            match moved {
+                Some(0) => {}
+                Some(clusters) => {
+                    report.files_moved += 1;
+                    report.clusters_moved += clusters;
+                    state.clusters_moved += clusters;
+                }
+                None => report.files_skipped += 1,
+            }
+            state.files_done += 1;
+            progress(&state);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FsOptions};
+    use crate::io::Seek;
+    use crate::io::SeekFrom;
+    use crate::test_support::{MB, image, pattern};
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_defragment_file() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        // interleave the clusters of two files
+        let mut a = root.create_file("a.log").await.unwrap();
+        let mut b = root.create_file("b.log").await.unwrap();
+        for i in 0..8 {
+            a.write_all(&pattern(i, 512)).await.unwrap();
+            b.write_all(&pattern(100 + i, 512)).await.unwrap();
+        }
+        a.flush().await.unwrap();
+        b.flush().await.unwrap();
+        let first_cluster = a.first_cluster().unwrap();
+        assert!(!read_chain(&fs, first_cluster).await.unwrap().contiguous);
+
+        let mut updates = Vec::new();
+        let moved = a
+            .defragment(|progress| updates.push(progress.clusters_moved()))
+            .await
+            .unwrap();
+        assert!(moved);
+        assert_eq!(updates, (1..=8).chain([8]).collect::<Vec<_>>());
+        let chain = read_chain(&fs, a.first_cluster().unwrap()).await.unwrap();
+        assert!(chain.contiguous);
+        assert_eq!(chain.len, 8);
+
+        // the file position survives the move
+        a.write_all(&pattern(8, 512)).await.unwrap();
+        a.seek(SeekFrom::Start(0)).await.unwrap();
+        let mut data = vec![0; 9 * 512];
+        a.read_exact(&mut data).await.unwrap();
+        let expected: Vec<u8> = (0..9).flat_map(|i| pattern(i, 512)).collect();
+        assert_eq!(data, expected);
+        a.flush().await.unwrap();
+
+        // already contiguous
+        assert!(!a.defragment(|_| {}).await.unwrap());
+        assert!(fs.check().await.unwrap().is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_defragment_volume() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let dir = root.create_dir("logs").await.unwrap();
+        let mut files = Vec::new();
+        for name in ["1.log", "2.log", "3.log"] {
+            files.push(dir.create_file(name).await.unwrap());
+        }
+        for i in 0..6 {
+            for (n, file) in files.iter_mut().enumerate() {
+                file.write_all(&pattern(i * 3 + n as u8, 512))
+                    .await
+                    .unwrap();
+            }
+        }
+        for file in &mut files {
+            file.flush().await.unwrap();
+        }
+        drop(files);
+        let free = fs.stats().await.unwrap().free_clusters();
+
+        let mut last = DefragProgress::default();
+        let report = fs.defragment(|progress| last = *progress).await.unwrap();
+        assert_eq!(report.files(), 3);
+        assert_eq!(report.files_moved(), 3);
+        assert_eq!(report.files_skipped(), 0);
+        assert_eq!(report.clusters_moved(), 18);
+        assert_eq!(last.files_done(), 3);
+        assert_eq!(last.clusters_moved(), 18);
+
+        assert!(fs.check().await.unwrap().is_clean());
+        assert_eq!(fs.stats().await.unwrap().free_clusters(), free);
+        for (n, name) in ["1.log", "2.log", "3.log"].into_iter().enumerate() {
+            let mut file = dir.open_file(name).await.unwrap();
+            let chain = read_chain(&fs, file.first_cluster().unwrap())
+                .await
+                .unwrap();
+            assert!(chain.contiguous);
+            let mut data = vec![0; 6 * 512];
+            file.read_exact(&mut data).await.unwrap();
+            let expected: Vec<u8> = (0..6).flat_map(|i| pattern(i * 3 + n as u8, 512)).collect();
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_defragment_volume_with_open_file() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut file = root.create_file("open.log").await.unwrap();
+        file.write_all(&pattern(1, 512)).await.unwrap();
+        file.flush().await.unwrap();
+        let clone = file.clone();
+        drop(file);
+        assert!(matches!(fs.defragment(|_| {}).await, Err(Error::FilesOpen)));
+
+        drop(clone);
+        let _dir = root.create_dir("logs").await.unwrap();
+        assert_eq!(fs.defragment(|_| {}).await.unwrap().files(), 1);
+    }
+}
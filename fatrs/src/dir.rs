@@ -1,5 +1,5 @@
-#[cfg(all(not(feature = "std"), feature = "alloc", feature = "lfn"))]
-use alloc::vec::Vec;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::{boxed::Box, vec::Vec};
 
 use core::char;
 use core::cmp;
@@ -16,10 +16,17 @@ use crate::dir_entry::{
 use crate::dir_entry::{LFN_ENTRY_LAST_FLAG, LFN_PART_LEN};
 use crate::dir_entry::{SFN_PADDING, SFN_SIZE};
 use crate::error::{Error, IoError};
+#[cfg(feature = "exfat")]
+use crate::error::ReadExactError;
+#[cfg(feature = "exfat")]
+use crate::exfat::{
+    DIR_ENTRY_LEN, ENTRY_TYPE_END, ENTRY_TYPE_FILE, ENTRY_TYPE_IN_USE, ENTRY_TYPE_LABEL, EntrySet,
+    ExFatVolume, label_entry, label_from_entry,
+};
 use crate::file::File;
 use crate::fs::{DiskSlice, FileSystem, FsIoAdapter, OemCpConverter, ReadWriteSeek};
 use crate::io::{self, IoBase, Read, Seek, SeekFrom, Write};
-use crate::time::TimeProvider;
+use crate::time::{Date, DateTime, TimeProvider};
 
 const LFN_PADDING: u16 = 0xFFFF;
 
@@ -27,15 +34,26 @@ pub(crate) enum DirRawStream<'a, IO: ReadWriteSeek, TP, OCC>
 where
     IO::Error: 'static,
 {
+    // Boxed with `alloc`, buffers and checkpoints of the optional features make a file much larger than the
+    // root directory slice
+    #[cfg(feature = "alloc")]
+    File(Box<File<'a, IO, TP, OCC>>),
+    #[cfg(not(feature = "alloc"))]
     File(File<'a, IO, TP, OCC>),
     Root(DiskSlice<FsIoAdapter<'a, IO, TP, OCC>, FsIoAdapter<'a, IO, TP, OCC>>),
 }
 
-impl<IO: ReadWriteSeek, TP, OCC> DirRawStream<'_, IO, TP, OCC>
+impl<'a, IO: ReadWriteSeek, TP, OCC> DirRawStream<'a, IO, TP, OCC>
 where
     IO::Error: 'static,
 {
-    fn abs_pos(&self) -> Option<u64> {
+    pub(crate) fn file(file: File<'a, IO, TP, OCC>) -> Self {
+        #[cfg(feature = "alloc")]
+        let file = Box::new(file);
+        DirRawStream::File(file)
+    }
+
+    pub(crate) fn abs_pos(&self) -> Option<u64> {
         match self {
             DirRawStream::File(file) => file.abs_pos(),
             DirRawStream::Root(slice) => Some(slice.abs_pos()),
@@ -126,8 +144,8 @@ pub struct Dir<'a, IO: ReadWriteSeek, TP, OCC>
 where
     IO::Error: 'static,
 {
-    stream: DirRawStream<'a, IO, TP, OCC>,
-    fs: &'a FileSystem<IO, TP, OCC>,
+    pub(crate) stream: DirRawStream<'a, IO, TP, OCC>,
+    pub(crate) fs: &'a FileSystem<IO, TP, OCC>,
 }
 
 impl<'a, IO: ReadWriteSeek, TP, OCC> Dir<'a, IO, TP, OCC> {
@@ -191,6 +209,70 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         Ok(None)
     }
 
+    /// Replaces the label stored in the volume entry of this directory. The entry is created if it doesn't exist and
+    /// deleted if `label` is `None`.
+    pub(crate) async fn set_volume_entry(
+        &self,
+        label: Option<[u8; SFN_SIZE]>,
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::set_volume_entry");
+        match (self.find_volume_entry().await?, label) {
+            (Some(e), Some(label)) => {
+                let mut data = e.data.renamed(label);
+                data.set_modified(self.fs.options.time_provider.get_current_date_time());
+                data.serialize(&mut self.fs.dir_entry_slice(e.entry_pos))
+                    .await?;
+            }
+            (Some(e), None) => {
+                let mut data = e.data.clone();
+                data.set_deleted();
+                data.serialize(&mut self.fs.dir_entry_slice(e.entry_pos))
+                    .await?;
+            }
+            (None, Some(label)) => {
+                let mut stream = self.find_free_entries(1).await?;
+                self.create_sfn_entry(label, FileAttributes::VOLUME_ID, None)
+                    .serialize(&mut stream)
+                    .await?;
+                stream.flush().await?;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Replaces the exFAT Volume Label entry of this directory. The entry is created if it doesn't exist and marked
+    /// as unused if `label` is empty.
+    #[cfg(feature = "exfat")]
+    pub(crate) async fn set_exfat_volume_entry(
+        &self,
+        label: &[u16],
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::set_exfat_volume_entry");
+        let entry = self.find_volume_entry().await?;
+        if label.is_empty() {
+            if let Some(e) = entry {
+                let mut slice = self.fs.dir_entry_slice(e.entry_pos);
+                slice
+                    .write_all(&[ENTRY_TYPE_LABEL & !ENTRY_TYPE_IN_USE])
+                    .await?;
+                slice.flush().await?;
+            }
+            return Ok(());
+        }
+        let label_entry = label_entry(label);
+        if let Some(e) = entry {
+            let mut slice = self.fs.dir_entry_slice(e.entry_pos);
+            slice.write_all(&label_entry).await?;
+            slice.flush().await?;
+        } else {
+            let (mut stream, _) = self.find_free_exfat_entries(1).await?;
+            stream.write_all(&label_entry).await?;
+            stream.flush().await?;
+        }
+        Ok(())
+    }
+
     async fn check_for_existence(
         &self,
         name: &str,
@@ -368,6 +450,7 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// * `Error::InvalidFileNameLength` will be returned if the file name is empty or if it is too long.
     /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains an invalid character.
     /// * `Error::NotEnoughSpace` will be returned if there is not enough free space to create a new file.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn create_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
         trace!("Dir::create_file {}", path);
@@ -587,6 +670,7 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// * `Error::InvalidFileNameLength` will be returned if the file name is empty or if it is too long.
     /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains an invalid character.
     /// * `Error::NotEnoughSpace` will be returned if there is not enough free space to create a new directory.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn create_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
         trace!("Dir::create_dir {}", path);
@@ -618,18 +702,23 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
                     e.create_sfn_entry(short_name, FileAttributes::DIRECTORY, Some(cluster));
                 let entry = e.write_entry(name, sfn_entry).await?;
                 let dir = entry.to_dir();
-                // create special entries "." and ".."
-                let dot_sfn = ShortNameGenerator::generatorerate_dot();
-                let sfn_entry =
-                    e.create_sfn_entry(dot_sfn, FileAttributes::DIRECTORY, entry.first_cluster());
-                dir.write_entry(".", sfn_entry).await?;
-                let dotdot_sfn = ShortNameGenerator::generatorerate_dotdot();
-                let sfn_entry = e.create_sfn_entry(
-                    dotdot_sfn,
-                    FileAttributes::DIRECTORY,
-                    e.stream.first_cluster(),
-                );
-                dir.write_entry("..", sfn_entry).await?;
+                // create special entries "." and ".." (exFAT directories do not have them)
+                if !self.fs.is_exfat() {
+                    let dot_sfn = ShortNameGenerator::generatorerate_dot();
+                    let sfn_entry = e.create_sfn_entry(
+                        dot_sfn,
+                        FileAttributes::DIRECTORY,
+                        entry.first_cluster(),
+                    );
+                    dir.write_entry(".", sfn_entry).await?;
+                    let dotdot_sfn = ShortNameGenerator::generatorerate_dotdot();
+                    let sfn_entry = e.create_sfn_entry(
+                        dotdot_sfn,
+                        FileAttributes::DIRECTORY,
+                        e.stream.first_cluster(),
+                    );
+                    dir.write_entry("..", sfn_entry).await?;
+                }
 
                 // Audit log: directory created
                 #[cfg(feature = "audit-log")]
@@ -675,6 +764,8 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
     /// * `Error::InvalidInput` will be returned if `path` points to a file that is not a directory.
     /// * `Error::DirectoryIsNotEmpty` will be returned if the specified directory is not empty.
+    /// * `Error::ReadOnly` will be returned if the entry has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn remove(&self, path: &str) -> Result<(), Error<IO::Error>> {
         trace!("Dir::remove {}", path);
@@ -706,44 +797,37 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
 
         // in case of directory check if it is empty
         let e = parent.find_entry(name, None, None).await?;
+        if e.attributes().contains(FileAttributes::READ_ONLY) {
+            return Err(Error::ReadOnly);
+        }
         if e.is_dir() && !e.to_dir().is_empty().await? {
             return Err(Error::DirectoryIsNotEmpty);
         }
 
         // Mark directory entries as deleted FIRST, before freeing data clusters
         // This is important because freeing clusters might affect the parent directory stream
-        let mut stream = parent.stream.clone();
-
-        // Get the absolute position of the stream start
-        // Use abs_pos() which works correctly for both File and DiskSlice
-        let stream_start_abs_pos = stream.abs_pos().unwrap_or(0);
-        trace!("Stream absolute start position: {}", stream_start_abs_pos);
-        trace!("Entry offset_range (absolute): {:?}", e.offset_range);
-
-        // Calculate relative offset within the stream
-        // offset_range contains absolute positions, but streams expect relative offsets
-        let relative_offset = e.offset_range.0.saturating_sub(stream_start_abs_pos);
-        trace!("Calculated relative offset: {}", relative_offset);
-
-        // Now seek to the relative offset within the stream
-        stream.seek(SeekFrom::Start(relative_offset)).await?;
-        let num = ((e.offset_range.1 - e.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as usize;
-        trace!("Will delete {} directory entries", num);
-        for i in 0..num {
-            let mut data = DirEntryData::deserialize(&mut stream).await?;
-            trace!("removing dir entry {} {:?}", i, data);
-            data.set_deleted();
-            stream
-                .seek(SeekFrom::Current(-i64::from(DIR_ENTRY_SIZE)))
-                .await?;
-            data.serialize(&mut stream).await?;
+        #[cfg(feature = "exfat")]
+        if let Some(ref set) = e.exfat {
+            self.delete_exfat_entry_set(set).await?;
+        } else {
+            parent.delete_entries(&e).await?;
         }
-        // remove requires stream flush
-        stream.flush().await?;
+        #[cfg(not(feature = "exfat"))]
+        parent.delete_entries(&e).await?;
 
         // Now free the file's data clusters
         if let Some(n) = e.first_cluster() {
             trace!("Freeing cluster chain starting at cluster {}", n);
+            #[cfg(feature = "exfat")]
+            if let Some(len) = e.no_fat_chain_len() {
+                // contiguous exFAT stream - only the allocation bitmap describes it
+                self.fs
+                    .free_contiguous_clusters(n, self.fs.clusters_from_bytes(len))
+                    .await?;
+            } else {
+                self.fs.free_cluster_chain(n).await?;
+            }
+            #[cfg(not(feature = "exfat"))]
             self.fs.free_cluster_chain(n).await?;
         }
 
@@ -776,6 +860,8 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// * `Error::NotFound` will be returned if `src_path` points to a non-existing directory entry or if `dst_path`
     ///   stripped from the last component does not point to an existing directory.
     /// * `Error::AlreadyExists` will be returned if `dst_path` points to an existing directory entry.
+    /// * `Error::ReadOnly` will be returned if the source entry has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn rename(
         &self,
@@ -830,6 +916,9 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         trace!("Dir::rename_internal {} {}", src_name, dst_name);
         // find existing file
         let e = self.find_entry(src_name, None, None).await?;
+        if e.attributes().contains(FileAttributes::READ_ONLY) {
+            return Err(Error::ReadOnly);
+        }
         // check if destionation filename is unused
         let r = dst_dir.check_for_existence(dst_name, None).await?;
         let short_name = match r {
@@ -846,37 +935,228 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
             // destionation file does not exist, short name has been generatorerated
             DirEntryOrShortName::ShortName(short_name) => short_name,
         };
+        // exFAT: move the entry set, keeping everything but the name
+        #[cfg(feature = "exfat")]
+        if let (Some(set), Some(volume)) = (&e.exfat, &self.fs.exfat) {
+            validate_long_name(dst_name)?;
+            let name_utf16: Vec<u16> = dst_name.encode_utf16().collect();
+            self.delete_exfat_entry_set(set).await?;
+            dst_dir
+                .write_exfat_set(set.renamed(&name_utf16, &volume.upcase))
+                .await?;
+            return Ok(());
+        }
         // free long and short name entries
+        self.delete_entries(&e).await?;
+        // save new directory entry
+        let sfn_entry = e.data.renamed(short_name);
+        dst_dir.write_entry(dst_name, sfn_entry).await?;
+        Ok(())
+    }
+
+    /// Changes the attributes of an existing file or directory.
+    ///
+    /// `path` is a '/' separated path relative to self directory. Only the `READ_ONLY`, `HIDDEN`, `SYSTEM` and
+    /// `ARCHIVE` bits are taken from `attrs`, the remaining bits of the entry are kept. The file or directory
+    /// does not need to be opened, but changes made this way are overwritten when a file that is open at the same
+    /// time updates its own entry.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if a component of `path` other than the last one is a file.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn set_attributes(
+        &self,
+        path: &str,
+        attrs: FileAttributes,
+    ) -> Result<(), Error<IO::Error>> {
+        const SETTABLE: FileAttributes = FileAttributes::READ_ONLY
+            .union(FileAttributes::HIDDEN)
+            .union(FileAttributes::SYSTEM)
+            .union(FileAttributes::ARCHIVE);
+        trace!("Dir::set_attributes {} {:?}", path, attrs);
+        let e = self.open_meta(path).await?;
+        let mut editor = e.editor();
+        let kept = editor.inner().attributes().difference(SETTABLE);
+        editor.set_attributes(kept | attrs.intersection(SETTABLE));
+        editor.flush(self.fs).await
+    }
+
+    /// Changes the timestamps of an existing file or directory.
+    ///
+    /// `path` is a '/' separated path relative to self directory. Timestamps passed as `None` are left unchanged.
+    /// Like [`Dir::set_attributes`] this works without opening the file or directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if a component of `path` other than the last one is a file.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn set_times(
+        &self,
+        path: &str,
+        created: Option<DateTime>,
+        accessed: Option<Date>,
+        modified: Option<DateTime>,
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::set_times {}", path);
+        let e = self.open_meta(path).await?;
+        let mut editor = e.editor();
+        if let Some(created) = created {
+            editor.set_created(created);
+        }
+        if let Some(accessed) = accessed {
+            editor.set_accessed(accessed);
+        }
+        if let Some(modified) = modified {
+            editor.set_modified(modified);
+        }
+        editor.flush(self.fs).await
+    }
+
+    /// Marks the long and short name entries of `e` as deleted.
+    async fn delete_entries(&self, e: &DirEntry<'a, IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        self.fs.check_writable()?;
         let mut stream = self.stream.clone();
 
+        // Get the absolute position of the stream start
+        // Use abs_pos() which works correctly for both File and DiskSlice
+        let stream_start_abs_pos = stream.abs_pos().unwrap_or(0);
+        trace!("Stream absolute start position: {}", stream_start_abs_pos);
+        trace!("Entry offset_range (absolute): {:?}", e.offset_range);
+
         // Calculate relative offset within the stream
         // offset_range contains absolute positions, but streams expect relative offsets
-        let stream_start_abs_pos = stream.abs_pos().unwrap_or(0);
         let relative_offset = e.offset_range.0.saturating_sub(stream_start_abs_pos);
+        trace!("Calculated relative offset: {}", relative_offset);
+
+        // Now seek to the relative offset within the stream
         stream.seek(SeekFrom::Start(relative_offset)).await?;
         let num = ((e.offset_range.1 - e.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as usize;
-        for _ in 0..num {
+        trace!("Will delete {} directory entries", num);
+        for i in 0..num {
             let mut data = DirEntryData::deserialize(&mut stream).await?;
-            trace!("removing LFN entry {:?}", data);
+            trace!("removing dir entry {} {:?}", i, data);
             data.set_deleted();
             stream
                 .seek(SeekFrom::Current(-i64::from(DIR_ENTRY_SIZE)))
                 .await?;
             data.serialize(&mut stream).await?;
         }
-        // save new directory entry
-        let sfn_entry = e.data.renamed(short_name);
-        dst_dir.write_entry(dst_name, sfn_entry).await?;
-
-        // rename requires stream flush (no async drop :()
+        // removal requires stream flush (no async drop :()
         stream.flush().await?;
         Ok(())
     }
 
+    /// Marks all entries of an exFAT entry set as unused.
+    #[cfg(feature = "exfat")]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn delete_exfat_entry_set(&self, set: &EntrySet) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::delete_exfat_entry_set");
+        self.fs.check_writable()?;
+        let mut disk = self.fs.disk.acquire().await;
+        for (entry, pos) in set.entries().iter().zip(set.positions()) {
+            disk.seek(SeekFrom::Start(*pos)).await?;
+            disk.write_all(&[entry[0] & !ENTRY_TYPE_IN_USE]).await?;
+        }
+        disk.flush().await?;
+        Ok(())
+    }
+
+    /// Creates an exFAT entry set for a new file or directory described by `raw_entry` and writes it.
+    #[cfg(feature = "exfat")]
+    async fn write_exfat_entry(
+        &self,
+        name: &str,
+        raw_entry: &DirFileEntryData,
+        volume: &ExFatVolume,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::write_exfat_entry {}", name);
+        validate_long_name(name)?;
+        let name_utf16: Vec<u16> = name.encode_utf16().collect();
+        let mut set = EntrySet::new(&name_utf16, 0, &volume.upcase);
+        raw_entry.apply_to_exfat(&mut set);
+        if raw_entry.is_dir() && set.first_cluster() != 0 {
+            // exFAT directories store their allocated size
+            let len = u64::from(self.fs.cluster_size());
+            set.set_data_length(len);
+            set.set_valid_data_length(len);
+        }
+        set.update_checksum();
+        self.write_exfat_set(set).await
+    }
+
+    /// Writes an exFAT entry set into the first run of unused entries that is long enough.
+    #[cfg(feature = "exfat")]
+    async fn write_exfat_set(
+        &self,
+        mut set: EntrySet,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        let (mut stream, start_pos) = self.find_free_exfat_entries(set.len()).await?;
+        let mut positions = Vec::with_capacity(set.len());
+        for entry in set.entries() {
+            stream.write_all(entry).await?;
+            // Unwrapping is safe because an entry was just written
+            positions.push(stream.abs_pos().unwrap() - DIR_ENTRY_LEN as u64);
+        }
+        let end_pos = stream.seek(SeekFrom::Current(0)).await?;
+        stream.flush().await?;
+        set.set_positions(positions);
+        Ok(exfat_dir_entry(set, (start_pos, end_pos), self.fs))
+    }
+
+    /// Finds the first run of `num_entries` unused exFAT entries. Returns a stream positioned at the run and the
+    /// position of the run in the directory.
+    #[cfg(feature = "exfat")]
+    async fn find_free_exfat_entries(
+        &self,
+        num_entries: usize,
+    ) -> Result<(DirRawStream<'a, IO, TP, OCC>, u64), Error<IO::Error>> {
+        self.fs.check_writable()?;
+        let mut stream = self.stream.clone();
+        let mut first_free = 0;
+        let mut num_free = 0;
+        let mut i = 0;
+        loop {
+            let mut entry = [0_u8; DIR_ENTRY_LEN];
+            let is_end = match stream.read_exact(&mut entry).await {
+                Err(ReadExactError::UnexpectedEof) => true,
+                Err(ReadExactError::Other(err)) => return Err(err),
+                Ok(()) => entry[0] == ENTRY_TYPE_END,
+            };
+            if num_free == 0 {
+                first_free = i;
+            }
+            if is_end {
+                // all remaining entries are unused
+                break;
+            } else if entry[0] & ENTRY_TYPE_IN_USE == 0 {
+                num_free += 1;
+                if num_free == num_entries {
+                    break;
+                }
+            } else {
+                num_free = 0;
+            }
+            i += 1;
+        }
+        let start_pos = (first_free * DIR_ENTRY_LEN) as u64;
+        stream.seek(SeekFrom::Start(start_pos)).await?;
+        Ok((stream, start_pos))
+    }
+
     async fn find_free_entries(
         &self,
         num_entries: u32,
     ) -> Result<DirRawStream<'a, IO, TP, OCC>, Error<IO::Error>> {
+        self.fs.check_writable()?;
         // Flush any dirty directory entries before reading to ensure consistency.
         // This prevents cache corruption when multiple files are created in the
         // same directory - without this, a read could see stale metadata.
@@ -968,6 +1248,10 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         raw_entry: DirFileEntryData,
     ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
         trace!("Dir::write_entry {}", name);
+        #[cfg(feature = "exfat")]
+        if let Some(volume) = &self.fs.exfat {
+            return self.write_exfat_entry(name, &raw_entry, volume).await;
+        }
         // check if name doesn't contain unsupported characters
         validate_long_name(name)?;
         // convert long name to UTF-16
@@ -1001,6 +1285,8 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
             fs: self.fs,
             entry_pos: start_abs_pos,
             offset_range: (start_pos, end_pos),
+            #[cfg(feature = "exfat")]
+            exfat: None,
         })
     }
 }
@@ -1059,6 +1345,10 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
         &mut self,
     ) -> Result<Option<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
         trace!("DirIter::read_dir_entry");
+        #[cfg(feature = "exfat")]
+        if self.fs.is_exfat() {
+            return self.read_exfat_entry().await;
+        }
         let mut lfn_builder = LongNameBuilder::new();
         let mut offset = self.stream.seek(SeekFrom::Current(0)).await?;
         let mut begin_offset = offset;
@@ -1101,6 +1391,8 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
                         fs: self.fs,
                         entry_pos: abs_pos,
                         offset_range: (begin_offset, offset),
+                        #[cfg(feature = "exfat")]
+                        exfat: None,
                     }));
                 }
                 DirEntryData::Lfn(data) => {
@@ -1112,6 +1404,77 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
         }
     }
 
+    /// Reads the next exFAT entry set. Entry sets with an invalid checksum are skipped.
+    #[cfg(feature = "exfat")]
+    #[allow(clippy::type_complexity)]
+    async fn read_exfat_entry(
+        &mut self,
+    ) -> Result<Option<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
+        loop {
+            let begin_offset = self.stream.seek(SeekFrom::Current(0)).await?;
+            let Some((entry, pos)) = self.read_exfat_raw_entry().await? else {
+                return Ok(None);
+            };
+            match entry[0] {
+                ENTRY_TYPE_END => return Ok(None),
+                ENTRY_TYPE_LABEL if !self.skip_volume => {
+                    // Present the volume label like a FAT volume ID entry
+                    let mut name = [SFN_PADDING; SFN_SIZE];
+                    for (dst, unit) in name.iter_mut().zip(label_from_entry(&entry)) {
+                        *dst = u8::try_from(unit).ok().filter(u8::is_ascii).unwrap_or(b'?');
+                    }
+                    return Ok(Some(DirEntry {
+                        data: DirFileEntryData::new(name, FileAttributes::VOLUME_ID),
+                        short_name: ShortName::new(&name),
+                        lfn_utf16: LfnBuffer::new(),
+                        fs: self.fs,
+                        entry_pos: pos,
+                        offset_range: (begin_offset, begin_offset + DIR_ENTRY_LEN as u64),
+                        exfat: None,
+                    }));
+                }
+                ENTRY_TYPE_FILE => {
+                    let mut entries = vec![entry];
+                    let mut positions = vec![pos];
+                    for _ in 0..EntrySet::secondary_count(&entry) {
+                        let Some((entry, pos)) = self.read_exfat_raw_entry().await? else {
+                            break;
+                        };
+                        entries.push(entry);
+                        positions.push(pos);
+                    }
+                    let end_offset = self.stream.seek(SeekFrom::Current(0)).await?;
+                    if let Some(set) = EntrySet::from_entries(entries, positions) {
+                        return Ok(Some(exfat_dir_entry(set, (begin_offset, end_offset), self.fs)));
+                    }
+                    warn!("skipping corrupted exFAT entry set at {}", pos);
+                    // continue right after the File entry - a valid set may start among its secondary entries
+                    self.stream
+                        .seek(SeekFrom::Start(begin_offset + DIR_ENTRY_LEN as u64))
+                        .await?;
+                }
+                // unused entries and primary entries without a name
+                _ => trace!("skip entry"),
+            }
+        }
+    }
+
+    /// Reads a single raw exFAT entry and its absolute position. Returns `None` at the end of the directory data.
+    #[cfg(feature = "exfat")]
+    async fn read_exfat_raw_entry(
+        &mut self,
+    ) -> Result<Option<([u8; DIR_ENTRY_LEN], u64)>, Error<IO::Error>> {
+        let mut entry = [0_u8; DIR_ENTRY_LEN];
+        match self.stream.read_exact(&mut entry).await {
+            Err(ReadExactError::UnexpectedEof) => return Ok(None),
+            Err(ReadExactError::Other(err)) => return Err(err),
+            Ok(()) => {}
+        }
+        // Unwrapping is safe because an entry was just read
+        let pos = self.stream.abs_pos().unwrap() - DIR_ENTRY_LEN as u64;
+        Ok(Some((entry, pos)))
+    }
+
     pub async fn next(&mut self) -> Option<Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>>> {
         if self.err {
             return None;
@@ -1149,6 +1512,25 @@ impl<IO: ReadWriteSeek, TP, OCC> Clone for DirIter<'_, IO, TP, OCC> {
     }
 }
 
+/// Creates the `DirEntry` of an exFAT file or directory.
+#[cfg(feature = "exfat")]
+fn exfat_dir_entry<IO: ReadWriteSeek, TP, OCC>(
+    set: EntrySet,
+    offset_range: (u64, u64),
+    fs: &FileSystem<IO, TP, OCC>,
+) -> DirEntry<'_, IO, TP, OCC> {
+    let data = DirFileEntryData::from_exfat(&set);
+    DirEntry {
+        short_name: ShortName::new(data.name()),
+        lfn_utf16: LfnBuffer::from_ucs2_units(set.name().into_iter()),
+        data,
+        fs,
+        entry_pos: set.positions()[0],
+        offset_range,
+        exfat: Some(set),
+    }
+}
+
 #[rustfmt::skip]
 fn validate_long_name<E: IoError>(name: &str) -> Result<(), Error<E>> {
     // check if length is valid
@@ -1171,7 +1553,7 @@ fn validate_long_name<E: IoError>(name: &str) -> Result<(), Error<E>> {
     Ok(())
 }
 
-fn lfn_checksum(short_name: &[u8; SFN_SIZE]) -> u8 {
+pub(crate) fn lfn_checksum(short_name: &[u8; SFN_SIZE]) -> u8 {
     let mut chksum = num::Wrapping(0_u8);
     for b in short_name {
         chksum = (chksum << 7) + (chksum >> 1) + num::Wrapping(*b);
@@ -1185,7 +1567,7 @@ pub(crate) struct LfnBuffer {
     ucs2_units: Vec<u16>,
 }
 
-const MAX_LONG_NAME_LEN: usize = 255;
+pub(crate) const MAX_LONG_NAME_LEN: usize = 255;
 
 #[cfg(feature = "lfn")]
 const MAX_LONG_DIR_ENTRIES: usize = MAX_LONG_NAME_LEN.div_ceil(LFN_PART_LEN);
@@ -1815,4 +2197,102 @@ mod tests {
         buf = generator.generatorerate().unwrap();
         assert_eq!(&buf, b"X40DA~2 TXT");
     }
+
+    #[tokio::test]
+    async fn test_set_attributes_and_times() {
+        use crate::fs::{FormatVolumeOptions, FsOptions, format_volume};
+        use crate::time::Time;
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        let mut image = vec![0_u8; 8 * 1024 * 1024];
+        format_volume(
+            &mut FromTokio::new(Cursor::new(&mut image)),
+            FormatVolumeOptions::new(),
+        )
+        .await
+        .unwrap();
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let dir = root.create_dir("docs").await.unwrap();
+        dir.create_file("a.txt").await.unwrap();
+
+        let attrs = FileAttributes::HIDDEN | FileAttributes::READ_ONLY;
+        root.set_attributes("docs/a.txt", attrs).await.unwrap();
+        assert_eq!(
+            root.open_meta("docs/a.txt").await.unwrap().attributes(),
+            attrs
+        );
+        // the directory bit is kept
+        root.set_attributes("docs", FileAttributes::SYSTEM | FileAttributes::VOLUME_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            root.open_meta("docs").await.unwrap().attributes(),
+            FileAttributes::SYSTEM | FileAttributes::DIRECTORY
+        );
+
+        let created = DateTime::new(Date::new(2001, 2, 3), Time::new(4, 5, 6, 0));
+        let modified = DateTime::new(Date::new(2020, 12, 31), Time::new(23, 59, 58, 0));
+        let accessed = Date::new(2021, 1, 1);
+        root.set_times("docs/a.txt", Some(created), Some(accessed), Some(modified))
+            .await
+            .unwrap();
+        root.set_times("docs/a.txt", None, None, None)
+            .await
+            .unwrap();
+        let e = root.open_meta("docs/a.txt").await.unwrap();
+        assert_eq!(e.created(), created);
+        assert_eq!(e.accessed(), accessed);
+        assert_eq!(e.modified(), modified);
+        assert!(matches!(
+            root.set_times("docs/b.txt", None, None, Some(modified))
+                .await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_attribute() {
+        use crate::fs::{FormatVolumeOptions, FsOptions, format_volume};
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        let mut image = vec![0_u8; 8 * 1024 * 1024];
+        format_volume(
+            &mut FromTokio::new(Cursor::new(&mut image)),
+            FormatVolumeOptions::new(),
+        )
+        .await
+        .unwrap();
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut file = root.create_file("a.txt").await.unwrap();
+        file.write_all(b"keep").await.unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+        root.set_attributes("a.txt", FileAttributes::READ_ONLY)
+            .await
+            .unwrap();
+
+        let mut file = root.open_file("a.txt").await.unwrap();
+        assert!(matches!(file.write(b"x").await, Err(Error::ReadOnly)));
+        assert!(matches!(file.truncate().await, Err(Error::ReadOnly)));
+        drop(file);
+        assert!(matches!(root.remove("a.txt").await, Err(Error::ReadOnly)));
+        assert!(matches!(
+            root.rename("a.txt", &root, "b.txt").await,
+            Err(Error::ReadOnly)
+        ));
+
+        root.set_attributes("a.txt", FileAttributes::empty())
+            .await
+            .unwrap();
+        root.rename("a.txt", &root, "b.txt").await.unwrap();
+        root.remove("b.txt").await.unwrap();
+    }
 }
@@ -1,20 +1,28 @@
 use bitflags::bitflags;
 use core::char;
+#[cfg(feature = "exfat")]
+use core::cmp;
 use core::fmt;
 #[cfg(not(feature = "unicode"))]
 use core::iter;
 use core::str;
 
-#[cfg(all(not(feature = "std"), feature = "alloc", feature = "lfn"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
+#[cfg(all(not(feature = "std"), feature = "exfat"))]
+use alloc::vec::Vec;
 
 use crate::FileContext;
 #[cfg(feature = "lfn")]
 use crate::dir::LfnBuffer;
 use crate::dir::{Dir, DirRawStream};
 use crate::error::{Error, IoError, ReadExactError};
+#[cfg(feature = "exfat")]
+use crate::exfat::EntrySet;
 use crate::file::File;
 use crate::fs::{FatType, FileSystem, OemCpConverter, ReadWriteSeek};
+#[cfg(feature = "tiny-mode")]
+use crate::io::Seek;
 use crate::io::{self, Read, ReadLeExt, Write, WriteLeExt};
 use crate::time::{Date, DateTime};
 
@@ -51,7 +59,7 @@ pub(crate) const SFN_PADDING: u8 = b' ';
 pub(crate) const LFN_PART_LEN: usize = 13;
 
 // Bit used in order field to mark last LFN entry
-#[cfg(feature = "lfn")]
+#[cfg(any(feature = "lfn", feature = "alloc"))]
 pub(crate) const LFN_ENTRY_LAST_FLAG: u8 = 0x40;
 
 // Character to upper case conversion which supports Unicode only if `unicode` feature is enabled
@@ -110,7 +118,7 @@ impl ShortName {
     }
 
     #[cfg(feature = "alloc")]
-    fn to_string<OCC: OemCpConverter>(&self, oem_cp_converter: &OCC) -> String {
+    pub(crate) fn to_string<OCC: OemCpConverter>(&self, oem_cp_converter: &OCC) -> String {
         // Strip non-ascii characters from short name
         self.as_bytes()
             .iter()
@@ -167,7 +175,7 @@ impl DirFileEntryData {
     }
 
     #[cfg(feature = "alloc")]
-    fn lowercase_name(&self) -> ShortName {
+    pub(crate) fn lowercase_name(&self) -> ShortName {
         let mut name_copy: [u8; SFN_SIZE] = self.name;
         if self.lowercase_basename() {
             name_copy[..8].make_ascii_lowercase();
@@ -179,10 +187,10 @@ impl DirFileEntryData {
     }
 
     pub(crate) fn first_cluster(&self, fat_type: FatType) -> Option<u32> {
-        let first_cluster_hi = if fat_type == FatType::Fat32 {
-            self.first_cluster_hi
-        } else {
+        let first_cluster_hi = if matches!(fat_type, FatType::Fat12 | FatType::Fat16) {
             0
+        } else {
+            self.first_cluster_hi
         };
         let n = (u32::from(first_cluster_hi) << 16) | u32::from(self.first_cluster_lo);
         if n == 0 { None } else { Some(n) }
@@ -190,7 +198,7 @@ impl DirFileEntryData {
 
     pub(crate) fn set_first_cluster(&mut self, cluster: Option<u32>, fat_type: FatType) {
         let n = cluster.unwrap_or(0);
-        if fat_type == FatType::Fat32 {
+        if !matches!(fat_type, FatType::Fat12 | FatType::Fat16) {
             self.first_cluster_hi = (n >> 16) as u16;
         }
         self.first_cluster_lo = (n & 0xFFFF) as u16;
@@ -204,7 +212,7 @@ impl DirFileEntryData {
         }
     }
 
-    fn set_size(&mut self, size: u32) {
+    pub(crate) fn set_size(&mut self, size: u32) {
         self.size = size;
     }
 
@@ -212,6 +220,14 @@ impl DirFileEntryData {
         self.attrs.contains(FileAttributes::DIRECTORY)
     }
 
+    pub(crate) fn attributes(&self) -> FileAttributes {
+        self.attrs
+    }
+
+    pub(crate) fn set_attributes(&mut self, attrs: FileAttributes) {
+        self.attrs = attrs;
+    }
+
     fn is_file(&self) -> bool {
         !self.is_dir()
     }
@@ -286,6 +302,53 @@ impl DirFileEntryData {
     }
 }
 
+#[cfg(feature = "exfat")]
+impl DirFileEntryData {
+    /// Builds the FAT view of an exFAT entry set. The short name is left blank.
+    pub(crate) fn from_exfat(set: &EntrySet) -> Self {
+        let split = |timestamp: u32| ((timestamp >> 16) as u16, timestamp as u16);
+        let (create_date, create_time) = split(set.timestamp(0).0);
+        let (modify_date, modify_time) = split(set.timestamp(1).0);
+        let (access_date, _) = split(set.timestamp(2).0);
+        let mut attrs = FileAttributes::from_bits_truncate(set.attributes() as u8);
+        attrs.remove(FileAttributes::VOLUME_ID);
+        let mut data = Self {
+            name: [SFN_PADDING; SFN_SIZE],
+            attrs,
+            create_time_0: set.timestamp(0).1,
+            create_time_1: create_time,
+            create_date,
+            access_date,
+            modify_time,
+            modify_date,
+            size: u32::try_from(set.data_length()).unwrap_or(u32::MAX),
+            ..Self::default()
+        };
+        data.set_first_cluster(Some(set.first_cluster()).filter(|n| *n != 0), FatType::ExFat);
+        data
+    }
+
+    /// Stores attributes, first cluster and timestamps in an exFAT entry set.
+    ///
+    /// Timestamps are only replaced if they changed, so the finer exFAT resolution and UTC offsets survive.
+    pub(crate) fn apply_to_exfat(&self, set: &mut EntrySet) {
+        let attrs = self.attrs.bits() & !FileAttributes::VOLUME_ID.bits();
+        set.set_attributes((set.attributes() & 0xFF00) | u16::from(attrs));
+        set.set_first_cluster(self.first_cluster(FatType::ExFat).unwrap_or(0));
+        let timestamps = [
+            (self.create_date, self.create_time_1, self.create_time_0),
+            (self.modify_date, self.modify_time, 0),
+            (self.access_date, 0, 0),
+        ];
+        for (index, (date, time, increment)) in timestamps.into_iter().enumerate() {
+            let timestamp = (u32::from(date) << 16) | u32::from(time);
+            if set.timestamp(index).0 != timestamp {
+                set.set_timestamp(index, timestamp, increment);
+            }
+        }
+    }
+}
+
 #[allow(dead_code)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone, Debug, Default)]
@@ -473,6 +536,9 @@ pub(crate) struct DirEntryEditor {
     /// Generation counter snapshot from when this editor was created.
     /// Used to detect if directory clusters have been reallocated.
     generation: u64,
+    /// exFAT entry set this editor writes back to (`data` mirrors its fields)
+    #[cfg(feature = "exfat")]
+    exfat: Option<EntrySet>,
 }
 
 impl DirEntryEditor {
@@ -482,6 +548,8 @@ impl DirEntryEditor {
             pos,
             dirty: false,
             generation,
+            #[cfg(feature = "exfat")]
+            exfat: None,
         }
     }
 
@@ -500,16 +568,98 @@ impl DirEntryEditor {
         }
     }
 
-    pub(crate) fn set_size(&mut self, size: u32) {
+    /// Returns the file size or `None` for a directory.
+    pub(crate) fn size(&self) -> Option<u64> {
+        #[cfg(feature = "exfat")]
+        if let Some(ref set) = self.exfat {
+            return self.data.is_file().then(|| set.data_length());
+        }
+        self.data.size().map(u64::from)
+    }
+
+    pub(crate) fn set_size(&mut self, size: u64) {
+        #[cfg(feature = "exfat")]
+        if let Some(ref mut set) = self.exfat {
+            if self.data.is_file() && size != set.data_length() {
+                // Files only grow by writing, so everything up to the new size is valid
+                let valid_len = if size > set.data_length() {
+                    size
+                } else {
+                    cmp::min(set.valid_data_length(), size)
+                };
+                set.set_data_length(size);
+                set.set_valid_data_length(valid_len);
+                self.data.set_size(u32::try_from(size).unwrap_or(u32::MAX));
+                self.dirty = true;
+            }
+            return;
+        }
         match self.data.size() {
-            Some(n) if size != n => {
-                self.data.set_size(size);
+            Some(n) if u64::from(n) != size => {
+                // File code never lets a FAT file grow beyond `u32::MAX`
+                self.data.set_size(size as u32);
                 self.dirty = true;
             }
             _ => {}
         }
     }
 
+    /// Returns the valid data length of an exFAT file. Bytes past it read as zeros.
+    #[cfg(feature = "exfat")]
+    pub(crate) fn valid_data_length(&self) -> Option<u64> {
+        self.exfat
+            .as_ref()
+            .filter(|_| self.data.is_file())
+            .map(EntrySet::valid_data_length)
+    }
+
+    #[cfg(feature = "exfat")]
+    pub(crate) fn set_valid_data_length(&mut self, len: u64) {
+        if let Some(ref mut set) = self.exfat {
+            if len != set.valid_data_length() {
+                set.set_valid_data_length(len);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Sets the allocated size of an exFAT directory. Does nothing for FAT directories, which have no size.
+    #[cfg(feature = "exfat")]
+    pub(crate) fn set_dir_len(&mut self, len: u64) {
+        if let Some(ref mut set) = self.exfat {
+            if self.data.is_dir() && len != set.data_length() {
+                set.set_data_length(len);
+                set.set_valid_data_length(len);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Returns the data length of an exFAT stream stored without a FAT chain (its clusters are contiguous).
+    #[cfg(feature = "exfat")]
+    pub(crate) fn no_fat_chain_len(&self) -> Option<u64> {
+        self.exfat
+            .as_ref()
+            .filter(|set| set.flags() & crate::exfat::FLAG_NO_FAT_CHAIN != 0)
+            .map(EntrySet::data_length)
+    }
+
+    /// Marks the stream as described by the FAT. Called once its FAT chain has been written.
+    #[cfg(feature = "exfat")]
+    pub(crate) fn clear_no_fat_chain(&mut self) {
+        if let Some(ref mut set) = self.exfat {
+            set.set_flags(set.flags() & !crate::exfat::FLAG_NO_FAT_CHAIN);
+            self.dirty = true;
+        }
+    }
+
+    pub(crate) fn set_attributes(&mut self, attrs: FileAttributes) {
+        if attrs != self.data.attributes() {
+            self.data.set_attributes(attrs);
+            self.dirty = true;
+        }
+    }
+
     pub(crate) fn set_created(&mut self, date_time: DateTime) {
         if date_time != self.data.created() {
             self.data.set_created(date_time);
@@ -563,6 +713,8 @@ impl DirEntryEditor {
     ) -> Result<(), Error<IO::Error>> {
         use core::sync::atomic::Ordering;
 
+        fs.check_writable()?;
+
         // Validate generation counter to prevent writing to reallocated clusters
         let current_generation = fs.cluster_generation.load(Ordering::Acquire);
         if current_generation != self.generation {
@@ -575,10 +727,28 @@ impl DirEntryEditor {
             return Err(Error::StaleDirectoryEntry);
         }
 
+        #[cfg(feature = "exfat")]
+        if let Some(ref set) = self.exfat {
+            let mut set = set.clone();
+            self.data.apply_to_exfat(&mut set);
+            set.update_checksum();
+            let mut disk = fs.disk.acquire().await;
+            // Only the File and Stream Extension entries are ever modified
+            for (entry, pos) in set.entries().iter().zip(set.positions()).take(2) {
+                disk.seek(io::SeekFrom::Start(*pos)).await?;
+                disk.write_all(entry).await?;
+            }
+            disk.flush().await?;
+            return Ok(());
+        }
+
         {
             let mut disk = fs.disk.acquire().await;
             // Position is valid - generation hasn't changed
             disk.seek(io::SeekFrom::Start(self.pos)).await?;
+            #[cfg(feature = "transaction-safe")]
+            fs.journal_write(&mut *disk, u64::from(DIR_ENTRY_SIZE))
+                .await?;
             self.data.serialize(&mut *disk).await?;
             // Flush to ensure entry is visible to subsequent reads.
             // Without this, directory reads could see stale data.
@@ -602,6 +772,9 @@ where
     pub(crate) lfn_utf16: LfnBuffer,
     pub(crate) entry_pos: u64,
     pub(crate) offset_range: (u64, u64),
+    /// Entry set of an exFAT file or directory
+    #[cfg(feature = "exfat")]
+    pub(crate) exfat: Option<EntrySet>,
     pub(crate) fs: &'a FileSystem<IO, TP, OCC>,
 }
 
@@ -676,10 +849,25 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
         self.data.first_cluster(self.fs.fat_type())
     }
 
-    fn editor(&self) -> DirEntryEditor {
+    pub(crate) fn editor(&self) -> DirEntryEditor {
         use core::sync::atomic::Ordering;
         let generation = self.fs.cluster_generation.load(Ordering::Acquire);
-        DirEntryEditor::new(self.data.clone(), self.entry_pos, generation)
+        #[allow(unused_mut)]
+        let mut editor = DirEntryEditor::new(self.data.clone(), self.entry_pos, generation);
+        #[cfg(feature = "exfat")]
+        {
+            editor.exfat.clone_from(&self.exfat);
+        }
+        editor
+    }
+
+    /// Returns the data length of an exFAT stream stored without a FAT chain.
+    #[cfg(feature = "exfat")]
+    pub(crate) fn no_fat_chain_len(&self) -> Option<u64> {
+        self.exfat
+            .as_ref()
+            .filter(|set| set.flags() & crate::exfat::FLAG_NO_FAT_CHAIN != 0)
+            .map(EntrySet::data_length)
     }
 
     pub(crate) fn is_same_entry(&self, other: &DirEntry<IO, TP, OCC>) -> bool {
@@ -764,7 +952,7 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
         match self.first_cluster() {
             Some(n) => {
                 let file = File::new(Some(n), Some(self.editor()), self.fs);
-                Dir::new(DirRawStream::File(file), self.fs)
+                Dir::new(DirRawStream::file(file), self.fs)
             }
             None => self.fs.root_dir(),
         }
@@ -773,6 +961,10 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     /// Returns file size or 0 for directory.
     #[must_use]
     pub fn len(&self) -> u64 {
+        #[cfg(feature = "exfat")]
+        if let Some(ref set) = self.exfat {
+            return if self.is_file() { set.data_length() } else { 0 };
+        }
         u64::from(self.data.size)
     }
 
@@ -829,6 +1021,15 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     }
 
     pub(crate) fn eq_name(&self, name: &str) -> bool {
+        // exFAT compares names using the up-case table of the volume
+        #[cfg(feature = "exfat")]
+        if let (Some(_), Some(volume)) = (&self.exfat, &self.fs.exfat) {
+            let name_utf16: Vec<u16> = name.encode_utf16().collect();
+            return volume
+                .upcase
+                .eq_names(self.lfn_utf16.as_ucs2_units(), &name_utf16);
+        }
+
         #[cfg(feature = "lfn")]
         {
             if self.eq_name_lfn(name) {
@@ -0,0 +1,95 @@
+//! Discard (TRIM) of released clusters
+//!
+//! Clusters released by `ClusterIterator::free` and `ClusterIterator::truncate`, and the clusters
+//! of exFAT streams without a FAT chain, are collected as runs of contiguous clusters. Once the FAT
+//! changes are written, every run is passed on to the storage with a single
+//! `fatrs_block_device::Discard::discard` call, letting flash devices erase or unmap the blocks
+//! instead of preserving stale data.
+//!
+//! Performance impact:
+//! - Deleting or truncating a contiguous file: one discard per run of clusters
+//! - The FAT cache is written before the discards so a power loss cannot leave discarded clusters
+//!   allocated
+
+use core::future::Future;
+use core::pin::Pin;
+
+use fatrs_block_device::Discard;
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(feature = "std")]
+use std::{boxed::Box, vec::Vec};
+
+/// Issues a discard of `len` bytes at byte `offset` of the storage.
+///
+/// Stored by the filesystem so that discards can be issued without a `Discard` bound on its IO type.
+pub(crate) type DiscardFn<IO> =
+    for<'a> fn(&'a mut IO, u64, u64) -> Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Returns the `DiscardFn` of a storage type. Failed discards are logged and otherwise ignored,
+/// releasing the clusters already succeeded.
+pub(crate) fn discard_fn<IO: Discard>() -> DiscardFn<IO> {
+    |io, offset, len| {
+        Box::pin(async move {
+            if io.discard(offset, len).await.is_err() {
+                warn!("Discard of {} bytes at offset {} failed", len, offset);
+            }
+        })
+    }
+}
+
+/// Runs of contiguous released clusters, as first cluster and number of clusters
+#[derive(Default)]
+pub(crate) struct ClusterRuns {
+    runs: Vec<(u32, u32)>,
+}
+
+impl ClusterRuns {
+    /// Records a released cluster, extending the last run if the cluster follows it.
+    pub(crate) fn push(&mut self, cluster: u32) {
+        if let Some((first, count)) = self.runs.last_mut() {
+            if *first + *count == cluster {
+                *count += 1;
+                return;
+            }
+        }
+        self.runs.push((cluster, 1));
+    }
+
+    /// Records the runs of `other` after the runs of `self`.
+    #[cfg(feature = "transaction-safe")]
+    pub(crate) fn append(&mut self, other: Self) {
+        for (first, count) in other.runs {
+            match self.runs.last_mut() {
+                Some((last_first, last_count)) if *last_first + *last_count == first => {
+                    *last_count += count;
+                }
+                _ => self.runs.push((first, count)),
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.runs.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contiguous_clusters_are_merged() {
+        let mut runs = ClusterRuns::default();
+        assert!(runs.is_empty());
+        for cluster in [5, 6, 7, 10, 11, 3, 4] {
+            runs.push(cluster);
+        }
+        assert_eq!(runs.iter().collect::<Vec<_>>(), [(5, 3), (10, 2), (3, 2)]);
+    }
+}
@@ -33,10 +33,20 @@ pub enum Error<T> {
     /// File is locked by another reader or writer (requires `file-locking` feature).
     #[cfg(feature = "file-locking")]
     FileLocked,
+    /// A transaction modifies more sectors than the transaction log can hold (requires `transaction-safe`
+    /// feature).
+    #[cfg(feature = "transaction-safe")]
+    TransactionLogFull,
     /// Directory entry position is stale due to cluster reallocation.
     /// This indicates the directory containing this file/directory was modified
     /// (entries deleted/moved) while this entry was open.
     StaleDirectoryEntry,
+    /// The clusters of a deleted file or directory have been reused, so it cannot be recovered.
+    ClustersInUse,
+    /// The file or directory has the `READ_ONLY` attribute, or the volume is mounted read-only.
+    ReadOnly,
+    /// The operation cannot be performed while files of the volume are open.
+    FilesOpen,
 }
 
 impl<T> IoError for Error<T>
@@ -88,7 +98,12 @@ impl<T: core::fmt::Display> core::fmt::Display for Error<T> {
             Error::CorruptedFileSystem => write!(f, "Corrupted file system"),
             #[cfg(feature = "file-locking")]
             Error::FileLocked => write!(f, "File is locked by another reader or writer"),
+            #[cfg(feature = "transaction-safe")]
+            Error::TransactionLogFull => write!(f, "Transaction does not fit in the transaction log"),
             Error::StaleDirectoryEntry => write!(f, "Directory entry position is stale due to cluster reallocation"),
+            Error::ClustersInUse => write!(f, "Clusters of the deleted entry are in use"),
+            Error::ReadOnly => write!(f, "Read-only file system or directory entry"),
+            Error::FilesOpen => write!(f, "Files of the volume are open"),
         }
     }
 }
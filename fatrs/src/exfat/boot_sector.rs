@@ -0,0 +1,323 @@
+use crate::error::{Error, IoError};
+use crate::io::{Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec;
+
+/// File system name stored in place of the OEM name in exFAT boot sectors.
+pub(crate) const EXFAT_SIGNATURE: &[u8; 8] = b"EXFAT   ";
+/// Number of sectors in the main (or backup) boot region: boot sector, 8 extended boot sectors, OEM parameters,
+/// a reserved sector and the checksum sector.
+pub(crate) const BOOT_REGION_SECTORS: u32 = 12;
+/// Offset of the `VolumeFlags` field, excluded from the boot region checksum.
+pub(crate) const VOLUME_FLAGS_OFFSET: u64 = 106;
+/// Offset of the `PercentInUse` field, excluded from the boot region checksum.
+const PERCENT_IN_USE_OFFSET: usize = 112;
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const EXTENDED_BOOT_SIGNATURE: [u8; 4] = [0x00, 0x00, 0x55, 0xAA];
+const JUMP_BOOT: [u8; 3] = [0xEB, 0x76, 0x90];
+
+pub(crate) const VOLUME_FLAG_ACTIVE_FAT: u16 = 1 << 0;
+pub(crate) const VOLUME_FLAG_DIRTY: u16 = 1 << 1;
+pub(crate) const VOLUME_FLAG_MEDIA_FAILURE: u16 = 1 << 2;
+
+/// Fields of the exFAT main boot sector.
+///
+/// exFAT has no BIOS Parameter Block - all sizes are stored as sector counts or as power-of-two shifts.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExFatBootSector {
+    pub(crate) partition_offset: u64,
+    pub(crate) volume_length: u64,
+    pub(crate) fat_offset: u32,
+    pub(crate) fat_length: u32,
+    pub(crate) cluster_heap_offset: u32,
+    pub(crate) cluster_count: u32,
+    pub(crate) root_dir_first_cluster: u32,
+    pub(crate) volume_serial_number: u32,
+    pub(crate) file_system_revision: u16,
+    pub(crate) volume_flags: u16,
+    pub(crate) bytes_per_sector_shift: u8,
+    pub(crate) sectors_per_cluster_shift: u8,
+    pub(crate) number_of_fats: u8,
+    pub(crate) drive_select: u8,
+    pub(crate) percent_in_use: u8,
+}
+
+impl ExFatBootSector {
+    /// Checks the file system name of a boot sector.
+    pub(crate) fn is_exfat(sector: &[u8]) -> bool {
+        sector.len() >= 11 && &sector[3..11] == EXFAT_SIGNATURE
+    }
+
+    fn parse(sector: &[u8]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([sector[i], sector[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(sector[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(sector[i..i + 8].try_into().unwrap());
+        Self {
+            partition_offset: u64_at(64),
+            volume_length: u64_at(72),
+            fat_offset: u32_at(80),
+            fat_length: u32_at(84),
+            cluster_heap_offset: u32_at(88),
+            cluster_count: u32_at(92),
+            root_dir_first_cluster: u32_at(96),
+            volume_serial_number: u32_at(100),
+            file_system_revision: u16_at(104),
+            volume_flags: u16_at(106),
+            bytes_per_sector_shift: sector[108],
+            sectors_per_cluster_shift: sector[109],
+            number_of_fats: sector[110],
+            drive_select: sector[111],
+            percent_in_use: sector[112],
+        }
+    }
+
+    /// Serializes the main boot sector. `sector` must be at least 512 bytes long and is expected to be zeroed.
+    pub(crate) fn serialize(&self, sector: &mut [u8]) {
+        sector[0..3].copy_from_slice(&JUMP_BOOT);
+        sector[3..11].copy_from_slice(EXFAT_SIGNATURE);
+        sector[64..72].copy_from_slice(&self.partition_offset.to_le_bytes());
+        sector[72..80].copy_from_slice(&self.volume_length.to_le_bytes());
+        sector[80..84].copy_from_slice(&self.fat_offset.to_le_bytes());
+        sector[84..88].copy_from_slice(&self.fat_length.to_le_bytes());
+        sector[88..92].copy_from_slice(&self.cluster_heap_offset.to_le_bytes());
+        sector[92..96].copy_from_slice(&self.cluster_count.to_le_bytes());
+        sector[96..100].copy_from_slice(&self.root_dir_first_cluster.to_le_bytes());
+        sector[100..104].copy_from_slice(&self.volume_serial_number.to_le_bytes());
+        sector[104..106].copy_from_slice(&self.file_system_revision.to_le_bytes());
+        sector[106..108].copy_from_slice(&self.volume_flags.to_le_bytes());
+        sector[108] = self.bytes_per_sector_shift;
+        sector[109] = self.sectors_per_cluster_shift;
+        sector[110] = self.number_of_fats;
+        sector[111] = self.drive_select;
+        sector[112] = self.percent_in_use;
+        sector[510..512].copy_from_slice(&BOOT_SIGNATURE);
+    }
+
+    pub(crate) fn bytes_per_sector(&self) -> u32 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    pub(crate) fn cluster_size(&self) -> u32 {
+        1 << (self.bytes_per_sector_shift + self.sectors_per_cluster_shift)
+    }
+
+    pub(crate) fn bytes_from_sectors(&self, sectors: u32) -> u64 {
+        u64::from(sectors) << self.bytes_per_sector_shift
+    }
+
+    fn validate<E: IoError>(&self, sector: &[u8]) -> Result<(), Error<E>> {
+        if sector[510..512] != BOOT_SIGNATURE {
+            error!("Invalid exFAT boot sector signature");
+            return Err(Error::CorruptedFileSystem);
+        }
+        if sector[0..3] != JUMP_BOOT {
+            warn!(
+                "Unknown jump instruction {:?} in exFAT boot sector",
+                &sector[0..3]
+            );
+        }
+        if sector[11..64].iter().any(|b| *b != 0) {
+            error!("Invalid exFAT boot sector: MustBeZero field is not zero");
+            return Err(Error::CorruptedFileSystem);
+        }
+        if !(9..=12).contains(&self.bytes_per_sector_shift) {
+            error!(
+                "Invalid exFAT BytesPerSectorShift: {}",
+                self.bytes_per_sector_shift
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        if self.sectors_per_cluster_shift > 25 - self.bytes_per_sector_shift {
+            error!(
+                "Invalid exFAT SectorsPerClusterShift: {}",
+                self.sectors_per_cluster_shift
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        if !(1..=2).contains(&self.number_of_fats) {
+            error!("Invalid exFAT NumberOfFats: {}", self.number_of_fats);
+            return Err(Error::CorruptedFileSystem);
+        }
+        if self.file_system_revision >> 8 != 1 {
+            error!(
+                "Unsupported exFAT revision {}.{}",
+                self.file_system_revision >> 8,
+                self.file_system_revision & 0xFF
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        if self.fat_offset < 2 * BOOT_REGION_SECTORS {
+            error!("Invalid exFAT FatOffset: {}", self.fat_offset);
+            return Err(Error::CorruptedFileSystem);
+        }
+        let min_fat_length = ((u64::from(self.cluster_count) + 2) * 4) >> self.bytes_per_sector_shift;
+        if u64::from(self.fat_length) < min_fat_length {
+            error!(
+                "Invalid exFAT FatLength: {} is too small for {} clusters",
+                self.fat_length, self.cluster_count
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        let fats_end = u64::from(self.fat_offset)
+            + u64::from(self.fat_length) * u64::from(self.number_of_fats);
+        if u64::from(self.cluster_heap_offset) < fats_end {
+            error!(
+                "Invalid exFAT ClusterHeapOffset: {} overlaps the FAT region",
+                self.cluster_heap_offset
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        let heap_end = u64::from(self.cluster_heap_offset)
+            + (u64::from(self.cluster_count) << self.sectors_per_cluster_shift);
+        if self.cluster_count == 0
+            || self.cluster_count > 0xFFFF_FFF5
+            || heap_end > self.volume_length
+        {
+            error!("Invalid exFAT ClusterCount: {}", self.cluster_count);
+            return Err(Error::CorruptedFileSystem);
+        }
+        if self.root_dir_first_cluster < 2 || self.root_dir_first_cluster > self.cluster_count + 1 {
+            error!(
+                "Invalid exFAT FirstClusterOfRootDirectory: {}",
+                self.root_dir_first_cluster
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        Ok(())
+    }
+
+    /// Reads and validates the main boot region, including its checksum.
+    pub(crate) async fn read<S: Read + Seek>(disk: &mut S) -> Result<Self, Error<S::Error>> {
+        disk.seek(SeekFrom::Start(0)).await?;
+        let mut first = [0_u8; 512];
+        disk.read_exact(&mut first).await?;
+        let boot = Self::parse(&first);
+        boot.validate(&first)?;
+
+        let bytes_per_sector = boot.bytes_per_sector() as usize;
+        let mut region = vec![0_u8; bytes_per_sector * BOOT_REGION_SECTORS as usize];
+        disk.seek(SeekFrom::Start(0)).await?;
+        disk.read_exact(&mut region).await?;
+        let checksum_sector = &region[bytes_per_sector * 11..];
+        let expected = boot_checksum(&region[..bytes_per_sector * 11], bytes_per_sector);
+        if checksum_sector
+            .chunks_exact(4)
+            .any(|c| u32::from_le_bytes(c.try_into().unwrap()) != expected)
+        {
+            error!("Invalid exFAT boot region checksum");
+            return Err(Error::CorruptedFileSystem);
+        }
+        Ok(boot)
+    }
+}
+
+/// Computes the boot region checksum of the first 11 sectors.
+///
+/// `VolumeFlags` and `PercentInUse` are skipped so they can be updated without rewriting the checksum sector.
+pub(crate) fn boot_checksum(sectors: &[u8], bytes_per_sector: usize) -> u32 {
+    let mut checksum = 0_u32;
+    for (i, b) in sectors[..bytes_per_sector * 11].iter().enumerate() {
+        if i == 106 || i == 107 || i == PERCENT_IN_USE_OFFSET {
+            continue;
+        }
+        checksum = checksum.rotate_right(1).wrapping_add(u32::from(*b));
+    }
+    checksum
+}
+
+/// Writes a complete boot region (main or backup) starting at the current position of `disk`.
+pub(crate) async fn write_boot_region<S: Write>(
+    disk: &mut S,
+    boot: &ExFatBootSector,
+) -> Result<(), Error<S::Error>> {
+    let bytes_per_sector = boot.bytes_per_sector() as usize;
+    let mut region = vec![0_u8; bytes_per_sector * BOOT_REGION_SECTORS as usize];
+    boot.serialize(&mut region[..bytes_per_sector]);
+    // extended boot sectors only carry a signature
+    for sector in region[bytes_per_sector..bytes_per_sector * 9].chunks_exact_mut(bytes_per_sector)
+    {
+        sector[bytes_per_sector - 4..].copy_from_slice(&EXTENDED_BOOT_SIGNATURE);
+    }
+    let checksum = boot_checksum(&region, bytes_per_sector);
+    for chunk in region[bytes_per_sector * 11..].chunks_exact_mut(4) {
+        chunk.copy_from_slice(&checksum.to_le_bytes());
+    }
+    disk.write_all(&region).await?;
+    Ok(())
+}
+
+/// Updates the `VolumeFlags` field of the main boot sector in place.
+pub(crate) async fn write_volume_flags<S: Write + Seek>(
+    disk: &mut S,
+    volume_flags: u16,
+) -> Result<(), S::Error> {
+    disk.seek(SeekFrom::Start(VOLUME_FLAGS_OFFSET)).await?;
+    disk.write_u16_le(volume_flags).await?;
+    Ok(())
+}
+
+/// Reads the FAT entry of `cluster` straight from the disk, bypassing the FAT cache.
+///
+/// Used at mount time, before the `FileSystem` object exists.
+pub(crate) async fn read_fat_entry<S: Read + Seek>(
+    disk: &mut S,
+    boot: &ExFatBootSector,
+    cluster: u32,
+) -> Result<u32, Error<S::Error>> {
+    let offset = boot.bytes_from_sectors(boot.fat_offset) + u64::from(cluster) * 4;
+    disk.seek(SeekFrom::Start(offset)).await?;
+    Ok(disk.read_u32_le().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_checksum_skips_volatile_fields() {
+        let mut region = [0_u8; 512 * 11];
+        region[0] = 0xEB;
+        region[200] = 0x12;
+        let checksum = boot_checksum(&region, 512);
+        region[106] = 0x02;
+        region[107] = 0xFF;
+        region[112] = 50;
+        assert_eq!(boot_checksum(&region, 512), checksum);
+        region[113] = 1;
+        assert_ne!(boot_checksum(&region, 512), checksum);
+    }
+
+    #[test]
+    fn test_serialize_parse_roundtrip() {
+        let boot = ExFatBootSector {
+            volume_length: 0x10_0000,
+            fat_offset: 128,
+            fat_length: 128,
+            cluster_heap_offset: 256,
+            cluster_count: 1000,
+            root_dir_first_cluster: 4,
+            volume_serial_number: 0x1234_5678,
+            file_system_revision: 0x0100,
+            bytes_per_sector_shift: 9,
+            sectors_per_cluster_shift: 3,
+            number_of_fats: 1,
+            drive_select: 0x80,
+            percent_in_use: 0xFF,
+            ..ExFatBootSector::default()
+        };
+        let mut sector = [0_u8; 512];
+        boot.serialize(&mut sector);
+        assert!(ExFatBootSector::is_exfat(&sector));
+        let parsed = ExFatBootSector::parse(&sector);
+        assert_eq!(parsed.cluster_count, 1000);
+        assert_eq!(parsed.cluster_size(), 4096);
+        assert!(
+            parsed
+                .validate::<core::convert::Infallible>(&sector)
+                .is_ok()
+        );
+    }
+}
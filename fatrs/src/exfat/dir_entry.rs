@@ -0,0 +1,312 @@
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+use super::UpcaseTable;
+
+pub(crate) const DIR_ENTRY_LEN: usize = 32;
+
+/// Entry type marking the end of the directory (no further entries are in use).
+pub(crate) const ENTRY_TYPE_END: u8 = 0x00;
+/// Set in the type of every entry that is in use.
+pub(crate) const ENTRY_TYPE_IN_USE: u8 = 0x80;
+pub(crate) const ENTRY_TYPE_BITMAP: u8 = 0x81;
+pub(crate) const ENTRY_TYPE_UPCASE: u8 = 0x82;
+pub(crate) const ENTRY_TYPE_LABEL: u8 = 0x83;
+pub(crate) const ENTRY_TYPE_FILE: u8 = 0x85;
+pub(crate) const ENTRY_TYPE_STREAM: u8 = 0xC0;
+pub(crate) const ENTRY_TYPE_NAME: u8 = 0xC1;
+
+/// `GeneralSecondaryFlags` bit: clusters have been allocated for the stream.
+pub(crate) const FLAG_ALLOCATION_POSSIBLE: u8 = 1 << 0;
+/// `GeneralSecondaryFlags` bit: the stream is contiguous and its FAT entries are not valid.
+pub(crate) const FLAG_NO_FAT_CHAIN: u8 = 1 << 1;
+
+pub(crate) const NAME_CHARS_PER_ENTRY: usize = 15;
+pub(crate) const MAX_LABEL_LEN: usize = 11;
+
+/// A File directory entry together with its secondary entries (Stream Extension and File Name entries).
+///
+/// exFAT stores all metadata of a file in such a set. The raw entries are kept so that unknown benign secondary
+/// entries survive a rewrite.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EntrySet {
+    entries: Vec<[u8; DIR_ENTRY_LEN]>,
+    /// Absolute position of each entry on the disk
+    positions: Vec<u64>,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for EntrySet {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "EntrySet {{ entries: {}, first_pos: {} }}",
+            self.entries.len(),
+            self.positions.first()
+        );
+    }
+}
+
+fn u16_at(entry: &[u8; DIR_ENTRY_LEN], i: usize) -> u16 {
+    u16::from_le_bytes([entry[i], entry[i + 1]])
+}
+
+fn u32_at(entry: &[u8; DIR_ENTRY_LEN], i: usize) -> u32 {
+    u32::from_le_bytes(entry[i..i + 4].try_into().unwrap())
+}
+
+fn u64_at(entry: &[u8; DIR_ENTRY_LEN], i: usize) -> u64 {
+    u64::from_le_bytes(entry[i..i + 8].try_into().unwrap())
+}
+
+impl EntrySet {
+    /// Creates a new entry set for `name`. Positions are assigned when the set is written.
+    pub(crate) fn new(name: &[u16], attributes: u16, upcase: &UpcaseTable) -> Self {
+        let name_entries = name.len().div_ceil(NAME_CHARS_PER_ENTRY);
+        let mut entries = Vec::with_capacity(2 + name_entries);
+
+        let mut file = [0_u8; DIR_ENTRY_LEN];
+        file[0] = ENTRY_TYPE_FILE;
+        file[1] = (1 + name_entries) as u8;
+        file[4..6].copy_from_slice(&attributes.to_le_bytes());
+        entries.push(file);
+
+        let mut stream = [0_u8; DIR_ENTRY_LEN];
+        stream[0] = ENTRY_TYPE_STREAM;
+        stream[1] = FLAG_ALLOCATION_POSSIBLE;
+        stream[3] = name.len() as u8;
+        stream[4..6].copy_from_slice(&upcase.name_hash(name).to_le_bytes());
+        entries.push(stream);
+
+        for chunk in name.chunks(NAME_CHARS_PER_ENTRY) {
+            let mut entry = [0_u8; DIR_ENTRY_LEN];
+            entry[0] = ENTRY_TYPE_NAME;
+            for (i, unit) in chunk.iter().enumerate() {
+                entry[2 + i * 2..4 + i * 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            entries.push(entry);
+        }
+
+        let mut set = Self {
+            entries,
+            positions: Vec::new(),
+        };
+        set.update_checksum();
+        set
+    }
+
+    /// Returns a copy of this set carrying another name. Benign secondary entries are dropped.
+    pub(crate) fn renamed(&self, name: &[u16], upcase: &UpcaseTable) -> Self {
+        let mut set = Self::new(name, self.attributes(), upcase);
+        // Keep attributes and timestamps of the File entry
+        set.entries[0][4..].copy_from_slice(&self.entries[0][4..]);
+        // Keep flags and location of the stream, take name length and hash of the new name
+        let mut stream = self.entries[1];
+        stream[3..6].copy_from_slice(&set.entries[1][3..6]);
+        set.entries[1] = stream;
+        set.update_checksum();
+        set
+    }
+
+    /// Builds a set from raw entries read from a directory. Returns `None` if the set is malformed.
+    pub(crate) fn from_entries(
+        entries: Vec<[u8; DIR_ENTRY_LEN]>,
+        positions: Vec<u64>,
+    ) -> Option<Self> {
+        let set = Self { entries, positions };
+        let valid = set.entries.len() >= 3
+            && set.entries.len() == usize::from(set.entries[0][1]) + 1
+            && set.entries[1][0] == ENTRY_TYPE_STREAM
+            && set.entries[2][0] == ENTRY_TYPE_NAME
+            && u16_at(&set.entries[0], 2) == set.checksum();
+        valid.then_some(set)
+    }
+
+    /// Number of secondary entries stored in the File entry
+    pub(crate) fn secondary_count(first_entry: &[u8; DIR_ENTRY_LEN]) -> usize {
+        usize::from(first_entry[1])
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn entries(&self) -> &[[u8; DIR_ENTRY_LEN]] {
+        &self.entries
+    }
+
+    pub(crate) fn positions(&self) -> &[u64] {
+        &self.positions
+    }
+
+    pub(crate) fn set_positions(&mut self, positions: Vec<u64>) {
+        debug_assert_eq!(positions.len(), self.entries.len());
+        self.positions = positions;
+    }
+
+    /// Computes the `SetChecksum` over all entries (skipping the checksum field itself).
+    fn checksum(&self) -> u16 {
+        let mut checksum = 0_u16;
+        for (n, entry) in self.entries.iter().enumerate() {
+            for (i, b) in entry.iter().enumerate() {
+                if n == 0 && (i == 2 || i == 3) {
+                    continue;
+                }
+                checksum = checksum.rotate_right(1).wrapping_add(u16::from(*b));
+            }
+        }
+        checksum
+    }
+
+    pub(crate) fn update_checksum(&mut self) {
+        let checksum = self.checksum();
+        self.entries[0][2..4].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    pub(crate) fn name(&self) -> Vec<u16> {
+        let name_len = usize::from(self.entries[1][3]);
+        self.entries[2..]
+            .iter()
+            .filter(|e| e[0] == ENTRY_TYPE_NAME)
+            .flat_map(|e| (0..NAME_CHARS_PER_ENTRY).map(move |i| u16_at(e, 2 + i * 2)))
+            .take(name_len)
+            .collect()
+    }
+
+    pub(crate) fn attributes(&self) -> u16 {
+        u16_at(&self.entries[0], 4)
+    }
+
+    pub(crate) fn set_attributes(&mut self, attributes: u16) {
+        self.entries[0][4..6].copy_from_slice(&attributes.to_le_bytes());
+    }
+
+    /// Returns a timestamp (`date << 16 | time` in FAT encoding) and its 10 ms increment.
+    ///
+    /// `index` selects the `Create` (0), `LastModified` (1) or `LastAccessed` (2) timestamp.
+    pub(crate) fn timestamp(&self, index: usize) -> (u32, u8) {
+        let increment = if index < 2 {
+            self.entries[0][20 + index]
+        } else {
+            0
+        };
+        (u32_at(&self.entries[0], 8 + index * 4), increment)
+    }
+
+    pub(crate) fn set_timestamp(&mut self, index: usize, timestamp: u32, increment: u8) {
+        self.entries[0][8 + index * 4..12 + index * 4].copy_from_slice(&timestamp.to_le_bytes());
+        if index < 2 {
+            self.entries[0][20 + index] = increment;
+        }
+        // UTC offset is not known
+        self.entries[0][22 + index] = 0;
+    }
+
+    pub(crate) fn flags(&self) -> u8 {
+        self.entries[1][1]
+    }
+
+    pub(crate) fn set_flags(&mut self, flags: u8) {
+        self.entries[1][1] = flags;
+    }
+
+    pub(crate) fn first_cluster(&self) -> u32 {
+        u32_at(&self.entries[1], 20)
+    }
+
+    pub(crate) fn set_first_cluster(&mut self, cluster: u32) {
+        self.entries[1][20..24].copy_from_slice(&cluster.to_le_bytes());
+    }
+
+    pub(crate) fn valid_data_length(&self) -> u64 {
+        u64_at(&self.entries[1], 8)
+    }
+
+    pub(crate) fn set_valid_data_length(&mut self, len: u64) {
+        self.entries[1][8..16].copy_from_slice(&len.to_le_bytes());
+    }
+
+    pub(crate) fn data_length(&self) -> u64 {
+        u64_at(&self.entries[1], 24)
+    }
+
+    pub(crate) fn set_data_length(&mut self, len: u64) {
+        self.entries[1][24..32].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+/// Returns the first cluster and data length of an allocation bitmap or up-case table entry.
+pub(crate) fn critical_entry_location(entry: &[u8; DIR_ENTRY_LEN]) -> (u32, u64) {
+    (u32_at(entry, 20), u64_at(entry, 24))
+}
+
+/// Returns the `TableChecksum` field of an up-case table entry.
+pub(crate) fn upcase_entry_checksum(entry: &[u8; DIR_ENTRY_LEN]) -> u32 {
+    u32_at(entry, 4)
+}
+
+/// Decodes the label stored in a Volume Label entry.
+pub(crate) fn label_from_entry(entry: &[u8; DIR_ENTRY_LEN]) -> Vec<u16> {
+    let len = usize::from(entry[1]).min(MAX_LABEL_LEN);
+    (0..len).map(|i| u16_at(entry, 2 + i * 2)).collect()
+}
+
+/// Encodes a Volume Label entry.
+pub(crate) fn label_entry(label: &[u16]) -> [u8; DIR_ENTRY_LEN] {
+    let mut entry = [0_u8; DIR_ENTRY_LEN];
+    entry[0] = ENTRY_TYPE_LABEL;
+    let len = label.len().min(MAX_LABEL_LEN);
+    entry[1] = len as u8;
+    for (i, unit) in label[..len].iter().enumerate() {
+        entry[2 + i * 2..4 + i * 2].copy_from_slice(&unit.to_le_bytes());
+    }
+    entry
+}
+
+/// Encodes an Allocation Bitmap or Up-case Table entry.
+pub(crate) fn critical_entry(
+    entry_type: u8,
+    first_cluster: u32,
+    len: u64,
+    checksum: u32,
+) -> [u8; DIR_ENTRY_LEN] {
+    let mut entry = [0_u8; DIR_ENTRY_LEN];
+    entry[0] = entry_type;
+    if entry_type == ENTRY_TYPE_UPCASE {
+        entry[4..8].copy_from_slice(&checksum.to_le_bytes());
+    }
+    entry[20..24].copy_from_slice(&first_cluster.to_le_bytes());
+    entry[24..32].copy_from_slice(&len.to_le_bytes());
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_set_roundtrip() {
+        let upcase = UpcaseTable::generate();
+        let name: Vec<u16> = "a rather long file name.txt".encode_utf16().collect();
+        let mut set = EntrySet::new(&name, 0x20, &upcase);
+        assert_eq!(set.len(), 4);
+        set.set_first_cluster(7);
+        set.set_data_length(5_000_000_000);
+        set.set_valid_data_length(5_000_000_000);
+        set.update_checksum();
+        let parsed = EntrySet::from_entries(set.entries.clone(), Vec::new()).unwrap();
+        assert_eq!(parsed.name(), name);
+        assert_eq!(parsed.first_cluster(), 7);
+        assert_eq!(parsed.data_length(), 5_000_000_000);
+        assert_eq!(parsed.attributes(), 0x20);
+    }
+
+    #[test]
+    fn test_entry_set_checksum_mismatch() {
+        let upcase = UpcaseTable::generate();
+        let name: Vec<u16> = "x".encode_utf16().collect();
+        let mut set = EntrySet::new(&name, 0, &upcase);
+        set.set_data_length(1);
+        assert!(EntrySet::from_entries(set.entries, Vec::new()).is_none());
+    }
+}
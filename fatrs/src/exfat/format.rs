@@ -0,0 +1,218 @@
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+use crate::boot_sector::determine_bytes_per_cluster;
+use crate::dir_entry::SFN_PADDING;
+use crate::error::Error;
+use crate::fs::{DiskSlice, FatType, FormatVolumeOptions, ReadWriteSeek, write_zeros};
+use crate::io::SeekFrom;
+use crate::table::{FatValue, RESERVED_FAT_ENTRIES, format_fat, write_fat};
+
+use super::boot_sector::{BOOT_REGION_SECTORS, ExFatBootSector, write_boot_region};
+use super::dir_entry::{
+    DIR_ENTRY_LEN, ENTRY_TYPE_BITMAP, ENTRY_TYPE_UPCASE, critical_entry, label_entry,
+};
+use super::upcase::{UpcaseTable, table_checksum};
+
+const MIN_VOLUME_BYTES: u64 = 1024 * 1024;
+const MAX_CLUSTER_SIZE: u32 = 32 * 1024 * 1024;
+const DEFAULT_VOLUME_ID: u32 = 0x1234_5678;
+
+/// Creates an exFAT file system. Called by `format_volume` when `FatType::ExFat` is requested.
+///
+/// The layout follows common formatters: a single FAT aligned to the cluster size, followed by the cluster heap
+/// holding the allocation bitmap, the up-case table and a one-cluster root directory.
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn format_volume<S: ReadWriteSeek>(
+    storage: &mut S,
+    options: &FormatVolumeOptions,
+) -> Result<(), Error<S::Error>>
+where
+    S::Error: 'static,
+{
+    trace!("exfat::format_volume");
+    let bytes_per_sector = options.bytes_per_sector.unwrap_or(512);
+    if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+        error!("Invalid bytes per sector for exFAT: {}", bytes_per_sector);
+        return Err(Error::InvalidInput);
+    }
+    let total_sectors = if let Some(total_sectors) = options.total_sectors {
+        u64::from(total_sectors)
+    } else {
+        let total_bytes = storage.seek(SeekFrom::End(0)).await?;
+        storage.seek(SeekFrom::Start(0)).await?;
+        total_bytes / u64::from(bytes_per_sector)
+    };
+    let total_bytes = total_sectors * u64::from(bytes_per_sector);
+    if total_bytes < MIN_VOLUME_BYTES {
+        error!("Volume is too small for exFAT: {} bytes", total_bytes);
+        return Err(Error::InvalidInput);
+    }
+
+    let bytes_per_cluster = options.bytes_per_cluster.unwrap_or_else(|| {
+        determine_bytes_per_cluster(total_bytes, bytes_per_sector, Some(FatType::ExFat))
+    });
+    if !bytes_per_cluster.is_power_of_two()
+        || bytes_per_cluster < u32::from(bytes_per_sector)
+        || bytes_per_cluster > MAX_CLUSTER_SIZE
+    {
+        error!("Invalid cluster size for exFAT: {}", bytes_per_cluster);
+        return Err(Error::InvalidInput);
+    }
+    let bytes_per_sector_shift = bytes_per_sector.trailing_zeros() as u8;
+    let sectors_per_cluster_shift =
+        (bytes_per_cluster / u32::from(bytes_per_sector)).trailing_zeros() as u8;
+    let sectors_per_cluster = 1_u64 << sectors_per_cluster_shift;
+    let number_of_fats = options.fats.unwrap_or(1);
+
+    // Layout: boot regions, FAT(s) and cluster heap, each aligned to the cluster size
+    let round_up = |n: u64| n.div_ceil(sectors_per_cluster) * sectors_per_cluster;
+    let fat_offset = round_up(u64::from(2 * BOOT_REGION_SECTORS));
+    let mut cluster_count = (total_sectors - fat_offset) >> sectors_per_cluster_shift;
+    let (fat_length, cluster_heap_offset) = loop {
+        let fat_length = ((cluster_count + 2) * 4).div_ceil(u64::from(bytes_per_sector));
+        let heap_offset = round_up(fat_offset + fat_length * u64::from(number_of_fats));
+        let new_count = total_sectors.saturating_sub(heap_offset) >> sectors_per_cluster_shift;
+        if new_count == cluster_count {
+            break (fat_length, heap_offset);
+        }
+        cluster_count = new_count;
+    };
+    if cluster_count == 0 || cluster_count > u64::from(FatType::ExFat.max_clusters()) {
+        error!("Invalid number of clusters for exFAT: {}", cluster_count);
+        return Err(Error::InvalidInput);
+    }
+    let (Ok(fat_offset), Ok(fat_length), Ok(cluster_heap_offset)) = (
+        u32::try_from(fat_offset),
+        u32::try_from(fat_length),
+        u32::try_from(cluster_heap_offset),
+    ) else {
+        return Err(Error::InvalidInput);
+    };
+    let cluster_count = cluster_count as u32;
+
+    // Contents of the cluster heap
+    let upcase = UpcaseTable::generate().to_bytes();
+    let bitmap_len = u64::from(cluster_count).div_ceil(8);
+    let clusters_for = |len: u64| len.div_ceil(u64::from(bytes_per_cluster)) as u32;
+    let bitmap_cluster = RESERVED_FAT_ENTRIES;
+    let upcase_cluster = bitmap_cluster + clusters_for(bitmap_len);
+    let root_cluster = upcase_cluster + clusters_for(upcase.len() as u64);
+    let used_clusters = root_cluster + 1 - RESERVED_FAT_ENTRIES;
+    if used_clusters > cluster_count {
+        error!("Volume is too small for exFAT metadata");
+        return Err(Error::InvalidInput);
+    }
+
+    let boot = ExFatBootSector {
+        partition_offset: u64::from(options.hidden_sectors.unwrap_or(0)),
+        volume_length: total_sectors,
+        fat_offset,
+        fat_length,
+        cluster_heap_offset,
+        cluster_count,
+        root_dir_first_cluster: root_cluster,
+        volume_serial_number: options.volume_id.unwrap_or(DEFAULT_VOLUME_ID),
+        file_system_revision: 0x0100,
+        volume_flags: 0,
+        bytes_per_sector_shift,
+        sectors_per_cluster_shift,
+        number_of_fats,
+        drive_select: options.drive_num.unwrap_or(0x80),
+        percent_in_use: (u64::from(used_clusters) * 100 / u64::from(cluster_count)) as u8,
+    };
+    let sector_bytes = |sectors: u32| boot.bytes_from_sectors(sectors);
+    let cluster_pos = |cluster: u32| {
+        sector_bytes(cluster_heap_offset)
+            + (u64::from(cluster - RESERVED_FAT_ENTRIES) * u64::from(bytes_per_cluster))
+    };
+
+    // Boot regions (main and backup), then zero everything up to the cluster heap
+    storage.seek(SeekFrom::Start(0)).await?;
+    write_boot_region(storage, &boot).await?;
+    write_boot_region(storage, &boot).await?;
+    write_zeros(
+        storage,
+        sector_bytes(cluster_heap_offset - 2 * BOOT_REGION_SECTORS),
+    )
+    .await?;
+
+    // FAT: reserved entries and chains of the system structures
+    {
+        let fat_pos = sector_bytes(fat_offset);
+        let bytes_per_fat = sector_bytes(fat_length);
+        storage.seek(SeekFrom::Start(fat_pos)).await?;
+        let mut fat = DiskSlice::<&mut S, S>::new(fat_pos, bytes_per_fat, number_of_fats, storage);
+        format_fat(&mut fat, FatType::ExFat, 0xF8, bytes_per_fat, cluster_count).await?;
+        for (first, last) in [
+            (bitmap_cluster, upcase_cluster - 1),
+            (upcase_cluster, root_cluster - 1),
+            (root_cluster, root_cluster),
+        ] {
+            for cluster in first..last {
+                write_fat(
+                    &mut fat,
+                    FatType::ExFat,
+                    cluster,
+                    FatValue::Data(cluster + 1),
+                )
+                .await?;
+            }
+            write_fat(&mut fat, FatType::ExFat, last, FatValue::EndOfChain).await?;
+        }
+    }
+
+    // Allocation bitmap with the system clusters marked as used
+    let mut bitmap = vec![0_u8; (bitmap_len as usize).next_multiple_of(bytes_per_cluster as usize)];
+    for index in 0..used_clusters as usize {
+        bitmap[index / 8] |= 1 << (index % 8);
+    }
+    storage
+        .seek(SeekFrom::Start(cluster_pos(bitmap_cluster)))
+        .await?;
+    storage.write_all(&bitmap).await?;
+
+    // Up-case table
+    storage
+        .seek(SeekFrom::Start(cluster_pos(upcase_cluster)))
+        .await?;
+    storage.write_all(&upcase).await?;
+
+    // Root directory
+    let mut root = vec![0_u8; bytes_per_cluster as usize];
+    let mut entries: Vec<[u8; DIR_ENTRY_LEN]> = Vec::new();
+    if let Some(volume_label) = options.volume_label {
+        let len = volume_label
+            .iter()
+            .rposition(|b| *b != SFN_PADDING)
+            .map_or(0, |p| p + 1);
+        let label: Vec<u16> = volume_label[..len].iter().map(|b| u16::from(*b)).collect();
+        entries.push(label_entry(&label));
+    }
+    entries.push(critical_entry(
+        ENTRY_TYPE_BITMAP,
+        bitmap_cluster,
+        bitmap_len,
+        0,
+    ));
+    entries.push(critical_entry(
+        ENTRY_TYPE_UPCASE,
+        upcase_cluster,
+        upcase.len() as u64,
+        table_checksum(&upcase),
+    ));
+    for (i, entry) in entries.iter().enumerate() {
+        root[i * DIR_ENTRY_LEN..(i + 1) * DIR_ENTRY_LEN].copy_from_slice(entry);
+    }
+    storage
+        .seek(SeekFrom::Start(cluster_pos(root_cluster)))
+        .await?;
+    storage.write_all(&root).await?;
+
+    storage.flush().await?;
+    storage.seek(SeekFrom::Start(0)).await?;
+    trace!("exfat::format_volume end");
+    Ok(())
+}
@@ -0,0 +1,678 @@
+//! exFAT support.
+//!
+//! exFAT keeps FAT-style cluster chains but tracks free space in an allocation bitmap, stores names as UTF-16
+//! directory entry sets compared through an on-disk up-case table and uses 64-bit file sizes. Volumes are mounted
+//! through the regular `FileSystem` type, which dispatches to the helpers in this module when the boot sector
+//! carries the exFAT signature.
+//!
+//! Based on the Microsoft exFAT File System Specification.
+
+mod boot_sector;
+mod dir_entry;
+mod format;
+mod upcase;
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+use core::cmp;
+
+use crate::boot_sector::BiosParameterBlock;
+use crate::dir_entry::SFN_PADDING;
+use crate::error::Error;
+use crate::fs::FsStatusFlags;
+use crate::io::{Read, Seek, SeekFrom, Write};
+use crate::table::RESERVED_FAT_ENTRIES;
+
+pub(crate) use boot_sector::{
+    ExFatBootSector, VOLUME_FLAG_DIRTY, VOLUME_FLAG_MEDIA_FAILURE, write_volume_flags,
+};
+pub(crate) use dir_entry::{
+    DIR_ENTRY_LEN, ENTRY_TYPE_END, ENTRY_TYPE_FILE, ENTRY_TYPE_IN_USE, ENTRY_TYPE_LABEL, EntrySet,
+    FLAG_NO_FAT_CHAIN, MAX_LABEL_LEN, label_entry, label_from_entry,
+};
+pub(crate) use format::format_volume;
+pub(crate) use upcase::UpcaseTable;
+
+use boot_sector::{VOLUME_FLAG_ACTIVE_FAT, read_fat_entry};
+use dir_entry::{
+    ENTRY_TYPE_BITMAP, ENTRY_TYPE_UPCASE, critical_entry_location, upcase_entry_checksum,
+};
+
+/// Size of the buffer used when scanning the allocation bitmap.
+const BITMAP_CHUNK_LEN: usize = 512;
+
+/// State of a mounted exFAT volume that has no FAT equivalent.
+#[derive(Debug)]
+pub(crate) struct ExFatVolume {
+    pub(crate) boot: ExFatBootSector,
+    /// Clusters holding the allocation bitmap, in order
+    bitmap_clusters: Vec<u32>,
+    pub(crate) upcase: UpcaseTable,
+    /// Volume label from the root directory (UTF-16)
+    pub(crate) label: Vec<u16>,
+}
+
+impl ExFatVolume {
+    /// Checks the boot sector signature and mounts the volume if it is exFAT.
+    ///
+    /// The storage is seeked back to the start so that a FAT boot sector can be read if `None` is returned.
+    pub(crate) async fn probe<S: Read + Seek>(
+        disk: &mut S,
+    ) -> Result<Option<Self>, Error<S::Error>> {
+        let mut sector = [0_u8; 512];
+        disk.read_exact(&mut sector).await?;
+        disk.seek(SeekFrom::Start(0)).await?;
+        if !ExFatBootSector::is_exfat(&sector) {
+            return Ok(None);
+        }
+        let volume = Self::mount(disk).await?;
+        disk.seek(SeekFrom::Start(0)).await?;
+        Ok(Some(volume))
+    }
+
+    async fn mount<S: Read + Seek>(disk: &mut S) -> Result<Self, Error<S::Error>> {
+        trace!("ExFatVolume::mount");
+        let boot = ExFatBootSector::read(disk).await?;
+        let mut volume = Self {
+            boot,
+            bitmap_clusters: Vec::new(),
+            upcase: UpcaseTable::default(),
+            label: Vec::new(),
+        };
+
+        // Scan the root directory for the critical primary entries
+        let mut bitmap = None;
+        let mut upcase = None;
+        let entries_per_cluster = volume.boot.cluster_size() as usize / DIR_ENTRY_LEN;
+        let mut cluster = Some(volume.boot.root_dir_first_cluster);
+        let mut visited = 0;
+        'root: while let Some(current) = cluster {
+            disk.seek(SeekFrom::Start(volume.offset_from_cluster(current)))
+                .await?;
+            for _ in 0..entries_per_cluster {
+                let mut entry = [0_u8; DIR_ENTRY_LEN];
+                disk.read_exact(&mut entry).await?;
+                match entry[0] {
+                    ENTRY_TYPE_END => break 'root,
+                    ENTRY_TYPE_BITMAP if bitmap.is_none() => bitmap = Some(entry),
+                    ENTRY_TYPE_UPCASE => upcase = Some(entry),
+                    ENTRY_TYPE_LABEL => volume.label = label_from_entry(&entry),
+                    _ => {}
+                }
+            }
+            visited += 1;
+            cluster = volume.next_cluster_raw(disk, current).await?;
+            if visited > volume.boot.cluster_count {
+                error!("exFAT root directory cluster chain contains a loop");
+                return Err(Error::CorruptedFileSystem);
+            }
+        }
+
+        let Some(bitmap) = bitmap else {
+            error!("exFAT allocation bitmap entry not found");
+            return Err(Error::CorruptedFileSystem);
+        };
+        let (bitmap_cluster, bitmap_len) = critical_entry_location(&bitmap);
+        if bitmap_len < u64::from(volume.boot.cluster_count).div_ceil(8) {
+            error!("exFAT allocation bitmap is too small: {} bytes", bitmap_len);
+            return Err(Error::CorruptedFileSystem);
+        }
+        volume.bitmap_clusters = volume.read_chain(disk, bitmap_cluster, bitmap_len).await?;
+
+        let Some(upcase) = upcase else {
+            error!("exFAT up-case table entry not found");
+            return Err(Error::CorruptedFileSystem);
+        };
+        let (upcase_cluster, upcase_len) = critical_entry_location(&upcase);
+        let mut data = Vec::new();
+        for c in volume.read_chain(disk, upcase_cluster, upcase_len).await? {
+            let start = data.len();
+            let len = cmp::min(
+                u64::from(volume.boot.cluster_size()),
+                upcase_len - start as u64,
+            ) as usize;
+            data.resize(start + len, 0);
+            disk.seek(SeekFrom::Start(volume.offset_from_cluster(c)))
+                .await?;
+            disk.read_exact(&mut data[start..]).await?;
+        }
+        if upcase::table_checksum(&data) != upcase_entry_checksum(&upcase) {
+            error!("exFAT up-case table checksum mismatch");
+            return Err(Error::CorruptedFileSystem);
+        }
+        volume.upcase = UpcaseTable::from_bytes(&data);
+        trace!(
+            "exFAT volume mounted: {} clusters of {} bytes",
+            volume.boot.cluster_count,
+            volume.boot.cluster_size()
+        );
+        Ok(volume)
+    }
+
+    /// Follows a FAT chain directly on the disk.
+    async fn next_cluster_raw<S: Read + Seek>(
+        &self,
+        disk: &mut S,
+        cluster: u32,
+    ) -> Result<Option<u32>, Error<S::Error>> {
+        let next = read_fat_entry(disk, &self.boot, cluster).await?;
+        Ok(self.is_valid_cluster(next).then_some(next))
+    }
+
+    /// Collects the clusters of a FAT chain long enough to hold `len` bytes.
+    async fn read_chain<S: Read + Seek>(
+        &self,
+        disk: &mut S,
+        first_cluster: u32,
+        len: u64,
+    ) -> Result<Vec<u32>, Error<S::Error>> {
+        let count = len.div_ceil(u64::from(self.boot.cluster_size()));
+        let mut clusters = Vec::new();
+        let mut cluster = Some(first_cluster).filter(|c| self.is_valid_cluster(*c));
+        while (clusters.len() as u64) < count {
+            let Some(current) = cluster else {
+                error!(
+                    "exFAT cluster chain starting at {} is too short",
+                    first_cluster
+                );
+                return Err(Error::CorruptedFileSystem);
+            };
+            clusters.push(current);
+            cluster = self.next_cluster_raw(disk, current).await?;
+        }
+        Ok(clusters)
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (RESERVED_FAT_ENTRIES..self.boot.cluster_count + RESERVED_FAT_ENTRIES).contains(&cluster)
+    }
+
+    /// Returns a BPB carrying the fields shared with FAT volumes (volume ID, label, status flags and sector size).
+    pub(crate) fn bpb(&self) -> BiosParameterBlock {
+        let mut volume_label = [SFN_PADDING; 11];
+        for (dst, unit) in volume_label.iter_mut().zip(&self.label) {
+            *dst = u8::try_from(*unit)
+                .ok()
+                .filter(u8::is_ascii)
+                .unwrap_or(b'?');
+        }
+        let flags = self.boot.volume_flags;
+        let status_flags = FsStatusFlags {
+            dirty: flags & VOLUME_FLAG_DIRTY != 0,
+            io_error: flags & VOLUME_FLAG_MEDIA_FAILURE != 0,
+        };
+        BiosParameterBlock {
+            bytes_per_sector: self.boot.bytes_per_sector() as u16,
+            reserved_1: status_flags.encode(),
+            volume_id: self.boot.volume_serial_number,
+            volume_label,
+            ..BiosParameterBlock::default()
+        }
+    }
+
+    pub(crate) fn cluster_size(&self) -> u32 {
+        self.boot.cluster_size()
+    }
+
+    pub(crate) fn offset_from_cluster(&self, cluster: u32) -> u64 {
+        debug_assert!(cluster >= RESERVED_FAT_ENTRIES);
+        self.boot.bytes_from_sectors(self.boot.cluster_heap_offset)
+            + (u64::from(cluster - RESERVED_FAT_ENTRIES) << self.cluster_shift())
+    }
+
+    pub(crate) fn bytes_from_clusters(&self, clusters: u32) -> u64 {
+        u64::from(clusters) << self.cluster_shift()
+    }
+
+    pub(crate) fn clusters_from_bytes(&self, bytes: u64) -> u32 {
+        bytes.div_ceil(u64::from(self.cluster_size())) as u32
+    }
+
+    fn cluster_shift(&self) -> u8 {
+        self.boot.bytes_per_sector_shift + self.boot.sectors_per_cluster_shift
+    }
+
+    /// Returns the position and size of the active FAT.
+    pub(crate) fn fat_range(&self) -> (u64, u64) {
+        let active_fat = if self.boot.number_of_fats == 2
+            && self.boot.volume_flags & VOLUME_FLAG_ACTIVE_FAT != 0
+        {
+            1
+        } else {
+            0
+        };
+        let fat_sector = self.boot.fat_offset + active_fat * self.boot.fat_length;
+        (
+            self.boot.bytes_from_sectors(fat_sector),
+            self.boot.bytes_from_sectors(self.boot.fat_length),
+        )
+    }
+
+    /// Returns the disk position of the bitmap byte holding bit `index` and the number of bitmap bytes
+    /// that follow it contiguously on the disk.
+    fn bitmap_pos(&self, index: u32) -> (u64, usize) {
+        let cluster_size = u64::from(self.cluster_size());
+        let byte = u64::from(index / 8);
+        let cluster = self.bitmap_clusters[(byte / cluster_size) as usize];
+        let offset_in_cluster = byte % cluster_size;
+        (
+            self.offset_from_cluster(cluster) + offset_in_cluster,
+            (cluster_size - offset_in_cluster) as usize,
+        )
+    }
+
+    /// Marks `count` clusters starting at `first_cluster` as allocated or free in the allocation bitmap.
+    pub(crate) async fn set_bitmap_range<S: Read + Write + Seek>(
+        &self,
+        disk: &mut S,
+        first_cluster: u32,
+        count: u32,
+        allocated: bool,
+    ) -> Result<(), Error<S::Error>> {
+        let mut index = first_cluster - RESERVED_FAT_ENTRIES;
+        let end = index + count;
+        while index < end {
+            let (pos, _) = self.bitmap_pos(index);
+            disk.seek(SeekFrom::Start(pos)).await?;
+            let mut byte = [0_u8];
+            disk.read_exact(&mut byte).await?;
+            let byte_end = cmp::min(end, (index / 8 + 1) * 8);
+            for bit in index..byte_end {
+                if allocated {
+                    byte[0] |= 1 << (bit % 8);
+                } else {
+                    byte[0] &= !(1 << (bit % 8));
+                }
+            }
+            disk.seek(SeekFrom::Start(pos)).await?;
+            disk.write_all(&byte).await?;
+            index = byte_end;
+        }
+        Ok(())
+    }
+
+    /// Finds the first free cluster, starting the search at `hint` and wrapping around.
+    pub(crate) async fn find_free_cluster<S: Read + Seek>(
+        &self,
+        disk: &mut S,
+        hint: Option<u32>,
+    ) -> Result<Option<u32>, Error<S::Error>> {
+        let total = self.boot.cluster_count;
+        let start = hint
+            .filter(|c| self.is_valid_cluster(*c))
+            .map_or(0, |c| c - RESERVED_FAT_ENTRIES);
+        if let Some(index) = self.scan_bitmap(disk, start, total, false).await? {
+            return Ok(Some(index + RESERVED_FAT_ENTRIES));
+        }
+        let wrapped = self.scan_bitmap(disk, 0, start, false).await?;
+        Ok(wrapped.map(|index| index + RESERVED_FAT_ENTRIES))
+    }
+
+    /// Counts clusters marked as free in the allocation bitmap.
+    pub(crate) async fn count_free_clusters<S: Read + Seek>(
+        &self,
+        disk: &mut S,
+    ) -> Result<u32, Error<S::Error>> {
+        let mut free = 0;
+        let mut index = 0;
+        let total = self.boot.cluster_count;
+        let mut buf = [0_u8; BITMAP_CHUNK_LEN];
+        while index < total {
+            let (pos, contiguous) = self.bitmap_pos(index);
+            let len = cmp::min(
+                cmp::min(contiguous, BITMAP_CHUNK_LEN),
+                (total - index).div_ceil(8) as usize,
+            );
+            disk.seek(SeekFrom::Start(pos)).await?;
+            disk.read_exact(&mut buf[..len]).await?;
+            for byte in &buf[..len] {
+                let bits = cmp::min(8, total - index);
+                let mask = if bits == 8 { 0xFF } else { (1_u8 << bits) - 1 };
+                free += (!byte & mask).count_ones();
+                index += bits;
+            }
+        }
+        Ok(free)
+    }
+
+    /// Returns the index of the first bit in `start..end` equal to `allocated`.
+    async fn scan_bitmap<S: Read + Seek>(
+        &self,
+        disk: &mut S,
+        start: u32,
+        end: u32,
+        allocated: bool,
+    ) -> Result<Option<u32>, Error<S::Error>> {
+        let mut buf = [0_u8; BITMAP_CHUNK_LEN];
+        let mut index = start;
+        while index < end {
+            let (pos, contiguous) = self.bitmap_pos(index);
+            let len = cmp::min(
+                cmp::min(contiguous, BITMAP_CHUNK_LEN),
+                (end.div_ceil(8) - index / 8) as usize,
+            );
+            disk.seek(SeekFrom::Start(pos)).await?;
+            disk.read_exact(&mut buf[..len]).await?;
+            for byte in &buf[..len] {
+                let skip = if allocated { 0x00 } else { 0xFF };
+                if *byte == skip {
+                    index = (index / 8 + 1) * 8;
+                    continue;
+                }
+                while index < end {
+                    if (byte >> (index % 8)) & 1 == u8::from(allocated) {
+                        return Ok(Some(index));
+                    }
+                    index += 1;
+                    if index % 8 == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FileSystem, FormatVolumeOptions, FsOptions, format_volume};
+    use crate::table::{FatValue, write_fat};
+    #[cfg(feature = "discard")]
+    use crate::test_support::TrimStorage;
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    const VOLUME_SIZE: usize = 16 * 1024 * 1024;
+    const CLUSTER_SIZE: u32 = 4096;
+
+    async fn exfat_image() -> Vec<u8> {
+        let mut image = vec![0_u8; VOLUME_SIZE];
+        let options = FormatVolumeOptions::new()
+            .fat_type(FatType::ExFat)
+            .bytes_per_cluster(CLUSTER_SIZE)
+            .volume_label(*b"EXFAT VOL  ");
+        format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+            .await
+            .unwrap();
+        image
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn test_format_and_mount() {
+        let mut image = exfat_image().await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.fat_type(), FatType::ExFat);
+        assert_eq!(fs.volume_label_as_bytes(), b"EXFAT VOL");
+        assert_eq!(
+            fs.read_volume_label_from_root_dir()
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("EXFAT VOL")
+        );
+        let stats = fs.stats().await.unwrap();
+        assert_eq!(stats.cluster_size(), CLUSTER_SIZE);
+        // allocation bitmap, up-case table and root directory are the only used clusters
+        let used = stats.total_clusters() - stats.free_clusters();
+        assert!((3..10).contains(&used));
+        assert!(fs.root_dir().iter().next().await.is_none());
+        fs.unmount().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_set_volume_label() {
+        let mut image = exfat_image().await;
+        {
+            let mut fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            fs.set_volume_label("Cämera 7").await.unwrap();
+            assert_eq!(fs.volume_label(), "C?mera 7");
+            fs.unmount().await.unwrap();
+        }
+        let mut fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(
+            fs.read_volume_label_from_root_dir()
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("C?mera 7")
+        );
+        assert_eq!(
+            fs.exfat.as_ref().unwrap().label,
+            "Cämera 7".encode_utf16().collect::<Vec<_>>()
+        );
+        fs.set_volume_label("").await.unwrap();
+        assert_eq!(fs.read_volume_label_from_root_dir().await.unwrap(), None);
+        fs.set_volume_label("NEW").await.unwrap();
+        assert_eq!(
+            fs.read_volume_label_from_root_dir()
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("NEW")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_operations() {
+        let mut image = exfat_image().await;
+        let data = pattern(3 * CLUSTER_SIZE as usize + 100);
+        {
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            let root = fs.root_dir();
+            let mut file = root
+                .create_file("A file with a rather long name.txt")
+                .await
+                .unwrap();
+            file.write_all(&data).await.unwrap();
+            file.flush().await.unwrap();
+
+            // Lookups are case-insensitive
+            let mut file = root
+                .open_file("a FILE with a rather LONG name.TXT")
+                .await
+                .unwrap();
+            file.seek(SeekFrom::Start(u64::from(CLUSTER_SIZE) + 7))
+                .await
+                .unwrap();
+            let mut buf = [0_u8; 16];
+            file.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, data[CLUSTER_SIZE as usize + 7..][..16]);
+
+            let dir = root.create_dir("sub").await.unwrap();
+            assert!(dir.is_empty().await.unwrap());
+            root.rename("A file with a rather long name.txt", &dir, "moved.bin")
+                .await
+                .unwrap();
+            assert!(
+                !root
+                    .exists("A file with a rather long name.txt")
+                    .await
+                    .unwrap()
+            );
+            let entry = dir.iter().next().await.unwrap().unwrap();
+            assert_eq!(entry.file_name(), "moved.bin");
+            assert_eq!(entry.len(), data.len() as u64);
+
+            // Fill the directory past its first cluster
+            for i in 0..40 {
+                dir.create_file(&format!("file number {i} with a long name"))
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(dir.iter().collect().await.len(), 41);
+
+            let mut file = dir.create_file("truncated").await.unwrap();
+            file.write_all(&data).await.unwrap();
+            file.seek(SeekFrom::Start(10)).await.unwrap();
+            file.truncate().await.unwrap();
+            file.flush().await.unwrap();
+            assert_eq!(dir.open_meta("truncated").await.unwrap().len(), 10);
+
+            for i in 0..40 {
+                dir.remove(&format!("file number {i} with a long name"))
+                    .await
+                    .unwrap();
+            }
+            dir.remove("truncated").await.unwrap();
+            fs.flush().await.unwrap();
+        }
+
+        // Everything is persisted on the volume
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut file = root.open_file("sub/moved.bin").await.unwrap();
+        let mut buf = vec![0_u8; data.len()];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+
+        let free_before = fs.stats().await.unwrap().free_clusters();
+        root.remove("sub/moved.bin").await.unwrap();
+        root.remove("sub").await.unwrap();
+        let stats = fs.stats().await.unwrap();
+        // data clusters of the file and the directory clusters are released
+        assert_eq!(stats.free_clusters(), free_before + 4 + 2);
+        fs.flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_contiguous_stream_and_valid_data_length() {
+        let mut image = exfat_image().await;
+        let data = pattern(2 * CLUSTER_SIZE as usize + 10);
+        let valid_len = CLUSTER_SIZE as usize + 5;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut file = root.create_file("prealloc").await.unwrap();
+        file.write_all(&data).await.unwrap();
+        file.flush().await.unwrap();
+
+        // Rewrite the entry the way other implementations store preallocated contiguous files
+        let entry = root.open_meta("prealloc").await.unwrap();
+        let mut set = entry.exfat.clone().unwrap();
+        set.set_flags(set.flags() | FLAG_NO_FAT_CHAIN);
+        set.set_valid_data_length(valid_len as u64);
+        set.update_checksum();
+        {
+            let mut disk = fs.disk.acquire().await;
+            for (entry, pos) in set.entries().iter().zip(set.positions()) {
+                disk.seek(SeekFrom::Start(*pos)).await.unwrap();
+                disk.write_all(entry).await.unwrap();
+            }
+        }
+        let first_cluster = set.first_cluster();
+        let result: Result<(), Error<std::io::Error>> = write_fat(
+            &mut fs.fat_slice(),
+            FatType::ExFat,
+            first_cluster,
+            FatValue::Free,
+        )
+        .await;
+        result.unwrap();
+
+        // Bytes past the valid data length read as zeros
+        let mut file = root.open_file("prealloc").await.unwrap();
+        let mut buf = vec![0_u8; data.len()];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf[..valid_len], data[..valid_len]);
+        assert!(buf[valid_len..].iter().all(|b| *b == 0));
+
+        // Appending writes the FAT chain and makes the whole file valid
+        file.write_all(b"tail").await.unwrap();
+        file.flush().await.unwrap();
+        let entry = root.open_meta("prealloc").await.unwrap();
+        let set = entry.exfat.as_ref().unwrap();
+        assert_eq!(set.flags() & FLAG_NO_FAT_CHAIN, 0);
+        assert_eq!(set.valid_data_length(), data.len() as u64 + 4);
+        let mut file = entry.to_file();
+        file.seek(SeekFrom::Start(2 * u64::from(CLUSTER_SIZE) + 6))
+            .await
+            .unwrap();
+        let mut tail = [0_u8; 8];
+        file.read_exact(&mut tail).await.unwrap();
+        assert_eq!(&tail, b"\0\0\0\0tail");
+        fs.flush().await.unwrap();
+    }
+
+    #[cfg(feature = "discard")]
+    #[tokio::test]
+    async fn test_discard_released_clusters() {
+        let mut image = exfat_image().await;
+        let storage = TrimStorage {
+            inner: FromTokio::new(Cursor::new(&mut image)),
+            discards: Vec::new(),
+        };
+        let mut fs = FileSystem::new(storage, FsOptions::new()).await.unwrap();
+        fs.enable_discard();
+        let root = fs.root_dir();
+        let cluster_size = u64::from(CLUSTER_SIZE);
+
+        // A contiguous stream without a FAT chain, as written by other implementations
+        let mut file = root.create_file("contiguous").await.unwrap();
+        file.write_all(&pattern(4 * CLUSTER_SIZE as usize))
+            .await
+            .unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+        let entry = root.open_meta("contiguous").await.unwrap();
+        let mut set = entry.exfat.clone().unwrap();
+        set.set_flags(set.flags() | FLAG_NO_FAT_CHAIN);
+        set.update_checksum();
+        {
+            let mut disk = fs.disk.acquire().await;
+            for (entry, pos) in set.entries().iter().zip(set.positions()) {
+                disk.seek(SeekFrom::Start(*pos)).await.unwrap();
+                disk.write_all(entry).await.unwrap();
+            }
+        }
+        let contiguous_offset = fs.offset_from_cluster(set.first_cluster());
+
+        // Interleaved writes give the file a fragmented FAT chain
+        let mut a = root.create_file("a").await.unwrap();
+        let mut b = root.create_file("b").await.unwrap();
+        for _ in 0..2 {
+            a.write_all(&pattern(CLUSTER_SIZE as usize)).await.unwrap();
+            b.write_all(&pattern(CLUSTER_SIZE as usize)).await.unwrap();
+        }
+        a.flush().await.unwrap();
+        b.flush().await.unwrap();
+        let mut fragmented = Vec::new();
+        let mut extents = a.extents();
+        while let Some(extent) = extents.next().await {
+            let extent = extent.unwrap();
+            fragmented.push((extent.offset, extent.size));
+        }
+        assert_eq!(fragmented.len(), 2);
+        drop(a);
+        drop(b);
+
+        fs.disk.acquire().await.discards.clear();
+        root.remove("contiguous").await.unwrap();
+        assert_eq!(
+            fs.disk.acquire().await.discards,
+            [(contiguous_offset, 4 * cluster_size)]
+        );
+
+        fs.disk.acquire().await.discards.clear();
+        root.remove("a").await.unwrap();
+        assert_eq!(fs.disk.acquire().await.discards, fragmented);
+        fs.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+/// Marker starting an identity run in the compressed up-case table.
+const IDENTITY_RUN_MARKER: u16 = 0xFFFF;
+/// Runs shorter than this are stored uncompressed.
+const MIN_COMPRESSED_RUN: u32 = 3;
+
+/// In-memory copy of the volume up-case table.
+///
+/// exFAT compares file names case-insensitively using a table stored on the volume instead of built-in
+/// Unicode rules. Only characters that change when up-cased are kept.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UpcaseTable {
+    mappings: Vec<(u16, u16)>,
+}
+
+impl UpcaseTable {
+    /// Decodes a (possibly compressed) up-case table read from the volume.
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        let mut mappings = Vec::new();
+        let mut units = data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]));
+        let mut index = 0_u32;
+        while let Some(unit) = units.next() {
+            if index > 0xFFFF {
+                break;
+            }
+            if unit == IDENTITY_RUN_MARKER {
+                if let Some(run) = units.next() {
+                    index += u32::from(run);
+                    continue;
+                }
+                break;
+            }
+            if u32::from(unit) != index {
+                mappings.push((index as u16, unit));
+            }
+            index += 1;
+        }
+        Self { mappings }
+    }
+
+    /// Builds the table written by `format_volume`.
+    ///
+    /// With the `unicode` feature all single-character simple up-case mappings of the Basic Multilingual Plane are
+    /// included, otherwise only ASCII letters are mapped.
+    pub(crate) fn generate() -> Self {
+        let mut mappings = Vec::new();
+        for unit in 0..=0xFFFF_u16 {
+            if let Some(upper) = Self::default_upcase(unit) {
+                if upper != unit {
+                    mappings.push((unit, upper));
+                }
+            }
+        }
+        Self { mappings }
+    }
+
+    #[cfg(feature = "unicode")]
+    fn default_upcase(unit: u16) -> Option<u16> {
+        let c = char::from_u32(u32::from(unit))?;
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) => u16::try_from(u32::from(u)).ok(),
+            _ => Some(unit),
+        }
+    }
+
+    #[cfg(not(feature = "unicode"))]
+    fn default_upcase(unit: u16) -> Option<u16> {
+        Some(if (u16::from(b'a')..=u16::from(b'z')).contains(&unit) {
+            unit - 0x20
+        } else {
+            unit
+        })
+    }
+
+    /// Encodes the table in the compressed on-disk form covering the whole 16-bit range.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut units: Vec<u16> = Vec::new();
+        let mut index = 0_u32;
+        for &(unit, upper) in &self.mappings {
+            push_identity_run(&mut units, index, u32::from(unit));
+            units.push(upper);
+            index = u32::from(unit) + 1;
+        }
+        push_identity_run(&mut units, index, 0x1_0000);
+        units.iter().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    /// Up-cases a single UTF-16 code unit.
+    pub(crate) fn upcase(&self, unit: u16) -> u16 {
+        match self.mappings.binary_search_by_key(&unit, |&(from, _)| from) {
+            Ok(i) => self.mappings[i].1,
+            Err(_) => unit,
+        }
+    }
+
+    /// Compares two names case-insensitively.
+    pub(crate) fn eq_names(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(x, y)| self.upcase(*x) == self.upcase(*y))
+    }
+
+    /// Computes the `NameHash` field of a stream extension entry.
+    pub(crate) fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash = 0_u16;
+        for unit in name {
+            for b in self.upcase(*unit).to_le_bytes() {
+                hash = hash.rotate_right(1).wrapping_add(u16::from(b));
+            }
+        }
+        hash
+    }
+}
+
+fn push_identity_run(units: &mut Vec<u16>, from: u32, to: u32) {
+    let mut start = from;
+    while start < to {
+        let run = (to - start).min(u32::from(IDENTITY_RUN_MARKER) - 1);
+        if run >= MIN_COMPRESSED_RUN {
+            units.push(IDENTITY_RUN_MARKER);
+            units.push(run as u16);
+        } else {
+            units.extend((start..start + run).map(|u| u as u16));
+        }
+        start += run;
+    }
+}
+
+/// Computes the `TableChecksum` of an up-case table as stored on the volume.
+pub(crate) fn table_checksum(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0_u32, |c, b| c.rotate_right(1).wrapping_add(u32::from(*b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_table_roundtrip() {
+        let table = UpcaseTable::generate();
+        let bytes = table.to_bytes();
+        let decoded = UpcaseTable::from_bytes(&bytes);
+        assert_eq!(decoded.mappings, table.mappings);
+        assert_eq!(decoded.upcase(u16::from(b'a')), u16::from(b'A'));
+        assert_eq!(decoded.upcase(u16::from(b'Z')), u16::from(b'Z'));
+        assert_eq!(decoded.upcase(u16::from(b'1')), u16::from(b'1'));
+    }
+
+    #[test]
+    fn test_uncompressed_table() {
+        let bytes: Vec<u8> = (0..=0x7F_u16)
+            .map(|u| {
+                if (0x61..=0x7A).contains(&u) {
+                    u - 0x20
+                } else {
+                    u
+                }
+            })
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let table = UpcaseTable::from_bytes(&bytes);
+        assert_eq!(table.mappings.len(), 26);
+        let name: Vec<u16> = "Hello.TXT".encode_utf16().collect();
+        let other: Vec<u16> = "hello.txt".encode_utf16().collect();
+        assert!(table.eq_names(&name, &other));
+        assert_eq!(table.name_hash(&name), table.name_hash(&other));
+    }
+}
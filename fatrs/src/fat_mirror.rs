@@ -0,0 +1,69 @@
+//! Deferred FAT mirror updates
+//!
+//! With `FsOptions::lazy_fat_mirroring` FAT changes are only written to the first FAT. The sectors
+//! written are recorded here and copied to the other FATs on `flush` and `unmount`, or once the number
+//! of recorded sectors reaches the configured threshold. Until then the first FAT is authoritative and
+//! the volume stays marked dirty, so `FileSystem::repair` can resync the copies after a power loss.
+//!
+//! Performance impact:
+//! - FAT-heavy workloads (allocating appends, many small files): half the FAT writes with two FATs
+//! - Memory cost: 4 bytes per recorded sector, bounded by the threshold
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+/// FAT sectors written to the first FAT only, sorted and without duplicates
+#[derive(Default)]
+pub(crate) struct DeferredMirrors {
+    sectors: Vec<u32>,
+}
+
+impl DeferredMirrors {
+    /// Records the FAT sectors `first..=last` (relative to the start of the FAT) as not mirrored yet.
+    pub(crate) fn mark(&mut self, first: u32, last: u32) {
+        for sector in first..=last {
+            if let Err(idx) = self.sectors.binary_search(&sector) {
+                self.sectors.insert(idx, sector);
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.sectors.len()
+    }
+
+    /// Returns true if any of the FAT sectors `first..=last` is not mirrored yet.
+    pub(crate) fn contains_any(&self, first: u32, last: u32) -> bool {
+        let idx = self.sectors.partition_point(|&sector| sector < first);
+        self.sectors.get(idx).is_some_and(|&sector| sector <= last)
+    }
+
+    /// Returns the recorded sectors, leaving none recorded.
+    pub(crate) fn take(&mut self) -> Vec<u32> {
+        core::mem::take(&mut self.sectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_and_take() {
+        let mut deferred = DeferredMirrors::default();
+        assert_eq!(deferred.len(), 0);
+        deferred.mark(7, 7);
+        deferred.mark(2, 3);
+        deferred.mark(3, 4);
+        assert_eq!(deferred.len(), 4);
+        assert!(deferred.contains_any(0, 2));
+        assert!(deferred.contains_any(5, 9));
+        assert!(!deferred.contains_any(5, 6));
+        assert!(!deferred.contains_any(8, 100));
+        assert_eq!(deferred.take(), [2, 3, 4, 7]);
+        assert_eq!(deferred.len(), 0);
+        assert!(!deferred.contains_any(0, 100));
+    }
+}
@@ -0,0 +1,342 @@
+//! Verification and repair of the FAT copies
+//!
+//! FAT volumes usually keep two copies of the FAT so a damaged sector in one of them can be
+//! recovered from the other. A power loss between the writes of the copies, or lazy FAT mirroring
+//! left unflushed, makes them diverge. `FileSystem::verify_fats` reports the entries that differ
+//! together with how many of them break a cluster chain in each copy, and
+//! `FileSystem::repair_fats` copies the differing sectors from the selected FAT to the others.
+//!
+//! Performance impact:
+//! - Reads every FAT copy once, sector by sector; only differing sectors are decoded
+//! - Memory cost: one sector buffer per FAT copy plus the divergent entries
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use core::cmp::Reverse;
+
+use crate::error::Error;
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::io::{Read, Seek, SeekFrom, Write};
+use crate::table::{FatValue, RESERVED_FAT_ENTRIES, read_fat, read_fat_raw};
+use crate::time::TimeProvider;
+
+/// A FAT entry holding different values in the FAT copies, found by [`FileSystem::verify_fats`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FatDivergence {
+    /// Cluster described by the entry
+    pub cluster: u32,
+    /// Raw value of the entry in every FAT copy, indexed by the copy number
+    pub values: Vec<u32>,
+}
+
+/// Result of a comparison of the FAT copies.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FatVerifyReport {
+    divergences: Vec<FatDivergence>,
+    invalid_entries: Vec<u32>,
+    source: Option<u8>,
+    /// Offsets of the FAT sectors that differ between the copies
+    sectors: Vec<u64>,
+}
+
+impl FatVerifyReport {
+    /// Entries that differ between the FAT copies, in cluster order.
+    #[must_use]
+    pub fn divergences(&self) -> &[FatDivergence] {
+        &self.divergences
+    }
+
+    /// Returns `true` if all FAT copies are identical.
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.sectors.is_empty()
+    }
+
+    /// Number of divergent entries of FAT copy `fat` that break a cluster chain: they point outside of the data
+    /// area, to themselves or to a cluster that is free in the same copy.
+    #[must_use]
+    pub fn invalid_entries(&self, fat: u8) -> u32 {
+        self.invalid_entries
+            .get(usize::from(fat))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// FAT copy written over the other copies by [`FileSystem::repair_fats`].
+    #[must_use]
+    pub fn source(&self) -> Option<u8> {
+        self.source
+    }
+
+    /// Number of entries agreeing with other copies, summed over the divergent entries of copy `fat`
+    fn agreement(&self, fat: usize) -> usize {
+        self.divergences
+            .iter()
+            .map(|d| d.values.iter().filter(|v| **v == d.values[fat]).count() - 1)
+            .sum()
+    }
+}
+
+/// How [`FileSystem::repair_fats`] selects the FAT copy written over the other ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatRepairSource {
+    /// The FAT copy with the given number, 0 being the first FAT.
+    Copy(u8),
+    /// The copy agreeing with the most other copies on the divergent entries. Ties are broken by chain validity.
+    Majority,
+    /// The copy with the fewest divergent entries breaking a cluster chain. Ties are broken by majority.
+    ChainValidity,
+}
+
+impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
+    /// Compares the FAT copies stored on the volume.
+    ///
+    /// The copies are compared sector by sector and every entry of a sector that differs is compared between the
+    /// copies, including FAT12 entries spanning two sectors. Entries holding different values are reported with
+    /// the value of every copy, together with the number of them breaking a cluster chain in each copy. Changes
+    /// still held in the FAT cache are not compared, call [`FileSystem::flush`] first to include them.
+    ///
+    /// Note: FAT32 volumes with mirroring disabled only maintain the active FAT, the other copies are expected to
+    /// differ.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn verify_fats(&self) -> Result<FatVerifyReport, Error<IO::Error>> {
+        trace!("FileSystem::verify_fats");
+        if self.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        let bpb = self.bpb();
+        let fat_type = self.fat_type();
+        let sector_size = u64::from(bpb.bytes_per_sector);
+        let fat_len = bpb.bytes_from_sectors(bpb.sectors_per_fat());
+        let bits_per_entry = u64::from(fat_type.bits_per_fat_entry());
+        let end_cluster = self.total_clusters() + RESERVED_FAT_ENTRIES;
+        let mut copies: Vec<_> = (0..bpb.fats).map(|fat| self.fat_copy_slice(fat)).collect();
+        let mut bufs = vec![vec![0_u8; sector_size as usize]; copies.len()];
+        let mut report = FatVerifyReport {
+            invalid_entries: vec![0; copies.len()],
+            ..FatVerifyReport::default()
+        };
+        // FAT12 entries can span two sectors, do not compare them twice
+        let mut next_cluster = RESERVED_FAT_ENTRIES;
+        for offset in (0..fat_len).step_by(sector_size as usize) {
+            for (copy, buf) in copies.iter_mut().zip(&mut bufs) {
+                copy.seek(SeekFrom::Start(offset)).await?;
+                copy.read_exact(buf).await?;
+            }
+            if bufs.iter().all(|buf| *buf == bufs[0]) {
+                continue;
+            }
+            report.sectors.push(offset);
+            let first = (offset * 8 / bits_per_entry) as u32;
+            let last = ((offset + sector_size) * 8).div_ceil(bits_per_entry) as u32;
+            for cluster in first.max(next_cluster)..last.min(end_cluster) {
+                let mut values = Vec::with_capacity(copies.len());
+                for copy in &mut copies {
+                    values.push(read_fat_raw(copy, fat_type, cluster).await?);
+                }
+                if values.iter().any(|value| *value != values[0]) {
+                    report.divergences.push(FatDivergence { cluster, values });
+                }
+                next_cluster = cluster + 1;
+            }
+        }
+        for (fat, copy) in copies.iter_mut().enumerate() {
+            for divergence in &report.divergences {
+                let cluster = divergence.cluster;
+                let valid = match read_fat(copy, fat_type, cluster).await? {
+                    FatValue::Data(next) if next < RESERVED_FAT_ENTRIES || next >= end_cluster => {
+                        false
+                    }
+                    FatValue::Data(next) if next == cluster => false,
+                    FatValue::Data(next) => read_fat(copy, fat_type, next).await? != FatValue::Free,
+                    FatValue::Free | FatValue::Bad | FatValue::EndOfChain => true,
+                };
+                if !valid {
+                    report.invalid_entries[fat] += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Makes all FAT copies identical to the one selected by `source`.
+    ///
+    /// Cached FAT changes are flushed first, then the copies are compared as [`FileSystem::verify_fats`] does and
+    /// the sectors that differ are copied from the selected FAT to the other ones. Ties between copies are resolved
+    /// in favour of the active FAT, then of the lowest copy number. Afterwards the free cluster count is
+    /// recomputed.
+    ///
+    /// [`FileSystem::repair`] always keeps the first FAT, use this method before it to keep another copy.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported, or if
+    ///   `FatRepairSource::Copy` selects a copy that does not exist.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn repair_fats(
+        &self,
+        source: FatRepairSource,
+    ) -> Result<FatVerifyReport, Error<IO::Error>> {
+        trace!("FileSystem::repair_fats");
+        self.check_writable()?;
+        let fats = self.bpb().fats;
+        if matches!(source, FatRepairSource::Copy(fat) if fat >= fats) {
+            return Err(Error::InvalidInput);
+        }
+        self.flush().await?;
+        let mut report = self.verify_fats().await?;
+        let active_fat = usize::from(self.bpb().active_fat());
+        let by_majority =
+            |fat: usize| (report.agreement(fat), Reverse(report.invalid_entries[fat]));
+        let by_validity =
+            |fat: usize| (Reverse(report.invalid_entries[fat]), report.agreement(fat));
+        let preference = |fat: usize| (fat == active_fat, Reverse(fat));
+        let selected = match source {
+            FatRepairSource::Copy(fat) => fat,
+            FatRepairSource::Majority => (0..usize::from(fats))
+                .max_by_key(|&fat| (by_majority(fat), preference(fat)))
+                .unwrap_or(0) as u8,
+            FatRepairSource::ChainValidity => (0..usize::from(fats))
+                .max_by_key(|&fat| (by_validity(fat), preference(fat)))
+                .unwrap_or(0) as u8,
+        };
+        report.source = Some(selected);
+        if report.is_consistent() {
+            return Ok(report);
+        }
+        info!("Repairing FAT copies from FAT {}", selected);
+        let mut from = self.fat_copy_slice(selected);
+        let mut buf = vec![0_u8; usize::from(self.bpb().bytes_per_sector)];
+        for &offset in &report.sectors {
+            from.seek(SeekFrom::Start(offset)).await?;
+            from.read_exact(&mut buf).await?;
+            for fat in (0..fats).filter(|&fat| fat != selected) {
+                let mut to = self.fat_copy_slice(fat);
+                to.seek(SeekFrom::Start(offset)).await?;
+                to.write_all(&buf).await?;
+            }
+        }
+        #[cfg(feature = "fat-cache")]
+        self.fat_cache.acquire().await.invalidate();
+        self.rebuild_free_cluster_info().await?;
+        self.flush().await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FsOptions};
+    use crate::table::write_fat;
+    use crate::test_support::{MB, image};
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_repair_from_valid_copy() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let mut file = fs.root_dir().create_file("data.bin").await.unwrap();
+        file.write_all(&[0x5A; 4 * 512]).await.unwrap();
+        file.flush().await.unwrap();
+        let first_cluster = file.first_cluster().unwrap();
+        drop(file);
+        fs.flush().await.unwrap();
+        assert!(fs.verify_fats().await.unwrap().is_consistent());
+
+        // the second cluster of the file points outside of the data area in the first FAT
+        let bogus = fs.total_clusters() + 100;
+        let mut fat = fs.fat_copy_slice(0);
+        let result: Result<(), Error<std::io::Error>> = write_fat(
+            &mut fat,
+            fs.fat_type(),
+            first_cluster + 1,
+            FatValue::Data(bogus),
+        )
+        .await;
+        result.unwrap();
+
+        let report = fs.verify_fats().await.unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.divergences(),
+            &[FatDivergence {
+                cluster: first_cluster + 1,
+                values: vec![bogus, first_cluster + 2],
+            }]
+        );
+        assert_eq!(report.invalid_entries(0), 1);
+        assert_eq!(report.invalid_entries(1), 0);
+
+        let report = fs
+            .repair_fats(FatRepairSource::ChainValidity)
+            .await
+            .unwrap();
+        assert_eq!(report.source(), Some(1));
+        assert!(fs.verify_fats().await.unwrap().is_consistent());
+        let mut file = fs.root_dir().open_file("data.bin").await.unwrap();
+        let mut data = vec![0; 4 * 512];
+        file.read_exact(&mut data).await.unwrap();
+        assert!(data.iter().all(|&b| b == 0x5A));
+        assert!(fs.check().await.unwrap().is_clean());
+
+        assert!(matches!(
+            fs.repair_fats(FatRepairSource::Copy(2)).await,
+            Err(Error::InvalidInput)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fat12_entry_spanning_sectors() {
+        let mut image = image(MB, FatType::Fat12).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        // entry 341 is stored in the last byte of the first FAT sector and the first byte of the second one
+        for value in [0x123, 0x0FE] {
+            let mut fat = fs.fat_copy_slice(1);
+            let result: Result<(), Error<std::io::Error>> =
+                write_fat(&mut fat, FatType::Fat12, 341, FatValue::Data(value)).await;
+            result.unwrap();
+
+            let report = fs.verify_fats().await.unwrap();
+            assert_eq!(
+                report.divergences(),
+                &[FatDivergence {
+                    cluster: 341,
+                    values: vec![0, value],
+                }]
+            );
+            let report = fs.repair_fats(FatRepairSource::Copy(0)).await.unwrap();
+            assert_eq!(report.source(), Some(0));
+            assert!(fs.verify_fats().await.unwrap().is_consistent());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_majority_falls_back_to_chain_validity() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        // a chain leading to a free cluster in the second FAT
+        let mut fat = fs.fat_copy_slice(1);
+        let result: Result<(), Error<std::io::Error>> =
+            write_fat(&mut fat, fs.fat_type(), 10, FatValue::Data(11)).await;
+        result.unwrap();
+        let report = fs.verify_fats().await.unwrap();
+        assert_eq!(report.invalid_entries(1), 1);
+        let report = fs.repair_fats(FatRepairSource::Majority).await.unwrap();
+        assert_eq!(report.source(), Some(0));
+        assert!(fs.verify_fats().await.unwrap().is_consistent());
+    }
+}
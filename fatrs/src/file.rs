@@ -1,12 +1,35 @@
 use core::cmp;
+use core::sync::atomic::Ordering;
 
-use crate::dir_entry::DirEntryEditor;
+use crate::dir_entry::{DirEntryEditor, FileAttributes};
 use crate::error::Error;
 use crate::fs::{FileSystem, ReadWriteSeek};
 use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
 use crate::time::{Date, DateTime, TimeProvider};
 
-const MAX_FILE_SIZE: u32 = u32::MAX;
+/// Number of cluster chain checkpoints kept by every open file.
+///
+/// Each checkpoint takes 8 bytes. The default of 8 can be raised with the `cluster-checkpoints-32` and
+/// `cluster-checkpoints-128` features; more checkpoints mean shorter FAT walks when seeking in large files.
+#[cfg(feature = "cluster-checkpoints-128")]
+pub const CLUSTER_CHECKPOINTS: usize = 128;
+
+#[cfg(all(
+    feature = "cluster-checkpoints-32",
+    not(feature = "cluster-checkpoints-128")
+))]
+pub const CLUSTER_CHECKPOINTS: usize = 32;
+
+#[cfg(all(
+    feature = "cluster-checkpoints",
+    not(feature = "cluster-checkpoints-32"),
+    not(feature = "cluster-checkpoints-128")
+))]
+pub const CLUSTER_CHECKPOINTS: usize = 8;
+
+// Clusters between checkpoints of a file until all checkpoints are used
+#[cfg(feature = "cluster-checkpoints")]
+const MIN_CHECKPOINT_INTERVAL: u32 = 8;
 
 /// A FAT filesystem file object used for reading and writing data.
 ///
@@ -41,7 +64,7 @@ pub struct FileContext {
     // Note: if offset points between clusters current_cluster is the previous cluster
     pub(crate) current_cluster: Option<u32>,
     // current position in this file
-    pub(crate) offset: u32,
+    pub(crate) offset: u64,
     // file dir entry editor - None for root dir
     pub(crate) entry: Option<DirEntryEditor>,
 
@@ -51,11 +74,22 @@ pub struct FileContext {
     pub(crate) is_contiguous: bool,
 
     // Phase 2 Optimization: Cluster chain checkpoints for O(log n) seeking
-    // Stores (offset, cluster) pairs at regular intervals
+    // Stores (cluster index, cluster) pairs at regular intervals, sorted by index
     #[cfg(feature = "cluster-checkpoints")]
-    pub(crate) checkpoints: [(u32, u32); 8], // Up to 8 checkpoints
+    pub(crate) checkpoints: [(u32, u32); CLUSTER_CHECKPOINTS],
     #[cfg(feature = "cluster-checkpoints")]
     pub(crate) checkpoint_count: u8,
+    // Distance between checkpoints in clusters, doubled when all checkpoints are used
+    #[cfg(feature = "cluster-checkpoints")]
+    pub(crate) checkpoint_interval: u32,
+
+    // Read-ahead buffer for sequential reads
+    #[cfg(feature = "read-ahead")]
+    pub(crate) read_ahead: crate::read_ahead::ReadAheadBuffer,
+
+    // Small writes not yet written to the device
+    #[cfg(feature = "write-coalescing")]
+    pub(crate) write_buffer: crate::write_coalescing::WriteBuffer,
 
     // Track whether we've logged first read/write for this file session
     #[cfg(feature = "audit-log")]
@@ -72,13 +106,111 @@ pub struct FileContext {
 /// An extent containing a file's data on disk.
 ///
 /// This is created by the `extents` method on `File`, and represents
-/// a byte range on the disk that contains a file's data. All values
-/// are in bytes.
+/// a run of contiguous clusters that contains a file's data.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Extent {
+    /// Position of the extent in the file, in bytes.
+    pub file_offset: u64,
+    /// Position of the extent on the volume, in bytes.
     pub offset: u64,
-    pub size: u32,
+    /// First sector of the extent on the underlying device, including the partition offset. This is the sector
+    /// `File::sector_at` returns for the start of the extent.
+    pub first_lba: u64,
+    /// Length of the extent in bytes.
+    pub size: u64,
+}
+
+/// An iterator over the extents of a file.
+///
+/// This struct is created by the `extents` method on `File`.
+pub struct Extents<'a, IO: ReadWriteSeek, TP, OCC>
+where
+    IO::Error: 'static,
+{
+    fs: &'a FileSystem<IO, TP, OCC>,
+    cluster: Option<u32>,
+    file_offset: u64,
+    bytes_left: Option<u64>,
+    contiguous_clusters: Option<u32>,
+    err: bool,
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> Extents<'_, IO, TP, OCC> {
+    /// Returns the next extent of the file, or `None` once the end of the file is reached.
+    ///
+    /// Adjacent clusters of the chain are merged into one extent. After an error is returned the iterator is
+    /// exhausted.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn next(&mut self) -> Option<Result<Extent, Error<IO::Error>>> {
+        if self.err || self.bytes_left == Some(0) {
+            return None;
+        }
+        let first_cluster = self.cluster?;
+        let mut clusters = 1;
+        if let Some(count) = self.contiguous_clusters {
+            // exFAT stream without a FAT chain
+            clusters = count;
+            self.cluster = None;
+        } else {
+            loop {
+                if self
+                    .bytes_left
+                    .is_some_and(|left| self.fs.bytes_from_clusters(clusters) >= left)
+                {
+                    // the rest of the chain is past the end of the file
+                    self.cluster = None;
+                    break;
+                }
+                let last_cluster = first_cluster + clusters - 1;
+                match self.fs.cluster_iter(last_cluster).next().await {
+                    Some(Ok(next)) if next == last_cluster + 1 => clusters += 1,
+                    Some(Ok(next)) => {
+                        self.cluster = Some(next);
+                        break;
+                    }
+                    Some(Err(err)) => {
+                        self.err = true;
+                        return Some(Err(err));
+                    }
+                    None => {
+                        self.cluster = None;
+                        break;
+                    }
+                }
+            }
+        }
+        let mut size = self.fs.bytes_from_clusters(clusters);
+        if let Some(ref mut bytes_left) = self.bytes_left {
+            size = cmp::min(size, *bytes_left);
+            *bytes_left -= size;
+        }
+        let offset = self.fs.offset_from_cluster(first_cluster);
+        let extent = Extent {
+            file_offset: self.file_offset,
+            offset,
+            first_lba: self.fs.partition_start_sector()
+                + offset / u64::from(self.fs.bytes_per_sector()),
+            size,
+        };
+        self.file_offset += size;
+        Some(Ok(extent))
+    }
+}
+
+/// What [`File::allocate_contiguous`] does when no free run of clusters is large enough.
+#[cfg(feature = "cluster-bitmap")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AllocationPolicy {
+    /// Fail with `Error::NotEnoughSpace`.
+    #[default]
+    Contiguous,
+    /// Fall back to allocating free clusters wherever they are, like [`File::allocate`].
+    BestEffort,
 }
 
 impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
@@ -96,9 +228,15 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
                 #[cfg(feature = "multi-cluster-io")]
                 is_contiguous: false, // Will be detected during allocation
                 #[cfg(feature = "cluster-checkpoints")]
-                checkpoints: [(0, 0); 8],
+                checkpoints: [(0, 0); CLUSTER_CHECKPOINTS],
                 #[cfg(feature = "cluster-checkpoints")]
                 checkpoint_count: 0,
+                #[cfg(feature = "cluster-checkpoints")]
+                checkpoint_interval: MIN_CHECKPOINT_INTERVAL,
+                #[cfg(feature = "read-ahead")]
+                read_ahead: crate::read_ahead::ReadAheadBuffer::default(),
+                #[cfg(feature = "write-coalescing")]
+                write_buffer: crate::write_coalescing::WriteBuffer::default(),
                 #[cfg(feature = "audit-log")]
                 logged_read: false,
                 #[cfg(feature = "audit-log")]
@@ -112,6 +250,7 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
             #[cfg(feature = "file-locking")]
             lock_info: None,
         }
+        .opened()
     }
 
     /// Create a new file with a lock held.
@@ -132,9 +271,15 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
                 #[cfg(feature = "multi-cluster-io")]
                 is_contiguous: false,
                 #[cfg(feature = "cluster-checkpoints")]
-                checkpoints: [(0, 0); 8],
+                checkpoints: [(0, 0); CLUSTER_CHECKPOINTS],
                 #[cfg(feature = "cluster-checkpoints")]
                 checkpoint_count: 0,
+                #[cfg(feature = "cluster-checkpoints")]
+                checkpoint_interval: MIN_CHECKPOINT_INTERVAL,
+                #[cfg(feature = "read-ahead")]
+                read_ahead: crate::read_ahead::ReadAheadBuffer::default(),
+                #[cfg(feature = "write-coalescing")]
+                write_buffer: crate::write_coalescing::WriteBuffer::default(),
                 #[cfg(feature = "audit-log")]
                 logged_read: false,
                 #[cfg(feature = "audit-log")]
@@ -147,6 +292,7 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
             fs,
             lock_info: Some(lock_type),
         }
+        .opened()
     }
 
     /// Create a file from a prexisting [`FileContext`] & [`FileSystem`].
@@ -165,19 +311,58 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
             #[cfg(feature = "file-locking")]
             lock_info: None,
         }
+        .opened()
+    }
+
+    /// Returns true for a regular file, false for a directory or the root directory.
+    fn is_regular_file(&self) -> bool {
+        self.context
+            .entry
+            .as_ref()
+            .is_some_and(|e| !e.inner().is_dir())
+    }
+
+    /// Counts the file in `FileSystem::open_files` until it is dropped.
+    fn opened(self) -> Self {
+        if self.is_regular_file() {
+            self.fs.open_files.fetch_add(1, Ordering::AcqRel);
+        }
+        self
+    }
+
+    /// Fails with `Error::ReadOnly` if the file has the `READ_ONLY` attribute or the volume is mounted read-only.
+    ///
+    /// Directories are written through `File` too, the attribute does not protect their contents.
+    fn check_writable(&self) -> Result<(), Error<IO::Error>> {
+        self.fs.check_writable()?;
+        let read_only = self.context.entry.as_ref().is_some_and(|e| {
+            let attrs = e.inner().attributes();
+            attrs.contains(FileAttributes::READ_ONLY) && !attrs.contains(FileAttributes::DIRECTORY)
+        });
+        if read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
     }
 
     /// Truncate file in current position.
     ///
     /// # Errors
     ///
-    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    /// * `Error::ReadOnly` will be returned if the file has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     ///
     /// # Panics
     ///
     /// Will panic if this is the root directory.
     pub async fn truncate(&mut self) -> Result<(), Error<IO::Error>> {
         trace!("File::truncate");
+        self.check_writable()?;
+        #[cfg(feature = "write-coalescing")]
+        self.flush_write_buffer().await?;
+        #[cfg(feature = "exfat")]
+        self.ensure_fat_chain().await?;
         if let Some(ref mut e) = self.context.entry {
             e.set_size(self.context.offset);
             if self.context.offset == 0 {
@@ -198,6 +383,10 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
                 self.context.first_cluster = None;
             }
         }
+        #[cfg(feature = "cluster-checkpoints")]
+        self.discard_checkpoints(self.fs.clusters_from_bytes(self.context.offset));
+        #[cfg(feature = "read-ahead")]
+        self.context.read_ahead.invalidate();
 
         // Refresh generation counter after freeing clusters.
         // The free_cluster_chain/truncate_cluster_chain operations increment the
@@ -211,28 +400,15 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
     }
 
     /// Phase 3 Optimization: Find the closest checkpoint to the target cluster index
-    /// Returns (starting_cluster, clusters_already_traversed)
+    /// Returns `(starting_cluster, clusters_already_traversed)`
     #[cfg(feature = "cluster-checkpoints")]
-    fn find_closest_checkpoint(&self, target_cluster_index: u32) -> (u32, u32) {
-        let first_cluster = self.context.first_cluster.unwrap();
-
-        // If no checkpoints recorded yet, start from beginning
-        if self.context.checkpoint_count == 0 {
+    fn find_closest_checkpoint(&self, first_cluster: u32, target_cluster_index: u32) -> (u32, u32) {
+        // Checkpoints are sorted by index, so the best one is found with a binary search
+        let checkpoints = &self.context.checkpoints[..usize::from(self.context.checkpoint_count)];
+        let pos = checkpoints.partition_point(|(index, _)| *index <= target_cluster_index);
+        let Some(&(best_index, best_cluster)) = pos.checked_sub(1).map(|i| &checkpoints[i]) else {
             return (first_cluster, 0);
-        }
-
-        // Find the checkpoint closest to but not exceeding target
-        let mut best_cluster = first_cluster;
-        let mut best_index = 0u32;
-
-        for i in 0..self.context.checkpoint_count as usize {
-            let (checkpoint_index, checkpoint_cluster) = self.context.checkpoints[i];
-            // Use checkpoint if it's closer to target than our current best
-            if checkpoint_index <= target_cluster_index && checkpoint_index > best_index {
-                best_index = checkpoint_index;
-                best_cluster = checkpoint_cluster;
-            }
-        }
+        };
 
         trace!(
             "Checkpoint seek: target={}, using checkpoint at index={} (saved {} cluster reads)",
@@ -243,68 +419,102 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
     }
 
     /// Phase 3 Optimization: Record a checkpoint at the current position
-    /// Checkpoints are stored at exponentially increasing intervals for logarithmic seek
+    /// Checkpoints are stored every `checkpoint_interval` clusters. When all of them are used the interval is
+    /// doubled and every other checkpoint is dropped, so they always cover the traversed part of the chain.
     #[cfg(feature = "cluster-checkpoints")]
     fn record_checkpoint(&mut self, cluster_index: u32, cluster: u32) {
-        // Record checkpoints at intervals: 8, 16, 32, 64, 128, 256, 512, 1024 clusters
-        // This gives O(log n) seek performance
-        let checkpoint_interval = 1u32 << (self.context.checkpoint_count.min(7) as u32 + 3);
-
-        if cluster_index > 0 && cluster_index % checkpoint_interval == 0 {
-            let idx = self.context.checkpoint_count as usize;
-            if idx < 8 {
-                self.context.checkpoints[idx] = (cluster_index, cluster);
-                self.context.checkpoint_count += 1;
-                trace!(
-                    "Recorded checkpoint: index={}, cluster={}",
-                    cluster_index, cluster
-                );
+        if cluster_index == 0 || cluster_index % self.context.checkpoint_interval != 0 {
+            return;
+        }
+        let count = usize::from(self.context.checkpoint_count);
+        if count > 0 && self.context.checkpoints[count - 1].0 >= cluster_index {
+            // only the part of the chain past the last checkpoint is recorded
+            return;
+        }
+        if count == CLUSTER_CHECKPOINTS {
+            let interval = self.context.checkpoint_interval * 2;
+            let mut kept = 0;
+            for i in 0..count {
+                let checkpoint = self.context.checkpoints[i];
+                if checkpoint.0 % interval == 0 {
+                    self.context.checkpoints[kept] = checkpoint;
+                    kept += 1;
+                }
+            }
+            self.context.checkpoint_count = kept as u8;
+            self.context.checkpoint_interval = interval;
+            trace!("Checkpoint interval increased to {} clusters", interval);
+            if cluster_index % interval != 0 {
+                return;
             }
         }
+        let idx = usize::from(self.context.checkpoint_count);
+        self.context.checkpoints[idx] = (cluster_index, cluster);
+        self.context.checkpoint_count += 1;
+        trace!(
+            "Recorded checkpoint: index={}, cluster={}",
+            cluster_index, cluster
+        );
     }
 
-    // /// Get the extents of a file on disk.
-    // ///
-    // /// This returns an iterator over the byte ranges on-disk occupied by
-    // /// this file.
-    // pub fn extents(&mut self) -> impl Iterator<Item = Result<Extent, Error<IO::Error>>> + 'a {
-    // let fs = self.fs;
-    // let cluster_size = fs.cluster_size();
-    // let mut bytes_left = match self.size() {
-    //     Some(s) => s,
-    //     None => return None.into_iter().flatten(),
-    // };
-    // let first = match self.context.first_cluster {
-    //     Some(f) => f,
-    //     None => return None.into_iter().flatten(),
-    // };
-
-    // Some(
-    //     core::iter::once(Ok(first))
-    //         .chain(fs.cluster_iter(first))
-    //         .map(move |cluster_err| match cluster_err {
-    //             Ok(cluster) => {
-    //                 let size = cluster_size.min(bytes_left);
-    //                 bytes_left -= size;
-    //                 Ok(Extent {
-    //                     offset: fs.offset_from_cluster(cluster),
-    //                     size,
-    //                 })
-    //             }
-    //             Err(e) => Err(e),
-    //         }),
-    // )
-    // .into_iter()
-    // .flatten()
-    // todo!("extents needs to be implemented using AsyncIterator");
-    // }
+    /// Drops the checkpoints of clusters which are no longer part of the file.
+    #[cfg(feature = "cluster-checkpoints")]
+    fn discard_checkpoints(&mut self, clusters: u32) {
+        let checkpoints = &self.context.checkpoints[..usize::from(self.context.checkpoint_count)];
+        self.context.checkpoint_count =
+            checkpoints.partition_point(|(index, _)| *index < clusters) as u8;
+        if self.context.checkpoint_count == 0 {
+            self.context.checkpoint_interval = MIN_CHECKPOINT_INTERVAL;
+        }
+    }
+
+    /// Get the extents of a file on disk.
+    ///
+    /// This returns an iterator over the runs of contiguous clusters occupied by this file, in file order.
+    /// The last extent ends at the end of the file. Directories have no size so their extents cover whole clusters.
+    #[must_use]
+    pub fn extents(&self) -> Extents<'a, IO, TP, OCC> {
+        Extents {
+            fs: self.fs,
+            cluster: self.context.first_cluster,
+            file_offset: 0,
+            bytes_left: self.size(),
+            contiguous_clusters: self
+                .no_fat_chain_len()
+                .map(|len| self.fs.clusters_from_bytes(len)),
+            err: false,
+        }
+    }
+
+    /// Returns the absolute sector of the device holding the byte at `offset` in this file.
+    ///
+    /// The partition offset is taken from the hidden sectors field of the boot sector, which `format_disk` and
+    /// most partitioning tools set to the first sector of the partition. `None` is returned if `offset` is not
+    /// below the file size.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn sector_at(&self, offset: u64) -> Result<Option<u64>, Error<IO::Error>> {
+        let mut extents = self.extents();
+        while let Some(extent) = extents.next().await {
+            let extent = extent?;
+            if offset < extent.file_offset + extent.size {
+                let offset_in_extent = offset.saturating_sub(extent.file_offset);
+                return Ok(Some(
+                    extent.first_lba + offset_in_extent / u64::from(self.fs.bytes_per_sector()),
+                ));
+            }
+        }
+        Ok(None)
+    }
 
     pub(crate) fn abs_pos(&self) -> Option<u64> {
         // Returns current position relative to filesystem start
         // Note: when between clusters it returns position after previous cluster
         match self.context.current_cluster {
             Some(n) => {
-                let cluster_size = self.fs.cluster_size();
+                let cluster_size = u64::from(self.fs.cluster_size());
                 let offset_mod_cluster_size = self.context.offset % cluster_size;
                 let offset_in_cluster = if offset_mod_cluster_size == 0 {
                     // position points between clusters - we are returning previous cluster so
@@ -313,7 +523,7 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
                 } else {
                     offset_mod_cluster_size
                 };
-                let offset_in_fs = self.fs.offset_from_cluster(n) + u64::from(offset_in_cluster);
+                let offset_in_fs = self.fs.offset_from_cluster(n) + offset_in_cluster;
                 Some(offset_in_fs)
             }
             None => None,
@@ -327,6 +537,37 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         Ok(())
     }
 
+    /// Writes the data collected by write coalescing to the device.
+    #[cfg(feature = "write-coalescing")]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn flush_write_buffer(&mut self) -> Result<(), Error<IO::Error>> {
+        if self.context.write_buffer.is_empty() {
+            return Ok(());
+        }
+        let (disk_offset, data) = self.context.write_buffer.pending();
+        trace!(
+            "write coalescing: writing {} bytes at {}",
+            data.len(),
+            disk_offset
+        );
+        {
+            let mut disk = self.fs.disk.acquire().await;
+            disk.seek(SeekFrom::Start(disk_offset)).await?;
+            #[cfg(feature = "transaction-safe")]
+            self.fs.journal_write(&mut *disk, data.len() as u64).await?;
+            let mut written = 0;
+            while written < data.len() {
+                let n = disk.write(&data[written..]).await?;
+                if n == 0 {
+                    return Err(Error::WriteZero);
+                }
+                written += n;
+            }
+        }
+        self.context.write_buffer.clear();
+        Ok(())
+    }
+
     /// Sets date and time of creation for this file.
     ///
     /// Note: it is set to a value from the `TimeProvider` when creating a file.
@@ -360,9 +601,9 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         }
     }
 
-    fn size(&self) -> Option<u32> {
+    fn size(&self) -> Option<u64> {
         match self.context.entry {
-            Some(ref e) => e.inner().size(),
+            Some(ref e) => e.size(),
             None => None,
         }
     }
@@ -376,7 +617,98 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
 
     fn bytes_left_in_file(&self) -> Option<usize> {
         // Note: seeking beyond end of file is not allowed so overflow is impossible
-        self.size().map(|s| (s - self.context.offset) as usize)
+        self.size()
+            .map(|s| usize::try_from(s - self.context.offset).unwrap_or(usize::MAX))
+    }
+
+    /// Returns the cluster following `cluster` in this file.
+    ///
+    /// exFAT streams flagged with `NoFatChain` are contiguous, so the FAT is not consulted for them.
+    async fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, Error<IO::Error>> {
+        #[cfg(feature = "exfat")]
+        if let (Some(len), Some(first_cluster)) = (self.no_fat_chain_len(), self.context.first_cluster) {
+            let next = cluster + 1;
+            return Ok((next < first_cluster + self.fs.clusters_from_bytes(len)).then_some(next));
+        }
+        match self.fs.cluster_iter(cluster).next().await {
+            Some(Err(err)) => Err(err),
+            Some(Ok(n)) => Ok(Some(n)),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "exfat")]
+    fn no_fat_chain_len(&self) -> Option<u64> {
+        self.context.entry.as_ref().and_then(DirEntryEditor::no_fat_chain_len)
+    }
+
+    #[cfg(not(feature = "exfat"))]
+    #[allow(clippy::unused_self)]
+    fn no_fat_chain_len(&self) -> Option<u64> {
+        None
+    }
+
+    /// Writes the FAT chain of an exFAT stream stored without one, so it can be resized.
+    #[cfg(feature = "exfat")]
+    async fn ensure_fat_chain(&mut self) -> Result<(), Error<IO::Error>> {
+        if let (Some(len), Some(first_cluster)) = (self.no_fat_chain_len(), self.context.first_cluster) {
+            trace!("writing FAT chain for contiguous stream at cluster {}", first_cluster);
+            let count = self.fs.clusters_from_bytes(len);
+            self.fs.write_contiguous_chain(first_cluster, count).await?;
+            if let Some(ref mut e) = self.context.entry {
+                e.clear_no_fat_chain();
+            }
+        }
+        Ok(())
+    }
+
+    /// Zeroes the part of an exFAT file between its valid data length and its size.
+    ///
+    /// Other implementations may preallocate files without writing them. The range has to contain zeros on the
+    /// disk before data can be written past the valid data length.
+    #[cfg(feature = "exfat")]
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn zero_unwritten_data(&mut self) -> Result<(), Error<IO::Error>> {
+        let valid_len = self
+            .context
+            .entry
+            .as_ref()
+            .and_then(DirEntryEditor::valid_data_length);
+        let (Some(valid_len), Some(size), Some(mut cluster)) = (valid_len, self.size(), self.context.first_cluster)
+        else {
+            return Ok(());
+        };
+        if valid_len >= size {
+            return Ok(());
+        }
+        trace!("zeroing file data from {} to {}", valid_len, size);
+        let cluster_size = u64::from(self.fs.cluster_size());
+        let mut cluster_start = 0;
+        loop {
+            let cluster_end = cluster_start + cluster_size;
+            if cluster_end > valid_len {
+                let from = cmp::max(valid_len, cluster_start);
+                let to = cmp::min(size, cluster_end);
+                let mut disk = self.fs.disk.acquire().await;
+                disk.seek(SeekFrom::Start(self.fs.offset_from_cluster(cluster) + from - cluster_start))
+                    .await?;
+                #[cfg(feature = "transaction-safe")]
+                self.fs.journal_write(&mut *disk, to - from).await?;
+                crate::fs::write_zeros(&mut *disk, to - from).await?;
+            }
+            if cluster_end >= size {
+                break;
+            }
+            cluster_start = cluster_end;
+            match self.next_cluster(cluster).await? {
+                Some(n) => cluster = n,
+                None => break,
+            }
+        }
+        if let Some(ref mut e) = self.context.entry {
+            e.set_valid_data_length(size);
+        }
+        Ok(())
     }
 
     fn set_first_cluster(&mut self, cluster: u32) {
@@ -390,8 +722,19 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         self.context.first_cluster
     }
 
+    /// Makes an empty file own an existing cluster chain. The caller is responsible for the chain being unused.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn attach_cluster_chain(&mut self, first_cluster: u32, size: u64) {
+        self.set_first_cluster(first_cluster);
+        if let Some(ref mut e) = self.context.entry {
+            e.set_size(size);
+        }
+    }
+
     #[allow(clippy::await_holding_refcell_ref)]
     pub async fn flush(&mut self) -> Result<(), Error<IO::Error>> {
+        #[cfg(feature = "write-coalescing")]
+        self.flush_write_buffer().await?;
         self.flush_dir_entry().await?;
         {
             let mut disk = self.fs.disk.acquire().await;
@@ -399,6 +742,92 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         }
         Ok(())
     }
+
+    /// Moves the data of this file into a contiguous run of free clusters.
+    ///
+    /// If the file is fragmented any large enough run is used. If it is already contiguous it is only moved when
+    /// `compact` is set and a run is available before its current location. Returns the number of clusters moved,
+    /// or `None` if the file is fragmented and no run is large enough.
+    #[cfg(feature = "cluster-bitmap")]
+    pub(crate) async fn relocate(
+        &mut self,
+        compact: bool,
+        progress: &mut impl FnMut(u32),
+    ) -> Result<Option<u32>, Error<IO::Error>> {
+        if self.fs.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        #[cfg(feature = "write-coalescing")]
+        self.flush_write_buffer().await?;
+        let Some(old_first_cluster) = self.context.first_cluster else {
+            return Ok(Some(0));
+        };
+        let chain = crate::defrag::read_chain(self.fs, old_first_cluster).await?;
+        let limit = match (chain.contiguous, compact) {
+            (true, false) => return Ok(Some(0)),
+            (true, true) => old_first_cluster,
+            (false, _) => u32::MAX,
+        };
+        let Some(new_first_cluster) =
+            crate::defrag::copy_chain(self.fs, &chain, limit, progress).await?
+        else {
+            return Ok(if chain.contiguous { Some(0) } else { None });
+        };
+        // The copy must be on disk before the entry points to it, and the entry must be on disk before the old
+        // chain is freed. A crash in between leaves a lost chain but never a damaged file.
+        self.fs.flush().await?;
+        self.set_first_cluster(new_first_cluster);
+        self.context.current_cluster = if self.context.offset == 0 {
+            None
+        } else {
+            let cluster_size = u64::from(self.fs.cluster_size());
+            let index = ((self.context.offset - 1) / cluster_size).min(u64::from(chain.len - 1));
+            Some(new_first_cluster + index as u32)
+        };
+        #[cfg(feature = "multi-cluster-io")]
+        {
+            self.context.is_contiguous = true;
+        }
+        #[cfg(feature = "cluster-checkpoints")]
+        self.discard_checkpoints(0);
+        #[cfg(feature = "read-ahead")]
+        self.context.read_ahead.invalidate();
+        self.flush().await?;
+        self.fs.free_cluster_chain(old_first_cluster).await?;
+        if let Some(ref mut e) = self.context.entry {
+            e.refresh_generation(self.fs);
+        }
+        Ok(Some(chain.len))
+    }
+
+    /// Defragments this file by moving its data into a contiguous run of free clusters.
+    ///
+    /// The data is copied and flushed first, then the directory entry is switched to the new chain and finally the
+    /// old chain is freed, so an interrupted defragmentation never damages the file. The file position is kept.
+    /// Other handles to the same file must not be used afterwards, because they still refer to the old chain.
+    ///
+    /// `progress` is called after every copied cluster and once when the file is done. Returns `true` if the file
+    /// was moved, and `false` if it was already contiguous or no free run of clusters is large enough.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "cluster-bitmap")]
+    pub async fn defragment(
+        &mut self,
+        mut progress: impl FnMut(&crate::DefragProgress),
+    ) -> Result<bool, Error<IO::Error>> {
+        trace!("File::defragment");
+        let moved = self
+            .relocate(false, &mut |clusters| {
+                progress(&crate::DefragProgress::new(0, 1, clusters));
+            })
+            .await?
+            .unwrap_or(0);
+        progress(&crate::DefragProgress::new(1, 1, moved));
+        Ok(moved > 0)
+    }
 }
 
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
@@ -407,7 +836,7 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
         if let Some(ref mut e) = self.context.entry {
             let now = self.fs.options.time_provider.get_current_date_time();
             e.set_modified(now);
-            let current_size = e.inner().size();
+            let current_size = e.size();
             if current_size.is_some_and(|s| offset > s) {
                 trace!("update_dir_entry: offset={}, current_size={:?}, setting new size", offset, current_size);
                 e.set_size(offset);
@@ -421,6 +850,280 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
         Ok(())
     }
 
+    /// Collects a write smaller than the write coalescing threshold in the write buffer. The buffer is written
+    /// when it is full or reaches the end of the cluster. Returns `None` if the write should go to the device
+    /// directly, in which case the buffer has been written first.
+    #[cfg(feature = "write-coalescing")]
+    async fn write_coalesced(&mut self, buf: &[u8]) -> Result<Option<usize>, Error<IO::Error>> {
+        let threshold = self.fs.options.write_coalescing;
+        let cluster_size = self.fs.cluster_size();
+        let offset = self.context.offset;
+        let offset_in_cluster = (offset % u64::from(cluster_size)) as u32;
+        // only data inside an already allocated cluster is buffered
+        let current_cluster = self
+            .context
+            .current_cluster
+            .filter(|_| offset_in_cluster != 0);
+        let Some(current_cluster) =
+            current_cluster.filter(|_| buf.len() < threshold && !self.is_dir())
+        else {
+            self.flush_write_buffer().await?;
+            return Ok(None);
+        };
+        if !self.context.write_buffer.continues_at(offset) {
+            self.flush_write_buffer().await?;
+        }
+        let bytes_left_in_cluster = (cluster_size - offset_in_cluster) as usize;
+        let write_size = cmp::min(buf.len(), bytes_left_in_cluster);
+        if self.context.write_buffer.len() + write_size > threshold {
+            self.flush_write_buffer().await?;
+        }
+        let disk_offset =
+            self.fs.offset_from_cluster(current_cluster) + u64::from(offset_in_cluster);
+        self.context
+            .write_buffer
+            .push(offset, disk_offset, &buf[..write_size], threshold);
+        self.context.offset += write_size as u64;
+
+        let offset = self.context.offset;
+        if let Some(ref mut e) = self.context.entry {
+            let now = self.fs.options.time_provider.get_current_date_time();
+            e.set_modified(now);
+            if e.size().is_some_and(|s| offset > s) {
+                e.set_size(offset);
+            }
+        }
+        if write_size == bytes_left_in_cluster || self.context.write_buffer.len() == threshold {
+            self.flush_write_buffer().await?;
+            self.flush_dir_entry().await?;
+        }
+        Ok(Some(write_size))
+    }
+
+    /// Serves a read from the read-ahead buffer, prefetching the contiguous clusters starting at `current_cluster`
+    /// first if the file is being read sequentially. Returns `None` if the read should go to the device instead.
+    #[cfg(feature = "read-ahead")]
+    async fn read_ahead(
+        &mut self,
+        current_cluster: u32,
+        offset_in_cluster: u32,
+        buf: &mut [u8],
+        bytes_left_in_file: usize,
+    ) -> Result<Option<usize>, Error<IO::Error>> {
+        let capacity = self.fs.options.read_ahead;
+        let offset = self.context.offset;
+        let read_size = cmp::min(buf.len(), bytes_left_in_file);
+        // directories are modified through other streams and contiguous exFAT streams have no FAT chain to follow
+        if capacity == 0 || read_size == 0 || self.is_dir() || self.no_fat_chain_len().is_some() {
+            return Ok(None);
+        }
+        let sequential = self.context.read_ahead.is_sequential(offset, read_size);
+        if self.context.read_ahead.get(offset).is_none() {
+            if !sequential || read_size >= capacity {
+                return Ok(None);
+            }
+            let prefetch_size = cmp::min(capacity, bytes_left_in_file);
+            let buffer = self.context.read_ahead.buffer_mut(capacity);
+            let prefetched = crate::multi_cluster_io::read_contiguous(
+                self.fs,
+                current_cluster,
+                offset_in_cluster,
+                &mut buffer[..prefetch_size],
+            )
+            .await?;
+            trace!(
+                "read-ahead: prefetched {} bytes at offset {}",
+                prefetched, offset
+            );
+            self.context
+                .read_ahead
+                .fill(offset, current_cluster, prefetched);
+        }
+        let Some(data) = self.context.read_ahead.get(offset) else {
+            return Ok(None);
+        };
+        let read_bytes = cmp::min(read_size, data.len());
+        buf[..read_bytes].copy_from_slice(&data[..read_bytes]);
+        self.context.offset += read_bytes as u64;
+        let cluster = self
+            .context
+            .read_ahead
+            .cluster_at(self.context.offset - 1, self.fs.cluster_size());
+        self.context.current_cluster = Some(cluster);
+
+        #[cfg(feature = "cluster-checkpoints")]
+        {
+            let cluster_idx =
+                ((self.context.offset - 1) / u64::from(self.fs.cluster_size())) as u32;
+            self.record_checkpoint(cluster_idx, cluster);
+        }
+
+        if let Some(ref mut e) = self.context.entry {
+            if self.fs.options.update_accessed_date {
+                let now = self.fs.options.time_provider.get_current_date();
+                e.set_accessed(now);
+            }
+        }
+        Ok(Some(read_bytes))
+    }
+
+    /// Changes the size of this file to `len` bytes.
+    ///
+    /// A larger size extends the file with zeros, a smaller one truncates it like [`File::truncate`]. Clusters past
+    /// the new size, including ones reserved with [`File::allocate`], are freed. The file position is kept unless it
+    /// is past the new end of the file, in which case it is moved to the end.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned if this is a directory or `len` exceeds the maximal file size.
+    /// * `Error::NotEnoughSpace` will be returned if there are not enough free clusters to extend the file. The
+    ///   file is left unchanged in this case.
+    /// * `Error::ReadOnly` will be returned if the file has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn set_len(&mut self, len: u64) -> Result<(), Error<IO::Error>> {
+        trace!("File::set_len {}", len);
+        self.check_writable()?;
+        let Some(size) = self.size().filter(|_| len <= self.fs.max_file_size()) else {
+            return Err(Error::InvalidInput);
+        };
+        let offset = self.context.offset;
+        if len > size {
+            // reserve the clusters first so that the file is unchanged if the volume is full
+            self.allocate(len).await?;
+            self.seek(SeekFrom::End(0)).await?;
+            crate::fs::write_zeros(self, len - size).await?;
+        } else {
+            self.seek(SeekFrom::Start(len)).await?;
+            self.truncate().await?;
+        }
+        self.seek(SeekFrom::Start(cmp::min(offset, len))).await?;
+        Ok(())
+    }
+
+    /// Reserves clusters for the first `len` bytes of this file without changing its size.
+    ///
+    /// Writes up to `len` bytes then use the reserved clusters, so they cannot fail for lack of space. Nothing is
+    /// done if the file already owns enough clusters. Reserved clusters past the end of the file are freed by
+    /// [`File::truncate`] and [`File::set_len`], and reported by [`FileSystem::check`] like any other chain longer
+    /// than its file, so truncate the file once it is complete.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned if this is a directory or `len` exceeds the maximal file size.
+    /// * `Error::NotEnoughSpace` will be returned if there are not enough free clusters. No cluster is reserved in
+    ///   this case.
+    /// * `Error::ReadOnly` will be returned if the file has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// [`FileSystem::check`]: crate::FileSystem::check
+    pub async fn allocate(&mut self, len: u64) -> Result<(), Error<IO::Error>> {
+        trace!("File::allocate {}", len);
+        self.check_writable()?;
+        if self.size().is_none() || len > self.fs.max_file_size() {
+            return Err(Error::InvalidInput);
+        }
+        #[cfg(feature = "exfat")]
+        self.ensure_fat_chain().await?;
+
+        // find the end of the current chain
+        let mut last_cluster = self.context.first_cluster;
+        let mut clusters = 0;
+        if let Some(mut cluster) = last_cluster {
+            clusters = 1;
+            while let Some(next) = self.next_cluster(cluster).await? {
+                cluster = next;
+                clusters += 1;
+            }
+            last_cluster = Some(cluster);
+        }
+
+        let needed = self.fs.clusters_from_bytes(len);
+        let mut prev_cluster = last_cluster;
+        for _ in clusters..needed {
+            match self.fs.alloc_cluster(prev_cluster, false).await {
+                Ok(cluster) => {
+                    if self.context.first_cluster.is_none() {
+                        self.set_first_cluster(cluster);
+                    }
+                    prev_cluster = Some(cluster);
+                }
+                Err(err) if prev_cluster == last_cluster => return Err(err),
+                Err(err) => {
+                    // give back the clusters reserved so far
+                    if let Some(cluster) = last_cluster {
+                        self.fs.truncate_cluster_chain(cluster).await?;
+                    } else if let Some(cluster) = self.context.first_cluster.take() {
+                        self.fs.free_cluster_chain(cluster).await?;
+                        if let Some(ref mut e) = self.context.entry {
+                            e.set_first_cluster(None, self.fs.fat_type());
+                        }
+                    }
+                    if let Some(ref mut e) = self.context.entry {
+                        e.refresh_generation(self.fs);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        self.flush_dir_entry().await
+    }
+
+    /// Reserves one contiguous run of clusters for the first `len` bytes of an empty file.
+    ///
+    /// The file data then occupies a single range of sectors, which can be read without going through the file
+    /// system, e.g. by DMA or a bootloader. Like [`File::allocate`] the size of the file is not changed, write the
+    /// data or call [`File::set_len`] afterwards. If no free run is large enough `policy` decides between failing
+    /// and a fragmented allocation. Returns `true` if the clusters are contiguous.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned if the file already owns clusters, this is a directory, `len`
+    ///   exceeds the maximal file size or the volume is exFAT, which is not supported.
+    /// * `Error::NotEnoughSpace` will be returned if no free run of clusters is large enough and `policy` is
+    ///   `AllocationPolicy::Contiguous`, or if there are not enough free clusters at all.
+    /// * `Error::ReadOnly` will be returned if the file has the `READ_ONLY` attribute or the volume is mounted
+    ///   read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "cluster-bitmap")]
+    pub async fn allocate_contiguous(
+        &mut self,
+        len: u64,
+        policy: AllocationPolicy,
+    ) -> Result<bool, Error<IO::Error>> {
+        trace!("File::allocate_contiguous {} {:?}", len, policy);
+        self.check_writable()?;
+        if self.fs.is_exfat()
+            || self.context.first_cluster.is_some()
+            || self.size().is_none()
+            || len > self.fs.max_file_size()
+        {
+            return Err(Error::InvalidInput);
+        }
+        if len == 0 {
+            return Ok(true);
+        }
+        let count = self.fs.clusters_from_bytes(len);
+        let Some(first_cluster) = self.fs.find_free_run(count, u32::MAX).await? else {
+            return match policy {
+                AllocationPolicy::Contiguous => Err(Error::NotEnoughSpace),
+                AllocationPolicy::BestEffort => {
+                    self.allocate(len).await?;
+                    Ok(false)
+                }
+            };
+        };
+        self.fs.alloc_contiguous_chain(first_cluster, count).await?;
+        self.set_first_cluster(first_cluster);
+        #[cfg(feature = "multi-cluster-io")]
+        {
+            self.context.is_contiguous = true;
+        }
+        self.flush_dir_entry().await?;
+        Ok(true)
+    }
+
     /// Manually close the file
     ///
     /// A [`FileContext`] is returned, which can be used in conjunction with the
@@ -449,6 +1152,12 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
             checkpoints: self.context.checkpoints,
             #[cfg(feature = "cluster-checkpoints")]
             checkpoint_count: self.context.checkpoint_count,
+            #[cfg(feature = "cluster-checkpoints")]
+            checkpoint_interval: self.context.checkpoint_interval,
+            #[cfg(feature = "read-ahead")]
+            read_ahead: crate::read_ahead::ReadAheadBuffer::default(),
+            #[cfg(feature = "write-coalescing")]
+            write_buffer: self.context.write_buffer.clone(),
             #[cfg(feature = "audit-log")]
             logged_read: self.context.logged_read,
             #[cfg(feature = "audit-log")]
@@ -492,6 +1201,12 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
             checkpoints: self.context.checkpoints,
             #[cfg(feature = "cluster-checkpoints")]
             checkpoint_count: self.context.checkpoint_count,
+            #[cfg(feature = "cluster-checkpoints")]
+            checkpoint_interval: self.context.checkpoint_interval,
+            #[cfg(feature = "read-ahead")]
+            read_ahead: crate::read_ahead::ReadAheadBuffer::default(),
+            #[cfg(feature = "write-coalescing")]
+            write_buffer: self.context.write_buffer.clone(),
             #[cfg(feature = "audit-log")]
             logged_read: self.context.logged_read,
             #[cfg(feature = "audit-log")]
@@ -518,6 +1233,9 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
 
 impl<IO: ReadWriteSeek, TP, OCC> Drop for File<'_, IO, TP, OCC> {
     fn drop(&mut self) {
+        if self.is_regular_file() {
+            self.fs.open_files.fetch_sub(1, Ordering::AcqRel);
+        }
         if let Some(e) = &self.context.entry {
             if e.dirty() {
                 error!("CRITICAL: Dropping dirty file before flushing - data loss imminent!");
@@ -528,6 +1246,17 @@ impl<IO: ReadWriteSeek, TP, OCC> Drop for File<'_, IO, TP, OCC> {
                 }
             }
         }
+        #[cfg(feature = "write-coalescing")]
+        if !self.context.write_buffer.is_empty() {
+            error!(
+                "CRITICAL: Dropping file with {} buffered bytes - call flush() before dropping File.",
+                self.context.write_buffer.len()
+            );
+            #[cfg(feature = "dirty-file-panic")]
+            {
+                panic!("Dropping file with unwritten buffered data");
+            }
+        }
 
         #[cfg(feature = "file-locking")]
         if let (Some(lock_info), Some(first_cluster)) = (self.lock_info, self.context.first_cluster) {
@@ -541,12 +1270,18 @@ impl<IO: ReadWriteSeek, TP, OCC> Drop for File<'_, IO, TP, OCC> {
 // This is intentional to prevent lock reference counting issues.
 impl<IO: ReadWriteSeek, TP, OCC> Clone for File<'_, IO, TP, OCC> {
     fn clone(&self) -> Self {
+        #[allow(unused_mut)]
+        let mut context = self.context.clone();
+        // pending writes stay with the original file so they are written once
+        #[cfg(feature = "write-coalescing")]
+        context.write_buffer.clear();
         File {
-            context: self.context.clone(),
+            context,
             fs: self.fs,
             #[cfg(feature = "file-locking")]
             lock_info: None, // Clones don't inherit locks
         }
+        .opened()
     }
 }
 
@@ -561,19 +1296,40 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
     #[allow(clippy::too_many_lines)]
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         trace!("File::read");
+        #[cfg(feature = "write-coalescing")]
+        self.flush_write_buffer().await?;
         let cluster_size = self.fs.cluster_size();
-        let current_cluster_opt = if self.context.offset % cluster_size == 0 {
+        let bytes_left_in_file = self.bytes_left_in_file().unwrap_or(buf.len());
+
+        // exFAT: data past the valid data length has never been written and reads as zeros
+        #[cfg(feature = "exfat")]
+        let bytes_left_in_file = {
+            let valid_len = self
+                .context
+                .entry
+                .as_ref()
+                .and_then(DirEntryEditor::valid_data_length);
+            match valid_len {
+                Some(valid_len) if self.context.offset >= valid_len && bytes_left_in_file > 0 => {
+                    let read_size = cmp::min(buf.len(), bytes_left_in_file);
+                    buf[..read_size].fill(0);
+                    self.seek(SeekFrom::Start(self.context.offset + read_size as u64))
+                        .await?;
+                    return Ok(read_size);
+                }
+                Some(valid_len) => cmp::min(
+                    bytes_left_in_file,
+                    usize::try_from(valid_len - self.context.offset).unwrap_or(usize::MAX),
+                ),
+                None => bytes_left_in_file,
+            }
+        };
+
+        let current_cluster_opt = if self.context.offset % u64::from(cluster_size) == 0 {
             // next cluster
             match self.context.current_cluster {
                 None => self.context.first_cluster,
-                Some(n) => {
-                    let r = self.fs.cluster_iter(n).next().await;
-                    match r {
-                        Some(Err(err)) => return Err(err),
-                        Some(Ok(n)) => Some(n),
-                        None => None,
-                    }
-                }
+                Some(n) => self.next_cluster(n).await?,
             }
         } else {
             self.context.current_cluster
@@ -581,14 +1337,23 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
         let Some(current_cluster) = current_cluster_opt else {
             return Ok(0);
         };
-        let offset_in_cluster = self.context.offset % cluster_size;
-        let bytes_left_in_file = self.bytes_left_in_file().unwrap_or(buf.len());
+        let offset_in_cluster = (self.context.offset % u64::from(cluster_size)) as u32;
+
+        // Small sequential reads are served from the read-ahead buffer
+        #[cfg(feature = "read-ahead")]
+        if let Some(read_bytes) = self
+            .read_ahead(current_cluster, offset_in_cluster, buf, bytes_left_in_file)
+            .await?
+        {
+            return Ok(read_bytes);
+        }
 
         // Phase 2 Optimization: Multi-cluster I/O
         // If reading more than one cluster and multi-cluster-io is enabled, try batched read
+        // (streams without a FAT chain are read cluster by cluster)
         #[cfg(feature = "multi-cluster-io")]
         {
-            if buf.len() > (cluster_size - offset_in_cluster) as usize {
+            if buf.len() > (cluster_size - offset_in_cluster) as usize && self.no_fat_chain_len().is_none() {
                 // Potential multi-cluster read
                 trace!("attempting multi-cluster read");
                 match crate::multi_cluster_io::read_contiguous(
@@ -604,23 +1369,21 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
                         let read_bytes = cmp::min(read_bytes, bytes_left_in_file);
 
                         let old_offset = self.context.offset;
-                        self.context.offset += read_bytes as u32;
+                        self.context.offset += read_bytes as u64;
                         let new_offset = self.context.offset;
 
                         // Update current cluster to match new offset
                         // FAT convention: when at a cluster boundary, current_cluster points to
                         // the previous cluster (the one just finished), not the next cluster.
-                        let old_cluster_index = if old_offset == 0 {
-                            0u32
-                        } else {
-                            (old_offset / cluster_size).saturating_sub(1)
-                        };
+                        // The read started in `current_cluster`.
+                        let cluster_size = u64::from(cluster_size);
+                        let old_cluster_index = (old_offset / cluster_size) as u32;
 
                         let new_cluster_index = if new_offset > 0 && new_offset % cluster_size == 0
                         {
-                            (new_offset / cluster_size).saturating_sub(1)
+                            ((new_offset / cluster_size) as u32).saturating_sub(1)
                         } else {
-                            new_offset / cluster_size
+                            (new_offset / cluster_size) as u32
                         };
 
                         let cluster_delta = new_cluster_index.saturating_sub(old_cluster_index);
@@ -628,8 +1391,7 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
                         if cluster_delta > 0 {
                             let mut cluster = current_cluster;
                             for _i in 0..cluster_delta {
-                                let mut iter = self.fs.cluster_iter(cluster);
-                                if let Some(Ok(next)) = iter.next().await {
+                                if let Ok(Some(next)) = self.next_cluster(cluster).await {
                                     cluster = next;
                                     // Record checkpoint during sequential traversal
                                     #[cfg(feature = "cluster-checkpoints")]
@@ -684,13 +1446,13 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
         if read_bytes == 0 {
             return Ok(0);
         }
-        self.context.offset += read_bytes as u32;
+        self.context.offset += read_bytes as u64;
         self.context.current_cluster = Some(current_cluster);
 
         // Record checkpoint for sequential reads
         #[cfg(feature = "cluster-checkpoints")]
-        if self.context.offset > 0 {
-            let cluster_idx = (self.context.offset / cluster_size).saturating_sub(1);
+        {
+            let cluster_idx = ((self.context.offset - 1) / u64::from(cluster_size)) as u32;
             self.record_checkpoint(cluster_idx, current_cluster);
         }
 
@@ -727,17 +1489,37 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         trace!("File::write");
         let cluster_size = self.fs.cluster_size();
-        let offset_in_cluster = self.context.offset % cluster_size;
-        let bytes_left_until_max_file_size = (MAX_FILE_SIZE - self.context.offset) as usize;
+        let offset_in_cluster = (self.context.offset % u64::from(cluster_size)) as u32;
+        let bytes_left_until_max_file_size =
+            usize::try_from(self.fs.max_file_size() - self.context.offset).unwrap_or(usize::MAX);
 
         // Exit early if we are going to write no data
         if buf.is_empty() || bytes_left_until_max_file_size == 0 {
             return Ok(0);
         }
+        self.check_writable()?;
+        #[cfg(feature = "read-ahead")]
+        self.context.read_ahead.invalidate();
 
         // Mark the volume 'dirty'
         self.fs.set_dirty_flag(true).await?;
 
+        // exFAT: the stream may need a FAT chain and zeroed data before it can be written
+        #[cfg(feature = "exfat")]
+        {
+            self.ensure_fat_chain().await?;
+            self.zero_unwritten_data().await?;
+        }
+
+        // Small writes inside the current cluster are collected in the write buffer
+        #[cfg(feature = "write-coalescing")]
+        if let Some(written_bytes) = self
+            .write_coalesced(&buf[..cmp::min(buf.len(), bytes_left_until_max_file_size)])
+            .await?
+        {
+            return Ok(written_bytes);
+        }
+
         // Phase 2 Optimization: Multi-cluster write for already allocated clusters
         // This provides the flash wear reduction benefit for large sequential writes
         #[cfg(feature = "multi-cluster-io")]
@@ -745,18 +1527,12 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
             // Check if we're at a cluster boundary and writing more than one cluster
             if offset_in_cluster == 0 && buf.len() >= cluster_size as usize {
                 // Get the cluster to write to (advance to next if at boundary, same logic as single-cluster path)
-                let write_cluster = if self.context.offset % cluster_size == 0 {
+                let write_cluster = if self.context.offset % u64::from(cluster_size) == 0 {
                     // At cluster boundary - get next cluster from chain
+                    // (None at the end of chain - fall through to single-cluster to allocate)
                     match self.context.current_cluster {
                         None => self.context.first_cluster,
-                        Some(n) => {
-                            let r = self.fs.cluster_iter(n).next().await;
-                            match r {
-                                Some(Err(err)) => return Err(err),
-                                Some(Ok(next)) => Some(next),
-                                None => None, // End of chain - fall through to single-cluster to allocate
-                            }
-                        }
+                        Some(n) => self.next_cluster(n).await?,
                     }
                 } else {
                     self.context.current_cluster
@@ -779,45 +1555,40 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
                                 cmp::min(written_bytes, bytes_left_until_max_file_size);
 
                             let old_offset = self.context.offset;
-                            self.context.offset += written_bytes as u32;
+                            self.context.offset += written_bytes as u64;
                             let new_offset = self.context.offset;
 
                             // Update current cluster to match new offset
                             // FAT convention: when at a cluster boundary, current_cluster points to
                             // the previous cluster (the one just finished), not the next cluster.
-                            let old_cluster_index = if old_offset == 0 {
-                                0u32
-                            } else {
-                                (old_offset / cluster_size).saturating_sub(1)
-                            };
+                            // The write started at the beginning of `current_cluster`.
+                            let cluster_size = u64::from(cluster_size);
+                            let old_cluster_index = (old_offset / cluster_size) as u32;
 
                             let new_cluster_index =
                                 if new_offset > 0 && new_offset % cluster_size == 0 {
-                                    (new_offset / cluster_size).saturating_sub(1)
+                                    ((new_offset / cluster_size) as u32).saturating_sub(1)
                                 } else {
-                                    new_offset / cluster_size
+                                    (new_offset / cluster_size) as u32
                                 };
 
                             let cluster_delta = new_cluster_index.saturating_sub(old_cluster_index);
 
-                            if cluster_delta > 0 {
-                                let mut cluster = current_cluster;
-                                for _i in 0..cluster_delta {
-                                    let mut iter = self.fs.cluster_iter(cluster);
-                                    if let Some(Ok(next)) = iter.next().await {
-                                        cluster = next;
-                                        // Record checkpoint during sequential write traversal
-                                        #[cfg(feature = "cluster-checkpoints")]
-                                        {
-                                            let cluster_idx = old_cluster_index + _i + 1;
-                                            self.record_checkpoint(cluster_idx, cluster);
-                                        }
-                                    } else {
-                                        break;
+                            let mut cluster = current_cluster;
+                            for _i in 0..cluster_delta {
+                                if let Ok(Some(next)) = self.next_cluster(cluster).await {
+                                    cluster = next;
+                                    // Record checkpoint during sequential write traversal
+                                    #[cfg(feature = "cluster-checkpoints")]
+                                    {
+                                        let cluster_idx = old_cluster_index + _i + 1;
+                                        self.record_checkpoint(cluster_idx, cluster);
                                     }
+                                } else {
+                                    break;
                                 }
-                                self.context.current_cluster = Some(cluster);
                             }
+                            self.context.current_cluster = Some(cluster);
 
                             self.update_dir_entry_after_write().await?;
                             trace!("multi-cluster write: {} bytes", written_bytes);
@@ -859,18 +1630,11 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
             return Ok(0);
         }
         // Get cluster for write possibly allocating new one
-        let current_cluster = if self.context.offset % cluster_size == 0 {
+        let current_cluster = if self.context.offset % u64::from(cluster_size) == 0 {
             // next cluster
             let next_cluster = match self.context.current_cluster {
                 None => self.context.first_cluster,
-                Some(n) => {
-                    let r = self.fs.cluster_iter(n).next().await;
-                    match r {
-                        Some(Err(err)) => return Err(err),
-                        Some(Ok(n)) => Some(n),
-                        None => None,
-                    }
-                }
+                Some(n) => self.next_cluster(n).await?,
             };
             if let Some(n) = next_cluster {
                 n
@@ -884,6 +1648,11 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
                 if self.context.first_cluster.is_none() {
                     self.set_first_cluster(new_cluster);
                 }
+                // exFAT directories record their allocated size
+                #[cfg(feature = "exfat")]
+                if let Some(ref mut e) = self.context.entry {
+                    e.set_dir_len(self.context.offset + u64::from(cluster_size));
+                }
                 new_cluster
             }
         } else {
@@ -900,19 +1669,21 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
         let written_bytes = {
             let mut disk = self.fs.disk.acquire().await;
             disk.seek(SeekFrom::Start(offset_in_fs)).await?;
+            #[cfg(feature = "transaction-safe")]
+            self.fs.journal_write(&mut *disk, write_size as u64).await?;
             disk.write(&buf[..write_size]).await?
         };
         if written_bytes == 0 {
             return Ok(0);
         }
         // some bytes were writter - update position and optionally size
-        self.context.offset += written_bytes as u32;
+        self.context.offset += written_bytes as u64;
         self.context.current_cluster = Some(current_cluster);
 
         // Record checkpoint for sequential writes
         #[cfg(feature = "cluster-checkpoints")]
-        if self.context.offset > 0 {
-            let cluster_idx = (self.context.offset / cluster_size).saturating_sub(1);
+        {
+            let cluster_idx = ((self.context.offset - 1) / u64::from(cluster_size)) as u32;
             self.record_checkpoint(cluster_idx, current_cluster);
         }
 
@@ -947,15 +1718,12 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
     async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
         trace!("File::seek");
         let size_opt = self.size();
-        let new_offset_opt: Option<u32> = match pos {
-            SeekFrom::Current(x) => i64::from(self.context.offset)
-                .checked_add(x)
-                .and_then(|n| u32::try_from(n).ok()),
-            SeekFrom::Start(x) => u32::try_from(x).ok(),
-            SeekFrom::End(o) => size_opt
-                .and_then(|s| i64::from(s).checked_add(o))
-                .and_then(|n| u32::try_from(n).ok()),
+        let new_offset_opt: Option<u64> = match pos {
+            SeekFrom::Current(x) => self.context.offset.checked_add_signed(x),
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::End(o) => size_opt.and_then(|s| s.checked_add_signed(o)),
         };
+        let new_offset_opt = new_offset_opt.filter(|n| *n <= self.fs.max_file_size());
         let Some(mut new_offset) = new_offset_opt else {
             error!("Invalid seek offset");
             return Err(Error::InvalidInput);
@@ -972,10 +1740,12 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
         );
         if new_offset == self.context.offset {
             // position is the same - nothing to do
-            return Ok(u64::from(self.context.offset));
+            return Ok(self.context.offset);
         }
-        let new_offset_in_clusters = self.fs.clusters_from_bytes(u64::from(new_offset));
-        let old_offset_in_clusters = self.fs.clusters_from_bytes(u64::from(self.context.offset));
+        #[cfg(feature = "read-ahead")]
+        self.context.read_ahead.invalidate();
+        let new_offset_in_clusters = self.fs.clusters_from_bytes(new_offset);
+        let old_offset_in_clusters = self.fs.clusters_from_bytes(self.context.offset);
         let new_cluster = if new_offset == 0 {
             None
         } else if new_offset_in_clusters == old_offset_in_clusters {
@@ -989,20 +1759,33 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
 
             // Phase 3 Optimization: Use cluster chain checkpoints for O(log n) seeking
             #[cfg(feature = "cluster-checkpoints")]
-            let (mut cluster, start_index) = self.find_closest_checkpoint(clusters_to_skip);
+            let (mut cluster, start_index) = {
+                let (mut cluster, mut start_index) =
+                    self.find_closest_checkpoint(first_cluster, clusters_to_skip);
+                // seeking forward can continue from the current cluster if it is closer than the checkpoint
+                if let Some(current_cluster) = self.context.current_cluster {
+                    let current_index = old_offset_in_clusters.saturating_sub(1);
+                    if current_index > start_index && current_index <= clusters_to_skip {
+                        cluster = current_cluster;
+                        start_index = current_index;
+                    }
+                }
+                (cluster, start_index)
+            };
 
             #[cfg(not(feature = "cluster-checkpoints"))]
             let (mut cluster, start_index) = (first_cluster, 0);
 
-            let mut iter = self.fs.cluster_iter(cluster);
             for i in start_index..clusters_to_skip {
-                cluster = if let Some(r) = iter.next().await {
-                    r?
+                cluster = if let Some(n) = self.next_cluster(cluster).await? {
+                    n
                 } else {
                     // cluster chain ends before the new position - seek to the end of the last cluster
-                    new_offset = self.fs.bytes_from_clusters(i + 1) as u32;
+                    new_offset = self.fs.bytes_from_clusters(i + 1);
                     break;
                 };
+                #[cfg(feature = "cluster-checkpoints")]
+                self.record_checkpoint(i + 1, cluster);
             }
             Some(cluster)
         } else {
@@ -1012,6 +1795,151 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
         };
         self.context.offset = new_offset;
         self.context.current_cluster = new_cluster;
-        Ok(u64::from(self.context.offset))
+        Ok(self.context.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FsOptions};
+    use crate::test_support::{MB, image, pattern};
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_write_whole_clusters_into_allocated_chain() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let mut file = fs.root_dir().create_file("data.bin").await.unwrap();
+        file.allocate(6 * 512).await.unwrap();
+        // cluster sized and larger writes starting on cluster boundaries of an existing chain
+        file.write_all(&pattern(0, 512)).await.unwrap();
+        file.write_all(&pattern(1, 512)).await.unwrap();
+        file.write_all(&pattern(2, 2 * 512)).await.unwrap();
+        file.write_all(&pattern(4, 512)).await.unwrap();
+        file.seek(SeekFrom::Start(0)).await.unwrap();
+        let mut data = vec![0; 5 * 512];
+        file.read_exact(&mut data).await.unwrap();
+        let expected: Vec<u8> = [
+            pattern(0, 512),
+            pattern(1, 512),
+            pattern(2, 2 * 512),
+            pattern(4, 512),
+        ]
+        .concat();
+        assert_eq!(data, expected);
+        file.flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_multiple_clusters_from_mid_cluster() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let mut file = fs.root_dir().create_file("data.bin").await.unwrap();
+        let expected: Vec<u8> = (0..6).flat_map(|i| pattern(i, 512)).collect();
+        file.write_all(&expected).await.unwrap();
+        file.seek(SeekFrom::Start(0)).await.unwrap();
+        // the second read starts in the middle of the second cluster and spans two more
+        let mut data = vec![0; expected.len()];
+        file.read_exact(&mut data[..600]).await.unwrap();
+        file.read_exact(&mut data[600..1624]).await.unwrap();
+        file.read_exact(&mut data[1624..]).await.unwrap();
+        assert_eq!(data, expected);
+        file.flush().await.unwrap();
+    }
+
+    #[cfg(feature = "cluster-bitmap")]
+    #[tokio::test]
+    async fn test_allocate_contiguous() {
+        use crate::defrag::read_chain;
+
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut file = root.create_file("boot.bin").await.unwrap();
+        assert!(
+            file.allocate_contiguous(40 * 512, AllocationPolicy::Contiguous)
+                .await
+                .unwrap()
+        );
+        let first_cluster = file.first_cluster().unwrap();
+        file.write_all(&pattern(1, 40 * 512)).await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(file.first_cluster(), Some(first_cluster));
+        let chain = read_chain(&fs, first_cluster).await.unwrap();
+        assert!(chain.contiguous);
+        assert_eq!(chain.len, 40);
+
+        // the file must be empty
+        assert!(matches!(
+            file.allocate_contiguous(512, AllocationPolicy::BestEffort)
+                .await,
+            Err(Error::InvalidInput)
+        ));
+
+        // leave only single free clusters between allocated ones
+        let mut a = root.create_file("a.bin").await.unwrap();
+        let mut b = root.create_file("b.bin").await.unwrap();
+        while a.write_all(&[0; 512]).await.is_ok() && b.write_all(&[0; 512]).await.is_ok() {}
+        a.flush().await.unwrap();
+        b.flush().await.unwrap();
+        drop(b);
+        root.remove("b.bin").await.unwrap();
+        let mut file = root.create_file("big.bin").await.unwrap();
+        assert!(matches!(
+            file.allocate_contiguous(4 * 512, AllocationPolicy::Contiguous)
+                .await,
+            Err(Error::NotEnoughSpace)
+        ));
+        assert_eq!(file.first_cluster(), None);
+        assert!(
+            !file
+                .allocate_contiguous(4 * 512, AllocationPolicy::BestEffort)
+                .await
+                .unwrap()
+        );
+        let chain = read_chain(&fs, file.first_cluster().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(chain.len, 4);
+        file.set_len(4 * 512).await.unwrap();
+        file.flush().await.unwrap();
+        assert!(fs.check().await.unwrap().is_clean());
+    }
+
+    #[cfg(all(feature = "cluster-bitmap", feature = "cluster-checkpoints"))]
+    #[tokio::test]
+    async fn test_defragment_resets_checkpoints() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let mut a = root.create_file("a.bin").await.unwrap();
+        let mut b = root.create_file("b.bin").await.unwrap();
+        for i in 0..80 {
+            a.write_all(&pattern(i, 512)).await.unwrap();
+            b.write_all(&pattern(i, 512)).await.unwrap();
+        }
+        b.flush().await.unwrap();
+        drop(b);
+        assert!(a.context.checkpoint_interval > MIN_CHECKPOINT_INTERVAL);
+
+        assert!(a.defragment(|_| {}).await.unwrap());
+        assert_eq!(a.context.checkpoint_count, 0);
+        assert_eq!(a.context.checkpoint_interval, MIN_CHECKPOINT_INTERVAL);
+        a.seek(SeekFrom::Start(0)).await.unwrap();
+        let mut data = vec![0; 80 * 512];
+        a.read_exact(&mut data).await.unwrap();
+        let expected: Vec<u8> = (0..80).flat_map(|i| pattern(i, 512)).collect();
+        assert_eq!(data, expected);
+        a.flush().await.unwrap();
     }
 }
@@ -10,23 +10,33 @@ use core::char;
 use core::cmp;
 use core::fmt::Debug;
 use core::marker::PhantomData;
-use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering};
 
-#[cfg(all(not(feature = "std"), feature = "alloc", feature = "lfn"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
 #[cfg(feature = "std")]
 use embedded_io_adapters::tokio_1::FromTokio;
 
-use crate::boot_sector::{BiosParameterBlock, BootSector, format_boot_sector};
+use crate::boot_sector::{
+    BiosParameterBlock, BootSector, EXT_FLAG_NO_MIRRORING, format_boot_sector,
+    read_backup_boot_sector,
+};
 use crate::dir::{Dir, DirRawStream};
-use crate::dir_entry::{DirFileEntryData, FileAttributes, SFN_PADDING, SFN_SIZE};
+use crate::dir_entry::{
+    DIR_ENTRY_DELETED_FLAG, DIR_ENTRY_REALLY_E5_FLAG, DIR_ENTRY_SIZE, DirFileEntryData,
+    FileAttributes, SFN_PADDING, SFN_SIZE,
+};
 use crate::error::Error;
 use crate::file::File;
 use crate::io::{self, IoBase, Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
+#[cfg(feature = "cluster-bitmap")]
+use crate::table::read_fat;
 use crate::table::{
-    ClusterIterator, RESERVED_FAT_ENTRIES, alloc_cluster, count_free_clusters, format_fat,
-    read_fat_flags,
+    ClusterIterator, RESERVED_FAT_ENTRIES, alloc_cluster, clear_fat_dirty_flag, count_free_clusters,
+    format_fat, read_fat_flags,
 };
+#[cfg(feature = "alloc")]
+use crate::table::{FatValue, write_fat};
 use crate::time::{DefaultTimeProvider, TimeProvider};
 
 #[cfg(all(feature = "alloc", not(feature = "std")))]
@@ -53,8 +63,11 @@ pub(crate) struct DirtyDirEntry {
 /// A type of FAT filesystem.
 ///
 /// `FatType` values are based on the size of File Allocation Table entry.
+///
+/// The enum is non-exhaustive because the `ExFat` variant only exists with the `exfat` feature.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[non_exhaustive]
 pub enum FatType {
     /// 12 bits per FAT entry
     Fat12,
@@ -62,12 +75,17 @@ pub enum FatType {
     Fat16,
     /// 32 bits per FAT entry
     Fat32,
+    /// exFAT volume (32 bits per FAT entry, free space tracked by an allocation bitmap)
+    #[cfg(feature = "exfat")]
+    ExFat,
 }
 
 impl FatType {
     const FAT16_MIN_CLUSTERS: u32 = 4085;
     const FAT32_MIN_CLUSTERS: u32 = 65525;
     const FAT32_MAX_CLUSTERS: u32 = 0x0FFF_FFF4;
+    #[cfg(feature = "exfat")]
+    const EXFAT_MAX_CLUSTERS: u32 = 0xFFFF_FFF5;
 
     pub(crate) fn from_clusters(total_clusters: u32) -> Self {
         if total_clusters < Self::FAT16_MIN_CLUSTERS {
@@ -84,6 +102,8 @@ impl FatType {
             FatType::Fat12 => 12,
             FatType::Fat16 => 16,
             FatType::Fat32 => 32,
+            #[cfg(feature = "exfat")]
+            FatType::ExFat => 32,
         }
     }
 
@@ -92,6 +112,8 @@ impl FatType {
             FatType::Fat12 => 0,
             FatType::Fat16 => Self::FAT16_MIN_CLUSTERS,
             FatType::Fat32 => Self::FAT32_MIN_CLUSTERS,
+            #[cfg(feature = "exfat")]
+            FatType::ExFat => 0,
         }
     }
 
@@ -100,6 +122,8 @@ impl FatType {
             FatType::Fat12 => Self::FAT16_MIN_CLUSTERS - 1,
             FatType::Fat16 => Self::FAT32_MIN_CLUSTERS - 1,
             FatType::Fat32 => Self::FAT32_MAX_CLUSTERS,
+            #[cfg(feature = "exfat")]
+            FatType::ExFat => Self::EXFAT_MAX_CLUSTERS,
         }
     }
 }
@@ -127,7 +151,7 @@ impl FsStatusFlags {
         self.io_error
     }
 
-    fn encode(self) -> u8 {
+    pub(crate) fn encode(self) -> u8 {
         let mut res = 0_u8;
         if self.dirty {
             res |= 1;
@@ -274,9 +298,10 @@ impl FsInfoSector {
 #[derive(Copy, Clone, Debug)]
 pub struct TransactionLogConfig {
     /// Starting sector for transaction log area.
-    /// Set to 0 for automatic placement (before data area).
+    /// Set to 0 for automatic placement (at the end of the reserved sectors).
     pub log_start_sector: u32,
-    /// Number of sectors allocated for transaction log (typically 4)
+    /// Number of sectors allocated for transaction log. The first 4 sectors hold the transaction
+    /// entries, every further sector (up to 64) holds the before-image of one sector modified by a transaction.
     pub log_sector_count: u32,
 }
 
@@ -286,7 +311,7 @@ impl TransactionLogConfig {
     pub fn automatic() -> Self {
         Self {
             log_start_sector: 0, // 0 means automatic placement
-            log_sector_count: 4, // 4 sectors = 2KB (supports 4 concurrent transactions)
+            log_sector_count: crate::transaction::DEFAULT_LOG_SECTORS, // 4 entries + 64 before-images
         }
     }
 }
@@ -298,6 +323,36 @@ impl Default for TransactionLogConfig {
     }
 }
 
+/// Checks that a transaction log area can hold at least one before-image and does not overlap the boot sector,
+/// the FSInfo sector, the backup boot sectors or the FAT.
+#[cfg(feature = "transaction-safe")]
+fn is_transaction_log_area_usable(bpb: &BiosParameterBlock, config: TransactionLogConfig) -> bool {
+    if config.log_sector_count <= crate::transaction::MAX_TRANSACTIONS as u32 {
+        return false;
+    }
+    let log_end = config
+        .log_start_sector
+        .saturating_add(config.log_sector_count);
+    let log = config.log_start_sector..log_end;
+    if log.contains(&0) {
+        return false;
+    }
+    if bpb.is_fat32() {
+        let backup = bpb.backup_boot_sector();
+        let backup_sectors = if backup == 0 {
+            0..0
+        } else {
+            backup..backup + 3
+        };
+        if log.contains(&bpb.fs_info_sector()) || backup_sectors.clone().any(|s| log.contains(&s)) {
+            return false;
+        }
+    }
+    // A log starting in the reserved area must end there too
+    let reserved = bpb.reserved_sectors();
+    log.start >= reserved || log.end <= reserved
+}
+
 /// A FAT filesystem mount options.
 ///
 /// Options are specified as an argument for `FileSystem::new` method.
@@ -305,6 +360,14 @@ impl Default for TransactionLogConfig {
 #[derive(Copy, Clone, Debug, Default)]
 pub struct FsOptions<TP, OCC> {
     pub(crate) update_accessed_date: bool,
+    pub(crate) read_only: bool,
+    pub(crate) backup_boot_sector_fallback: bool,
+    #[cfg(feature = "read-ahead")]
+    pub(crate) read_ahead: usize,
+    #[cfg(feature = "write-coalescing")]
+    pub(crate) write_coalescing: usize,
+    #[cfg(feature = "alloc")]
+    pub(crate) lazy_fat_mirroring: u32,
     pub(crate) oem_cp_converter: OCC,
     pub(crate) time_provider: TP,
     #[cfg(feature = "transaction-safe")]
@@ -319,6 +382,14 @@ impl FsOptions<DefaultTimeProvider, LossyOemCpConverter> {
     pub fn new() -> Self {
         Self {
             update_accessed_date: false,
+            read_only: false,
+            backup_boot_sector_fallback: false,
+            #[cfg(feature = "read-ahead")]
+            read_ahead: 0,
+            #[cfg(feature = "write-coalescing")]
+            write_coalescing: 0,
+            #[cfg(feature = "alloc")]
+            lazy_fat_mirroring: 0,
             oem_cp_converter: LossyOemCpConverter::new(),
             time_provider: DefaultTimeProvider::new(),
             #[cfg(feature = "transaction-safe")]
@@ -337,6 +408,69 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
         self
     }
 
+    /// If enabled the volume is mounted read-only.
+    ///
+    /// Nothing is ever written to the storage of a read-only volume: every operation that would modify it fails
+    /// with `Error::ReadOnly`, the dirty flag is left alone, accessed dates are not updated and `unmount` does not
+    /// update the FS Information Sector.
+    #[must_use]
+    pub fn read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    /// If enabled a FAT32 volume whose boot sector is damaged is mounted using the backup boot sector.
+    ///
+    /// The backup is only used if the primary boot sector is invalid, and only if it is a valid FAT32 boot sector
+    /// at sector 6, the position used by `format_volume` and other formatters. The damaged boot sector is not
+    /// modified: check `FileSystem::used_backup_boot_sector` after mounting and call
+    /// `FileSystem::restore_boot_sector` to rewrite it.
+    #[must_use]
+    pub fn backup_boot_sector_fallback(mut self, enabled: bool) -> Self {
+        self.backup_boot_sector_fallback = enabled;
+        self
+    }
+
+    /// Sets the size in bytes of the read-ahead buffer of every open file. 0 (the default) disables read-ahead.
+    ///
+    /// When a file is read sequentially in chunks smaller than this size, the contiguous clusters following the
+    /// current position are fetched with a single device read and the next reads are served from memory. The
+    /// buffer is allocated on the first prefetch and dropped on seek, write and truncate. Changes made through
+    /// other handles to the same file are not seen until then.
+    #[cfg(feature = "read-ahead")]
+    #[must_use]
+    pub fn read_ahead(mut self, size: usize) -> Self {
+        self.read_ahead = size;
+        self
+    }
+
+    /// Sets the size in bytes of the write coalescing buffer of every open file. 0 (the default) disables it.
+    ///
+    /// Writes smaller than this size inside an already allocated cluster are collected in RAM and written to the
+    /// device together when the buffer is full, when the end of the cluster is reached, before any read, truncate
+    /// or larger write, and on `flush`. Buffered data is lost if the file is dropped without being flushed,
+    /// which panics with the `dirty-file-panic` feature.
+    #[cfg(feature = "write-coalescing")]
+    #[must_use]
+    pub fn write_coalescing(mut self, size: usize) -> Self {
+        self.write_coalescing = size;
+        self
+    }
+
+    /// Defers copying FAT changes to the other FATs until `flush` or `unmount`, or until `max_dirty_sectors`
+    /// FAT sectors wait to be copied. 0 (the default) writes every FAT change to all copies immediately.
+    ///
+    /// In between only the first FAT is written. It stays authoritative and the volume stays marked dirty, so
+    /// after a power loss `FileSystem::repair` makes the other copies identical to it again. With two FATs this
+    /// halves the FAT writes. Has no effect on volumes with a single FAT, FAT32 volumes with mirroring disabled
+    /// and exFAT volumes.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn lazy_fat_mirroring(mut self, max_dirty_sectors: u32) -> Self {
+        self.lazy_fat_mirroring = max_dirty_sectors;
+        self
+    }
+
     /// Changes default OEM code page encoder-decoder.
     pub fn oem_cp_converter<OCC2: OemCpConverter>(
         self,
@@ -344,6 +478,14 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
     ) -> FsOptions<TP, OCC2> {
         FsOptions::<TP, OCC2> {
             update_accessed_date: self.update_accessed_date,
+            read_only: self.read_only,
+            backup_boot_sector_fallback: self.backup_boot_sector_fallback,
+            #[cfg(feature = "read-ahead")]
+            read_ahead: self.read_ahead,
+            #[cfg(feature = "write-coalescing")]
+            write_coalescing: self.write_coalescing,
+            #[cfg(feature = "alloc")]
+            lazy_fat_mirroring: self.lazy_fat_mirroring,
             oem_cp_converter,
             time_provider: self.time_provider,
             #[cfg(feature = "transaction-safe")]
@@ -357,6 +499,14 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
     pub fn time_provider<TP2: TimeProvider>(self, time_provider: TP2) -> FsOptions<TP2, OCC> {
         FsOptions::<TP2, OCC> {
             update_accessed_date: self.update_accessed_date,
+            read_only: self.read_only,
+            backup_boot_sector_fallback: self.backup_boot_sector_fallback,
+            #[cfg(feature = "read-ahead")]
+            read_ahead: self.read_ahead,
+            #[cfg(feature = "write-coalescing")]
+            write_coalescing: self.write_coalescing,
+            #[cfg(feature = "alloc")]
+            lazy_fat_mirroring: self.lazy_fat_mirroring,
             oem_cp_converter: self.oem_cp_converter,
             time_provider,
             #[cfg(feature = "transaction-safe")]
@@ -369,7 +519,9 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
     /// Enable transaction-safe mode with automatic log placement.
     ///
     /// This enables power-loss resilient metadata writes using a two-phase commit protocol.
-    /// The transaction log will be automatically placed in reserved sectors before the data area.
+    /// The transaction log will be automatically placed in the last reserved sectors, which
+    /// `FormatVolumeOptions::with_transaction_log` sets aside when the volume is created. If the volume does not
+    /// have enough reserved sectors the log is disabled and `FileSystem::with_transaction` fails.
     ///
     /// Note: The log location is calculated at mount time based on the filesystem layout,
     /// not when this method is called. This ensures correct placement regardless of volume size.
@@ -398,12 +550,13 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
     ///
     /// # Arguments
     /// * `log_start_sector` - First sector to use for transaction log
-    /// * `log_sector_count` - Number of sectors to allocate (typically 4)
+    /// * `log_sector_count` - Number of sectors to allocate: 4 for the transaction entries plus one for every
+    ///   sector a single transaction may modify (at most 64)
     ///
     /// # Example
     /// ```ignore
     /// let options = FsOptions::new()
-    ///     .with_transaction_log_at(100, 8); // 8 sectors for larger log
+    ///     .with_transaction_log_at(100, 20); // room for 16 modified sectors per transaction
     /// let fs = FileSystem::new(disk, options).await?;
     /// ```
     #[cfg(feature = "transaction-safe")]
@@ -462,6 +615,12 @@ impl FileSystemStats {
     }
 }
 
+/// Storage of a filesystem, with `tiny-mode` behind the sector window shared by all accesses
+#[cfg(not(feature = "tiny-mode"))]
+pub(crate) type Disk<IO> = IO;
+#[cfg(feature = "tiny-mode")]
+pub(crate) type Disk<IO> = crate::sector_window::SectorWindow<IO>;
+
 /// A FAT filesystem object.
 ///
 /// `FileSystem` struct is representing a state of a mounted FAT volume.
@@ -469,7 +628,7 @@ pub struct FileSystem<IO: Read + Write + Seek, TP, OCC>
 where
     IO::Error: 'static,
 {
-    pub(crate) disk: Shared<IO>,
+    pub(crate) disk: Shared<Disk<IO>>,
     pub(crate) options: FsOptions<TP, OCC>,
     fat_type: FatType,
     bpb: BiosParameterBlock,
@@ -480,14 +639,23 @@ where
     /// Status flags stored as atomic u8 for thread safety (Send + Sync)
     /// Bit 0: dirty, Bit 1: io_error
     current_status_flags: AtomicU8,
+    /// Status flags read from the boot sector on mount. The dirty flag is cleared by a successful repair.
+    mount_status_flags: AtomicU8,
+    /// Set if the volume was mounted using the backup boot sector, cleared by `restore_boot_sector`
+    used_backup_boot_sector: AtomicBool,
     /// Generation counter incremented on cluster deallocation
     /// Used to detect stale directory entry positions
     pub(crate) cluster_generation: AtomicU64,
+    /// Number of `File` objects of regular files, directories are not counted
+    pub(crate) open_files: AtomicU32,
     /// Registry of dirty directory entries awaiting flush.
     /// Used to prevent directory entry cache corruption when multiple
     /// files are created/modified in the same directory.
     #[cfg(feature = "alloc")]
     pub(crate) dirty_dir_entries: Shared<Vec<DirtyDirEntry>>,
+    /// FAT sectors not yet copied to the other FATs (see `FsOptions::lazy_fat_mirroring`)
+    #[cfg(feature = "alloc")]
+    deferred_mirrors: Shared<crate::fat_mirror::DeferredMirrors>,
     #[cfg(feature = "fat-cache")]
     pub(crate) fat_cache: Shared<crate::fat_cache::FatCache>,
     #[cfg(feature = "dir-cache")]
@@ -496,10 +664,22 @@ where
     pub(crate) cluster_bitmap: Shared<crate::cluster_bitmap::ClusterBitmap>,
     #[cfg(feature = "transaction-safe")]
     pub(crate) transaction_log: Shared<crate::transaction::TransactionLog>,
+    /// Held for the duration of a transaction, transactions share the log's before-image area
+    #[cfg(feature = "transaction-safe")]
+    transaction_lock: Shared<()>,
     #[cfg(feature = "file-locking")]
     pub(crate) file_locks: Shared<crate::file_locking::FileLockManager>,
     #[cfg(feature = "audit-log")]
     pub(crate) audit_log: Shared<crate::audit::AuditLog>,
+    /// exFAT specific volume state, `None` for FAT12/16/32 volumes
+    #[cfg(feature = "exfat")]
+    pub(crate) exfat: Option<crate::exfat::ExFatVolume>,
+    /// Set by `enable_discard` if released clusters are discarded on the storage
+    #[cfg(feature = "discard")]
+    discard: Option<crate::discard::DiscardFn<IO>>,
+    /// Clusters released by the active transaction, discarded once it is committed
+    #[cfg(all(feature = "discard", feature = "transaction-safe"))]
+    pending_discards: Shared<crate::discard::ClusterRuns>,
 }
 
 /// The underlying storage device
@@ -529,7 +709,8 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     ///
     /// Supplied `storage` parameter cannot be seeked. If there is a need to read a fragment of disk
     /// image (e.g. partition) library user should wrap the file struct in a struct limiting
-    /// access to partition bytes only e.g. `fscommon::StreamSlice`.
+    /// access to partition bytes only e.g. [`StreamSlice`](crate::StreamSlice), or use
+    /// [`FileSystem::open_partition`] to mount a partition of an MBR-partitioned disk.
     ///
     /// Note: creating multiple filesystem objects with a single underlying storage can
     /// cause a filesystem corruption.
@@ -547,7 +728,7 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     /// let fs = FileSystem::new(disk, options).await?;
     ///
     /// // Or specify custom log location
-    /// let options = FsOptions::new().with_transaction_log_at(100, 8);
+    /// let options = FsOptions::new().with_transaction_log_at(100, 20);
     /// let fs = FileSystem::new(disk, options).await?;
     /// ```
     ///
@@ -564,24 +745,51 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     /// Panics in non-optimized build if `storage` position returned by `seek` is not zero.
     pub async fn new<T: IntoStorage<IO>>(
         storage: T,
-        options: FsOptions<TP, OCC>,
+        mut options: FsOptions<TP, OCC>,
     ) -> Result<Self, Error<IO::Error>> {
         // Make sure given image is not seeked
+        #[cfg(not(feature = "tiny-mode"))]
         let mut disk = storage.into_storage();
+        #[cfg(feature = "tiny-mode")]
+        let mut disk = crate::sector_window::SectorWindow::new(storage.into_storage());
         trace!("FileSystem::new");
         debug_assert!(disk.seek(SeekFrom::Current(0)).await? == 0);
 
-        // read boot sector
-        let bpb = {
-            let boot = BootSector::deserialize(&mut disk).await?;
-            boot.validate()?;
-            boot.bpb
-        };
+        // exFAT boot sectors have no BIOS Parameter Block, so they are recognised by their file system name first
+        #[cfg(feature = "exfat")]
+        let exfat = crate::exfat::ExFatVolume::probe(&mut disk).await?;
+        #[cfg(feature = "exfat")]
+        let exfat_layout = exfat.as_ref().map(|volume| {
+            let boot = &volume.boot;
+            (volume.bpb(), FatType::ExFat, boot.cluster_heap_offset, boot.cluster_count)
+        });
+        #[cfg(not(feature = "exfat"))]
+        let exfat_layout = None;
 
+        let mut used_backup_boot_sector = false;
+        let (bpb, fat_type, first_data_sector, total_clusters) = if let Some(layout) = exfat_layout {
+            layout
+        } else {
+            // read boot sector
+            let primary = match BootSector::deserialize(&mut disk).await {
+                Ok(boot) => boot.validate().map(|()| boot),
+                Err(err) => Err(err),
+            };
+            let bpb = match primary {
+                Ok(boot) => boot.bpb,
+                Err(Error::CorruptedFileSystem) if options.backup_boot_sector_fallback => {
+                    warn!("Boot sector is invalid, mounting using the backup boot sector");
+                    used_backup_boot_sector = true;
+                    read_backup_boot_sector(&mut disk).await?.bpb
+                }
+                Err(err) => return Err(err),
+            };
+            let first_data_sector = bpb.first_data_sector();
+            let total_clusters = bpb.total_clusters();
+            let fat_type = FatType::from_clusters(total_clusters);
+            (bpb, fat_type, first_data_sector, total_clusters)
+        };
         let root_dir_sectors = bpb.root_dir_sectors();
-        let first_data_sector = bpb.first_data_sector();
-        let total_clusters = bpb.total_clusters();
-        let fat_type = FatType::from_clusters(total_clusters);
 
         // read FSInfo sector if this is FAT32
         let mut fs_info = if fat_type == FatType::Fat32 {
@@ -606,14 +814,31 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         let status_flags = bpb.status_flags();
         #[cfg(feature = "fat-cache")]
         let sector_size = u32::from(bpb.bytes_per_sector);
+        #[cfg(feature = "transaction-safe")]
+        let bytes_per_sector = bpb.bytes_per_sector;
 
         // Extract transaction log config before moving options
         #[cfg(feature = "transaction-safe")]
         let transaction_log_config = {
-            let mut config = options.transaction_log_config.unwrap_or_else(TransactionLogConfig::automatic);
-            // If log_start_sector is 0, calculate automatic placement
+            let mut config = options
+                .transaction_log_config
+                .unwrap_or_else(TransactionLogConfig::automatic);
+            // If log_start_sector is 0, place the log at the end of the reserved sectors
             if config.log_start_sector == 0 {
-                config.log_start_sector = first_data_sector.saturating_sub(4);
+                config.log_start_sector = bpb
+                    .reserved_sectors()
+                    .saturating_sub(config.log_sector_count);
+            }
+            #[cfg(feature = "exfat")]
+            let usable = fat_type != FatType::ExFat && is_transaction_log_area_usable(&bpb, config);
+            #[cfg(not(feature = "exfat"))]
+            let usable = is_transaction_log_area_usable(&bpb, config);
+            if !usable {
+                trace!(
+                    "Transaction log disabled: {} sectors at sector {} do not fit the volume layout",
+                    config.log_sector_count, config.log_start_sector
+                );
+                config.log_sector_count = 0;
             }
             config
         };
@@ -630,19 +855,9 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
                 options.audit_config
             };
 
-            // If log_start_sector is 0, calculate automatic placement
-            // Place after transaction log (if enabled) or before data area
+            // If log_start_sector is 0, calculate automatic placement before the data area
             if config.log_start_sector == 0 {
-                #[cfg(feature = "transaction-safe")]
-                {
-                    config.log_start_sector = transaction_log_config.log_start_sector
-                        .saturating_sub(config.log_sector_count);
-                }
-                #[cfg(not(feature = "transaction-safe"))]
-                {
-                    config.log_start_sector = first_data_sector
-                        .saturating_sub(config.log_sector_count);
-                }
+                config.log_start_sector = first_data_sector.saturating_sub(config.log_sector_count);
             }
             trace!("Audit log config: {} sectors starting at sector {}, total_sectors={}",
                    config.log_sector_count, config.log_start_sector, total_sectors);
@@ -651,6 +866,11 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
 
         trace!("FileSystem::new end");
 
+        // Updating accessed dates would write to a read-only volume
+        if options.read_only {
+            options.update_accessed_date = false;
+        }
+
         let fs = Self {
             disk: Shared::new(disk),
             options,
@@ -661,9 +881,14 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             total_clusters,
             fs_info: Shared::new(fs_info),
             current_status_flags: AtomicU8::new(status_flags.encode()),
+            mount_status_flags: AtomicU8::new(status_flags.encode()),
+            used_backup_boot_sector: AtomicBool::new(used_backup_boot_sector),
             cluster_generation: AtomicU64::new(0),
+            open_files: AtomicU32::new(0),
             #[cfg(feature = "alloc")]
             dirty_dir_entries: Shared::new(Vec::new()),
+            #[cfg(feature = "alloc")]
+            deferred_mirrors: Shared::new(crate::fat_mirror::DeferredMirrors::default()),
             #[cfg(feature = "fat-cache")]
             fat_cache: Shared::new(crate::fat_cache::FatCache::new(sector_size)),
             #[cfg(feature = "dir-cache")]
@@ -671,37 +896,35 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             #[cfg(feature = "cluster-bitmap")]
             cluster_bitmap: Shared::new(crate::cluster_bitmap::ClusterBitmap::new(total_clusters)),
             #[cfg(feature = "transaction-safe")]
-            transaction_log: Shared::new(crate::transaction::TransactionLog::new(
-                transaction_log_config.log_start_sector,
-                transaction_log_config.log_sector_count,
-            )),
+            transaction_log: Shared::new({
+                let mut tx_log = crate::transaction::TransactionLog::new(
+                    transaction_log_config.log_start_sector,
+                    transaction_log_config.log_sector_count,
+                );
+                tx_log.set_bytes_per_sector(bytes_per_sector);
+                tx_log
+            }),
+            #[cfg(feature = "transaction-safe")]
+            transaction_lock: Shared::new(()),
             #[cfg(feature = "file-locking")]
             file_locks: Shared::new(crate::file_locking::FileLockManager::new()),
             #[cfg(feature = "audit-log")]
             audit_log: Shared::new(crate::audit::AuditLog::new(audit_config)),
+            #[cfg(feature = "exfat")]
+            exfat,
+            #[cfg(feature = "discard")]
+            discard: None,
+            #[cfg(all(feature = "discard", feature = "transaction-safe"))]
+            pending_discards: Shared::new(crate::discard::ClusterRuns::default()),
         };
 
-        // Build cluster bitmap from FAT (one-time cost at mount for 10-100x allocation speedup)
-        #[cfg(feature = "cluster-bitmap")]
-        {
-            trace!("Building cluster bitmap from FAT...");
-            let mut bitmap = fs.cluster_bitmap.acquire().await;
-            let mut fat = fs.fat_slice();
-            bitmap
-                .build_from_fat(&mut fat, fat_type, total_clusters)
-                .await?;
-            trace!(
-                "Cluster bitmap built: {} free clusters",
-                bitmap.free_count()
-            );
-        }
-
         // Initialize and recover transaction log (power-loss resilience)
+        // (before anything is derived from the FAT, which may be rolled back)
         #[cfg(feature = "transaction-safe")]
-        {
+        if fs.transaction_log.acquire().await.is_enabled() {
             trace!("Loading transaction log for recovery...");
-            let mut tx_log = fs.transaction_log.acquire().await;
             let mut disk = fs.disk.acquire().await;
+            let mut tx_log = fs.transaction_log.acquire().await;
 
             // Load transaction log from disk
             tx_log.load(&mut *disk).await?;
@@ -712,46 +935,64 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             #[cfg(feature = "std")]
             use std::vec::Vec;
 
-            // Collect slots and states first to avoid borrow checker issues
-            let incomplete: Vec<_> = tx_log
-                .get_incomplete_transactions()
-                .map(|(slot, entry)| (slot, entry.state, entry.tx_type))
+            // Collect slots first to avoid borrow checker issues; committed transactions have all of
+            // their writes on disk and only need their entry cleared
+            let unfinished: Vec<_> = tx_log
+                .get_all_transaction_info()
+                .into_iter()
+                .flatten()
+                .filter(|info| info.state != crate::transaction::TransactionState::Empty)
+                .map(|info| (info.slot, info.state, info.tx_type))
                 .collect();
 
-            if !incomplete.is_empty() {
+            if !unfinished.is_empty() && fs.options.read_only {
+                warn!(
+                    "Found {} incomplete transaction(s), skipping recovery on a read-only volume",
+                    unfinished.len()
+                );
+            } else if !unfinished.is_empty() {
                 warn!(
                     "Found {} incomplete transaction(s), performing recovery...",
-                    incomplete.len()
+                    unfinished.len()
                 );
-                for (slot, state, tx_type) in incomplete {
+                for (slot, state, tx_type) in unfinished {
                     warn!("Recovering transaction slot {}: {:?}", slot, tx_type);
-                    // For pending transactions, we roll back (clear the intent)
-                    // For in-progress transactions, we attempt to complete them
-                    match state {
-                        crate::transaction::TransactionState::Pending => {
-                            // Transaction was logged but not started - safe to clear
-                            tx_log.clear(&mut *disk, slot).await?;
-                            info!("Rolled back pending transaction slot {}", slot);
-                        }
-                        crate::transaction::TransactionState::InProgress => {
-                            // Transaction was in progress - may be partially complete
-                            // For now, we clear it (conservative approach)
-                            // In the future, we could add logic to verify and complete/rollback
-                            tx_log.clear(&mut *disk, slot).await?;
-                            warn!(
-                                "Cleared in-progress transaction slot {} (conservative recovery)",
-                                slot
-                            );
-                        }
-                        _ => {}
+                    if state == crate::transaction::TransactionState::Committed {
+                        tx_log.clear(&mut *disk, slot).await?;
+                        info!("Cleared committed transaction slot {}", slot);
+                    } else if tx_log.rollback(&mut *disk, slot).await? {
+                        info!("Rolled back transaction slot {}", slot);
+                    } else {
+                        error!(
+                            "Before-images of transaction slot {} are corrupted, the transaction was not rolled back",
+                            slot
+                        );
                     }
                 }
+                // The FSInfo free cluster count may describe the state that was rolled back
+                fs.fs_info.acquire().await.free_cluster_count = None;
                 info!("Transaction recovery complete");
             } else {
                 trace!("No incomplete transactions found");
             }
         }
 
+        // Build cluster bitmap from FAT (one-time cost at mount for 10-100x allocation speedup)
+        // (exFAT volumes have an on-disk allocation bitmap which is used instead)
+        #[cfg(feature = "cluster-bitmap")]
+        if !fs.is_exfat() {
+            trace!("Building cluster bitmap from FAT...");
+            let mut bitmap = fs.cluster_bitmap.acquire().await;
+            let mut fat = fs.fat_slice();
+            bitmap
+                .build_from_fat(&mut fat, fat_type, total_clusters)
+                .await?;
+            trace!(
+                "Cluster bitmap built: {} free clusters",
+                bitmap.free_count()
+            );
+        }
+
         // Load audit log from disk
         #[cfg(feature = "audit-log")]
         {
@@ -775,6 +1016,53 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         self.fat_type
     }
 
+    pub(crate) fn bpb(&self) -> &BiosParameterBlock {
+        &self.bpb
+    }
+
+    pub(crate) fn total_clusters(&self) -> u32 {
+        self.total_clusters
+    }
+
+    /// Returns `true` if the mounted volume is exFAT.
+    #[cfg_attr(not(feature = "exfat"), allow(clippy::unused_self))]
+    pub(crate) fn is_exfat(&self) -> bool {
+        #[cfg(feature = "exfat")]
+        {
+            self.exfat.is_some()
+        }
+        #[cfg(not(feature = "exfat"))]
+        {
+            false
+        }
+    }
+
+    /// Returns `true` if the volume is mounted read-only.
+    ///
+    /// See [`FsOptions::read_only`].
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.options.read_only
+    }
+
+    /// Fails with `Error::ReadOnly` if the volume is mounted read-only.
+    pub(crate) fn check_writable(&self) -> Result<(), Error<IO::Error>> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Returns the maximal size of a single file.
+    pub(crate) fn max_file_size(&self) -> u64 {
+        if self.is_exfat() {
+            // exFAT sizes are 64-bit, keep offsets representable by `SeekFrom`
+            i64::MAX as u64
+        } else {
+            u64::from(u32::MAX)
+        }
+    }
+
     /// Returns a volume identifier read from BPB in the Boot Sector.
     pub fn volume_id(&self) -> u32 {
         self.bpb.volume_id
@@ -805,26 +1093,159 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
                 .sectors_from_clusters(cluster - RESERVED_FAT_ENTRIES)
     }
 
+    /// Returns the size of a sector in bytes.
+    #[must_use]
+    pub fn bytes_per_sector(&self) -> u16 {
+        self.bpb.bytes_per_sector
+    }
+
+    /// Returns the sector of the underlying device where the volume starts, taken from the hidden sectors field of
+    /// the boot sector (the partition offset on exFAT).
+    pub(crate) fn partition_start_sector(&self) -> u64 {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            return exfat.boot.partition_offset;
+        }
+        u64::from(self.bpb.hidden_sectors)
+    }
+
     pub fn cluster_size(&self) -> u32 {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            return exfat.cluster_size();
+        }
         self.bpb.cluster_size()
     }
 
     pub(crate) fn offset_from_cluster(&self, cluster: u32) -> u64 {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            return exfat.offset_from_cluster(cluster);
+        }
         self.offset_from_sector(self.sector_from_cluster(cluster))
     }
 
     pub(crate) fn bytes_from_clusters(&self, clusters: u32) -> u64 {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            return exfat.bytes_from_clusters(clusters);
+        }
         self.bpb
             .bytes_from_sectors(self.bpb.sectors_from_clusters(clusters))
     }
 
     pub(crate) fn clusters_from_bytes(&self, bytes: u64) -> u32 {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            return exfat.clusters_from_bytes(bytes);
+        }
         self.bpb.clusters_from_bytes(bytes)
     }
 
-    fn fat_slice(&self) -> impl ReadWriteSeek<Error = Error<IO::Error>> + '_ {
+    /// Returns the FAT region without caching.
+    fn raw_fat_slice(&self) -> FatSlice<'_, IO, TP, OCC> {
         let io = FsIoAdapter { fs: self };
-        let disk_slice = fat_slice(io, &self.bpb);
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            // Note: the second exFAT FAT is only used by TexFAT and is not kept in sync
+            let (begin, size) = exfat.fat_range();
+            return FatSlice::new(DiskSlice::new(begin, size, 1, io), self);
+        }
+        FatSlice::new(fat_slice(io, &self.bpb), self)
+    }
+
+    /// Records FAT changes written to the first FAT only and copies them to the other FATs once
+    /// `FsOptions::lazy_fat_mirroring` sectors are waiting.
+    #[cfg(feature = "alloc")]
+    async fn defer_fat_mirror(&self, offset: u64, len: usize) -> Result<(), Error<IO::Error>> {
+        let sector_size = u64::from(self.bpb.bytes_per_sector);
+        let first = (offset / sector_size) as u32;
+        let last = ((offset + len as u64 - 1) / sector_size) as u32;
+        let pending = {
+            let mut deferred = self.deferred_mirrors.acquire().await;
+            deferred.mark(first, last);
+            deferred.len()
+        };
+        if pending >= self.options.lazy_fat_mirroring as usize {
+            self.sync_fat_mirrors().await?;
+        }
+        Ok(())
+    }
+
+    /// Copies the FAT sectors recorded by lazy mirroring from the first FAT to the other ones.
+    #[cfg(feature = "alloc")]
+    async fn sync_fat_mirrors(&self) -> Result<(), Error<IO::Error>> {
+        let sectors = self.deferred_mirrors.acquire().await.take();
+        if sectors.is_empty() {
+            return Ok(());
+        }
+        let sector_size = u64::from(self.bpb.bytes_per_sector);
+        let mut primary = self.fat_copy_slice(0);
+        let mut buf = [0_u8; 512];
+        for &sector in &sectors {
+            let start = u64::from(sector) * sector_size;
+            for offset in (start..start + sector_size).step_by(buf.len()) {
+                primary.seek(SeekFrom::Start(offset)).await?;
+                primary.read_exact(&mut buf).await?;
+                for fat in 1..self.bpb.fats {
+                    let mut mirror = self.fat_copy_slice(fat);
+                    mirror.seek(SeekFrom::Start(offset)).await?;
+                    mirror.write_all(&buf).await?;
+                }
+            }
+        }
+        trace!("Copied {} FAT sectors to the other FATs", sectors.len());
+        Ok(())
+    }
+
+    /// Returns true if FAT changes in `len` bytes at `offset` of the FAT have not been copied to the
+    /// other FATs yet.
+    #[cfg(feature = "alloc")]
+    pub(crate) async fn fat_mirror_pending(&self, offset: u64, len: u64) -> bool {
+        let sector_size = u64::from(self.bpb.bytes_per_sector);
+        let first = (offset / sector_size) as u32;
+        let last = ((offset + len - 1) / sector_size) as u32;
+        self.deferred_mirrors
+            .acquire()
+            .await
+            .contains_any(first, last)
+    }
+
+    /// Returns a single copy of the FAT, bypassing the cache and mirroring.
+    pub(crate) fn fat_copy_slice(&self, index: u8) -> DiskSlice<FsIoAdapter<'_, IO, TP, OCC>> {
+        let sectors_per_fat = self.bpb.sectors_per_fat();
+        let first_sector = self.bpb.reserved_sectors() + u32::from(index) * sectors_per_fat;
+        DiskSlice::from_sectors(first_sector, sectors_per_fat, 1, &self.bpb, FsIoAdapter { fs: self })
+    }
+
+    /// Returns the fixed root directory region of a FAT12/FAT16 volume.
+    pub(crate) fn fixed_root_dir_slice(&self) -> DiskSlice<FsIoAdapter<'_, IO, TP, OCC>> {
+        DiskSlice::from_sectors(
+            self.first_data_sector - self.root_dir_sectors,
+            self.root_dir_sectors,
+            1,
+            &self.bpb,
+            FsIoAdapter { fs: self },
+        )
+    }
+
+    /// Returns the data of a single cluster.
+    pub(crate) fn cluster_slice(&self, cluster: u32) -> DiskSlice<FsIoAdapter<'_, IO, TP, OCC>> {
+        DiskSlice::new(
+            self.offset_from_cluster(cluster),
+            u64::from(self.cluster_size()),
+            1,
+            FsIoAdapter { fs: self },
+        )
+    }
+
+    /// Returns a single directory entry stored at an absolute position.
+    pub(crate) fn dir_entry_slice(&self, pos: u64) -> DiskSlice<FsIoAdapter<'_, IO, TP, OCC>> {
+        DiskSlice::new(pos, u64::from(DIR_ENTRY_SIZE), 1, FsIoAdapter { fs: self })
+    }
+
+    pub(crate) fn fat_slice(&self) -> impl ReadWriteSeek<Error = Error<IO::Error>> + '_ {
+        let disk_slice = self.raw_fat_slice();
 
         #[cfg(feature = "fat-cache")]
         {
@@ -850,14 +1271,34 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         &self,
         cluster: u32,
     ) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        #[cfg(feature = "exfat")]
+        if self.exfat.is_some() {
+            // Release the clusters in the allocation bitmap before the FAT chain is cut
+            if let Some(next) = self.cluster_iter(cluster).next().await.transpose()? {
+                self.free_exfat_chain(next).await?;
+            }
+        }
+        #[cfg(feature = "transaction-safe")]
+        self.note_released_chain(cluster, true).await?;
         let mut iter = self.cluster_iter(cluster);
         let num_free = iter.truncate().await?;
+        #[cfg(feature = "discard")]
+        self.discard_clusters(iter.take_released()).await?;
         let mut fs_info = self.fs_info.acquire().await;
         fs_info.map_free_clusters(|n| n + num_free);
         Ok(())
     }
 
     pub(crate) async fn free_cluster_chain(&self, cluster: u32) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        #[cfg(feature = "exfat")]
+        if self.exfat.is_some() {
+            self.free_exfat_chain(cluster).await?;
+        }
+        #[cfg(feature = "transaction-safe")]
+        self.note_released_chain(cluster, false).await?;
+
         // Collect clusters to free (for bitmap update)
         #[cfg(feature = "cluster-bitmap")]
         let mut clusters_to_free = {
@@ -884,6 +1325,8 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         // Free the cluster chain
         let mut iter = self.cluster_iter(cluster);
         let num_free = iter.free().await?;
+        #[cfg(feature = "discard")]
+        self.discard_clusters(iter.take_released()).await?;
 
         // Update bitmap
         #[cfg(feature = "cluster-bitmap")]
@@ -904,6 +1347,229 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         Ok(())
     }
 
+    /// Frees clusters that do not form a proper chain, such as the lost chains found by the consistency checker.
+    #[cfg(feature = "alloc")]
+    pub(crate) async fn free_clusters(&self, clusters: &[u32]) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        #[cfg(feature = "transaction-safe")]
+        {
+            let mut tx_log = self.transaction_log.acquire().await;
+            for &cluster in clusters {
+                tx_log.note_released_cluster(cluster);
+            }
+        }
+
+        #[cfg(feature = "discard")]
+        let mut runs = crate::discard::ClusterRuns::default();
+        {
+            let mut fat = self.fat_slice();
+            for &cluster in clusters {
+                write_fat(&mut fat, self.fat_type(), cluster, FatValue::Free).await?;
+                #[cfg(feature = "discard")]
+                runs.push(cluster);
+            }
+        }
+        #[cfg(feature = "discard")]
+        self.discard_clusters(runs).await?;
+
+        #[cfg(feature = "cluster-bitmap")]
+        {
+            let mut bitmap = self.cluster_bitmap.acquire().await;
+            for &cluster in clusters {
+                bitmap.set_free(cluster);
+            }
+        }
+
+        let mut fs_info = self.fs_info.acquire().await;
+        fs_info.map_free_clusters(|n| n + clusters.len() as u32);
+
+        // Increment generation counter to invalidate cached directory entry positions
+        self.cluster_generation.fetch_add(1, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Releases the clusters of a FAT chain in the exFAT allocation bitmap. FAT entries are left untouched.
+    ///
+    /// The clusters are discarded when the caller releases the FAT chain afterwards.
+    #[cfg(feature = "exfat")]
+    async fn free_exfat_chain(&self, cluster: u32) -> Result<(), Error<IO::Error>> {
+        let Some(exfat) = &self.exfat else {
+            return Ok(());
+        };
+        let mut clusters = Vec::new();
+        let mut iter = self.cluster_iter(cluster);
+        while let Some(result) = iter.next().await {
+            clusters.push(result?);
+        }
+        let mut io = FsIoAdapter { fs: self };
+        for c in clusters {
+            exfat.set_bitmap_range(&mut io, c, 1, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Discards released clusters on the storage if enabled with `enable_discard`.
+    ///
+    /// The FAT cache is written first, so the discarded clusters are marked free on the volume. Clusters released
+    /// by an active transaction are queued instead, a rollback makes them part of their old chains again.
+    #[cfg(feature = "discard")]
+    async fn discard_clusters(
+        &self,
+        runs: crate::discard::ClusterRuns,
+    ) -> Result<(), Error<IO::Error>> {
+        let Some(discard) = self.discard else {
+            return Ok(());
+        };
+        if runs.is_empty() {
+            return Ok(());
+        }
+        #[cfg(feature = "transaction-safe")]
+        if self
+            .transaction_log
+            .acquire()
+            .await
+            .active_transaction()
+            .is_some()
+        {
+            self.pending_discards.acquire().await.append(runs);
+            return Ok(());
+        }
+        #[cfg(feature = "fat-cache")]
+        {
+            let mut cache = self.fat_cache.acquire().await;
+            let mut disk_slice = self.raw_fat_slice();
+            cache.flush(&mut disk_slice).await?;
+        }
+        let cluster_size = u64::from(self.cluster_size());
+        let mut disk = self.disk.acquire().await;
+        for (first, count) in runs.iter() {
+            let offset = self.offset_from_cluster(first);
+            let len = u64::from(count) * cluster_size;
+            #[cfg(feature = "tiny-mode")]
+            let io = disk.discard_range(offset, len).await?;
+            #[cfg(not(feature = "tiny-mode"))]
+            let io = &mut *disk;
+            discard(io, offset, len).await;
+        }
+        Ok(())
+    }
+
+    /// Frees a contiguous run of clusters of an exFAT stream that has no FAT chain.
+    #[cfg(feature = "exfat")]
+    pub(crate) async fn free_contiguous_clusters(
+        &self,
+        first_cluster: u32,
+        count: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        if let Some(exfat) = &self.exfat {
+            exfat
+                .set_bitmap_range(&mut FsIoAdapter { fs: self }, first_cluster, count, false)
+                .await?;
+            #[cfg(feature = "discard")]
+            {
+                let mut runs = crate::discard::ClusterRuns::default();
+                for cluster in first_cluster..first_cluster + count {
+                    runs.push(cluster);
+                }
+                self.discard_clusters(runs).await?;
+            }
+            self.fs_info
+                .acquire()
+                .await
+                .map_free_clusters(|n| n + count);
+            self.cluster_generation.fetch_add(1, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Writes a FAT chain for a contiguous run of clusters.
+    ///
+    /// exFAT streams flagged with `NoFatChain` have no valid FAT entries. Before such a stream can grow or shrink
+    /// the chain has to be materialized.
+    #[cfg(feature = "exfat")]
+    pub(crate) async fn write_contiguous_chain(
+        &self,
+        first_cluster: u32,
+        count: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        let mut fat = self.fat_slice();
+        let last_cluster = first_cluster + count - 1;
+        for cluster in first_cluster..last_cluster {
+            write_fat(&mut fat, self.fat_type, cluster, FatValue::Data(cluster + 1)).await?;
+        }
+        write_fat(&mut fat, self.fat_type, last_cluster, FatValue::EndOfChain).await
+    }
+
+    /// Allocates a contiguous run of free clusters as a single chain.
+    ///
+    /// The caller is responsible for every cluster of the run being free.
+    #[cfg(feature = "alloc")]
+    pub(crate) async fn alloc_contiguous_chain(
+        &self,
+        first_cluster: u32,
+        count: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        let mut fat = self.fat_slice();
+        let last_cluster = first_cluster + count - 1;
+        for cluster in first_cluster..last_cluster {
+            write_fat(&mut fat, self.fat_type, cluster, FatValue::Data(cluster + 1)).await?;
+        }
+        write_fat(&mut fat, self.fat_type, last_cluster, FatValue::EndOfChain).await?;
+        #[cfg(feature = "cluster-bitmap")]
+        {
+            let mut bitmap = self.cluster_bitmap.acquire().await;
+            for cluster in first_cluster..=last_cluster {
+                bitmap.set_allocated(cluster);
+            }
+        }
+        #[cfg(feature = "transaction-safe")]
+        {
+            let mut tx_log = self.transaction_log.acquire().await;
+            for cluster in first_cluster..=last_cluster {
+                tx_log.note_allocated_cluster(cluster);
+            }
+        }
+        self.fs_info
+            .acquire()
+            .await
+            .map_free_clusters(|n| n.saturating_sub(count));
+        Ok(())
+    }
+
+    /// Finds a run of `count` free clusters starting below `limit`.
+    ///
+    /// The cluster bitmap is only a hint, every candidate run is confirmed with the FAT.
+    #[cfg(feature = "cluster-bitmap")]
+    pub(crate) async fn find_free_run(
+        &self,
+        count: u32,
+        limit: u32,
+    ) -> Result<Option<u32>, Error<IO::Error>> {
+        let mut fat = self.fat_slice();
+        let mut start = RESERVED_FAT_ENTRIES;
+        'search: loop {
+            let found = self
+                .cluster_bitmap
+                .acquire()
+                .await
+                .find_contiguous_free(count, start);
+            let Some(first_cluster) = found.filter(|cluster| *cluster < limit) else {
+                return Ok(None);
+            };
+            for cluster in first_cluster..first_cluster + count {
+                if read_fat(&mut fat, self.fat_type, cluster).await? != FatValue::Free {
+                    self.cluster_bitmap.acquire().await.set_allocated(cluster);
+                    start = cluster + 1;
+                    continue 'search;
+                }
+            }
+            return Ok(Some(first_cluster));
+        }
+    }
+
     #[allow(clippy::await_holding_refcell_ref)]
     pub(crate) async fn alloc_cluster(
         &self,
@@ -911,6 +1577,25 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         zero: bool,
     ) -> Result<u32, Error<IO::Error>> {
         trace!("alloc_cluster");
+        self.check_writable()?;
+
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            let hint = self.fs_info.acquire().await.next_free_cluster;
+            let mut io = FsIoAdapter { fs: self };
+            let cluster = exfat
+                .find_free_cluster(&mut io, hint)
+                .await?
+                .ok_or(Error::NotEnoughSpace)?;
+            exfat.set_bitmap_range(&mut io, cluster, 1, true).await?;
+            let mut fat = self.fat_slice();
+            write_fat(&mut fat, self.fat_type, cluster, FatValue::EndOfChain).await?;
+            if let Some(n) = prev_cluster {
+                write_fat(&mut fat, self.fat_type, n, FatValue::Data(cluster)).await?;
+            }
+            trace!("allocated exFAT cluster {}", cluster);
+            return self.finish_alloc_cluster(cluster, zero).await;
+        }
 
         // Use cluster bitmap for fast allocation if enabled
         #[cfg(feature = "cluster-bitmap")]
@@ -955,10 +1640,23 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             bitmap.set_allocated(cluster);
         }
 
+        self.finish_alloc_cluster(cluster, zero).await
+    }
+
+    /// Zeroes a newly allocated cluster if requested and updates the free cluster hints.
+    async fn finish_alloc_cluster(&self, cluster: u32, zero: bool) -> Result<u32, Error<IO::Error>> {
+        #[cfg(feature = "transaction-safe")]
+        self.transaction_log
+            .acquire()
+            .await
+            .note_allocated_cluster(cluster);
         if zero {
             let mut disk = self.disk.acquire().await;
             disk.seek(SeekFrom::Start(self.offset_from_cluster(cluster)))
                 .await?;
+            #[cfg(feature = "transaction-safe")]
+            self.journal_write(&mut *disk, u64::from(self.cluster_size()))
+                .await?;
             write_zeros(&mut *disk, u64::from(self.cluster_size())).await?;
         }
         let mut fs_info = self.fs_info.acquire().await;
@@ -967,13 +1665,28 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         Ok(cluster)
     }
 
+    fn mount_status_flags(&self) -> FsStatusFlags {
+        FsStatusFlags::decode(self.mount_status_flags.load(Ordering::Acquire))
+    }
+
+    /// Forgets the dirty flag found on mount and marks the allocation table as cleanly unmounted.
+    ///
+    /// The boot sector is updated by the next flush.
+    pub(crate) async fn clear_mount_dirty_flag(&self) -> Result<(), Error<IO::Error>> {
+        let mut flags = self.mount_status_flags();
+        flags.dirty = false;
+        self.mount_status_flags
+            .store(flags.encode(), Ordering::Release);
+        clear_fat_dirty_flag(&mut self.fat_slice(), self.fat_type).await
+    }
+
     /// Returns status flags for this volume.
     ///
     /// # Errors
     ///
     /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn read_status_flags(&self) -> Result<FsStatusFlags, Error<IO::Error>> {
-        let bpb_status = self.bpb.status_flags();
+        let bpb_status = self.mount_status_flags();
         let fat_status = read_fat_flags(&mut self.fat_slice(), self.fat_type).await?;
         Ok(FsStatusFlags {
             dirty: bpb_status.dirty || fat_status.dirty,
@@ -981,6 +1694,120 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         })
     }
 
+    /// Returns `true` if the volume was mounted using the backup boot sector because its boot sector is damaged.
+    ///
+    /// See [`FsOptions::backup_boot_sector_fallback`].
+    #[must_use]
+    pub fn used_backup_boot_sector(&self) -> bool {
+        self.used_backup_boot_sector.load(Ordering::Acquire)
+    }
+
+    /// Rewrites the boot sector with a copy of the backup boot sector.
+    ///
+    /// Repairs a damaged boot sector after the volume was mounted using the backup boot sector (see
+    /// [`FsOptions::backup_boot_sector_fallback`]). The status flags of the mounted volume are kept.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if the volume has no backup boot sector (FAT12, FAT16 and exFAT).
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn restore_boot_sector(&self) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
+        let backup_sector = self.bpb.backup_boot_sector();
+        if self.fat_type != FatType::Fat32 || backup_sector == 0 {
+            return Err(Error::InvalidInput);
+        }
+        let mut buf = [0_u8; 4096];
+        let sector = &mut buf[..usize::from(self.bpb.bytes_per_sector)];
+        let mut disk = self.disk.acquire().await;
+        disk.seek(SeekFrom::Start(self.offset_from_sector(backup_sector)))
+            .await?;
+        disk.read_exact(sector).await?;
+        // Status flags are kept in the reserved_1 field, they are not updated in the backup
+        sector[0x041] = self.current_status_flags.load(Ordering::Acquire);
+        disk.seek(SeekFrom::Start(0)).await?;
+        disk.write_all(sector).await?;
+        disk.flush().await?;
+        self.used_backup_boot_sector.store(false, Ordering::Release);
+        info!("Boot sector restored from the backup boot sector");
+        Ok(())
+    }
+
+    /// Returns the zero-based index of the only FAT in use if FAT mirroring is disabled, `None` if every FAT is
+    /// updated.
+    #[must_use]
+    pub fn active_fat(&self) -> Option<u8> {
+        if self.fat_type == FatType::Fat32 && !self.bpb.mirroring_enabled() {
+            Some(self.bpb.active_fat() as u8)
+        } else {
+            None
+        }
+    }
+
+    /// Selects the FATs in use (FAT32 only).
+    ///
+    /// `Some(index)` disables FAT mirroring and makes the FAT with this zero-based index the only one read and written,
+    /// `None` enables mirroring to every FAT again. The FAT currently in use is copied to the newly used FATs first,
+    /// so the active FAT can be switched at any time, e.g. away from a worn flash region. The change is stored in the
+    /// extended flags of the boot sector and of the backup boot sector. Inactive FATs are not updated, so they are
+    /// reported by `verify_fats` once they become outdated.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if the volume is not FAT32 or has no FAT with the given index.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn set_active_fat(&mut self, active_fat: Option<u8>) -> Result<(), Error<IO::Error>> {
+        trace!("FileSystem::set_active_fat {:?}", active_fat);
+        self.check_writable()?;
+        let fats = self.bpb.fats;
+        if self.fat_type != FatType::Fat32 || active_fat.is_some_and(|fat| fat >= fats) {
+            return Err(Error::InvalidInput);
+        }
+        if self.active_fat() == active_fat {
+            return Ok(());
+        }
+        // Write cached and deferred FAT changes before copying the FAT in use
+        self.flush().await?;
+        let source = self.bpb.active_fat() as u8;
+        let mut from = self.fat_copy_slice(source);
+        let fat_len = self.bpb.bytes_from_sectors(self.bpb.sectors_per_fat());
+        let mut buf = [0_u8; 512];
+        let targets =
+            (0..fats).filter(|&fat| fat != source && active_fat.is_none_or(|active| active == fat));
+        for target in targets {
+            let mut to = self.fat_copy_slice(target);
+            from.seek(SeekFrom::Start(0)).await?;
+            for _ in (0..fat_len).step_by(buf.len()) {
+                from.read_exact(&mut buf).await?;
+                to.write_all(&buf).await?;
+            }
+            debug!("Copied FAT {} to FAT {}", source, target);
+        }
+
+        // Note: only the extended flags are written to avoid rewriting entire boot-sector
+        let extended_flags = active_fat.map_or(0, |fat| EXT_FLAG_NO_MIRRORING | u16::from(fat));
+        let backup_sector = Some(self.bpb.backup_boot_sector()).filter(|sector| *sector != 0);
+        {
+            let mut disk = self.disk.acquire().await;
+            for sector in [Some(0), backup_sector].into_iter().flatten() {
+                disk.seek(SeekFrom::Start(self.offset_from_sector(sector) + 0x028))
+                    .await?;
+                disk.write_u16_le(extended_flags).await?;
+            }
+            disk.flush().await?;
+        }
+        self.bpb.extended_flags = extended_flags;
+        #[cfg(feature = "fat-cache")]
+        self.fat_cache.acquire().await.invalidate();
+        Ok(())
+    }
+
     /// Returns filesystem statistics like number of total and free clusters.
     ///
     /// For FAT32 volumes number of free clusters from the FS Information Sector is returned (may be incorrect).
@@ -1005,6 +1832,17 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
 
     /// Forces free clusters recalculation.
     async fn recalc_free_clusters(&self) -> Result<u32, Error<IO::Error>> {
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            let free_cluster_count = exfat
+                .count_free_clusters(&mut FsIoAdapter { fs: self })
+                .await?;
+            self.fs_info
+                .acquire()
+                .await
+                .set_free_cluster_count(free_cluster_count);
+            return Ok(free_cluster_count);
+        }
         let mut fat = self.fat_slice();
         let free_cluster_count =
             count_free_clusters(&mut fat, self.fat_type, self.total_clusters).await?;
@@ -1015,9 +1853,34 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         Ok(free_cluster_count)
     }
 
+    /// Recomputes the free cluster count and the cluster bitmap from the FAT.
+    pub(crate) async fn rebuild_free_cluster_info(&self) -> Result<(), Error<IO::Error>> {
+        #[cfg(feature = "cluster-bitmap")]
+        if !self.is_exfat() {
+            let mut bitmap = self.cluster_bitmap.acquire().await;
+            let mut fat = self.fat_slice();
+            bitmap
+                .build_from_fat(&mut fat, self.fat_type, self.total_clusters)
+                .await?;
+        }
+        self.recalc_free_clusters().await?;
+        Ok(())
+    }
+
+    /// Calls `f` with the storage object the filesystem was created with.
+    #[cfg(test)]
+    pub(crate) async fn with_storage<R>(&self, f: impl FnOnce(&mut IO) -> R) -> R {
+        let mut disk = self.disk.acquire().await;
+        #[cfg(feature = "tiny-mode")]
+        let storage = disk.get_mut();
+        #[cfg(not(feature = "tiny-mode"))]
+        let storage = &mut *disk;
+        f(storage)
+    }
+
     /// Unmounts the filesystem.
     ///
-    /// Updates the FS Information Sector if needed.
+    /// Updates the FS Information Sector if needed. Nothing is written if the volume is mounted read-only.
     ///
     /// # Errors
     ///
@@ -1105,6 +1968,9 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             let mut disk = self.disk.acquire().await;
             for entry in &entries {
                 disk.seek(io::SeekFrom::Start(entry.pos)).await?;
+                #[cfg(feature = "transaction-safe")]
+                self.journal_write(&mut *disk, u64::from(DIR_ENTRY_SIZE))
+                    .await?;
                 entry.data.serialize(&mut *disk).await?;
             }
             disk.flush().await?;
@@ -1126,6 +1992,11 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     /// the dirty flag.
     #[allow(clippy::missing_errors_doc)]
     pub async fn flush(&self) -> Result<(), Error<IO::Error>> {
+        // A read-only volume has nothing to write back
+        if self.options.read_only {
+            return Ok(());
+        }
+
         // Flush any dirty directory entries first
         #[cfg(feature = "alloc")]
         self.flush_dirty_dir_entries().await?;
@@ -1137,11 +2008,14 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         #[cfg(feature = "fat-cache")]
         {
             let mut cache = self.fat_cache.acquire().await;
-            let io = FsIoAdapter { fs: self };
-            let mut disk_slice = fat_slice(io, &self.bpb);
+            let mut disk_slice = self.raw_fat_slice();
             cache.flush(&mut disk_slice).await?;
         }
 
+        // The dirty flag must stay set until every FAT copy is up to date
+        #[cfg(feature = "alloc")]
+        self.sync_fat_mirrors().await?;
+
         self.flush_fs_info().await?;
 
         // Flush audit log if enabled
@@ -1160,19 +2034,102 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
 
         // Flush the underlying disk to ensure all data is written
         {
-            let mut disk = self.disk.acquire().await;
-            disk.flush().await?;
+            let mut disk = self.disk.acquire().await;
+            disk.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the before-images of the sectors about to be overwritten by a write of `len` bytes at the current
+    /// position of `disk` if a transaction is active.
+    ///
+    /// Sectors of clusters that were free when the transaction began are skipped, a rollback frees them again.
+    /// Must be called with the disk lock held, the position of `disk` is kept.
+    #[cfg(feature = "transaction-safe")]
+    pub(crate) async fn journal_write(
+        &self,
+        disk: &mut IO,
+        len: u64,
+    ) -> Result<(), Error<IO::Error>> {
+        let mut tx_log = self.transaction_log.acquire().await;
+        if tx_log.active_transaction().is_none() || len == 0 {
+            return Ok(());
+        }
+        let offset = disk.seek(SeekFrom::Current(0)).await?;
+        let sector_size = u64::from(self.bpb.bytes_per_sector);
+        for sector in offset / sector_size..=(offset + len - 1) / sector_size {
+            let sector = u32::try_from(sector).map_err(|_| Error::InvalidInput)?;
+            if sector >= self.first_data_sector {
+                let cluster = (sector - self.first_data_sector)
+                    / u32::from(self.bpb.sectors_per_cluster)
+                    + RESERVED_FAT_ENTRIES;
+                if tx_log.is_new_cluster(cluster) {
+                    continue;
+                }
+            }
+            tx_log.journal_sector(disk, sector).await?;
+        }
+        disk.seek(SeekFrom::Start(offset)).await?;
+        Ok(())
+    }
+
+    /// Records the clusters of a chain about to be freed by the active transaction.
+    ///
+    /// `cluster` itself is skipped if `keep_first` is set, as done when a chain is truncated.
+    #[cfg(feature = "transaction-safe")]
+    async fn note_released_chain(
+        &self,
+        cluster: u32,
+        keep_first: bool,
+    ) -> Result<(), Error<IO::Error>> {
+        if self
+            .transaction_log
+            .acquire()
+            .await
+            .active_transaction()
+            .is_none()
+        {
+            return Ok(());
+        }
+        let mut released = Vec::new();
+        if !keep_first {
+            released.push(cluster);
+        }
+        let mut iter = self.cluster_iter(cluster);
+        while let Some(next) = iter.next().await {
+            released.push(next?);
+        }
+        let mut tx_log = self.transaction_log.acquire().await;
+        for cluster in released {
+            tx_log.note_released_cluster(cluster);
         }
-
         Ok(())
     }
 
+    /// Drops every cached view of the volume after the sectors written by a transaction were restored.
+    #[cfg(feature = "transaction-safe")]
+    async fn discard_rolled_back_state(&self) -> Result<(), Error<IO::Error>> {
+        #[cfg(feature = "fat-cache")]
+        self.fat_cache.acquire().await.invalidate();
+        #[cfg(feature = "dir-cache")]
+        self.dir_cache.acquire().await.clear();
+        self.dirty_dir_entries.acquire().await.clear();
+        self.deferred_mirrors.acquire().await.take();
+        // Clusters allocated by the transaction are free again
+        self.cluster_generation.fetch_add(1, Ordering::Release);
+        self.rebuild_free_cluster_info().await
+    }
+
     async fn flush_fs_info(&self) -> Result<(), Error<IO::Error>> {
         let mut fs_info = self.fs_info.acquire().await;
         if self.fat_type == FatType::Fat32 && fs_info.dirty {
             let mut disk = self.disk.acquire().await;
             let fs_info_sector_offset = self.offset_from_sector(u32::from(self.bpb.fs_info_sector));
             disk.seek(SeekFrom::Start(fs_info_sector_offset)).await?;
+            #[cfg(feature = "transaction-safe")]
+            self.journal_write(&mut *disk, u64::from(self.bpb.bytes_per_sector))
+                .await?;
             fs_info.serialize(&mut *disk).await?;
             fs_info.dirty = false;
         }
@@ -1180,8 +2137,11 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     }
 
     pub(crate) async fn set_dirty_flag(&self, dirty: bool) -> Result<(), IO::Error> {
+        if self.options.read_only {
+            return Ok(());
+        }
         // Do not overwrite flags read from BPB on mount
-        let mut flags = self.bpb.status_flags();
+        let mut flags = self.mount_status_flags();
         flags.dirty = dirty;
         // Check if flags has changed
         let current_flags =
@@ -1191,6 +2151,23 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
             return Ok(());
         }
         let encoded = flags.encode();
+        #[cfg(feature = "exfat")]
+        if let Some(exfat) = &self.exfat {
+            // exFAT keeps the flags in VolumeFlags which is excluded from the boot region checksum
+            let mut volume_flags = exfat.boot.volume_flags
+                & !(crate::exfat::VOLUME_FLAG_DIRTY | crate::exfat::VOLUME_FLAG_MEDIA_FAILURE);
+            if flags.dirty {
+                volume_flags |= crate::exfat::VOLUME_FLAG_DIRTY;
+            }
+            if flags.io_error {
+                volume_flags |= crate::exfat::VOLUME_FLAG_MEDIA_FAILURE;
+            }
+            let mut disk = self.disk.acquire().await;
+            crate::exfat::write_volume_flags(&mut *disk, volume_flags).await?;
+            disk.flush().await?;
+            self.current_status_flags.store(encoded, Ordering::Release);
+            return Ok(());
+        }
         // Note: only one field is written to avoid rewriting entire boot-sector which could be dangerous
         // Compute reserver_1 field offset and write new flags
         let offset = if self.fat_type() == FatType::Fat32 {
@@ -1212,15 +2189,14 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
         trace!("root_dir");
         let root_rdr = {
             match self.fat_type {
-                FatType::Fat12 | FatType::Fat16 => DirRawStream::Root(DiskSlice::from_sectors(
-                    self.first_data_sector - self.root_dir_sectors,
-                    self.root_dir_sectors,
-                    1,
-                    &self.bpb,
-                    FsIoAdapter { fs: self },
-                )),
+                FatType::Fat12 | FatType::Fat16 => DirRawStream::Root(self.fixed_root_dir_slice()),
                 FatType::Fat32 => {
-                    DirRawStream::File(File::new(Some(self.bpb.root_dir_first_cluster), None, self))
+                    DirRawStream::file(File::new(Some(self.bpb.root_dir_first_cluster), None, self))
+                }
+                #[cfg(feature = "exfat")]
+                FatType::ExFat => {
+                    let root_cluster = self.exfat.as_ref().map(|v| v.boot.root_dir_first_cluster);
+                    DirRawStream::file(File::new(root_cluster, None, self))
                 }
             }
         };
@@ -1228,6 +2204,31 @@ impl<IO: ReadWriteSeek, TP, OCC> FileSystem<IO, TP, OCC> {
     }
 }
 
+#[cfg(feature = "discard")]
+impl<IO: ReadWriteSeek + crate::Discard, TP, OCC> FileSystem<IO, TP, OCC> {
+    /// Discards clusters on the storage when they are released.
+    ///
+    /// Once enabled, deleting or truncating a file passes every run of contiguous released
+    /// clusters to [`Discard::discard`](crate::Discard::discard), so flash based storage can
+    /// erase or unmap them (TRIM). Failed discards are logged and otherwise ignored.
+    /// Clusters released inside `FileSystem::with_transaction` are discarded once the transaction is
+    /// committed, and not at all if it is rolled back.
+    ///
+    /// Discarded clusters lose their contents, so deleted files can no longer be recovered with
+    /// `Dir::undelete`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let stream = HeapPageStream::new(device, 4096)?;
+    /// let mut fs = FileSystem::new(stream, FsOptions::new()).await?;
+    /// fs.enable_discard();
+    /// ```
+    pub fn enable_discard(&mut self) {
+        self.discard = Some(crate::discard::discard_fn::<IO>());
+    }
+}
+
 impl<IO: ReadWriteSeek, TP, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
     /// Returns a volume label from BPB in the Boot Sector as `String`.
     ///
@@ -1288,39 +2289,144 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP
         let entry_opt = self.root_dir().find_volume_entry().await?;
         Ok(entry_opt.map(|e| *e.raw_short_name()))
     }
+
+    /// Changes the volume label.
+    ///
+    /// The label is stored in the volume entry of the root directory, which is created or deleted as needed, and in
+    /// the BPB of the boot sector and of the backup boot sector on FAT32. Lowercase ASCII letters are converted to
+    /// uppercase on FAT volumes. An empty label removes the label. Boot sectors without an extended BPB have no label
+    /// field and only the root directory entry is updated.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidFileNameLength` will be returned if the label is longer than 11 characters (11 bytes in the
+    ///   OEM codepage on FAT volumes).
+    /// * `Error::UnsupportedFileNameCharacter` will be returned if the label contains a character that is not allowed
+    ///   in a label or cannot be encoded in the OEM codepage.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn set_volume_label(&mut self, label: &str) -> Result<(), Error<IO::Error>> {
+        trace!("FileSystem::set_volume_label {}", label);
+        self.check_writable()?;
+        if label
+            .chars()
+            .any(|c| c < ' ' || "\"*+,./:;<=>?[\\]|".contains(c))
+        {
+            return Err(Error::UnsupportedFileNameCharacter);
+        }
+        let label = label.trim_end_matches(' ');
+
+        #[cfg(feature = "exfat")]
+        if self.exfat.is_some() {
+            let label_utf16: Vec<u16> = label.encode_utf16().collect();
+            if label_utf16.len() > crate::exfat::MAX_LABEL_LEN {
+                return Err(Error::InvalidFileNameLength);
+            }
+            self.root_dir().set_exfat_volume_entry(&label_utf16).await?;
+            self.disk.acquire().await.flush().await?;
+            if let Some(exfat) = &mut self.exfat {
+                exfat.label = label_utf16;
+                self.bpb.volume_label = exfat.bpb().volume_label;
+            }
+            return Ok(());
+        }
+
+        let mut raw_label = [SFN_PADDING; SFN_SIZE];
+        let mut len = 0;
+        for c in label.chars() {
+            let Some(b) = self.options.oem_cp_converter.encode(c.to_ascii_uppercase()) else {
+                return Err(Error::UnsupportedFileNameCharacter);
+            };
+            if len == SFN_SIZE {
+                return Err(Error::InvalidFileNameLength);
+            }
+            raw_label[len] = b;
+            len += 1;
+        }
+        // FAT encodes character 0xE5 as 0x05 because 0xE5 marks deleted entries
+        if raw_label[0] == DIR_ENTRY_DELETED_FLAG {
+            raw_label[0] = DIR_ENTRY_REALLY_E5_FLAG;
+        }
+        let entry_label = if len == 0 { None } else { Some(raw_label) };
+        self.root_dir().set_volume_entry(entry_label).await?;
+
+        let bpb_label = entry_label.unwrap_or(*b"NO NAME    ");
+        // the label field exists only if the extended boot signature is 0x29
+        if self.bpb.ext_sig == 0x29 {
+            // Note: only the label field is written to avoid rewriting entire boot-sector
+            let offset = if self.fat_type() == FatType::Fat32 {
+                0x047
+            } else {
+                0x02B
+            };
+            let backup_sector = Some(self.bpb.backup_boot_sector())
+                .filter(|sector| self.fat_type() == FatType::Fat32 && *sector != 0);
+            let mut disk = self.disk.acquire().await;
+            for sector in [Some(0), backup_sector].into_iter().flatten() {
+                disk.seek(io::SeekFrom::Start(
+                    self.offset_from_sector(sector) + offset,
+                ))
+                .await?;
+                disk.write_all(&bpb_label).await?;
+            }
+            disk.flush().await?;
+            self.bpb.volume_label = bpb_label;
+        } else {
+            self.disk.acquire().await.flush().await?;
+        }
+        Ok(())
+    }
 }
 
 /// Implementation for transaction-safe operations (requires TimeProvider for timestamping)
 #[cfg(feature = "transaction-safe")]
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> FileSystem<IO, TP, OCC> {
-    /// Perform a transaction-safe metadata write operation
+    /// Perform a transaction-safe write operation
+    ///
+    /// This wraps a critical operation with two-phase commit for power-loss resilience. Before a sector is
+    /// overwritten for the first time during the transaction, its current contents are stored in the transaction
+    /// log. If the operation fails the stored sectors are written back right away, if power is lost before the
+    /// transaction commits they are written back on the next mount.
+    ///
+    /// Writes made by other tasks while the transaction is active become part of it. Boot sector updates (volume
+    /// status flags, `set_volume_label`, `restore_boot_sector` and `set_active_fat`) are not journaled.
+    /// Clusters that were free when the transaction began are not journaled either, a rollback frees them again.
+    /// The log therefore only bounds the number of sectors in use that the operation overwrites, see
+    /// [`TransactionStatistics::sector_capacity`](crate::TransactionStatistics::sector_capacity).
     ///
-    /// This wraps a critical metadata operation with two-phase commit for power-loss resilience.
-    /// The operation is logged before execution and cleared after successful completion.
+    /// `File` and `Dir` objects used by the operation must not outlive it, they do not see a rollback.
     ///
     /// # Arguments
     /// * `tx_type` - Type of transaction being performed
-    /// * `affected_sectors` - List of disk sectors that will be modified
+    /// * `affected_sectors` - Disk sectors the operation modifies without going through the filesystem
     /// * `operation` - Async closure that performs the actual operation
     ///
     /// # Safety Guarantees
     /// - If power is lost before operation completes, filesystem remains consistent
     /// - On next mount, incomplete transactions are detected and rolled back
-    /// - Prevents corruption from partial metadata writes
+    /// - Once this method returns `Ok` all changes made by the operation are on disk
     ///
     /// # Example
     /// ```ignore
-    /// fs.with_transaction(
-    ///     TransactionType::FatUpdate,
-    ///     &[fat_sector],
-    ///     |disk| async move {
-    ///         // Perform FAT update
-    ///         disk.seek(SeekFrom::Start(offset)).await?;
-    ///         disk.write_u32_le(value).await?;
-    ///         Ok(())
-    ///     }
-    /// ).await?;
+    /// fs.with_transaction(TransactionType::DirEntryUpdate, &[], || async {
+    ///     let mut file = fs.root_dir().create_file("config.txt").await?;
+    ///     file.truncate().await?;
+    ///     file.write_all(b"mode=safe").await?;
+    ///     file.flush().await
+    /// })
+    /// .await?;
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::InvalidInput` will be returned if the volume has no usable transaction log.
+    /// * `Error::TransactionLogFull` will be returned if the operation modifies more sectors than the transaction
+    ///   log can hold. The changes made by the operation are rolled back.
+    /// * Errors returned by `operation` are passed through after the changes made by the operation are rolled back.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub async fn with_transaction<F, Fut>(
         &self,
         tx_type: crate::transaction::TransactionType,
@@ -1331,45 +2437,119 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> FileSystem<IO, TP, OCC> {
         F: FnOnce() -> Fut,
         Fut: core::future::Future<Output = Result<(), Error<IO::Error>>>,
     {
-        let mut tx_log = self.transaction_log.acquire().await;
+        self.check_writable()?;
+        if !self.transaction_log.acquire().await.is_enabled() {
+            error!("Volume has no usable transaction log area");
+            return Err(Error::InvalidInput);
+        }
+        let _transaction = self.transaction_lock.acquire().await;
+
+        // Start from a state that is completely on disk, it is the state a rollback returns to
+        self.flush().await?;
 
         // Get current timestamp from TimeProvider
         let timestamp = self.options.time_provider.get_current_date_time().to_unix_timestamp();
 
         // Begin transaction (allocate slot and prepare intent)
-        let slot = tx_log
-            .begin_transaction(tx_type, affected_sectors, timestamp)
+        let slot = self
+            .transaction_log
+            .acquire()
+            .await
+            .begin_transaction(tx_type, timestamp)
             .ok_or(Error::NotEnoughSpace)?; // All transaction slots full
 
-        // Write intent to disk
-        {
-            let mut disk = self.disk.acquire().await;
-            tx_log.write_intent(&mut *disk, slot).await?;
-        }
+        let mut result = async {
+            {
+                let mut disk = self.disk.acquire().await;
+                let mut tx_log = self.transaction_log.acquire().await;
+                tx_log.write_intent(&mut *disk, slot).await?;
+                for &sector in affected_sectors {
+                    tx_log.journal_sector(&mut *disk, sector).await?;
+                }
+            }
 
-        // Mark as in progress
-        tx_log.mark_in_progress(slot);
+            // Perform the actual operation
+            operation().await?;
 
-        // Perform the actual operation
-        let result = operation().await;
+            // Every write of the operation must be on disk before the commit record
+            self.flush().await
+        }
+        .await;
 
+        // Clusters released by the operation are only discarded if it is committed
+        #[cfg(feature = "discard")]
+        let released = core::mem::take(&mut *self.pending_discards.acquire().await);
+        let mut disk = self.disk.acquire().await;
+        let mut tx_log = self.transaction_log.acquire().await;
         if result.is_ok() {
             // Operation succeeded - commit transaction
-            let mut disk = self.disk.acquire().await;
-            tx_log.commit(&mut *disk, slot).await?;
-
-            // Clear the transaction entry
-            tx_log.clear(&mut *disk, slot).await?;
-        } else {
-            // Operation failed - clear transaction log
-            // The rollback will happen on next mount if power is lost here
-            let mut disk = self.disk.acquire().await;
-            tx_log.clear(&mut *disk, slot).await.ok(); // Best effort
+            result = tx_log.commit(&mut *disk, slot).await;
+            if result.is_ok() {
+                // Clear the transaction entry
+                tx_log.clear(&mut *disk, slot).await?;
+                drop(tx_log);
+                drop(disk);
+                #[cfg(feature = "discard")]
+                self.discard_clusters(released).await?;
+                return Ok(());
+            }
+        } else if tx_log.overflowed() {
+            result = Err(Error::TransactionLogFull);
         }
 
+        // Operation failed - restore the sectors it has written
+        // If power is lost here the rollback is repeated on next mount
+        if !tx_log.rollback(&mut *disk, slot).await? {
+            error!(
+                "Before-images of transaction slot {} are corrupted, the transaction was not rolled back",
+                slot
+            );
+        }
+        drop(tx_log);
+        drop(disk);
+        self.discard_rolled_back_state().await?;
         result
     }
 
+    /// Perform several operations as one atomic unit
+    ///
+    /// Shorthand for [`FileSystem::with_transaction`] with [`TransactionType::User`](crate::TransactionType::User)
+    /// for application code. If power is lost or `operation` fails either all of its changes are visible after the
+    /// next mount or none of them.
+    ///
+    /// Note: clusters that were free when the transaction began are not journaled. A rolled-back transaction frees
+    /// them again but does not restore their contents, so data of deleted files stored there can no longer be
+    /// recovered with [`Dir::undelete`](crate::Dir::undelete) even though the deletion is still visible.
+    ///
+    /// # Example
+    /// ```ignore
+    /// fs.atomic(|| async {
+    ///     let root = fs.root_dir();
+    ///     let mut file = root.create_file("config.tmp").await?;
+    ///     file.truncate().await?;
+    ///     file.write_all(b"mode=safe").await?;
+    ///     file.flush().await?;
+    ///     drop(file);
+    ///     if root.exists("config.txt").await? {
+    ///         root.remove("config.txt").await?;
+    ///     }
+    ///     root.rename("config.tmp", &root, "config.txt").await
+    /// })
+    /// .await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileSystem::with_transaction`].
+    pub async fn atomic<F, Fut>(&self, operation: F) -> Result<(), Error<IO::Error>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: core::future::Future<Output = Result<(), Error<IO::Error>>>,
+    {
+        self.with_transaction(crate::transaction::TransactionType::User, &[], operation)
+            .await
+    }
+
     /// Get transaction log statistics
     ///
     /// Returns information about transaction log usage and recovery history.
@@ -1377,9 +2557,10 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> FileSystem<IO, TP, OCC> {
     pub async fn transaction_statistics(&self) -> crate::transaction::TransactionStatistics {
         let tx_log = self.transaction_log.acquire().await;
         crate::transaction::TransactionStatistics {
-            total_slots: 4,
+            total_slots: crate::transaction::MAX_TRANSACTIONS,
             used_slots: tx_log.get_incomplete_transactions().count(),
             sequence_number: tx_log.sequence,
+            sector_capacity: tx_log.image_capacity(),
         }
     }
 
@@ -1515,10 +2696,92 @@ impl<IO: ReadWriteSeek, TP, OCC> Clone for FsIoAdapter<'_, IO, TP, OCC> {
     }
 }
 
+impl<IO: ReadWriteSeek, TP, OCC> SliceStorage<Self> for FsIoAdapter<'_, IO, TP, OCC> {
+    #[cfg_attr(not(feature = "transaction-safe"), allow(unused_variables))]
+    async fn prepare_write(&mut self, len: u64) -> Result<(), Error<IO::Error>> {
+        #[cfg(feature = "transaction-safe")]
+        self.fs
+            .journal_write(&mut *self.fs.disk.acquire().await, len)
+            .await?;
+        Ok(())
+    }
+}
+
+/// The FAT region used by FAT reads and writes.
+///
+/// Writes go to every FAT copy or, with `FsOptions::lazy_fat_mirroring`, to the first FAT only while the
+/// written sectors are recorded to be copied later.
+pub(crate) struct FatSlice<'a, IO: ReadWriteSeek, TP, OCC>
+where
+    IO::Error: 'static,
+{
+    inner: DiskSlice<FsIoAdapter<'a, IO, TP, OCC>>,
+    #[cfg(feature = "alloc")]
+    fs: &'a FileSystem<IO, TP, OCC>,
+    /// True if mirror writes are deferred
+    #[cfg(feature = "alloc")]
+    deferred: bool,
+}
+
+impl<'a, IO: ReadWriteSeek, TP, OCC> FatSlice<'a, IO, TP, OCC> {
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    fn new(
+        mut inner: DiskSlice<FsIoAdapter<'a, IO, TP, OCC>>,
+        fs: &'a FileSystem<IO, TP, OCC>,
+    ) -> Self {
+        #[cfg(feature = "alloc")]
+        let deferred = fs.options.lazy_fat_mirroring > 0 && inner.mirrors > 1;
+        #[cfg(feature = "alloc")]
+        if deferred {
+            inner.mirrors = 1;
+        }
+        Self {
+            inner,
+            #[cfg(feature = "alloc")]
+            fs,
+            #[cfg(feature = "alloc")]
+            deferred,
+        }
+    }
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> IoBase for FatSlice<'_, IO, TP, OCC> {
+    type Error = Error<IO::Error>;
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> Read for FatSlice<'_, IO, TP, OCC> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read(buf).await
+    }
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> Write for FatSlice<'_, IO, TP, OCC> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        #[cfg(feature = "alloc")]
+        let offset = self.inner.offset;
+        let size = self.inner.write(buf).await?;
+        #[cfg(feature = "alloc")]
+        if self.deferred && size > 0 {
+            self.fs.defer_fat_mirror(offset, size).await?;
+        }
+        Ok(size)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> Seek for FatSlice<'_, IO, TP, OCC> {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.inner.seek(pos).await
+    }
+}
+
 fn fat_slice<S: ReadWriteSeek, B: BorrowMut<S>>(
     io: B,
     bpb: &BiosParameterBlock,
-) -> impl ReadWriteSeek<Error = Error<S::Error>>
+) -> DiskSlice<B, S>
 where
     S::Error: 'static,
 {
@@ -1534,6 +2797,20 @@ where
     DiskSlice::from_sectors(fat_first_sector, sectors_per_fat, mirrors, bpb, io)
 }
 
+/// Storage object written through a `DiskSlice`.
+pub(crate) trait SliceStorage<S: IoBase>: BorrowMut<S> {
+    /// Called before `len` bytes are written at the current position of the storage.
+    ///
+    /// Errors are returned from `DiskSlice::write` before anything is written.
+    async fn prepare_write(&mut self, len: u64) -> Result<(), Error<S::Error>>;
+}
+
+impl<S: IoBase> SliceStorage<S> for &mut S {
+    async fn prepare_write(&mut self, _len: u64) -> Result<(), Error<S::Error>> {
+        Ok(())
+    }
+}
+
 pub(crate) struct DiskSlice<B, S = B>
 where
     S: IoBase,
@@ -1618,7 +2895,7 @@ impl<B: BorrowMut<S>, S: Read + Seek> Read for DiskSlice<B, S> {
     }
 }
 
-impl<B: BorrowMut<S>, S: Write + Seek> Write for DiskSlice<B, S> {
+impl<B: SliceStorage<S>, S: Write + Seek> Write for DiskSlice<B, S> {
     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         let offset = self.begin + self.offset;
         let write_size = cmp::min(self.size - self.offset, buf.len() as u64) as usize;
@@ -1626,13 +2903,17 @@ impl<B: BorrowMut<S>, S: Write + Seek> Write for DiskSlice<B, S> {
             return Ok(0);
         }
         // Write data
-        let storage = self.inner.borrow_mut();
         for i in 0..self.mirrors {
             let abs_pos = offset + u64::from(i) * self.size;
-            storage
+            self.inner
+                .borrow_mut()
                 .seek(SeekFrom::Start(abs_pos))
                 .await?;
-            storage.write_all(&buf[..write_size]).await?;
+            self.inner.prepare_write(write_size as u64).await?;
+            self.inner
+                .borrow_mut()
+                .write_all(&buf[..write_size])
+                .await?;
         }
         self.offset += write_size as u64;
         Ok(write_size)
@@ -1725,7 +3006,7 @@ impl OemCpConverter for LossyOemCpConverter {
     }
 }
 
-async fn write_zeros<IO: ReadWriteSeek>(disk: &mut IO, mut len: u64) -> Result<(), IO::Error> {
+pub(crate) async fn write_zeros<IO: ReadWriteSeek>(disk: &mut IO, mut len: u64) -> Result<(), IO::Error> {
     const ZEROS: [u8; 512] = [0_u8; 512];
     while len > 0 {
         let write_size = cmp::min(len, ZEROS.len() as u64) as usize;
@@ -1760,6 +3041,7 @@ pub struct FormatVolumeOptions {
     pub(crate) fat_type: Option<FatType>,
     pub(crate) max_root_dir_entries: Option<u16>,
     pub(crate) fats: Option<u8>,
+    pub(crate) active_fat: Option<u8>,
     pub(crate) media: Option<u8>,
     pub(crate) sectors_per_track: Option<u16>,
     pub(crate) heads: Option<u16>,
@@ -1767,6 +3049,7 @@ pub struct FormatVolumeOptions {
     pub(crate) volume_id: Option<u32>,
     pub(crate) volume_label: Option<[u8; SFN_SIZE]>,
     pub(crate) reserved_sectors: Option<u16>,
+    pub(crate) hidden_sectors: Option<u32>,
 }
 
 impl FormatVolumeOptions {
@@ -1864,6 +3147,18 @@ impl FormatVolumeOptions {
         self
     }
 
+    /// Disable FAT mirroring and set the only FAT that is used (FAT32 only)
+    ///
+    /// By default changes are written to every FAT. With mirroring disabled only the FAT with the zero-based index
+    /// `active_fat` is read and written, halving the FAT writes of a volume with two FATs. Every FAT is initialized,
+    /// so the active FAT can be switched later with `FileSystem::set_active_fat`.
+    /// Formatting fails with `Error::InvalidInput` if the volume is not FAT32 or has no FAT with this index.
+    #[must_use]
+    pub fn active_fat(mut self, active_fat: u8) -> Self {
+        self.active_fat = Some(active_fat);
+        self
+    }
+
     /// Set media field for Bios Parameters Block
     ///
     /// Default is `0xF8`.
@@ -1918,6 +3213,18 @@ impl FormatVolumeOptions {
         self
     }
 
+    /// Set number of hidden sectors for Bios Parameters Block
+    ///
+    /// This is the number of sectors preceding the volume on the disk, i.e. the first sector of the
+    /// partition containing it. Boot code uses it to locate the volume.
+    ///
+    /// Default is `0`.
+    #[must_use]
+    pub fn hidden_sectors(mut self, hidden_sectors: u32) -> Self {
+        self.hidden_sectors = Some(hidden_sectors);
+        self
+    }
+
     /// Set number of reserved sectors
     ///
     /// Reserved sectors are located before the FAT and can be used for bootloader code,
@@ -1940,27 +3247,30 @@ impl FormatVolumeOptions {
 
     /// Configure reserved sectors for transaction log
     ///
-    /// This is a convenience method that adds 4 reserved sectors for the transaction log.
-    /// It calculates the appropriate base reserved sectors for the FAT type and adds space
-    /// for the transaction log.
+    /// This is a convenience method that adds 68 reserved sectors for the transaction log
+    /// (4 transaction entries and 64 before-images). It calculates the appropriate base reserved
+    /// sectors for the FAT type and adds space for the transaction log, which
+    /// `FsOptions::with_transaction_log` places at the end of the reserved sectors.
     ///
     /// Equivalent to:
-    /// - FAT12/16: `reserved_sectors(1 + 4)` = 5 sectors
-    /// - FAT32: `reserved_sectors(8 + 4)` = 12 sectors
+    /// - FAT12/16: `reserved_sectors(1 + 68)` = 69 sectors
+    /// - FAT32 or automatic FAT type: `reserved_sectors(9 + 68)` = 77 sectors, the log follows the three backup
+    ///   boot sectors starting at sector 6
+    ///
+    /// Call [`FormatVolumeOptions::fat_type`] first to get the FAT12/16 layout.
     ///
     /// **Only use this when the `transaction-safe` feature is enabled.**
     #[cfg(feature = "transaction-safe")]
     #[must_use]
     pub fn with_transaction_log(mut self) -> Self {
-        // For FAT32, we need 8 base sectors (includes backup boot sector)
+        // For FAT32, we need 9 base sectors (includes the backup boot sectors 6 to 8)
         // For FAT12/16, we need 1 base sector
-        // Add 4 sectors for transaction log
-        let base_reserved = if self.fat_type == Some(FatType::Fat32) {
-            8
-        } else {
+        let base_reserved = if matches!(self.fat_type, Some(FatType::Fat12 | FatType::Fat16)) {
             1
+        } else {
+            9
         };
-        self.reserved_sectors = Some(base_reserved + 4);
+        self.reserved_sectors = Some(base_reserved + crate::transaction::DEFAULT_LOG_SECTORS as u16);
         self
     }
 }
@@ -1972,7 +3282,7 @@ impl FormatVolumeOptions {
 /// Only quick formatting is supported. To achieve a full format zero entire partition before calling this function.
 /// Supplied `storage` parameter cannot be seeked (internal pointer must be on position 0).
 /// To format a fragment of a disk image (e.g. partition) library user should wrap the file struct in a struct
-/// limiting access to partition bytes only e.g. `fscommon::StreamSlice`.
+/// limiting access to partition bytes only e.g. [`StreamSlice`](crate::StreamSlice).
 ///
 /// # Errors
 ///
@@ -1998,6 +3308,11 @@ where
     trace!("format_volume");
     debug_assert!(storage.seek(SeekFrom::Current(0)).await? == 0);
 
+    #[cfg(feature = "exfat")]
+    if options.fat_type == Some(FatType::ExFat) {
+        return crate::exfat::format_volume(storage, &options).await;
+    }
+
     let bytes_per_sector = options.bytes_per_sector.unwrap_or(512);
     let total_sectors = if let Some(total_sectors) = options.total_sectors {
         total_sectors
@@ -2054,8 +3369,13 @@ where
     let sectors_per_all_fats = bpb.sectors_per_all_fats();
     storage.seek(SeekFrom::Start(fat_pos)).await?;
     write_zeros(storage, bpb.bytes_from_sectors(sectors_per_all_fats)).await?;
+    // Initialize every FAT, including FATs not active with mirroring disabled, so that any of them can be activated
+    let all_fats = BiosParameterBlock {
+        extended_flags: 0,
+        ..bpb.clone()
+    };
     {
-        let mut fat_slice = fat_slice::<S, &mut S>(storage, bpb);
+        let mut fat_slice = fat_slice::<S, &mut S>(storage, &all_fats);
         let sectors_per_fat = bpb.sectors_per_fat();
         let bytes_per_fat = bpb.bytes_from_sectors(sectors_per_fat);
         format_fat(
@@ -2076,7 +3396,7 @@ where
     write_zeros(storage, bpb.bytes_from_sectors(root_dir_sectors)).await?;
     if fat_type == FatType::Fat32 {
         let root_dir_first_cluster = {
-            let mut fat_slice = fat_slice::<S, &mut S>(storage, bpb);
+            let mut fat_slice = fat_slice::<S, &mut S>(storage, &all_fats);
             alloc_cluster(&mut fat_slice, fat_type, None, None, 1).await?
         };
         assert!(root_dir_first_cluster == bpb.root_dir_first_cluster);
@@ -2159,4 +3479,345 @@ mod tests {
     fn filesystem_is_sync_when_io_is_send() {
         assert_sync::<FileSystem<MockSendStorage, DefaultTimeProvider, LossyOemCpConverter>>();
     }
+
+    /// RAM kept by a mounted filesystem with three open files: the filesystem state, the shared sector
+    /// window and the file handles. The storage itself needs no buffer as it is only accessed in whole blocks.
+    #[cfg(feature = "tiny-mode")]
+    #[test]
+    fn test_tiny_mode_ram_budget() {
+        use crate::file::File;
+        use core::mem::size_of;
+
+        type Fs = FileSystem<MockSendStorage, DefaultTimeProvider, LossyOemCpConverter>;
+        type FsFile = File<'static, MockSendStorage, DefaultTimeProvider, LossyOemCpConverter>;
+        let fs = size_of::<Fs>() + size_of::<FsInfoSector>() + size_of::<Disk<MockSendStorage>>();
+        let files = 3 * size_of::<FsFile>();
+        assert!(fs + files < 1024, "filesystem {fs} B, files {files} B");
+    }
+
+    #[tokio::test]
+    async fn test_set_volume_label() {
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        for fat_type in [FatType::Fat16, FatType::Fat32] {
+            let mut image = vec![0_u8; 40 * 1024 * 1024];
+            let options = FormatVolumeOptions::new().fat_type(fat_type);
+            format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+                .await
+                .unwrap();
+            {
+                let mut fs =
+                    FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                        .await
+                        .unwrap();
+                assert_eq!(fs.read_volume_label_from_root_dir().await.unwrap(), None);
+                fs.set_volume_label("Customer 42").await.unwrap();
+                assert_eq!(fs.volume_label(), "CUSTOMER 42");
+                assert!(matches!(
+                    fs.set_volume_label("TOO LONG LABEL").await,
+                    Err(Error::InvalidFileNameLength)
+                ));
+                assert!(matches!(
+                    fs.set_volume_label("A/B").await,
+                    Err(Error::UnsupportedFileNameCharacter)
+                ));
+                fs.root_dir().create_file("data.bin").await.unwrap();
+                fs.unmount().await.unwrap();
+            }
+            if fat_type == FatType::Fat32 {
+                // the backup boot sector carries the same label
+                assert_eq!(&image[6 * 512 + 0x47..6 * 512 + 0x52], b"CUSTOMER 42");
+            }
+
+            let mut fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            assert_eq!(fs.volume_label(), "CUSTOMER 42");
+            assert_eq!(
+                fs.read_volume_label_from_root_dir()
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("CUSTOMER 42")
+            );
+            fs.set_volume_label("LAB").await.unwrap();
+            assert_eq!(
+                fs.read_volume_label_from_root_dir()
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("LAB")
+            );
+            fs.set_volume_label("").await.unwrap();
+            assert_eq!(fs.volume_label(), "NO NAME");
+            assert_eq!(fs.read_volume_label_from_root_dir().await.unwrap(), None);
+            assert!(fs.root_dir().file_exists("data.bin").await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_mount() {
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        let mut image = vec![0_u8; 40 * 1024 * 1024];
+        let options = FormatVolumeOptions::new().fat_type(FatType::Fat32);
+        format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+            .await
+            .unwrap();
+        {
+            // leave the volume dirty, a read-only mount must not clear the flag
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            let mut file = fs.root_dir().create_file("data.bin").await.unwrap();
+            file.write_all(b"payload").await.unwrap();
+            file.flush().await.unwrap();
+        }
+        let original = image.clone();
+        {
+            let fs = FileSystem::new(
+                FromTokio::new(Cursor::new(&mut image)),
+                FsOptions::new().read_only(true).update_accessed_date(true),
+            )
+            .await
+            .unwrap();
+            assert!(fs.is_read_only());
+            let root = fs.root_dir();
+            let mut file = root.open_file("data.bin").await.unwrap();
+            let mut buf = [0_u8; 7];
+            file.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"payload");
+            assert!(matches!(file.write(b"x").await, Err(Error::ReadOnly)));
+            assert!(matches!(file.truncate().await, Err(Error::ReadOnly)));
+            file.flush().await.unwrap();
+            assert!(matches!(
+                root.create_file("new.bin").await,
+                Err(Error::ReadOnly)
+            ));
+            assert!(matches!(root.create_dir("dir").await, Err(Error::ReadOnly)));
+            assert!(matches!(
+                root.remove("data.bin").await,
+                Err(Error::ReadOnly)
+            ));
+            assert!(matches!(
+                root.rename("data.bin", &root, "moved.bin").await,
+                Err(Error::ReadOnly)
+            ));
+            assert!(matches!(
+                root.set_attributes("data.bin", FileAttributes::HIDDEN)
+                    .await,
+                Err(Error::ReadOnly)
+            ));
+            fs.flush().await.unwrap();
+        }
+        assert!(image == original);
+    }
+
+    /// Reads the first two FAT copies of a volume
+    async fn fat_copies<IO: ReadWriteSeek, TP, OCC>(fs: &FileSystem<IO, TP, OCC>) -> [Vec<u8>; 2]
+    where
+        IO::Error: core::fmt::Debug,
+    {
+        let fat_len = fs.bpb.bytes_from_sectors(fs.bpb.sectors_per_fat()) as usize;
+        let mut copies = [vec![0; fat_len], vec![0; fat_len]];
+        for (index, copy) in copies.iter_mut().enumerate() {
+            let mut fat = fs.fat_copy_slice(index as u8);
+            fat.read_exact(copy).await.unwrap();
+        }
+        copies
+    }
+
+    #[tokio::test]
+    async fn test_lazy_fat_mirroring() {
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        // Writes cached FAT sectors like an eviction does, without the mirroring done by `flush`
+        #[cfg_attr(not(feature = "fat-cache"), allow(unused_variables))]
+        async fn write_fat_cache<IO: ReadWriteSeek, TP, OCC>(fs: &FileSystem<IO, TP, OCC>)
+        where
+            IO::Error: core::fmt::Debug,
+        {
+            #[cfg(feature = "fat-cache")]
+            {
+                let mut cache = fs.fat_cache.acquire().await;
+                cache
+                    .flush::<_, IO::Error>(&mut fs.raw_fat_slice())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let mut image = vec![0_u8; 8 * 1024 * 1024];
+        let options = FormatVolumeOptions::new()
+            .fat_type(FatType::Fat16)
+            .bytes_per_cluster(512);
+        format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+            .await
+            .unwrap();
+
+        for threshold in [1, 64] {
+            let fs = FileSystem::new(
+                FromTokio::new(Cursor::new(&mut image)),
+                FsOptions::new().lazy_fat_mirroring(threshold),
+            )
+            .await
+            .unwrap();
+            let name = format!("{threshold}.bin");
+            let mut file = fs.root_dir().create_file(&name).await.unwrap();
+            // 300 clusters span two FAT sectors
+            file.write_all(&[0xAB; 300 * 512]).await.unwrap();
+            file.flush().await.unwrap();
+            write_fat_cache(&fs).await;
+
+            let [primary, mirror] = fat_copies(&fs).await;
+            if threshold == 1 {
+                assert!(primary == mirror);
+            } else {
+                assert!(primary != mirror);
+                assert!(fs.fat_mirror_pending(0, primary.len() as u64).await);
+                // The first FAT is authoritative, pending copies are not reported
+                assert!(fs.check().await.unwrap().is_clean());
+                assert!(
+                    FsStatusFlags::decode(fs.current_status_flags.load(Ordering::Acquire)).dirty()
+                );
+            }
+            fs.flush().await.unwrap();
+            let [primary, mirror] = fat_copies(&fs).await;
+            assert!(primary == mirror);
+            assert!(
+                !FsStatusFlags::decode(fs.current_status_flags.load(Ordering::Acquire)).dirty()
+            );
+        }
+
+        // A power loss leaves the copies out of sync and the volume dirty, repair copies the first FAT
+        {
+            let fs = FileSystem::new(
+                FromTokio::new(Cursor::new(&mut image)),
+                FsOptions::new().lazy_fat_mirroring(64),
+            )
+            .await
+            .unwrap();
+            let mut file = fs.root_dir().create_file("lost.bin").await.unwrap();
+            file.write_all(&[0xCD; 8 * 512]).await.unwrap();
+            file.flush().await.unwrap();
+            write_fat_cache(&fs).await;
+        }
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert!(fs.read_status_flags().await.unwrap().dirty());
+        let report = fs.repair(crate::RepairOptions::new()).await.unwrap();
+        assert!(!report.problems().is_empty());
+        assert!(
+            report
+                .problems()
+                .iter()
+                .all(|problem| matches!(problem, crate::Problem::FatMismatch { fat: 1, .. }))
+        );
+        let [primary, mirror] = fat_copies(&fs).await;
+        assert!(primary == mirror);
+        assert!(fs.check().await.unwrap().is_clean());
+        fs.unmount().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_active_fat_without_mirroring() {
+        use embedded_io_adapters::tokio_1::FromTokio;
+        use std::io::Cursor;
+
+        let mut image = vec![0_u8; 40 * 1024 * 1024];
+        let options = FormatVolumeOptions::new()
+            .fat_type(FatType::Fat32)
+            .bytes_per_cluster(512);
+        assert!(matches!(
+            format_volume(
+                &mut FromTokio::new(Cursor::new(&mut image)),
+                options.clone().active_fat(2)
+            )
+            .await,
+            Err(Error::InvalidInput)
+        ));
+        format_volume(
+            &mut FromTokio::new(Cursor::new(&mut image)),
+            options.active_fat(1),
+        )
+        .await
+        .unwrap();
+
+        {
+            let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+                .await
+                .unwrap();
+            assert_eq!(fs.active_fat(), Some(1));
+            let [formatted, _] = fat_copies(&fs).await;
+            let mut file = fs.root_dir().create_file("one.bin").await.unwrap();
+            file.write_all(&[0x11; 16 * 512]).await.unwrap();
+            file.flush().await.unwrap();
+            drop(file);
+            fs.flush().await.unwrap();
+            // Only the active FAT is written
+            let [inactive, active] = fat_copies(&fs).await;
+            assert!(inactive == formatted);
+            assert!(active != formatted);
+            fs.unmount().await.unwrap();
+        }
+
+        let mut fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.active_fat(), Some(1));
+        assert!(fs.check().await.unwrap().is_clean());
+        assert!(matches!(
+            fs.set_active_fat(Some(2)).await,
+            Err(Error::InvalidInput)
+        ));
+
+        // Switching copies the FAT in use to the new active FAT
+        fs.set_active_fat(Some(0)).await.unwrap();
+        assert_eq!(fs.active_fat(), Some(0));
+        let [active, inactive] = fat_copies(&fs).await;
+        assert!(active == inactive);
+        let mut file = fs.root_dir().create_file("two.bin").await.unwrap();
+        file.write_all(&[0x22; 16 * 512]).await.unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+        fs.flush().await.unwrap();
+        let [active, stale] = fat_copies(&fs).await;
+        assert!(stale == inactive);
+        assert!(active != inactive);
+        assert!(!fs.verify_fats().await.unwrap().is_consistent());
+
+        fs.set_active_fat(None).await.unwrap();
+        assert_eq!(fs.active_fat(), None);
+        let [primary, mirror] = fat_copies(&fs).await;
+        assert!(primary == mirror);
+        fs.unmount().await.unwrap();
+
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.active_fat(), None);
+        assert!(fs.check().await.unwrap().is_clean());
+        assert!(fs.root_dir().open_file("one.bin").await.is_ok());
+        assert!(fs.root_dir().open_file("two.bin").await.is_ok());
+        fs.unmount().await.unwrap();
+        // The backup boot sector is kept in sync
+        let bytes_per_sector = 512;
+        assert_eq!(
+            image[0x028..0x02A],
+            image[6 * bytes_per_sector + 0x028..6 * bytes_per_sector + 0x02A]
+        );
+
+        let mut image = vec![0_u8; 8 * 1024 * 1024];
+        let options = FormatVolumeOptions::new()
+            .fat_type(FatType::Fat16)
+            .active_fat(0);
+        assert!(matches!(
+            format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options).await,
+            Err(Error::InvalidInput)
+        ));
+    }
 }
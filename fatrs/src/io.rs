@@ -42,7 +42,6 @@ pub(crate) trait WriteLeExt {
     async fn write_u8(&mut self, n: u8) -> Result<(), Self::Error>;
     async fn write_u16_le(&mut self, n: u16) -> Result<(), Self::Error>;
     async fn write_u32_le(&mut self, n: u32) -> Result<(), Self::Error>;
-    async fn write_u64_le(&mut self, n: u64) -> Result<(), Self::Error>;
 }
 
 impl<T: Write> WriteLeExt for T {
@@ -62,9 +61,4 @@ impl<T: Write> WriteLeExt for T {
         self.write_all(&n.to_le_bytes()).await?;
         Ok(())
     }
-
-    async fn write_u64_le(&mut self, n: u64) -> Result<(), Self::Error> {
-        self.write_all(&n.to_le_bytes()).await?;
-        Ok(())
-    }
 }
@@ -103,21 +103,56 @@ mod error;
 mod file;
 mod fs;
 mod io;
+mod partition;
 mod table;
 mod time;
 
 #[cfg(feature = "fat-cache")]
 mod fat_cache;
 
+#[cfg(all(
+    feature = "tiny-mode",
+    any(
+        feature = "fat-cache",
+        feature = "read-ahead",
+        feature = "write-coalescing",
+        feature = "cluster-checkpoints",
+        feature = "dir-cache",
+        feature = "cluster-bitmap",
+        feature = "transaction-safe",
+        feature = "audit-log",
+        feature = "exfat"
+    )
+))]
+compile_error!("`tiny-mode` cannot be combined with features adding file or filesystem buffers.");
+
+#[cfg(feature = "tiny-mode")]
+mod sector_window;
+
+#[cfg(feature = "alloc")]
+mod fat_mirror;
+
 #[cfg(feature = "multi-cluster-io")]
 mod multi_cluster_io;
 
+#[cfg(feature = "read-ahead")]
+mod read_ahead;
+
+#[cfg(feature = "write-coalescing")]
+mod write_coalescing;
+
+#[cfg(feature = "discard")]
+mod discard;
+
 #[cfg(feature = "dir-cache")]
 mod dir_cache;
 
 #[cfg(feature = "cluster-bitmap")]
 mod cluster_bitmap;
 
+#[cfg(feature = "cluster-bitmap")]
+mod defrag;
+
 #[cfg(feature = "transaction-safe")]
 mod transaction;
 
@@ -127,11 +162,37 @@ mod file_locking;
 #[cfg(feature = "audit-log")]
 mod audit;
 
+#[cfg(feature = "exfat")]
+mod exfat;
+
+#[cfg(feature = "alloc")]
+mod check;
+
+#[cfg(feature = "alloc")]
+mod fat_verify;
+
+#[cfg(feature = "alloc")]
+mod undelete;
+
+#[cfg(test)]
+mod test_support;
+
+#[cfg(feature = "alloc")]
+pub use crate::check::{CheckReport, LostChainAction, Problem, RepairOptions};
+#[cfg(feature = "alloc")]
+pub use crate::fat_verify::{FatDivergence, FatRepairSource, FatVerifyReport};
+#[cfg(feature = "cluster-bitmap")]
+pub use crate::defrag::{DefragProgress, DefragReport};
+#[cfg(feature = "alloc")]
+pub use crate::undelete::DeletedEntry;
+#[cfg(feature = "discard")]
+pub use fatrs_block_device::Discard;
 pub use crate::dir::*;
 pub use crate::dir_entry::*;
 pub use crate::error::*;
 pub use crate::file::*;
 pub use crate::fs::*;
+pub use crate::partition::*;
 pub use crate::time::*;
 
 #[cfg(feature = "transaction-safe")]
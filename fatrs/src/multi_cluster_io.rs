@@ -14,6 +14,8 @@ use core::cmp;
 use crate::error::Error;
 use crate::fs::{FileSystem, ReadWriteSeek};
 use crate::io::SeekFrom;
+#[cfg(feature = "tiny-mode")]
+use crate::io::{Read, Seek, Write};
 
 /// Maximum number of contiguous clusters to batch in one operation
 /// This prevents excessive memory usage while still providing good performance
@@ -137,6 +139,8 @@ where
     let written = {
         let mut disk = fs.disk.acquire().await;
         disk.seek(SeekFrom::Start(offset_in_fs)).await?;
+        #[cfg(feature = "transaction-safe")]
+        fs.journal_write(&mut *disk, write_size as u64).await?;
 
         let mut written = 0;
         while written < write_size {
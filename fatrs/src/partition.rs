@@ -0,0 +1,1945 @@
+//! Partition table support.
+//!
+//! Removable media such as SD cards and USB sticks usually start with a Master Boot Record (MBR)
+//! or a GUID Partition Table (GPT) and keep the FAT volume inside one of the partitions. This module
+//! parses both partition table types, exposes the partitions they describe and provides
+//! [`StreamSlice`] which limits a storage object to the bytes of a single partition so it can be
+//! handed to [`FileSystem::new`].
+
+use core::cmp;
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::string::String;
+use crc::{CRC_32_ISO_HDLC, Crc};
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+#[cfg(all(feature = "alloc", feature = "std"))]
+use std::vec::Vec;
+
+use crate::boot_sector::{BootSector, format_boot_sector};
+use crate::error::Error;
+use crate::fs::{
+    FatType, FileSystem, FormatVolumeOptions, FsOptions, IntoStorage, ReadSeek, ReadWriteSeek,
+    format_volume,
+};
+use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// Size of a logical sector used for LBA addressing in the partition table.
+pub const PARTITION_TABLE_SECTOR_SIZE: u64 = 512;
+
+const SECTOR_SIZE: usize = PARTITION_TABLE_SECTOR_SIZE as usize;
+const PARTITION_TABLE_OFFSET: usize = 446;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const PRIMARY_PARTITIONS: usize = 4;
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+// Protects against loops in a corrupted extended partition chain
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+/// A partition described by a Master Boot Record.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MbrPartition {
+    index: usize,
+    partition_type: u8,
+    bootable: bool,
+    first_sector: u32,
+    sector_count: u32,
+}
+
+impl MbrPartition {
+    /// Partition index (see [`partition`] for the numbering scheme)
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Partition type byte, e.g. `0x0C` for FAT32 with LBA addressing
+    #[must_use]
+    pub fn partition_type(&self) -> u8 {
+        self.partition_type
+    }
+
+    /// Returns `true` if the partition is marked as active (bootable)
+    #[must_use]
+    pub fn is_bootable(&self) -> bool {
+        self.bootable
+    }
+
+    /// Returns `true` if this is a logical partition stored in the extended partition chain
+    #[must_use]
+    pub fn is_logical(&self) -> bool {
+        self.index >= PRIMARY_PARTITIONS
+    }
+
+    /// Returns `true` if the partition type is one of the types used for FAT volumes
+    ///
+    /// With the `exfat` feature type `0x07` is accepted as well. It is shared with NTFS, so the volume
+    /// itself decides whether it can be mounted.
+    #[must_use]
+    pub fn is_fat(&self) -> bool {
+        matches!(
+            self.partition_type,
+            0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E | 0x11 | 0x14 | 0x16 | 0x1B | 0x1C | 0x1E
+        ) || (cfg!(feature = "exfat") && self.partition_type == 0x07)
+    }
+
+    /// Absolute LBA of the first sector of the partition
+    #[must_use]
+    pub fn first_sector(&self) -> u32 {
+        self.first_sector
+    }
+
+    /// Number of sectors in the partition
+    #[must_use]
+    pub fn sector_count(&self) -> u32 {
+        self.sector_count
+    }
+
+    /// Offset of the partition from the beginning of the disk in bytes
+    #[must_use]
+    pub fn byte_offset(&self) -> u64 {
+        u64::from(self.first_sector) * PARTITION_TABLE_SECTOR_SIZE
+    }
+
+    /// Size of the partition in bytes
+    #[must_use]
+    pub fn byte_len(&self) -> u64 {
+        u64::from(self.sector_count) * PARTITION_TABLE_SECTOR_SIZE
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct MbrEntry {
+    status: u8,
+    partition_type: u8,
+    first_lba: u32,
+    sector_count: u32,
+}
+
+impl MbrEntry {
+    fn parse(sector: &[u8; SECTOR_SIZE], slot: usize) -> Self {
+        let raw =
+            &sector[PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE..][..PARTITION_ENTRY_SIZE];
+        Self {
+            status: raw[0],
+            partition_type: raw[4],
+            first_lba: u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]),
+            sector_count: u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]]),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.partition_type == 0 || self.sector_count == 0
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.partition_type, 0x05 | 0x0F | 0x85)
+    }
+
+    fn to_partition<E>(self, index: usize, base_lba: u32) -> Result<MbrPartition, Error<E>> {
+        let first_sector = base_lba.checked_add(self.first_lba).ok_or_else(|| {
+            error!(
+                "MBR partition {} starts beyond the addressable range",
+                index
+            );
+            Error::CorruptedFileSystem
+        })?;
+        Ok(MbrPartition {
+            index,
+            partition_type: self.partition_type,
+            bootable: self.status & 0x80 != 0,
+            first_sector,
+            sector_count: self.sector_count,
+        })
+    }
+}
+
+async fn read_sector<S: ReadSeek>(
+    storage: &mut S,
+    lba: u64,
+    sector: &mut [u8; SECTOR_SIZE],
+) -> Result<(), Error<S::Error>> {
+    storage
+        .seek(SeekFrom::Start(lba * PARTITION_TABLE_SECTOR_SIZE))
+        .await?;
+    storage.read_exact(sector).await?;
+    Ok(())
+}
+
+fn has_boot_signature(sector: &[u8; SECTOR_SIZE]) -> bool {
+    sector[SECTOR_SIZE - 2..] == BOOT_SIGNATURE
+}
+
+/// Checks if a sector holds a FAT boot sector rather than a partition table.
+///
+/// Both structures end with the same signature so the BPB is inspected instead. A cheap sanity check
+/// runs first so that probing a regular MBR does not log BPB validation errors.
+pub(crate) async fn is_fat_boot_sector(sector: &[u8; SECTOR_SIZE]) -> bool {
+    #[cfg(feature = "exfat")]
+    if has_boot_signature(sector) && crate::exfat::ExFatBootSector::is_exfat(sector) {
+        return true;
+    }
+    if !has_boot_signature(sector) || (sector[0] != 0xEB && sector[0] != 0xE9) {
+        return false;
+    }
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]);
+    let sectors_per_cluster = sector[13];
+    let reserved_sectors = u16::from_le_bytes([sector[14], sector[15]]);
+    let fats = sector[16];
+    if !bytes_per_sector.is_power_of_two()
+        || !(512..=4096).contains(&bytes_per_sector)
+        || !sectors_per_cluster.is_power_of_two()
+        || reserved_sectors == 0
+        || fats == 0
+    {
+        return false;
+    }
+    let mut rdr = &sector[..];
+    match BootSector::deserialize(&mut rdr).await {
+        Ok(boot) => boot.validate::<core::convert::Infallible>().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Calls `f` for every data partition in the MBR until it returns `false`.
+async fn visit_mbr_partitions<S, F>(storage: &mut S, mut f: F) -> Result<(), Error<S::Error>>
+where
+    S: ReadSeek,
+    F: FnMut(MbrPartition) -> bool,
+{
+    let mut sector = [0_u8; SECTOR_SIZE];
+    read_sector(storage, 0, &mut sector).await?;
+    if !has_boot_signature(&sector) {
+        error!("Invalid MBR signature: {:?}", &sector[SECTOR_SIZE - 2..]);
+        return Err(Error::CorruptedFileSystem);
+    }
+
+    let mut extended_first_lba = None;
+    for slot in 0..PRIMARY_PARTITIONS {
+        let entry = MbrEntry::parse(&sector, slot);
+        if entry.is_empty() {
+            continue;
+        }
+        if entry.is_extended() {
+            if extended_first_lba.is_some() {
+                warn!(
+                    "MBR contains more than one extended partition, ignoring slot {}",
+                    slot
+                );
+            } else {
+                extended_first_lba = Some(entry.first_lba);
+            }
+            continue;
+        }
+        if !f(entry.to_partition(slot, 0)?) {
+            return Ok(());
+        }
+    }
+
+    let Some(extended_first_lba) = extended_first_lba else {
+        return Ok(());
+    };
+
+    // Walk the chain of Extended Boot Records. The first entry of every EBR describes a logical
+    // partition relative to that EBR, the second one links to the next EBR relative to the start
+    // of the extended partition.
+    let mut ebr_lba = extended_first_lba;
+    let mut index = PRIMARY_PARTITIONS;
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        read_sector(storage, u64::from(ebr_lba), &mut sector).await?;
+        if !has_boot_signature(&sector) {
+            error!("Invalid EBR signature at sector {}", ebr_lba);
+            return Err(Error::CorruptedFileSystem);
+        }
+        let logical = MbrEntry::parse(&sector, 0);
+        let next = MbrEntry::parse(&sector, 1);
+        if !logical.is_empty() {
+            if !f(logical.to_partition(index, ebr_lba)?) {
+                return Ok(());
+            }
+            index += 1;
+        }
+        if next.is_empty() || !next.is_extended() {
+            return Ok(());
+        }
+        ebr_lba = extended_first_lba
+            .checked_add(next.first_lba)
+            .ok_or_else(|| {
+                error!(
+                    "EBR link at sector {} points beyond the addressable range",
+                    ebr_lba
+                );
+                Error::CorruptedFileSystem
+            })?;
+    }
+    error!("Extended partition chain is too long or contains a loop");
+    Err(Error::CorruptedFileSystem)
+}
+
+/// A globally unique identifier as stored in a GUID Partition Table.
+///
+/// Bytes are kept in the on-disk (mixed-endian) order. The [`Display`](core::fmt::Display)
+/// implementation prints the canonical textual form, e.g. `C12A7328-F81F-11D2-BA4B-00A0C93EC93B`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    /// EFI System Partition
+    pub const EFI_SYSTEM: Guid = Guid::from_fields(
+        0xC12A_7328,
+        0xF81F,
+        0x11D2,
+        [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+    );
+
+    /// Microsoft Basic Data Partition (FAT, exFAT and NTFS volumes)
+    pub const MICROSOFT_BASIC_DATA: Guid = Guid::from_fields(
+        0xEBD0_A0A2,
+        0xB9E5,
+        0x4433,
+        [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+    );
+
+    /// Creates a GUID from its on-disk byte representation.
+    #[must_use]
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(bytes)
+    }
+
+    /// Creates a GUID from the fields of its canonical textual form.
+    #[must_use]
+    pub const fn from_fields(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        let d1 = data1.to_le_bytes();
+        let d2 = data2.to_le_bytes();
+        let d3 = data3.to_le_bytes();
+        Self([
+            d1[0], d1[1], d1[2], d1[3], d2[0], d2[1], d3[0], d3[1], data4[0], data4[1], data4[2],
+            data4[3], data4[4], data4[5], data4[6], data4[7],
+        ])
+    }
+
+    /// Returns the on-disk byte representation.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// Returns `true` for the all-zero GUID which marks unused partition entries.
+    #[must_use]
+    pub fn is_nil(&self) -> bool {
+        self.0 == [0; 16]
+    }
+}
+
+impl core::fmt::Display for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            b[8],
+            b[9],
+            b[10],
+            b[11],
+            b[12],
+            b[13],
+            b[14],
+            b[15]
+        )
+    }
+}
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_SIZE: usize = 92;
+const GPT_ENTRY_SIZE: usize = 128;
+const GPT_NAME_LEN: usize = 36;
+// Upper bound of the partition entry array, the UEFI minimum is 16 KiB
+const MAX_GPT_ENTRY_ARRAY_SIZE: u64 = 1024 * 1024;
+const PROTECTIVE_MBR_TYPE: u8 = 0xEE;
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// A partition described by a GUID Partition Table.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GptPartition {
+    index: usize,
+    type_guid: Guid,
+    unique_guid: Guid,
+    first_sector: u64,
+    last_sector: u64,
+    attributes: u64,
+    name: [u16; GPT_NAME_LEN],
+}
+
+impl GptPartition {
+    fn parse(index: usize, raw: &[u8]) -> Self {
+        let mut type_guid = [0_u8; 16];
+        type_guid.copy_from_slice(&raw[0..16]);
+        let mut unique_guid = [0_u8; 16];
+        unique_guid.copy_from_slice(&raw[16..32]);
+        let mut name = [0_u16; GPT_NAME_LEN];
+        for (i, c) in name.iter_mut().enumerate() {
+            *c = u16::from_le_bytes([raw[56 + i * 2], raw[57 + i * 2]]);
+        }
+        Self {
+            index,
+            type_guid: Guid(type_guid),
+            unique_guid: Guid(unique_guid),
+            first_sector: read_u64(raw, 32),
+            last_sector: read_u64(raw, 40),
+            attributes: read_u64(raw, 48),
+            name,
+        }
+    }
+
+    /// Index of the entry in the partition entry array
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Partition type GUID, e.g. [`Guid::EFI_SYSTEM`]
+    #[must_use]
+    pub fn type_guid(&self) -> Guid {
+        self.type_guid
+    }
+
+    /// GUID unique to this partition
+    #[must_use]
+    pub fn unique_guid(&self) -> Guid {
+        self.unique_guid
+    }
+
+    /// Attribute flags of the partition entry
+    #[must_use]
+    pub fn attributes(&self) -> u64 {
+        self.attributes
+    }
+
+    /// Returns `true` if the partition type can hold a FAT volume (EFI System or Microsoft Basic Data)
+    #[must_use]
+    pub fn is_fat(&self) -> bool {
+        self.type_guid == Guid::EFI_SYSTEM || self.type_guid == Guid::MICROSOFT_BASIC_DATA
+    }
+
+    /// LBA of the first sector of the partition
+    #[must_use]
+    pub fn first_sector(&self) -> u64 {
+        self.first_sector
+    }
+
+    /// LBA of the last sector of the partition (inclusive)
+    #[must_use]
+    pub fn last_sector(&self) -> u64 {
+        self.last_sector
+    }
+
+    /// Number of sectors in the partition
+    #[must_use]
+    pub fn sector_count(&self) -> u64 {
+        self.last_sector - self.first_sector + 1
+    }
+
+    /// Offset of the partition from the beginning of the disk in bytes
+    #[must_use]
+    pub fn byte_offset(&self) -> u64 {
+        self.first_sector * PARTITION_TABLE_SECTOR_SIZE
+    }
+
+    /// Size of the partition in bytes
+    #[must_use]
+    pub fn byte_len(&self) -> u64 {
+        self.sector_count() * PARTITION_TABLE_SECTOR_SIZE
+    }
+
+    /// Partition name as UTF-16 code units (without the trailing NUL padding)
+    #[must_use]
+    pub fn name_utf16(&self) -> &[u16] {
+        let len = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(GPT_NAME_LEN);
+        &self.name[..len]
+    }
+
+    /// Partition name, invalid UTF-16 sequences are replaced by `U+FFFD`
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn name(&self) -> String {
+        char::decode_utf16(self.name_utf16().iter().copied())
+            .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+fn read_u64(raw: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0_u8; 8];
+    bytes.copy_from_slice(&raw[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_u32(raw: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        raw[offset],
+        raw[offset + 1],
+        raw[offset + 2],
+        raw[offset + 3],
+    ])
+}
+
+#[derive(Copy, Clone, Debug)]
+struct GptHeader {
+    backup_lba: u64,
+    entries_lba: u64,
+    entry_count: u32,
+    entry_size: u32,
+    entries_crc32: u32,
+}
+
+impl GptHeader {
+    fn parse(sector: &[u8; SECTOR_SIZE], lba: u64) -> Option<Self> {
+        if &sector[0..8] != GPT_SIGNATURE {
+            return None;
+        }
+        let header_size = read_u32(sector, 12) as usize;
+        if !(GPT_HEADER_SIZE..=SECTOR_SIZE).contains(&header_size) {
+            warn!("Invalid GPT header size {} at LBA {}", header_size, lba);
+            return None;
+        }
+        let mut digest = CRC32.digest();
+        digest.update(&sector[..16]);
+        digest.update(&[0; 4]);
+        digest.update(&sector[20..header_size]);
+        if digest.finalize() != read_u32(sector, 16) {
+            warn!("GPT header checksum mismatch at LBA {}", lba);
+            return None;
+        }
+        if read_u64(sector, 24) != lba {
+            warn!("GPT header at LBA {} describes a different location", lba);
+            return None;
+        }
+        let header = Self {
+            backup_lba: read_u64(sector, 32),
+            entries_lba: read_u64(sector, 72),
+            entry_count: read_u32(sector, 80),
+            entry_size: read_u32(sector, 84),
+            entries_crc32: read_u32(sector, 88),
+        };
+        let entry_size = header.entry_size as usize;
+        if entry_size < GPT_ENTRY_SIZE || !entry_size.is_power_of_two() || entry_size > SECTOR_SIZE
+        {
+            warn!("Unsupported GPT partition entry size {}", entry_size);
+            return None;
+        }
+        if header.entries_array_size() > MAX_GPT_ENTRY_ARRAY_SIZE {
+            warn!(
+                "GPT partition entry array is too big: {} entries",
+                header.entry_count
+            );
+            return None;
+        }
+        Some(header)
+    }
+
+    fn entries_array_size(&self) -> u64 {
+        u64::from(self.entry_count) * u64::from(self.entry_size)
+    }
+}
+
+async fn read_gpt_header<S: ReadSeek>(
+    storage: &mut S,
+    lba: u64,
+) -> Result<Option<GptHeader>, Error<S::Error>> {
+    let mut sector = [0_u8; SECTOR_SIZE];
+    match read_sector(storage, lba, &mut sector).await {
+        Ok(()) => Ok(GptHeader::parse(&sector, lba)),
+        Err(Error::UnexpectedEof) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+async fn verify_gpt_entries<S: ReadSeek>(
+    storage: &mut S,
+    header: &GptHeader,
+) -> Result<bool, Error<S::Error>> {
+    let mut sector = [0_u8; SECTOR_SIZE];
+    let mut digest = CRC32.digest();
+    let mut remaining = header.entries_array_size();
+    let mut entries_lba = header.entries_lba;
+    while remaining > 0 {
+        read_sector(storage, entries_lba, &mut sector).await?;
+        let len = cmp::min(remaining, PARTITION_TABLE_SECTOR_SIZE);
+        digest.update(&sector[..len as usize]);
+        remaining -= len;
+        entries_lba += 1;
+    }
+    Ok(digest.finalize() == header.entries_crc32)
+}
+
+/// Reads the primary GPT header, falling back to the backup header.
+///
+/// The backup header location is taken from the primary header if only the partition entry array
+/// is damaged, otherwise the last sector of the disk is used.
+async fn read_gpt<S: ReadSeek>(storage: &mut S) -> Result<GptHeader, Error<S::Error>> {
+    let backup_lba = match read_gpt_header(storage, 1).await? {
+        Some(header) => {
+            if verify_gpt_entries(storage, &header).await? {
+                return Ok(header);
+            }
+            warn!("Primary GPT partition entry array checksum mismatch");
+            header.backup_lba
+        }
+        None => (storage.seek(SeekFrom::End(0)).await? / PARTITION_TABLE_SECTOR_SIZE)
+            .checked_sub(1)
+            .ok_or(Error::CorruptedFileSystem)?,
+    };
+    warn!(
+        "Primary GPT is invalid, using the backup GPT at LBA {}",
+        backup_lba
+    );
+    if let Some(header) = read_gpt_header(storage, backup_lba).await? {
+        if verify_gpt_entries(storage, &header).await? {
+            return Ok(header);
+        }
+    }
+    error!("Both primary and backup GPT are invalid");
+    Err(Error::CorruptedFileSystem)
+}
+
+/// Calls `f` for every used entry in the GPT until it returns `false`.
+async fn visit_gpt_partitions<S, F>(storage: &mut S, mut f: F) -> Result<(), Error<S::Error>>
+where
+    S: ReadSeek,
+    F: FnMut(GptPartition) -> bool,
+{
+    let header = read_gpt(storage).await?;
+    let entry_size = header.entry_size as usize;
+    let entries_per_sector = SECTOR_SIZE / entry_size;
+    let mut sector = [0_u8; SECTOR_SIZE];
+    for index in 0..header.entry_count as usize {
+        let slot = index % entries_per_sector;
+        if slot == 0 {
+            let lba = header.entries_lba + (index / entries_per_sector) as u64;
+            read_sector(storage, lba, &mut sector).await?;
+        }
+        let partition = GptPartition::parse(index, &sector[slot * entry_size..][..entry_size]);
+        if partition.type_guid.is_nil() {
+            continue;
+        }
+        if partition.first_sector > partition.last_sector {
+            error!("GPT partition {} ends before it starts", index);
+            return Err(Error::CorruptedFileSystem);
+        }
+        if !f(partition) {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// A partition read from the partition table of a disk.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Partition {
+    /// Partition from a Master Boot Record
+    Mbr(MbrPartition),
+    /// Partition from a GUID Partition Table
+    Gpt(GptPartition),
+}
+
+impl Partition {
+    /// Partition index (see [`partition`] for the numbering scheme)
+    #[must_use]
+    pub fn index(&self) -> usize {
+        match self {
+            Partition::Mbr(p) => p.index(),
+            Partition::Gpt(p) => p.index(),
+        }
+    }
+
+    /// Returns `true` if the partition type indicates a FAT volume
+    #[must_use]
+    pub fn is_fat(&self) -> bool {
+        match self {
+            Partition::Mbr(p) => p.is_fat(),
+            Partition::Gpt(p) => p.is_fat(),
+        }
+    }
+
+    /// Offset of the partition from the beginning of the disk in bytes
+    #[must_use]
+    pub fn byte_offset(&self) -> u64 {
+        match self {
+            Partition::Mbr(p) => p.byte_offset(),
+            Partition::Gpt(p) => p.byte_offset(),
+        }
+    }
+
+    /// Size of the partition in bytes
+    #[must_use]
+    pub fn byte_len(&self) -> u64 {
+        match self {
+            Partition::Mbr(p) => p.byte_len(),
+            Partition::Gpt(p) => p.byte_len(),
+        }
+    }
+}
+
+/// Layout of sector 0 of a disk
+enum DiskLayout {
+    /// No partition table, sector 0 is a FAT boot sector
+    Superfloppy,
+    Mbr,
+    Gpt,
+}
+
+async fn disk_layout<S: ReadSeek>(storage: &mut S) -> Result<DiskLayout, Error<S::Error>> {
+    let mut sector = [0_u8; SECTOR_SIZE];
+    read_sector(storage, 0, &mut sector).await?;
+    if is_fat_boot_sector(&sector).await {
+        trace!("sector 0 contains a FAT boot sector, treating the disk as a superfloppy");
+        return Ok(DiskLayout::Superfloppy);
+    }
+    let protective = (0..PRIMARY_PARTITIONS)
+        .any(|slot| MbrEntry::parse(&sector, slot).partition_type == PROTECTIVE_MBR_TYPE);
+    Ok(if protective {
+        DiskLayout::Gpt
+    } else {
+        DiskLayout::Mbr
+    })
+}
+
+/// Calls `f` for every partition of a MBR or GPT partitioned disk until it returns `false`.
+async fn visit_partitions<S, F>(
+    storage: &mut S,
+    layout: &DiskLayout,
+    mut f: F,
+) -> Result<(), Error<S::Error>>
+where
+    S: ReadSeek,
+    F: FnMut(Partition) -> bool,
+{
+    match layout {
+        DiskLayout::Superfloppy => Ok(()),
+        DiskLayout::Mbr => visit_mbr_partitions(storage, |p| f(Partition::Mbr(p))).await,
+        DiskLayout::Gpt => visit_gpt_partitions(storage, |p| f(Partition::Gpt(p))).await,
+    }
+}
+
+/// Reads a single partition from the partition table of `storage`.
+///
+/// A GUID Partition Table is used if the MBR contains a protective partition, otherwise the MBR
+/// partitions are used. Partitions are numbered from zero:
+///
+/// * GPT partitions are numbered by their position in the partition entry array.
+/// * MBR indices `0..=3` correspond to the four primary slots (empty slots and extended partition
+///   containers are skipped but keep their index). Logical partitions found in the extended
+///   partition chain are numbered from `4` onwards, in the order they appear in the chain.
+///
+/// This matches the numbering used by Linux minus one (`sda1` is index `0`, `sda5` is index `4`).
+///
+/// # Errors
+///
+/// * `Error::NotFound` will be returned if there is no data partition with the given index.
+/// * `Error::CorruptedFileSystem` will be returned if the partition table is invalid.
+/// * `Error::Io` will be returned if the provided storage object returned an I/O error.
+pub async fn partition<S: ReadSeek>(
+    storage: &mut S,
+    index: usize,
+) -> Result<Partition, Error<S::Error>> {
+    let layout = disk_layout(storage).await?;
+    let mut found = None;
+    visit_partitions(storage, &layout, |partition| {
+        if partition.index() == index {
+            found = Some(partition);
+        }
+        found.is_none()
+    })
+    .await?;
+    found.ok_or(Error::NotFound)
+}
+
+/// Reads all data partitions from the partition table of `storage`.
+///
+/// See [`partition`] for how the partition table type is selected. An empty list is returned for
+/// a disk without a partition table.
+///
+/// # Errors
+///
+/// * `Error::CorruptedFileSystem` will be returned if the partition table is invalid.
+/// * `Error::Io` will be returned if the provided storage object returned an I/O error.
+#[cfg(feature = "alloc")]
+pub async fn partitions<S: ReadSeek>(storage: &mut S) -> Result<Vec<Partition>, Error<S::Error>> {
+    let layout = disk_layout(storage).await?;
+    let mut partitions = Vec::new();
+    visit_partitions(storage, &layout, |partition| {
+        partitions.push(partition);
+        true
+    })
+    .await?;
+    Ok(partitions)
+}
+
+/// Returns the byte range of the first volume accepted by `accept`.
+///
+/// A disk without a partition table ("superfloppy") has a FAT boot sector in sector 0 and is
+/// treated as a single volume with index 0 spanning the entire storage.
+async fn volume_range<S, F>(storage: &mut S, mut accept: F) -> Result<(u64, u64), Error<S::Error>>
+where
+    S: ReadSeek,
+    F: FnMut(Option<&Partition>) -> bool,
+{
+    let layout = disk_layout(storage).await?;
+    if let DiskLayout::Superfloppy = layout {
+        if !accept(None) {
+            return Err(Error::NotFound);
+        }
+        let size = storage.seek(SeekFrom::End(0)).await?;
+        return Ok((0, size));
+    }
+    let mut found = None;
+    visit_partitions(storage, &layout, |partition| {
+        if accept(Some(&partition)) {
+            found = Some(partition);
+        }
+        found.is_none()
+    })
+    .await?;
+    let partition = found.ok_or(Error::NotFound)?;
+    if !partition.is_fat() {
+        warn!(
+            "Partition {} does not have a FAT partition type",
+            partition.index()
+        );
+    }
+    Ok((partition.byte_offset(), partition.byte_len()))
+}
+
+/// A storage wrapper limiting access to a byte range of the underlying storage.
+///
+/// Positions reported by `seek` are relative to the beginning of the slice. Reads past the end of the
+/// slice return 0 bytes and writes past the end write nothing.
+#[derive(Clone, Debug)]
+pub struct StreamSlice<T> {
+    inner: T,
+    begin: u64,
+    size: u64,
+    offset: u64,
+}
+
+impl<T> StreamSlice<T> {
+    /// Creates a slice of `size` bytes of `inner` starting at byte `begin`.
+    pub fn new(inner: T, begin: u64, size: u64) -> Self {
+        Self {
+            inner,
+            begin,
+            size,
+            offset: 0,
+        }
+    }
+
+    /// Offset of the slice in the underlying storage
+    #[must_use]
+    pub fn begin(&self) -> u64 {
+        self.begin
+    }
+
+    /// Size of the slice in bytes
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the underlying storage object.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn bytes_left(&self, len: usize) -> usize {
+        cmp::min(self.size.saturating_sub(self.offset), len as u64) as usize
+    }
+}
+
+impl<T: IoBase> IoBase for StreamSlice<T> {
+    type Error = T::Error;
+}
+
+impl<T: Read + Seek> Read for StreamSlice<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let read_size = self.bytes_left(buf.len());
+        if read_size == 0 {
+            return Ok(0);
+        }
+        self.inner
+            .seek(SeekFrom::Start(self.begin + self.offset))
+            .await?;
+        let size = self.inner.read(&mut buf[..read_size]).await?;
+        self.offset += size as u64;
+        Ok(size)
+    }
+}
+
+impl<T: Write + Seek> Write for StreamSlice<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let write_size = self.bytes_left(buf.len());
+        if write_size == 0 {
+            return Ok(0);
+        }
+        self.inner
+            .seek(SeekFrom::Start(self.begin + self.offset))
+            .await?;
+        let size = self.inner.write(&buf[..write_size]).await?;
+        self.offset += size as u64;
+        Ok(size)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+impl<T: IoBase> Seek for StreamSlice<T> {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        // The underlying error type cannot be constructed here, so seeking before the start of the
+        // slice clamps to the start instead of failing.
+        let new_offset = match pos {
+            SeekFrom::Start(x) => x,
+            SeekFrom::Current(x) => self.offset.saturating_add_signed(x),
+            SeekFrom::End(x) => self.size.saturating_add_signed(x),
+        };
+        self.offset = new_offset;
+        Ok(new_offset)
+    }
+}
+
+#[cfg(feature = "discard")]
+impl<T: crate::Discard> crate::Discard for StreamSlice<T> {
+    type Error = Error<T::Error>;
+
+    /// Discards a range of the slice. `Error::InvalidInput` is returned if the range does not end inside the slice.
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), Self::Error> {
+        if offset.checked_add(len).is_none_or(|end| end > self.size) {
+            return Err(Error::InvalidInput);
+        }
+        self.inner
+            .discard(self.begin + offset, len)
+            .await
+            .map_err(Error::Io)
+    }
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> FileSystem<StreamSlice<IO>, TP, OCC> {
+    /// Creates a new filesystem object instance for a partition of a partitioned disk.
+    ///
+    /// The partition table (MBR or GPT) of `storage` is parsed and the partition with the given
+    /// `index` is mounted (see [`partition`](crate::partition()) for the numbering). If sector 0 is a
+    /// FAT boot sector instead of a partition table the whole disk is used as a single volume with
+    /// index 0.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::NotFound` will be returned if there is no partition with the given index.
+    /// * `Error::CorruptedFileSystem` will be returned if the partition table is invalid or the
+    ///   partition does not contain a valid FAT filesystem.
+    /// * `Error::Io` will be returned if the provided storage object returned an I/O error.
+    pub async fn open_partition<T: IntoStorage<IO>>(
+        storage: T,
+        index: usize,
+        options: FsOptions<TP, OCC>,
+    ) -> Result<Self, Error<IO::Error>> {
+        let mut disk = storage.into_storage();
+        trace!("FileSystem::open_partition {}", index);
+        let (begin, size) =
+            volume_range(&mut disk, |p| p.map_or(0, Partition::index) == index).await?;
+        Self::new(StreamSlice::new(disk, begin, size), options).await
+    }
+
+    /// Creates a new filesystem object instance for the first FAT partition of a partitioned disk.
+    ///
+    /// The first partition with a FAT partition type is mounted: a FAT type byte in a MBR or the
+    /// EFI System / Microsoft Basic Data type GUID in a GPT. A disk without a partition table is
+    /// mounted as a whole.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::NotFound` will be returned if there is no FAT partition on the disk.
+    /// * `Error::CorruptedFileSystem` will be returned if the partition table is invalid or the
+    ///   partition does not contain a valid FAT filesystem.
+    /// * `Error::Io` will be returned if the provided storage object returned an I/O error.
+    pub async fn open_first_fat_partition<T: IntoStorage<IO>>(
+        storage: T,
+        options: FsOptions<TP, OCC>,
+    ) -> Result<Self, Error<IO::Error>> {
+        let mut disk = storage.into_storage();
+        trace!("FileSystem::open_first_fat_partition");
+        let (begin, size) = volume_range(&mut disk, |p| p.is_none_or(Partition::is_fat)).await?;
+        Self::new(StreamSlice::new(disk, begin, size), options).await
+    }
+}
+
+/// Partition table type written by [`format_disk`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum PartitionTableType {
+    /// Master Boot Record with up to 4 primary partitions
+    #[default]
+    Mbr,
+    /// GUID Partition Table with a protective MBR and up to 128 partitions
+    Gpt,
+}
+
+/// A disk formatting options
+///
+/// This struct implements a builder pattern.
+/// Options are specified as an argument for `format_disk` function.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Default, Debug, Clone)]
+pub struct FormatDiskOptions {
+    pub(crate) partition_table: PartitionTableType,
+    pub(crate) disk_signature: Option<u32>,
+    pub(crate) disk_guid: Option<Guid>,
+}
+
+impl FormatDiskOptions {
+    /// Create options struct for `format_disk` function
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set partition table type
+    ///
+    /// Default is [`PartitionTableType::Mbr`].
+    #[must_use]
+    pub fn partition_table(mut self, partition_table: PartitionTableType) -> Self {
+        self.partition_table = partition_table;
+        self
+    }
+
+    /// Set MBR disk signature
+    ///
+    /// Default is `0x12345678`.
+    #[must_use]
+    pub fn disk_signature(mut self, disk_signature: u32) -> Self {
+        self.disk_signature = Some(disk_signature);
+        self
+    }
+
+    /// Set GPT disk GUID
+    ///
+    /// Default is derived from the disk signature and the disk size. Set it explicitly if the GUID
+    /// has to be globally unique.
+    #[must_use]
+    pub fn disk_guid(mut self, disk_guid: Guid) -> Self {
+        self.disk_guid = Some(disk_guid);
+        self
+    }
+}
+
+/// A partition formatting options
+///
+/// This struct implements a builder pattern.
+/// Options are specified as an argument for `format_disk` function.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct PartitionOptions {
+    pub(crate) size: Option<u64>,
+    pub(crate) volume_options: FormatVolumeOptions,
+    pub(crate) type_guid: Option<Guid>,
+    pub(crate) unique_guid: Option<Guid>,
+    pub(crate) name: [u16; GPT_NAME_LEN],
+    pub(crate) bootable: bool,
+}
+
+impl Default for PartitionOptions {
+    fn default() -> Self {
+        Self {
+            size: None,
+            volume_options: FormatVolumeOptions::default(),
+            type_guid: None,
+            unique_guid: None,
+            name: [0; GPT_NAME_LEN],
+            bootable: false,
+        }
+    }
+}
+
+impl PartitionOptions {
+    /// Create options struct for a partition created by `format_disk` function
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set partition size in bytes
+    ///
+    /// The size is rounded down to whole sectors. Default is all remaining space, which is only
+    /// allowed for the last partition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is smaller than one sector.
+    #[must_use]
+    pub fn size(mut self, size: u64) -> Self {
+        assert!(
+            size >= PARTITION_TABLE_SECTOR_SIZE,
+            "Invalid partition size"
+        );
+        self.size = Some(size);
+        self
+    }
+
+    /// Set options used to format the FAT volume inside the partition
+    ///
+    /// The number of hidden sectors is always set to the partition offset.
+    #[must_use]
+    pub fn volume_options(mut self, volume_options: FormatVolumeOptions) -> Self {
+        self.volume_options = volume_options;
+        self
+    }
+
+    /// Set GPT partition type GUID
+    ///
+    /// Default is [`Guid::MICROSOFT_BASIC_DATA`]. Using [`Guid::EFI_SYSTEM`] also sets the MBR
+    /// partition type to `0xEF`.
+    #[must_use]
+    pub fn type_guid(mut self, type_guid: Guid) -> Self {
+        self.type_guid = Some(type_guid);
+        self
+    }
+
+    /// Set GPT unique partition GUID
+    ///
+    /// Default is derived from the disk GUID and the partition index.
+    #[must_use]
+    pub fn unique_guid(mut self, unique_guid: Guid) -> Self {
+        self.unique_guid = Some(unique_guid);
+        self
+    }
+
+    /// Set GPT partition name
+    ///
+    /// Default is empty name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is longer than 36 UTF-16 code units.
+    #[must_use]
+    pub fn name(mut self, name: &str) -> Self {
+        assert!(
+            name.encode_utf16().count() <= GPT_NAME_LEN,
+            "Partition name is too long"
+        );
+        self.name = [0; GPT_NAME_LEN];
+        for (dst, c) in self.name.iter_mut().zip(name.encode_utf16()) {
+            *dst = c;
+        }
+        self
+    }
+
+    /// Set MBR active (bootable) flag
+    ///
+    /// Default is `false`.
+    #[must_use]
+    pub fn bootable(mut self, bootable: bool) -> Self {
+        self.bootable = bootable;
+        self
+    }
+}
+
+// Partitions are aligned to 1 MiB like most partitioning tools do
+const PARTITION_ALIGNMENT_SECTORS: u64 = 2048;
+const GPT_ENTRY_COUNT: usize = 128;
+const GPT_ENTRIES_SECTORS: u64 = (GPT_ENTRY_COUNT * GPT_ENTRY_SIZE / SECTOR_SIZE) as u64;
+const MAX_MBR_SECTOR: u64 = u32::MAX as u64;
+
+/// Computes the sector ranges (first and last sector, inclusive) of the partitions.
+fn partition_ranges(
+    partitions: &[PartitionOptions],
+    last_usable: u64,
+) -> impl Iterator<Item = Option<(u64, u64)>> + '_ {
+    let count = partitions.len();
+    partitions
+        .iter()
+        .enumerate()
+        .scan(0_u64, move |next_free, (index, options)| {
+            let first = next_free.div_ceil(PARTITION_ALIGNMENT_SECTORS).max(1)
+                * PARTITION_ALIGNMENT_SECTORS;
+            let last = match options.size {
+                Some(size) => first + size / PARTITION_TABLE_SECTOR_SIZE - 1,
+                None if index + 1 == count => last_usable,
+                None => return Some(None),
+            };
+            *next_free = last + 1;
+            Some((first <= last && last <= last_usable).then_some((first, last)))
+        })
+}
+
+/// Returns the MBR partition type matching a formatted volume.
+fn mbr_partition_type(options: &PartitionOptions, fat_type: FatType) -> u8 {
+    if options.type_guid == Some(Guid::EFI_SYSTEM) {
+        return 0xEF;
+    }
+    match fat_type {
+        FatType::Fat12 => 0x01,
+        FatType::Fat16 => 0x0E,
+        FatType::Fat32 => 0x0C,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => 0x07,
+    }
+}
+
+/// Converts LBA to the CHS tuple stored in MBR partition entries (255 heads, 63 sectors per track).
+fn lba_to_chs(lba: u64) -> [u8; 3] {
+    const HEADS: u64 = 255;
+    const SECTORS: u64 = 63;
+    let cylinder = lba / (HEADS * SECTORS);
+    if cylinder > 1023 {
+        return [0xFE, 0xFF, 0xFF];
+    }
+    let head = (lba / SECTORS) % HEADS;
+    let sector = lba % SECTORS + 1;
+    [
+        head as u8,
+        sector as u8 | ((cylinder >> 2) & 0xC0) as u8,
+        cylinder as u8,
+    ]
+}
+
+fn write_mbr_entry(
+    sector: &mut [u8; SECTOR_SIZE],
+    slot: usize,
+    bootable: bool,
+    partition_type: u8,
+    first: u64,
+    count: u64,
+) {
+    let raw =
+        &mut sector[PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE..][..PARTITION_ENTRY_SIZE];
+    raw[0] = if bootable { 0x80 } else { 0 };
+    raw[1..4].copy_from_slice(&lba_to_chs(first));
+    raw[4] = partition_type;
+    raw[5..8].copy_from_slice(&lba_to_chs(first + count - 1));
+    // safe cast: values are clamped or checked against MAX_MBR_SECTOR by the caller
+    raw[8..12].copy_from_slice(&(first as u32).to_le_bytes());
+    raw[12..16].copy_from_slice(&(count as u32).to_le_bytes());
+}
+
+fn derived_guid(seed: u64, index: u64) -> Guid {
+    let mut bytes = [0_u8; 16];
+    for (i, chunk) in bytes.chunks_mut(4).enumerate() {
+        let mut digest = CRC32.digest();
+        digest.update(&seed.to_le_bytes());
+        digest.update(&index.to_le_bytes());
+        digest.update(&[i as u8]);
+        chunk.copy_from_slice(&digest.finalize().to_le_bytes());
+    }
+    // Mark as a random (version 4, RFC 4122 variant) GUID
+    bytes[7] = (bytes[7] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    Guid(bytes)
+}
+
+async fn write_sector<S: ReadWriteSeek>(
+    storage: &mut S,
+    lba: u64,
+    sector: &[u8; SECTOR_SIZE],
+) -> Result<(), Error<S::Error>> {
+    storage
+        .seek(SeekFrom::Start(lba * PARTITION_TABLE_SECTOR_SIZE))
+        .await?;
+    storage.write_all(sector).await?;
+    Ok(())
+}
+
+struct GptLayout {
+    disk_guid: Guid,
+    last_usable: u64,
+}
+
+/// Writes the GPT partition entry array at `entries_lba` and returns its CRC32.
+async fn write_gpt_entries<S: ReadWriteSeek>(
+    storage: &mut S,
+    entries_lba: u64,
+    layout: &GptLayout,
+    partitions: &[PartitionOptions],
+) -> Result<u32, Error<S::Error>> {
+    let entries_per_sector = SECTOR_SIZE / GPT_ENTRY_SIZE;
+    let mut ranges = partition_ranges(partitions, layout.last_usable).flatten();
+    let mut digest = CRC32.digest();
+    for sector_index in 0..GPT_ENTRIES_SECTORS {
+        let mut sector = [0_u8; SECTOR_SIZE];
+        for (slot, raw) in sector.chunks_mut(GPT_ENTRY_SIZE).enumerate() {
+            let index = sector_index as usize * entries_per_sector + slot;
+            let (Some(options), Some((first, last))) = (partitions.get(index), ranges.next())
+            else {
+                break;
+            };
+            let type_guid = options.type_guid.unwrap_or(Guid::MICROSOFT_BASIC_DATA);
+            let seed = read_u64(layout.disk_guid.as_bytes(), 0);
+            let unique_guid = options
+                .unique_guid
+                .unwrap_or_else(|| derived_guid(seed, index as u64));
+            raw[0..16].copy_from_slice(type_guid.as_bytes());
+            raw[16..32].copy_from_slice(unique_guid.as_bytes());
+            raw[32..40].copy_from_slice(&first.to_le_bytes());
+            raw[40..48].copy_from_slice(&last.to_le_bytes());
+            for (i, c) in options.name.iter().enumerate() {
+                raw[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+        digest.update(&sector);
+        write_sector(storage, entries_lba + sector_index, &sector).await?;
+    }
+    Ok(digest.finalize())
+}
+
+async fn write_gpt_header<S: ReadWriteSeek>(
+    storage: &mut S,
+    lba: u64,
+    backup_lba: u64,
+    entries_lba: u64,
+    entries_crc32: u32,
+    layout: &GptLayout,
+) -> Result<(), Error<S::Error>> {
+    let mut sector = [0_u8; SECTOR_SIZE];
+    sector[0..8].copy_from_slice(GPT_SIGNATURE);
+    sector[8..12].copy_from_slice(&0x0001_0000_u32.to_le_bytes());
+    sector[12..16].copy_from_slice(&(GPT_HEADER_SIZE as u32).to_le_bytes());
+    sector[24..32].copy_from_slice(&lba.to_le_bytes());
+    sector[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+    sector[40..48].copy_from_slice(&(2 + GPT_ENTRIES_SECTORS).to_le_bytes());
+    sector[48..56].copy_from_slice(&layout.last_usable.to_le_bytes());
+    sector[56..72].copy_from_slice(layout.disk_guid.as_bytes());
+    sector[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+    sector[80..84].copy_from_slice(&(GPT_ENTRY_COUNT as u32).to_le_bytes());
+    sector[84..88].copy_from_slice(&(GPT_ENTRY_SIZE as u32).to_le_bytes());
+    sector[88..92].copy_from_slice(&entries_crc32.to_le_bytes());
+    let header_crc32 = CRC32.checksum(&sector[..GPT_HEADER_SIZE]);
+    sector[16..20].copy_from_slice(&header_crc32.to_le_bytes());
+    write_sector(storage, lba, &sector).await
+}
+
+/// Create a partition table and FAT filesystems on a disk (format a disk)
+///
+/// Writes an MBR or a GPT (protective MBR, primary and backup header and partition entry arrays)
+/// describing `partitions` and formats each partition with [`format_volume`]. Partitions are
+/// placed one after another, each aligned to 1 MiB.
+///
+/// Warning: this function overrides the partition table and FAT filesystem structures and causes a
+/// loss of all data on provided storage. Please use it with caution.
+///
+/// # Errors
+///
+/// Errors that can be returned:
+///
+/// * `Error::InvalidInput` will be returned if `partitions` is empty, contains more partitions than
+///   the partition table can describe or does not fit on the disk, or if one of the volumes cannot
+///   be formatted with its `FormatVolumeOptions`.
+/// * `Error::Io` will be returned if the provided storage object returned an I/O error.
+pub async fn format_disk<S: ReadWriteSeek>(
+    storage: &mut S,
+    options: FormatDiskOptions,
+    partitions: &[PartitionOptions],
+) -> Result<(), Error<S::Error>>
+where
+    S::Error: 'static,
+{
+    trace!("format_disk");
+    let total_sectors = storage.seek(SeekFrom::End(0)).await? / PARTITION_TABLE_SECTOR_SIZE;
+    let is_gpt = options.partition_table == PartitionTableType::Gpt;
+    let (max_partitions, last_usable) = if is_gpt {
+        // Backup partition entry array and header are stored at the end of the disk
+        (
+            GPT_ENTRY_COUNT,
+            total_sectors.saturating_sub(GPT_ENTRIES_SECTORS + 2),
+        )
+    } else {
+        (
+            PRIMARY_PARTITIONS,
+            cmp::min(total_sectors.saturating_sub(1), MAX_MBR_SECTOR),
+        )
+    };
+    if partitions.is_empty() || partitions.len() > max_partitions {
+        error!("Invalid number of partitions: {}", partitions.len());
+        return Err(Error::InvalidInput);
+    }
+    if partition_ranges(partitions, last_usable).any(|range| range.is_none()) {
+        error!(
+            "Partitions do not fit on a disk with {} sectors",
+            total_sectors
+        );
+        return Err(Error::InvalidInput);
+    }
+
+    // Format volumes first so the partition types can be derived from the FAT types
+    let disk_signature = options.disk_signature.unwrap_or(0x1234_5678);
+    let mut mbr = [0_u8; SECTOR_SIZE];
+    mbr[440..444].copy_from_slice(&disk_signature.to_le_bytes());
+    mbr[SECTOR_SIZE - 2..].copy_from_slice(&BOOT_SIGNATURE);
+    for (slot, (partition, (first, last))) in partitions
+        .iter()
+        .zip(partition_ranges(partitions, last_usable).flatten())
+        .enumerate()
+    {
+        let bytes_per_sector = partition.volume_options.bytes_per_sector.unwrap_or(512);
+        let hidden_sectors = first * PARTITION_TABLE_SECTOR_SIZE / u64::from(bytes_per_sector);
+        let volume_options = partition
+            .volume_options
+            .clone()
+            .hidden_sectors(u32::try_from(hidden_sectors).unwrap_or(u32::MAX));
+        let begin = first * PARTITION_TABLE_SECTOR_SIZE;
+        let size = (last - first + 1) * PARTITION_TABLE_SECTOR_SIZE;
+        let volume_sectors = u32::try_from(size / u64::from(bytes_per_sector)).map_err(|_| {
+            error!("Partition {} is too big for a FAT volume", slot);
+            Error::InvalidInput
+        })?;
+        let (_, fat_type) =
+            format_boot_sector::<S::Error>(&volume_options, volume_sectors, bytes_per_sector)?;
+        let mut slice = StreamSlice::new(&mut *storage, begin, size);
+        format_volume(&mut slice, volume_options).await?;
+        if !is_gpt {
+            let partition_type = mbr_partition_type(partition, fat_type);
+            write_mbr_entry(
+                &mut mbr,
+                slot,
+                partition.bootable,
+                partition_type,
+                first,
+                last - first + 1,
+            );
+        }
+    }
+
+    if is_gpt {
+        let count = cmp::min(total_sectors - 1, MAX_MBR_SECTOR);
+        write_mbr_entry(&mut mbr, 0, false, PROTECTIVE_MBR_TYPE, 1, count);
+        let last_lba = total_sectors - 1;
+        let layout = GptLayout {
+            disk_guid: options
+                .disk_guid
+                .unwrap_or_else(|| derived_guid(u64::from(disk_signature), total_sectors)),
+            last_usable,
+        };
+        let backup_entries_lba = last_lba - GPT_ENTRIES_SECTORS;
+        let entries_crc32 = write_gpt_entries(storage, 2, &layout, partitions).await?;
+        write_gpt_entries(storage, backup_entries_lba, &layout, partitions).await?;
+        write_gpt_header(storage, 1, last_lba, 2, entries_crc32, &layout).await?;
+        write_gpt_header(
+            storage,
+            last_lba,
+            1,
+            backup_entries_lba,
+            entries_crc32,
+            &layout,
+        )
+        .await?;
+    }
+    write_sector(storage, 0, &mbr).await?;
+
+    storage.flush().await?;
+    storage.seek(SeekFrom::Start(0)).await?;
+    trace!("format_disk end");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn write_entry(
+        sector: &mut [u8],
+        slot: usize,
+        partition_type: u8,
+        first_lba: u32,
+        sector_count: u32,
+    ) {
+        let raw = &mut sector[PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE..]
+            [..PARTITION_ENTRY_SIZE];
+        raw[4] = partition_type;
+        raw[8..12].copy_from_slice(&first_lba.to_le_bytes());
+        raw[12..16].copy_from_slice(&sector_count.to_le_bytes());
+    }
+
+    fn write_signature(disk: &mut [u8], lba: u32) {
+        let end = (lba as usize + 1) * SECTOR_SIZE;
+        disk[end - 2..end].copy_from_slice(&BOOT_SIGNATURE);
+    }
+
+    /// 8 MiB disk: primary FAT partition at 1 MiB, extended partition at 4 MiB holding two logical
+    /// partitions.
+    fn partitioned_disk() -> Vec<u8> {
+        let mut disk = vec![0_u8; (8 * MB) as usize];
+        write_entry(&mut disk, 0, 0x0E, 2048, 4096);
+        write_entry(&mut disk, 1, 0x0F, 8192, 8192);
+        write_signature(&mut disk, 0);
+
+        let ebr1 = 8192 * SECTOR_SIZE;
+        write_entry(&mut disk[ebr1..], 0, 0x06, 2048, 2048);
+        write_entry(&mut disk[ebr1..], 1, 0x05, 4096, 4096);
+        write_signature(&mut disk, 8192);
+
+        let ebr2 = (8192 + 4096) * SECTOR_SIZE;
+        write_entry(&mut disk[ebr2..], 0, 0x0B, 2048, 2048);
+        write_signature(&mut disk, 8192 + 4096);
+        disk
+    }
+
+    #[tokio::test]
+    async fn test_mbr_partitions() {
+        let mut storage = FromTokio::new(Cursor::new(partitioned_disk()));
+        let partitions: Vec<_> = partitions(&mut storage)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| match p {
+                Partition::Mbr(p) => p,
+                Partition::Gpt(_) => panic!("unexpected GPT partition"),
+            })
+            .collect();
+        let summary: Vec<_> = partitions
+            .iter()
+            .map(|p| {
+                (
+                    p.index(),
+                    p.partition_type(),
+                    p.first_sector(),
+                    p.sector_count(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, 0x0E, 2048, 4096),
+                (4, 0x06, 10240, 2048),
+                (5, 0x0B, 14336, 2048)
+            ]
+        );
+        assert!(partitions[1].is_logical());
+        assert_eq!(
+            partition(&mut storage, 5).await.unwrap(),
+            Partition::Mbr(partitions[2])
+        );
+        assert!(matches!(
+            partition(&mut storage, 1).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extended_partition_loop() {
+        let mut disk = partitioned_disk();
+        // Point the second EBR back at the first one
+        let ebr2 = (8192 + 4096) * SECTOR_SIZE;
+        write_entry(&mut disk[ebr2..], 1, 0x05, 0, 4096);
+        let mut storage = FromTokio::new(Cursor::new(disk));
+        assert!(matches!(
+            partitions(&mut storage).await,
+            Err(Error::CorruptedFileSystem)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_open_partition() {
+        let mut storage = FromTokio::new(Cursor::new(partitioned_disk()));
+        for index in [0, 5] {
+            let partition = partition(&mut storage, index).await.unwrap();
+            let mut slice =
+                StreamSlice::new(&mut storage, partition.byte_offset(), partition.byte_len());
+            format_volume(&mut slice, FormatVolumeOptions::new())
+                .await
+                .unwrap();
+        }
+
+        let fs = FileSystem::open_partition(storage, 5, FsOptions::new())
+            .await
+            .unwrap();
+        let stats = fs.stats().await.unwrap();
+        assert!(u64::from(stats.total_clusters() * stats.cluster_size()) <= MB);
+        fs.root_dir().create_file("test.txt").await.unwrap();
+        fs.unmount().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_superfloppy() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0_u8; MB as usize]));
+        format_volume(&mut storage, FormatVolumeOptions::new())
+            .await
+            .unwrap();
+        assert!(matches!(
+            FileSystem::open_partition(&mut storage, 1, FsOptions::new()).await,
+            Err(Error::NotFound)
+        ));
+        storage.seek(SeekFrom::Start(0)).await.unwrap();
+        let fs = FileSystem::open_partition(storage, 0, FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.stats().await.unwrap().cluster_size(), fs.cluster_size());
+    }
+
+    const GPT_DISK_SECTORS: u64 = 8192;
+    const GPT_ENTRIES_SECTORS: u64 = 32;
+
+    fn write_gpt_header(disk: &mut [u8], lba: u64, backup_lba: u64, entries_lba: u64) {
+        let entries_crc = CRC32.checksum(&disk[entries_lba as usize * SECTOR_SIZE..][..128 * 128]);
+        let header = &mut disk[lba as usize * SECTOR_SIZE..][..SECTOR_SIZE];
+        header[0..8].copy_from_slice(GPT_SIGNATURE);
+        header[8..12].copy_from_slice(&0x0001_0000_u32.to_le_bytes());
+        header[12..16].copy_from_slice(&(GPT_HEADER_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&lba.to_le_bytes());
+        header[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&128_u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128_u32.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = CRC32.checksum(&header[..GPT_HEADER_SIZE]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    }
+
+    fn write_gpt_entry(
+        disk: &mut [u8],
+        index: usize,
+        type_guid: Guid,
+        first: u64,
+        last: u64,
+        name: &str,
+    ) {
+        for entries_lba in [2, GPT_DISK_SECTORS - 1 - GPT_ENTRIES_SECTORS] {
+            let raw = &mut disk[entries_lba as usize * SECTOR_SIZE + index * 128..][..128];
+            raw[0..16].copy_from_slice(type_guid.as_bytes());
+            raw[16] = index as u8 + 1;
+            raw[32..40].copy_from_slice(&first.to_le_bytes());
+            raw[40..48].copy_from_slice(&last.to_le_bytes());
+            for (i, c) in name.encode_utf16().enumerate() {
+                raw[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+    }
+
+    /// 4 MiB disk with an EFI System Partition at index 0 and a Basic Data partition at index 2.
+    fn gpt_disk() -> Vec<u8> {
+        let mut disk = vec![0_u8; (GPT_DISK_SECTORS * 512) as usize];
+        write_entry(
+            &mut disk,
+            0,
+            PROTECTIVE_MBR_TYPE,
+            1,
+            (GPT_DISK_SECTORS - 1) as u32,
+        );
+        write_signature(&mut disk, 0);
+        write_gpt_entry(
+            &mut disk,
+            0,
+            Guid::EFI_SYSTEM,
+            2048,
+            4095,
+            "EFI system partition",
+        );
+        write_gpt_entry(&mut disk, 2, Guid::MICROSOFT_BASIC_DATA, 4096, 8000, "data");
+        let last = GPT_DISK_SECTORS - 1;
+        write_gpt_header(&mut disk, 1, last, 2);
+        write_gpt_header(&mut disk, last, 1, last - GPT_ENTRIES_SECTORS);
+        disk
+    }
+
+    async fn gpt_summary(
+        disk: Vec<u8>,
+    ) -> Result<Vec<(usize, Guid, u64, String)>, Error<std::io::Error>> {
+        let mut storage = FromTokio::new(Cursor::new(disk));
+        Ok(partitions(&mut storage)
+            .await?
+            .into_iter()
+            .map(|p| match p {
+                Partition::Gpt(p) => (p.index(), p.type_guid(), p.first_sector(), p.name()),
+                Partition::Mbr(_) => panic!("unexpected MBR partition"),
+            })
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_gpt_partitions() {
+        let expected = vec![
+            (
+                0,
+                Guid::EFI_SYSTEM,
+                2048,
+                String::from("EFI system partition"),
+            ),
+            (2, Guid::MICROSOFT_BASIC_DATA, 4096, String::from("data")),
+        ];
+        assert_eq!(gpt_summary(gpt_disk()).await.unwrap(), expected);
+
+        // Corrupted primary header
+        let mut disk = gpt_disk();
+        disk[SECTOR_SIZE + 40] ^= 0xFF;
+        assert_eq!(gpt_summary(disk).await.unwrap(), expected);
+
+        // Corrupted primary partition entry array
+        let mut disk = gpt_disk();
+        disk[2 * SECTOR_SIZE + 56] ^= 0xFF;
+        assert_eq!(gpt_summary(disk).await.unwrap(), expected);
+
+        // Both copies corrupted
+        let mut disk = gpt_disk();
+        disk[SECTOR_SIZE + 40] ^= 0xFF;
+        let last = (GPT_DISK_SECTORS - 1) as usize;
+        disk[last * SECTOR_SIZE + 40] ^= 0xFF;
+        assert!(matches!(
+            gpt_summary(disk).await,
+            Err(Error::CorruptedFileSystem)
+        ));
+    }
+
+    #[test]
+    fn test_guid_display() {
+        assert_eq!(
+            Guid::EFI_SYSTEM.to_string(),
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+        );
+        assert_eq!(Guid::EFI_SYSTEM.as_bytes()[..4], [0x28, 0x73, 0x2A, 0xC1]);
+    }
+
+    #[tokio::test]
+    async fn test_open_first_fat_partition_gpt() {
+        let mut storage = FromTokio::new(Cursor::new(gpt_disk()));
+        let Partition::Gpt(esp) = partition(&mut storage, 0).await.unwrap() else {
+            panic!("expected GPT partition");
+        };
+        let mut slice = StreamSlice::new(&mut storage, esp.byte_offset(), esp.byte_len());
+        format_volume(
+            &mut slice,
+            FormatVolumeOptions::new().volume_label(*b"ESP        "),
+        )
+        .await
+        .unwrap();
+
+        let fs = FileSystem::open_first_fat_partition(storage, FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.volume_label_as_bytes(), b"ESP");
+    }
+
+    #[tokio::test]
+    async fn test_format_disk_mbr() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0_u8; (16 * MB) as usize]));
+        let partitions = [
+            PartitionOptions::new().size(3 * MB + 1000).bootable(true),
+            PartitionOptions::new()
+                .volume_options(FormatVolumeOptions::new().volume_label(*b"DATA       ")),
+        ];
+        format_disk(&mut storage, FormatDiskOptions::new(), &partitions)
+            .await
+            .unwrap();
+
+        let Partition::Mbr(first) = partition(&mut storage, 0).await.unwrap() else {
+            panic!("expected MBR partition");
+        };
+        assert_eq!((first.first_sector(), first.sector_count()), (2048, 6145));
+        assert_eq!(first.partition_type(), 0x01);
+        assert!(first.is_bootable());
+        let Partition::Mbr(second) = partition(&mut storage, 1).await.unwrap() else {
+            panic!("expected MBR partition");
+        };
+        // Aligned to the next MiB after the first partition, extends to the end of the disk
+        assert_eq!(second.first_sector(), 5 * 2048);
+        assert_eq!(second.first_sector() + second.sector_count(), 16 * 2048);
+        assert!(second.is_fat() && !second.is_bootable());
+
+        let fs = FileSystem::open_partition(storage, 1, FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.volume_label_as_bytes(), b"DATA");
+
+        // sector_at returns sectors of the whole disk
+        let mut file = fs.root_dir().create_file("boot.bin").await.unwrap();
+        file.write_all(&[0xAA; 2048]).await.unwrap();
+        file.flush().await.unwrap();
+        let extent = file.extents().next().await.unwrap().unwrap();
+        assert_eq!(
+            file.sector_at(1024).await.unwrap(),
+            Some(extent.first_lba + 2)
+        );
+    }
+
+    #[cfg(feature = "discard")]
+    #[tokio::test]
+    async fn test_discard_through_partition() {
+        use crate::Discard;
+        use crate::test_support::TrimStorage;
+
+        let mut disk = vec![0_u8; (8 * MB) as usize];
+        let storage = TrimStorage {
+            inner: FromTokio::new(Cursor::new(&mut disk)),
+            discards: Vec::new(),
+        };
+        let mut slice = StreamSlice::new(storage, 4096, 8192);
+        slice.discard(512, 1024).await.unwrap();
+        assert!(matches!(
+            slice.discard(8192 - 512, 1024).await,
+            Err(Error::InvalidInput)
+        ));
+        assert!(matches!(
+            slice.discard(512, u64::MAX).await,
+            Err(Error::InvalidInput)
+        ));
+        let mut storage = slice.into_inner();
+        assert_eq!(storage.discards, [(4096 + 512, 1024)]);
+        storage.discards.clear();
+
+        let partitions = [PartitionOptions::new().size(MB), PartitionOptions::new()];
+        format_disk(&mut storage, FormatDiskOptions::new(), &partitions)
+            .await
+            .unwrap();
+        let Partition::Mbr(second) = partition(&mut storage, 1).await.unwrap() else {
+            panic!("expected MBR partition");
+        };
+        let mut fs = FileSystem::open_partition(storage, 1, FsOptions::new())
+            .await
+            .unwrap();
+        fs.enable_discard();
+        let root = fs.root_dir();
+        let mut file = root.create_file("data.bin").await.unwrap();
+        file.write_all(&vec![0x5A; 3 * fs.cluster_size() as usize])
+            .await
+            .unwrap();
+        file.flush().await.unwrap();
+        let extent = file.extents().next().await.unwrap().unwrap();
+        drop(file);
+        fs.with_storage(|storage| storage.inner.discards.clear())
+            .await;
+        root.remove("data.bin").await.unwrap();
+        // the slice adds the partition offset to the volume offset of the released clusters
+        let discards = fs
+            .with_storage(|storage| core::mem::take(&mut storage.inner.discards))
+            .await;
+        assert_eq!(
+            discards,
+            [(second.byte_offset() + extent.offset, extent.size)]
+        );
+        fs.flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_extents_on_partitioned_disk() {
+        let mut disk = vec![0_u8; (8 * MB) as usize];
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i % 251) as u8).collect();
+        let extents = {
+            let mut storage = FromTokio::new(Cursor::new(&mut disk));
+            let partitions = [PartitionOptions::new().size(MB), PartitionOptions::new()];
+            format_disk(&mut storage, FormatDiskOptions::new(), &partitions)
+                .await
+                .unwrap();
+            let fs = FileSystem::open_partition(storage, 1, FsOptions::new())
+                .await
+                .unwrap();
+            let mut file = fs.root_dir().create_file("data.bin").await.unwrap();
+            file.write_all(&data).await.unwrap();
+            file.flush().await.unwrap();
+            let mut extents = Vec::new();
+            let mut iter = file.extents();
+            while let Some(extent) = iter.next().await {
+                let extent = extent.unwrap();
+                // extents and sector_at both count sectors from the start of the disk
+                assert_eq!(
+                    file.sector_at(extent.file_offset).await.unwrap(),
+                    Some(extent.first_lba)
+                );
+                extents.push(extent);
+            }
+            extents
+        };
+        assert!(!extents.is_empty());
+        for extent in &extents {
+            assert!(extent.first_lba >= 2 * 2048);
+            let start = (extent.first_lba * 512) as usize;
+            let file_offset = extent.file_offset as usize;
+            assert_eq!(
+                disk[start..start + extent.size as usize],
+                data[file_offset..file_offset + extent.size as usize]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_format_disk_gpt() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0_u8; (40 * MB) as usize]));
+        let partitions = [
+            PartitionOptions::new()
+                .size(4 * MB)
+                .type_guid(Guid::EFI_SYSTEM)
+                .name("EFI System"),
+            PartitionOptions::new()
+                .name("data")
+                .volume_options(FormatVolumeOptions::new().fat_type(FatType::Fat32)),
+        ];
+        let options = FormatDiskOptions::new().partition_table(PartitionTableType::Gpt);
+        format_disk(&mut storage, options, &partitions)
+            .await
+            .unwrap();
+
+        let summary: Vec<_> = super::partitions(&mut storage)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|p| match p {
+                Partition::Gpt(p) => (p.index(), p.type_guid(), p.first_sector(), p.name()),
+                Partition::Mbr(_) => panic!("unexpected MBR partition"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, Guid::EFI_SYSTEM, 2048, String::from("EFI System")),
+                (
+                    1,
+                    Guid::MICROSOFT_BASIC_DATA,
+                    5 * 2048,
+                    String::from("data")
+                ),
+            ]
+        );
+
+        // The backup GPT must be valid on its own
+        let mut disk = storage.into_inner().into_inner();
+        disk[SECTOR_SIZE..2 * SECTOR_SIZE].fill(0);
+        let mut storage = FromTokio::new(Cursor::new(disk));
+        assert_eq!(super::partitions(&mut storage).await.unwrap().len(), 2);
+
+        let fs = FileSystem::open_partition(storage, 1, FsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat32);
+    }
+
+    #[tokio::test]
+    async fn test_format_disk_invalid() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0_u8; (8 * MB) as usize]));
+        let too_big = [PartitionOptions::new().size(8 * MB)];
+        assert!(matches!(
+            format_disk(&mut storage, FormatDiskOptions::new(), &too_big).await,
+            Err(Error::InvalidInput)
+        ));
+        let unsized_first = [PartitionOptions::new(), PartitionOptions::new().size(MB)];
+        assert!(matches!(
+            format_disk(&mut storage, FormatDiskOptions::new(), &unsized_first).await,
+            Err(Error::InvalidInput)
+        ));
+        let too_many = vec![PartitionOptions::new().size(MB); 5];
+        assert!(matches!(
+            format_disk(&mut storage, FormatDiskOptions::new(), &too_many).await,
+            Err(Error::InvalidInput)
+        ));
+    }
+}
@@ -0,0 +1,351 @@
+//! Shared sector window of the `tiny-mode` feature
+//!
+//! Like the `win` buffer of FatFs built with `FF_FS_TINY`, a single 512-byte buffer owned by the
+//! filesystem serves every storage access that does not cover whole 512-byte blocks: FAT entries,
+//! directory entries and the partial sectors of the data of all open files. Transfers of whole aligned
+//! blocks go to the storage directly. The storage therefore only sees reads and writes of aligned
+//! 512-byte blocks and needs no buffer of its own.
+//!
+//! A modified block is written back when another block is loaded into the window, when an aligned read
+//! covers it and when the filesystem or a file is flushed.
+
+use core::cmp;
+
+use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// Size of the window and alignment of all storage accesses
+pub(crate) const WINDOW_SIZE: usize = 512;
+const WINDOW_SIZE_U64: u64 = WINDOW_SIZE as u64;
+
+/// Storage stream merging partial block accesses in a single block buffer.
+pub(crate) struct SectorWindow<IO> {
+    inner: IO,
+    /// Stream position
+    pos: u64,
+    /// Offset of the block held in `buf`, `None` if the window is empty
+    block: Option<u64>,
+    /// Number of bytes of `buf` backed by the storage, less than `WINDOW_SIZE` for a block at its end
+    len: usize,
+    /// `buf` holds changes not written to the storage yet
+    dirty: bool,
+    buf: [u8; WINDOW_SIZE],
+}
+
+impl<IO: Read + Write + Seek> SectorWindow<IO> {
+    pub(crate) fn new(inner: IO) -> Self {
+        Self {
+            inner,
+            pos: 0,
+            block: None,
+            len: 0,
+            dirty: false,
+            buf: [0; WINDOW_SIZE],
+        }
+    }
+
+    /// Returns the storage, which may lack the changes held by the window.
+    #[cfg(test)]
+    pub(crate) fn get_mut(&mut self) -> &mut IO {
+        &mut self.inner
+    }
+
+    /// Returns the storage for a discard of `len` bytes at `offset`.
+    ///
+    /// The window is written back and emptied first if it holds one of the blocks.
+    #[cfg(feature = "discard")]
+    pub(crate) async fn discard_range(
+        &mut self,
+        offset: u64,
+        len: u64,
+    ) -> Result<&mut IO, IO::Error> {
+        if self.holds_block_in(offset, len) {
+            self.write_back().await?;
+            self.block = None;
+        }
+        Ok(&mut self.inner)
+    }
+
+    fn holds_block_in(&self, offset: u64, len: u64) -> bool {
+        self.block
+            .is_some_and(|block| block + WINDOW_SIZE_U64 > offset && block < offset + len)
+    }
+
+    async fn write_back(&mut self) -> Result<(), IO::Error> {
+        if let (true, Some(block)) = (self.dirty, self.block) {
+            self.inner.seek(SeekFrom::Start(block)).await?;
+            self.inner.write_all(&self.buf[..self.len]).await?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Makes the window hold the block starting at `block`.
+    async fn load(&mut self, block: u64) -> Result<(), IO::Error> {
+        if self.block == Some(block) {
+            return Ok(());
+        }
+        self.write_back().await?;
+        self.block = None;
+        self.inner.seek(SeekFrom::Start(block)).await?;
+        let mut len = 0;
+        while len < WINDOW_SIZE {
+            let n = self.inner.read(&mut self.buf[len..]).await?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        self.block = Some(block);
+        self.len = len;
+        Ok(())
+    }
+
+    /// Returns the length of an access of `len` bytes at the stream position done on whole blocks, 0 if
+    /// the access has to go through the window.
+    fn aligned_len(&self, len: usize) -> usize {
+        if self.pos % WINDOW_SIZE_U64 == 0 {
+            len - len % WINDOW_SIZE
+        } else {
+            0
+        }
+    }
+}
+
+impl<IO: IoBase> IoBase for SectorWindow<IO> {
+    type Error = IO::Error;
+}
+
+impl<IO: Read + Write + Seek> SectorWindow<IO> {
+    /// Reads the partial block at the stream position or the whole blocks following it.
+    async fn read_part(&mut self, buf: &mut [u8]) -> Result<usize, IO::Error> {
+        let aligned_len = self.aligned_len(buf.len());
+        if aligned_len > 0 {
+            // The storage has to hold the changes of the window before it is read
+            if self.holds_block_in(self.pos, aligned_len as u64) {
+                self.write_back().await?;
+            }
+            self.inner.seek(SeekFrom::Start(self.pos)).await?;
+            let n = self.inner.read(&mut buf[..aligned_len]).await?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let offset = (self.pos % WINDOW_SIZE_U64) as usize;
+        self.load(self.pos - offset as u64).await?;
+        let n = cmp::min(buf.len(), self.len.saturating_sub(offset));
+        buf[..n].copy_from_slice(&self.buf[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Writes the partial block at the stream position or the whole blocks following it.
+    async fn write_part(&mut self, buf: &[u8]) -> Result<usize, IO::Error> {
+        let aligned_len = self.aligned_len(buf.len());
+        if aligned_len > 0 {
+            // The blocks are replaced, changes of one of them in the window are outdated
+            if self.holds_block_in(self.pos, aligned_len as u64) {
+                self.block = None;
+                self.dirty = false;
+            }
+            self.inner.seek(SeekFrom::Start(self.pos)).await?;
+            let n = self.inner.write(&buf[..aligned_len]).await?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let offset = (self.pos % WINDOW_SIZE_U64) as usize;
+        self.load(self.pos - offset as u64).await?;
+        let n = cmp::min(buf.len(), self.len.saturating_sub(offset));
+        if n > 0 {
+            self.buf[offset..offset + n].copy_from_slice(&buf[..n]);
+            self.dirty = true;
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+// Accesses are split on block boundaries but completed in a single call, like on the storage itself
+impl<IO: Read + Write + Seek> Read for SectorWindow<IO> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            let n = self.read_part(&mut buf[done..]).await?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+        Ok(done)
+    }
+}
+
+impl<IO: Read + Write + Seek> Write for SectorWindow<IO> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut done = 0;
+        while done < buf.len() {
+            let n = self.write_part(&buf[done..]).await?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+        Ok(done)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.write_back().await?;
+        self.inner.flush().await
+    }
+}
+
+impl<IO: Read + Write + Seek> Seek for SectorWindow<IO> {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            // Relative positions are resolved by the storage, which reports invalid ones
+            SeekFrom::Current(_) | SeekFrom::End(_) => {
+                self.inner.seek(SeekFrom::Start(self.pos)).await?;
+                self.inner.seek(pos).await?
+            }
+        };
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FileSystem, FsOptions};
+    use crate::test_support::{MB, image, pattern};
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    /// Storage that only accepts reads and writes of whole aligned blocks
+    struct BlockStorage<'a> {
+        inner: FromTokio<Cursor<&'a mut Vec<u8>>>,
+        pos: u64,
+    }
+
+    impl BlockStorage<'_> {
+        fn check_aligned(&self, len: usize) {
+            assert_eq!(
+                self.pos % WINDOW_SIZE_U64,
+                0,
+                "unaligned access at {}",
+                self.pos
+            );
+            assert_eq!(
+                len % WINDOW_SIZE,
+                0,
+                "partial block access of {} bytes",
+                len
+            );
+        }
+    }
+
+    impl IoBase for BlockStorage<'_> {
+        type Error = std::io::Error;
+    }
+
+    impl Read for BlockStorage<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.check_aligned(buf.len());
+            self.inner.read_exact(buf).await.unwrap();
+            self.pos += buf.len() as u64;
+            Ok(buf.len())
+        }
+    }
+
+    impl Write for BlockStorage<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.check_aligned(buf.len());
+            self.inner.write_all(buf).await?;
+            self.pos += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            self.inner.flush().await
+        }
+    }
+
+    impl Seek for BlockStorage<'_> {
+        async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+            self.pos = self.inner.seek(pos).await?;
+            Ok(self.pos)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accesses_match_plain_storage() {
+        let mut data = vec![0_u8; 16 * WINDOW_SIZE];
+        let mut expected = data.clone();
+        let mut window = SectorWindow::new(BlockStorage {
+            inner: FromTokio::new(Cursor::new(&mut data)),
+            pos: 0,
+        });
+        let mut seed = 1_u32;
+        for round in 0..500 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let pos = (seed >> 8) as usize % (expected.len() - 1);
+            let len = cmp::min(1 + (seed >> 20) as usize % 1500, expected.len() - pos);
+            window.seek(SeekFrom::Start(pos as u64)).await.unwrap();
+            if round % 3 == 0 {
+                let mut buf = vec![0; len];
+                window.read_exact(&mut buf).await.unwrap();
+                assert_eq!(
+                    buf,
+                    expected[pos..pos + len],
+                    "read of {len} bytes at {pos}"
+                );
+            } else {
+                let buf = pattern(round as u8, len);
+                window.write_all(&buf).await.unwrap();
+                expected[pos..pos + len].copy_from_slice(&buf);
+            }
+        }
+        window.flush().await.unwrap();
+        drop(window);
+        assert!(data == expected);
+    }
+
+    #[tokio::test]
+    async fn test_filesystem_accesses_whole_blocks() {
+        let mut image = image(8 * MB, FatType::Fat16).await;
+        let storage = BlockStorage {
+            inner: FromTokio::new(Cursor::new(&mut image)),
+            pos: 0,
+        };
+        let fs = FileSystem::new(storage, FsOptions::new()).await.unwrap();
+        let root = fs.root_dir();
+        let names = ["a.txt", "b.txt", "c.txt"];
+        // Small appends to files sharing the window, and writes of whole blocks in between
+        let mut files = Vec::new();
+        for name in names {
+            files.push(root.create_file(name).await.unwrap());
+        }
+        for round in 0..40 {
+            for (i, file) in files.iter_mut().enumerate() {
+                let len = if round % 10 == 9 { 1024 } else { 100 };
+                file.write_all(&pattern((round * 3 + i) as u8, len))
+                    .await
+                    .unwrap();
+            }
+        }
+        for mut file in files {
+            file.flush().await.unwrap();
+        }
+
+        for (i, name) in names.into_iter().enumerate() {
+            let mut file = root.open_file(name).await.unwrap();
+            for round in 0..40 {
+                let len = if round % 10 == 9 { 1024 } else { 100 };
+                let mut buf = vec![0; len];
+                file.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, pattern((round * 3 + i) as u8, len));
+            }
+        }
+        drop(root);
+        assert!(fs.check().await.unwrap().is_clean());
+        fs.unmount().await.unwrap();
+    }
+}
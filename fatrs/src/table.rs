@@ -13,6 +13,12 @@ pub(crate) struct Fat<S> {
 pub(crate) type Fat12 = Fat<u8>;
 pub(crate) type Fat16 = Fat<u16>;
 pub(crate) type Fat32 = Fat<u32>;
+#[cfg(feature = "exfat")]
+pub(crate) type ExFat = Fat<ExFatEntry>;
+
+/// Marker for exFAT allocation table entries (full 32-bit values, no reserved bits).
+#[cfg(feature = "exfat")]
+pub(crate) struct ExFatEntry;
 
 pub const RESERVED_FAT_ENTRIES: u32 = 2;
 
@@ -67,7 +73,7 @@ pub(crate) trait FatTrait {
         Error<E>: From<S::Error> + From<ReadExactError<S::Error>>;
 }
 
-async fn read_fat<S, E>(fat: &mut S, fat_type: FatType, cluster: u32) -> Result<FatValue, Error<E>>
+pub(crate) async fn read_fat<S, E>(fat: &mut S, fat_type: FatType, cluster: u32) -> Result<FatValue, Error<E>>
 where
     S: Read + Seek,
     E: IoError,
@@ -77,10 +83,28 @@ where
         FatType::Fat12 => Fat12::get(fat, cluster).await,
         FatType::Fat16 => Fat16::get(fat, cluster).await,
         FatType::Fat32 => Fat32::get(fat, cluster).await,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => ExFat::get(fat, cluster).await,
+    }
+}
+
+/// Reads the raw value of a FAT entry, including values without a meaning.
+pub(crate) async fn read_fat_raw<S, E>(fat: &mut S, fat_type: FatType, cluster: u32) -> Result<u32, Error<E>>
+where
+    S: Read + Seek,
+    E: IoError,
+    Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+{
+    match fat_type {
+        FatType::Fat12 => Fat12::get_raw(fat, cluster).await,
+        FatType::Fat16 => Fat16::get_raw(fat, cluster).await,
+        FatType::Fat32 => Fat32::get_raw(fat, cluster).await,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => ExFat::get_raw(fat, cluster).await,
     }
 }
 
-async fn write_fat<S, E>(
+pub(crate) async fn write_fat<S, E>(
     fat: &mut S,
     fat_type: FatType,
     cluster: u32,
@@ -96,6 +120,8 @@ where
         FatType::Fat12 => Fat12::set(fat, cluster, value).await,
         FatType::Fat16 => Fat16::set(fat, cluster, value).await,
         FatType::Fat32 => Fat32::set(fat, cluster, value).await,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => ExFat::set(fat, cluster, value).await,
     }
 }
 
@@ -131,6 +157,8 @@ where
         FatType::Fat12 => Fat12::find_free(fat, start_cluster, end_cluster).await,
         FatType::Fat16 => Fat16::find_free(fat, start_cluster, end_cluster).await,
         FatType::Fat32 => Fat32::find_free(fat, start_cluster, end_cluster).await,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => ExFat::find_free(fat, start_cluster, end_cluster).await,
     }
 }
 
@@ -180,20 +208,50 @@ where
         FatType::Fat12 => 0xFFF,
         FatType::Fat16 => Fat16::get_raw(fat, 1).await?,
         FatType::Fat32 => Fat32::get_raw(fat, 1).await?,
+        // exFAT keeps volume flags in the boot sector only
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => 0,
     };
     let dirty = match fat_type {
         FatType::Fat12 => false,
         FatType::Fat16 => val & (1 << 15) == 0,
         FatType::Fat32 => val & (1 << 27) == 0,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => false,
     };
     let io_error = match fat_type {
         FatType::Fat12 => false,
         FatType::Fat16 => val & (1 << 14) == 0,
         FatType::Fat32 => val & (1 << 26) == 0,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => false,
     };
     Ok(FsStatusFlags { dirty, io_error })
 }
 
+/// Sets the clean shutdown bit in the second FAT entry (FAT16 and FAT32 only).
+pub(crate) async fn clear_fat_dirty_flag<S, E>(fat: &mut S, fat_type: FatType) -> Result<(), Error<E>>
+where
+    S: Read + Write + Seek,
+    E: IoError,
+    Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+{
+    match fat_type {
+        FatType::Fat16 => {
+            let val = Fat16::get_raw(fat, 1).await?;
+            Fat16::set_raw(fat, 1, val | (1 << 15)).await
+        }
+        FatType::Fat32 => {
+            let val = Fat32::get_raw(fat, 1).await?;
+            Fat32::set_raw(fat, 1, val | (1 << 27)).await
+        }
+        FatType::Fat12 => Ok(()),
+        // exFAT keeps volume flags in the boot sector only
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => Ok(()),
+    }
+}
+
 pub(crate) async fn count_free_clusters<S, E>(
     fat: &mut S,
     fat_type: FatType,
@@ -209,6 +267,8 @@ where
         FatType::Fat12 => Fat12::count_free(fat, end_cluster).await,
         FatType::Fat16 => Fat16::count_free(fat, end_cluster).await,
         FatType::Fat32 => Fat32::count_free(fat, end_cluster).await,
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => ExFat::count_free(fat, end_cluster).await,
     }
 }
 
@@ -239,6 +299,11 @@ where
             fat.write_u32_le(u32::from(media) | 0xFFF_FF00).await?;
             fat.write_u32_le(0xFFFF_FFFF).await?;
         }
+        #[cfg(feature = "exfat")]
+        FatType::ExFat => {
+            fat.write_u32_le(0xFFFF_FFF8).await?;
+            fat.write_u32_le(0xFFFF_FFFF).await?;
+        }
     }
     // mark entries at the end of FAT as used (after FAT but before sector end)
     let start_cluster = total_clusters + RESERVED_FAT_ENTRIES;
@@ -248,7 +313,7 @@ where
         write_fat(fat, fat_type, cluster, FatValue::EndOfChain).await?;
     }
     // mark special entries 0x0FFFFFF0 - 0x0FFFFFFF as BAD if they exists on FAT32 volume
-    if end_cluster > 0x0FFF_FFF0 {
+    if fat_type == FatType::Fat32 && end_cluster > 0x0FFF_FFF0 {
         let end_bad_cluster = cmp::min(0x0FFF_FFFF + 1, end_cluster);
         for cluster in 0x0FFF_FFF0..end_bad_cluster {
             write_fat(fat, fat_type, cluster, FatValue::Bad).await?;
@@ -627,11 +692,114 @@ impl FatTrait for Fat32 {
     }
 }
 
+#[cfg(feature = "exfat")]
+impl FatTrait for ExFat {
+    async fn get_raw<S, E>(fat: &mut S, cluster: u32) -> Result<u32, Error<E>>
+    where
+        S: Read + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        fat.seek(io::SeekFrom::Start(u64::from(cluster) * 4))
+            .await?;
+        Ok(fat.read_u32_le().await?)
+    }
+
+    async fn get<S, E>(fat: &mut S, cluster: u32) -> Result<FatValue, Error<E>>
+    where
+        S: Read + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        // Note: exFAT uses all 32 bits; 0xFFFFFFF8 - 0xFFFFFFFE are media descriptors and treated as end-of-chain
+        let val = Self::get_raw(fat, cluster).await?;
+        Ok(match val {
+            0 => FatValue::Free,
+            0xFFFF_FFF7 => FatValue::Bad,
+            0xFFFF_FFF8..=0xFFFF_FFFF => FatValue::EndOfChain,
+            n => FatValue::Data(n),
+        })
+    }
+
+    async fn set<S, E>(fat: &mut S, cluster: u32, value: FatValue) -> Result<(), Error<E>>
+    where
+        S: Read + Write + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        let raw_val = match value {
+            FatValue::Free => 0,
+            FatValue::Bad => 0xFFFF_FFF7,
+            FatValue::EndOfChain => 0xFFFF_FFFF,
+            FatValue::Data(n) => n,
+        };
+        Self::set_raw(fat, cluster, raw_val).await
+    }
+
+    async fn find_free<S, E>(
+        fat: &mut S,
+        start_cluster: u32,
+        end_cluster: u32,
+    ) -> Result<u32, Error<E>>
+    where
+        S: Read + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        // Note: this only looks at the FAT. Allocation on a mounted exFAT volume is driven by the allocation bitmap
+        // because contiguous (NoFatChain) files do not use FAT entries at all.
+        let mut cluster = start_cluster;
+        fat.seek(io::SeekFrom::Start(u64::from(cluster) * 4))
+            .await?;
+        while cluster < end_cluster {
+            if fat.read_u32_le().await? == 0 {
+                return Ok(cluster);
+            }
+            cluster += 1;
+        }
+        Err(Error::NotEnoughSpace)
+    }
+
+    async fn count_free<S, E>(fat: &mut S, end_cluster: u32) -> Result<u32, Error<E>>
+    where
+        S: Read + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        let mut count = 0;
+        let mut cluster = RESERVED_FAT_ENTRIES;
+        fat.seek(io::SeekFrom::Start(u64::from(cluster) * 4))
+            .await?;
+        while cluster < end_cluster {
+            if fat.read_u32_le().await? == 0 {
+                count += 1;
+            }
+            cluster += 1;
+        }
+        Ok(count)
+    }
+
+    async fn set_raw<S, E>(fat: &mut S, cluster: u32, raw_value: u32) -> Result<(), Error<E>>
+    where
+        S: Read + Write + Seek,
+        E: IoError,
+        Error<E>: From<S::Error> + From<ReadExactError<S::Error>>,
+    {
+        fat.seek(io::SeekFrom::Start(u64::from(cluster) * 4))
+            .await?;
+        fat.write_u32_le(raw_value).await?;
+        Ok(())
+    }
+}
+
 pub(crate) struct ClusterIterator<B, E, S = B> {
     fat: B,
     fat_type: FatType,
     cluster: Option<u32>,
     err: bool,
+    /// Clusters released by `free` and `truncate`
+    #[cfg(feature = "discard")]
+    released: crate::discard::ClusterRuns,
     // phantom is needed to add type bounds on the storage type
     phantom_s: PhantomData<S>,
     phantom_e: PhantomData<E>,
@@ -650,6 +818,8 @@ where
             fat_type,
             cluster: Some(cluster),
             err: false,
+            #[cfg(feature = "discard")]
+            released: crate::discard::ClusterRuns::default(),
             phantom_s: PhantomData,
             phantom_e: PhantomData,
         }
@@ -679,11 +849,19 @@ where
         while let Some(n) = self.cluster {
             self.next().await;
             write_fat(self.fat.borrow_mut(), self.fat_type, n, FatValue::Free).await?;
+            #[cfg(feature = "discard")]
+            self.released.push(n);
             num_free += 1;
         }
         Ok(num_free)
     }
 
+    /// Returns the clusters released so far, merged into contiguous runs.
+    #[cfg(feature = "discard")]
+    pub(crate) fn take_released(&mut self) -> crate::discard::ClusterRuns {
+        core::mem::take(&mut self.released)
+    }
+
     pub async fn next(&mut self) -> Option<Result<u32, Error<E>>> {
         if self.err {
             return None;
@@ -0,0 +1,73 @@
+//! Fixtures shared by the unit tests.
+
+use crate::fs::{FatType, FormatVolumeOptions, format_volume};
+#[cfg(feature = "discard")]
+use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+use embedded_io_adapters::tokio_1::FromTokio;
+use std::io::Cursor;
+
+pub(crate) const MB: usize = 1024 * 1024;
+
+/// Returns a freshly formatted volume image with 512 byte clusters.
+pub(crate) async fn image(size: usize, fat_type: FatType) -> Vec<u8> {
+    let mut image = vec![0_u8; size];
+    let options = FormatVolumeOptions::new()
+        .fat_type(fat_type)
+        .bytes_per_cluster(512);
+    format_volume(&mut FromTokio::new(Cursor::new(&mut image)), options)
+        .await
+        .unwrap();
+    image
+}
+
+/// Returns test data that differs between seeds and does not repeat every sector.
+pub(crate) fn pattern(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| seed.wrapping_add((i / 7) as u8)).collect()
+}
+
+/// Storage recording the discarded ranges
+#[cfg(feature = "discard")]
+pub(crate) struct TrimStorage<'a> {
+    pub(crate) inner: FromTokio<Cursor<&'a mut Vec<u8>>>,
+    pub(crate) discards: Vec<(u64, u64)>,
+}
+
+#[cfg(feature = "discard")]
+impl IoBase for TrimStorage<'_> {
+    type Error = std::io::Error;
+}
+
+#[cfg(feature = "discard")]
+impl Read for TrimStorage<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read(buf).await
+    }
+}
+
+#[cfg(feature = "discard")]
+impl Write for TrimStorage<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+#[cfg(feature = "discard")]
+impl Seek for TrimStorage<'_> {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.inner.seek(pos).await
+    }
+}
+
+#[cfg(feature = "discard")]
+impl crate::Discard for TrimStorage<'_> {
+    type Error = std::io::Error;
+
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), Self::Error> {
+        self.discards.push((offset, len));
+        Ok(())
+    }
+}
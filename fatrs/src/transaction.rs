@@ -7,18 +7,23 @@
 //!
 //! ## Intent Log
 //! - Reserved sectors on disk store pending operations
+//! - The first `MAX_TRANSACTIONS` log sectors hold the transaction entries, the remaining sectors
+//!   hold before-images of the sectors modified by the active transaction
 //! - Each transaction records: operation type, affected sectors, checksums
-//! - Log is checked on mount and replayed/rolled back as needed
+//! - Log is checked on mount and rolled back as needed
 //!
 //! ## Two-Phase Commit Protocol
 //! 1. **Write Intent**: Record operation details to intent log
-//! 2. **Perform Operation**: Execute the actual disk writes
-//! 3. **Clear Intent**: Mark transaction as complete
+//! 2. **Perform Operation**: Execute the actual disk writes. Before a sector is written for the
+//!    first time its current contents are copied to the log and the entry is updated
+//! 3. **Commit**: Flush all writes and mark the transaction as committed
+//! 4. **Clear Intent**: Mark transaction as complete
 //!
 //! ## Recovery
 //! - On mount, check for incomplete transactions
-//! - If intent exists, determine if operation completed
-//! - Replay or rollback as appropriate
+//! - A pending or in-progress transaction is rolled back by writing the before-images back to
+//!   their sectors, which is idempotent if power is lost again during recovery
+//! - A committed transaction has all of its writes on disk and is only cleared
 //!
 //! # Safety Guarantees
 //!
@@ -37,12 +42,26 @@
 #![allow(clippy::doc_markdown)]
 #![allow(dead_code)]
 
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeSet;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(feature = "std")]
+use std::collections::BTreeSet;
+
 use crate::error::Error;
-use crate::io::{Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
+use crate::io::{Read, ReadLeExt, Seek, SeekFrom, Write};
 use core::fmt::Debug;
+use crc::{CRC_32_ISO_HDLC, Crc};
+
+/// Maximum number of transaction entries in the log (one entry per log sector)
+pub(crate) const MAX_TRANSACTIONS: usize = 4;
+
+/// Maximum number of sectors whose before-images can be stored for a single transaction
+pub(crate) const MAX_JOURNALED_SECTORS: usize = 64;
 
-/// Maximum number of concurrent transactions that can be logged
-const MAX_TRANSACTIONS: usize = 4;
+/// Default size of the transaction log in sectors (entries followed by before-image slots)
+pub(crate) const DEFAULT_LOG_SECTORS: u32 = (MAX_TRANSACTIONS + MAX_JOURNALED_SECTORS) as u32;
 
 /// Size of each transaction log entry in bytes
 const TRANSACTION_ENTRY_SIZE: usize = 512;
@@ -51,7 +70,17 @@ const TRANSACTION_ENTRY_SIZE: usize = 512;
 const TRANSACTION_MAGIC: u32 = 0x5458_4E46; // "TXNF"
 
 /// Transaction log version
-const TRANSACTION_VERSION: u16 = 1;
+const TRANSACTION_VERSION: u16 = 2;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Checksum of a single before-image, bound to the sector it belongs to.
+fn image_crc32(sector: u32, image: &[u8]) -> u32 {
+    let mut digest = CRC32.digest();
+    digest.update(&sector.to_le_bytes());
+    digest.update(image);
+    digest.finalize()
+}
 
 /// Type of filesystem operation being logged
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -70,6 +99,8 @@ pub enum TransactionType {
     FileMetadataUpdate = 4,
     /// Cluster chain modification (extend/truncate file)
     ClusterChainUpdate = 5,
+    /// Group of operations started by application code
+    User = 6,
 }
 
 impl TransactionType {
@@ -81,6 +112,7 @@ impl TransactionType {
             3 => Some(TransactionType::FsInfoUpdate),
             4 => Some(TransactionType::FileMetadataUpdate),
             5 => Some(TransactionType::ClusterChainUpdate),
+            6 => Some(TransactionType::User),
             _ => None,
         }
     }
@@ -124,9 +156,11 @@ impl TransactionState {
 /// - Timestamp (8 bytes): Operation timestamp
 /// - Sector count (2 bytes): Number of affected sectors
 /// - Sectors (up to 64 × 4 bytes = 256 bytes): List of affected sector numbers
-/// - Backup data (200 bytes): Original sector data for rollback
+/// - Image CRC32 (4 bytes): XOR of the checksums of the before-images stored in the log
 /// - CRC32 (4 bytes): Checksum of entry
-/// - Reserved (22 bytes): For future use
+/// - Reserved (226 bytes): For future use
+///
+/// The before-image of `affected_sectors[i]` is stored in log sector `MAX_TRANSACTIONS + i`.
 #[derive(Debug, Clone)]
 pub struct TransactionEntry {
     pub magic: u32,
@@ -137,7 +171,7 @@ pub struct TransactionEntry {
     pub timestamp: u64,
     pub affected_sectors: [u32; 64],
     pub sector_count: u16,
-    pub backup_data: [u8; 200],
+    pub image_crc32: u32,
     pub crc32: u32,
 }
 
@@ -153,37 +187,44 @@ impl TransactionEntry {
             timestamp: 0,
             affected_sectors: [0; 64],
             sector_count: 0,
-            backup_data: [0; 200],
+            image_crc32: 0,
             crc32: 0,
         }
     }
 
     /// Serialize transaction entry to bytes
+    ///
+    /// The entry is written with a single call, so an interrupted update leaves either the old or the new entry
+    /// on storage with sector-atomic writes.
     pub async fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), W::Error> {
-        writer.write_u32_le(self.magic).await?;
-        writer.write_u16_le(self.version).await?;
-        writer.write_u8(self.tx_type as u8).await?;
-        writer.write_u8(self.state as u8).await?;
-        writer.write_u32_le(self.sequence).await?;
-        writer.write_u64_le(self.timestamp).await?;
-        writer.write_u16_le(self.sector_count).await?;
+        writer.write_all(&self.to_bytes()).await
+    }
+
+    fn to_bytes(&self) -> [u8; TRANSACTION_ENTRY_SIZE] {
+        let mut buf = [0u8; TRANSACTION_ENTRY_SIZE];
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&self.magic.to_le_bytes());
+        put(&self.version.to_le_bytes());
+        put(&[self.tx_type as u8, self.state as u8]);
+        put(&self.sequence.to_le_bytes());
+        put(&self.timestamp.to_le_bytes());
+        put(&self.sector_count.to_le_bytes());
 
         // Write affected sectors
         for &sector in &self.affected_sectors {
-            writer.write_u32_le(sector).await?;
+            put(&sector.to_le_bytes());
         }
 
-        // Write backup data
-        writer.write_all(&self.backup_data).await?;
-
-        // Write CRC
-        writer.write_u32_le(self.crc32).await?;
-
-        // Write reserved bytes (padding to 512 bytes)
-        let reserved = [0u8; 22];
-        writer.write_all(&reserved).await?;
+        // Write before-image checksum
+        put(&self.image_crc32.to_le_bytes());
 
-        Ok(())
+        // Write CRC, the remaining bytes are reserved
+        put(&self.crc32.to_le_bytes());
+        buf
     }
 
     /// Deserialize transaction entry from bytes
@@ -204,15 +245,14 @@ impl TransactionEntry {
             *sector = reader.read_u32_le().await?;
         }
 
-        // Read backup data
-        let mut backup_data = [0u8; 200];
-        reader.read_exact(&mut backup_data).await?;
+        // Read before-image checksum
+        let image_crc32 = reader.read_u32_le().await?;
 
         // Read CRC
         let crc32 = reader.read_u32_le().await?;
 
         // Skip reserved bytes
-        let mut reserved = [0u8; 22];
+        let mut reserved = [0u8; 226];
         reader.read_exact(&mut reserved).await?;
 
         Ok(Self {
@@ -224,16 +264,13 @@ impl TransactionEntry {
             timestamp,
             affected_sectors,
             sector_count,
-            backup_data,
+            image_crc32,
             crc32,
         })
     }
 
     /// Calculate CRC32 checksum of entry data using the `crc` crate.
     pub fn calculate_crc32(&self) -> u32 {
-        use crc::{CRC_32_ISO_HDLC, Crc};
-
-        const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
         let mut digest = CRC32.digest();
 
         // Hash magic, version, type, state
@@ -246,12 +283,13 @@ impl TransactionEntry {
         digest.update(&self.sector_count.to_le_bytes());
 
         // Hash affected sectors
-        for &sector in &self.affected_sectors[..self.sector_count as usize] {
+        let count = usize::from(self.sector_count).min(MAX_JOURNALED_SECTORS);
+        for &sector in &self.affected_sectors[..count] {
             digest.update(&sector.to_le_bytes());
         }
 
-        // Hash backup data
-        digest.update(&self.backup_data);
+        // Hash before-image checksum
+        digest.update(&self.image_crc32.to_le_bytes());
 
         digest.finalize()
     }
@@ -261,9 +299,11 @@ impl TransactionEntry {
         self.crc32 == self.calculate_crc32()
     }
 
-    /// Check if entry is valid (magic and CRC match)
+    /// Check if entry is valid (magic, version and CRC match)
     pub fn is_valid(&self) -> bool {
-        self.magic == TRANSACTION_MAGIC && self.verify_crc32()
+        self.magic == TRANSACTION_MAGIC
+            && self.version == TRANSACTION_VERSION
+            && self.verify_crc32()
     }
 }
 
@@ -275,16 +315,27 @@ impl Default for TransactionEntry {
 
 /// Transaction log manager
 ///
-/// Manages a circular buffer of transaction entries stored in reserved sectors.
+/// Manages the transaction entries and before-images stored in reserved sectors.
+/// Only one transaction can be active at a time because all transactions share the before-image area.
 pub struct TransactionLog {
     /// Starting sector of transaction log area
     log_start_sector: u32,
     /// Number of sectors allocated for transaction log
     log_sector_count: u32,
+    /// Size of a sector in bytes
+    bytes_per_sector: u32,
     /// Current transaction sequence number (monotonic counter)
     pub(crate) sequence: u32,
     /// Active transaction entries
     entries: [TransactionEntry; MAX_TRANSACTIONS],
+    /// Slot of the transaction whose writes are being journaled
+    active: Option<usize>,
+    /// True if the active transaction ran out of before-image slots
+    overflowed: bool,
+    /// Clusters that were free when the active transaction began and were allocated by it
+    new_clusters: BTreeSet<u32>,
+    /// Clusters freed by the active transaction that were in use when it began
+    released_clusters: BTreeSet<u32>,
 }
 
 /// Transaction log statistics
@@ -297,6 +348,9 @@ pub struct TransactionStatistics {
     pub used_slots: usize,
     /// Current sequence number (monotonic counter)
     pub sequence_number: u32,
+    /// Number of sectors in use before the transaction that a single transaction can overwrite, 0 if the volume
+    /// has no usable transaction log
+    pub sector_capacity: usize,
 }
 
 /// Detailed information about a single transaction
@@ -329,6 +383,7 @@ impl TransactionLog {
         Self {
             log_start_sector,
             log_sector_count,
+            bytes_per_sector: 512,
             sequence: 0,
             entries: [
                 TransactionEntry::new(),
@@ -336,9 +391,81 @@ impl TransactionLog {
                 TransactionEntry::new(),
                 TransactionEntry::new(),
             ],
+            active: None,
+            overflowed: false,
+            new_clusters: BTreeSet::new(),
+            released_clusters: BTreeSet::new(),
         }
     }
 
+    pub(crate) fn set_bytes_per_sector(&mut self, bytes_per_sector: u16) {
+        self.bytes_per_sector = u32::from(bytes_per_sector);
+    }
+
+    /// Number of sectors a single transaction can modify before the log is full
+    pub fn image_capacity(&self) -> usize {
+        (self.log_sector_count as usize)
+            .saturating_sub(MAX_TRANSACTIONS)
+            .min(MAX_JOURNALED_SECTORS)
+    }
+
+    /// Returns true if the log has room for the transaction entries and at least one before-image
+    pub fn is_enabled(&self) -> bool {
+        self.image_capacity() > 0
+    }
+
+    /// Slot of the transaction currently being journaled
+    pub fn active_transaction(&self) -> Option<usize> {
+        self.active
+    }
+
+    /// Returns true if a write of the active transaction was rejected because the log was full
+    pub(crate) fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Records a cluster allocated by the active transaction
+    ///
+    /// A cluster that was free when the transaction began is free again after a rollback, so its
+    /// sectors do not need before-images.
+    pub(crate) fn note_allocated_cluster(&mut self, cluster: u32) {
+        if self.active.is_some() && !self.released_clusters.contains(&cluster) {
+            self.new_clusters.insert(cluster);
+        }
+    }
+
+    /// Records a cluster freed by the active transaction
+    ///
+    /// A rollback makes the cluster part of its old chain again, so its sectors keep being
+    /// journaled if it is reused by the same transaction.
+    pub(crate) fn note_released_cluster(&mut self, cluster: u32) {
+        if self.active.is_some() && !self.new_clusters.contains(&cluster) {
+            self.released_clusters.insert(cluster);
+        }
+    }
+
+    /// Returns true if the cluster was free when the active transaction began
+    pub(crate) fn is_new_cluster(&self, cluster: u32) -> bool {
+        self.new_clusters.contains(&cluster)
+    }
+
+    /// Stops journaling writes for the given slot
+    fn end_transaction(&mut self, slot: usize) {
+        if self.active == Some(slot) {
+            self.active = None;
+            self.new_clusters.clear();
+            self.released_clusters.clear();
+        }
+    }
+
+    fn sector_offset(&self, log_sector: usize) -> u64 {
+        (u64::from(self.log_start_sector) + log_sector as u64) * u64::from(self.bytes_per_sector)
+    }
+
+    fn image_offset(&self, index: usize) -> u64 {
+        self.sector_offset(MAX_TRANSACTIONS + index)
+    }
+
     /// Initialize transaction log on disk
     pub async fn initialize<IO: Read + Write + Seek>(
         &mut self,
@@ -346,8 +473,7 @@ impl TransactionLog {
     ) -> Result<(), Error<IO::Error>> {
         // Write empty transaction entries
         for i in 0..MAX_TRANSACTIONS {
-            let sector = self.log_start_sector + i as u32;
-            disk.seek(SeekFrom::Start(u64::from(sector) * 512)).await?;
+            disk.seek(SeekFrom::Start(self.sector_offset(i))).await?;
 
             let mut entry = TransactionEntry::new();
             entry.crc32 = entry.calculate_crc32();
@@ -367,10 +493,14 @@ impl TransactionLog {
 
         // Read all transaction entries
         for i in 0..MAX_TRANSACTIONS {
-            let sector = self.log_start_sector + i as u32;
-            disk.seek(SeekFrom::Start(u64::from(sector) * 512)).await?;
+            disk.seek(SeekFrom::Start(self.sector_offset(i))).await?;
 
-            let entry = TransactionEntry::deserialize(disk).await?;
+            // A sector that was never initialized is treated as an empty slot
+            let entry = match TransactionEntry::deserialize(disk).await {
+                Ok(entry) if entry.is_valid() => entry,
+                Ok(_) | Err(Error::CorruptedFileSystem) => TransactionEntry::new(),
+                Err(err) => return Err(err),
+            };
 
             // Track highest sequence number
             if entry.sequence > max_sequence {
@@ -382,24 +512,24 @@ impl TransactionLog {
 
         // Continue from highest sequence number
         self.sequence = max_sequence.saturating_add(1);
+        self.active = None;
 
         Ok(())
     }
 
     /// Begin a new transaction
     ///
-    /// Returns the transaction slot index, or None if all slots are full.
+    /// Returns the transaction slot index, or None if all slots are full or another transaction
+    /// is active. Affected sectors are added by [`TransactionLog::journal_sector`].
     ///
     /// # Arguments
     /// * `tx_type` - The type of transaction being started
-    /// * `affected_sectors` - List of sectors that will be modified (max 64)
     /// * `timestamp` - Unix timestamp (seconds since epoch) for when transaction started
-    pub fn begin_transaction(
-        &mut self,
-        tx_type: TransactionType,
-        affected_sectors: &[u32],
-        timestamp: u64,
-    ) -> Option<usize> {
+    pub fn begin_transaction(&mut self, tx_type: TransactionType, timestamp: u64) -> Option<usize> {
+        if self.active.is_some() {
+            return None;
+        }
+
         // Find an empty slot
         let slot = self
             .entries
@@ -411,17 +541,117 @@ impl TransactionLog {
         entry.state = TransactionState::Pending;
         entry.sequence = self.sequence;
         entry.timestamp = timestamp;
-        entry.sector_count = affected_sectors.len().min(64) as u16;
+        entry.sector_count = 0;
+        entry.affected_sectors = [0; MAX_JOURNALED_SECTORS];
+        entry.image_crc32 = 0;
+        entry.crc32 = entry.calculate_crc32();
 
-        for (i, &sector) in affected_sectors.iter().take(64).enumerate() {
-            entry.affected_sectors[i] = sector;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.active = Some(slot);
+        self.overflowed = false;
+        self.new_clusters.clear();
+        self.released_clusters.clear();
+
+        Some(slot)
+    }
+
+    /// Store the before-image of a sector that is about to be written by the active transaction
+    ///
+    /// Does nothing if no transaction is active or the sector was already journaled. Otherwise the
+    /// current sector contents are copied to the log and the updated entry is written, so the
+    /// sector can be restored if power is lost before the transaction commits. The disk position
+    /// is left undefined.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::TransactionLogFull` will be returned if the log has no free before-image slot.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn journal_sector<IO: Read + Write + Seek>(
+        &mut self,
+        disk: &mut IO,
+        sector: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        let Some(slot) = self.active else {
+            return Ok(());
+        };
+        let count = usize::from(self.entries[slot].sector_count);
+        if self.entries[slot].affected_sectors[..count].contains(&sector) {
+            return Ok(());
+        }
+        if count >= self.image_capacity() {
+            self.overflowed = true;
+            return Err(Error::TransactionLogFull);
         }
 
+        let mut image = vec![0u8; self.bytes_per_sector as usize];
+        disk.seek(SeekFrom::Start(
+            u64::from(sector) * u64::from(self.bytes_per_sector),
+        ))
+        .await?;
+        disk.read_exact(&mut image).await?;
+        disk.seek(SeekFrom::Start(self.image_offset(count))).await?;
+        disk.write_all(&image).await?;
+        // The image must be durable before the entry referencing it
+        disk.flush().await?;
+
+        let entry = &mut self.entries[slot];
+        entry.affected_sectors[count] = sector;
+        entry.sector_count += 1;
+        entry.image_crc32 ^= image_crc32(sector, &image);
+        entry.state = TransactionState::InProgress;
         entry.crc32 = entry.calculate_crc32();
+        self.write_intent(disk, slot).await
+    }
 
-        self.sequence = self.sequence.wrapping_add(1);
+    /// Write the before-images of a transaction back to their sectors and clear the entry
+    ///
+    /// Returns `false` without touching the volume if the stored images fail verification.
+    /// The entry is cleared in both cases.
+    pub async fn rollback<IO: Read + Write + Seek>(
+        &mut self,
+        disk: &mut IO,
+        slot: usize,
+    ) -> Result<bool, Error<IO::Error>> {
+        if slot >= MAX_TRANSACTIONS {
+            return Err(Error::InvalidInput);
+        }
 
-        Some(slot)
+        // Writes made from now on are not part of the transaction
+        self.end_transaction(slot);
+
+        let entry = self.entries[slot].clone();
+        let count = usize::from(entry.sector_count);
+        let mut restored = count <= self.image_capacity();
+        let mut image = vec![0u8; self.bytes_per_sector as usize];
+
+        if restored {
+            // Verify every image before the first one is written back
+            let mut crc = 0;
+            for (i, &sector) in entry.affected_sectors[..count].iter().enumerate() {
+                disk.seek(SeekFrom::Start(self.image_offset(i))).await?;
+                disk.read_exact(&mut image).await?;
+                crc ^= image_crc32(sector, &image);
+            }
+            restored = crc == entry.image_crc32;
+        }
+
+        if restored {
+            for (i, &sector) in entry.affected_sectors[..count].iter().enumerate() {
+                disk.seek(SeekFrom::Start(self.image_offset(i))).await?;
+                disk.read_exact(&mut image).await?;
+                disk.seek(SeekFrom::Start(
+                    u64::from(sector) * u64::from(self.bytes_per_sector),
+                ))
+                .await?;
+                disk.write_all(&image).await?;
+            }
+            disk.flush().await?;
+        }
+
+        self.clear(disk, slot).await?;
+        Ok(restored)
     }
 
     /// Write transaction intent to disk
@@ -434,8 +664,7 @@ impl TransactionLog {
             return Err(Error::InvalidInput);
         }
 
-        let sector = self.log_start_sector + slot as u32;
-        disk.seek(SeekFrom::Start(u64::from(sector) * 512)).await?;
+        disk.seek(SeekFrom::Start(self.sector_offset(slot))).await?;
 
         self.entries[slot].serialize(disk).await?;
         disk.flush().await?;
@@ -466,8 +695,7 @@ impl TransactionLog {
         self.entries[slot].crc32 = self.entries[slot].calculate_crc32();
 
         // Write updated state
-        let sector = self.log_start_sector + slot as u32;
-        disk.seek(SeekFrom::Start(u64::from(sector) * 512)).await?;
+        disk.seek(SeekFrom::Start(self.sector_offset(slot))).await?;
         self.entries[slot].serialize(disk).await?;
         disk.flush().await?;
 
@@ -484,13 +712,14 @@ impl TransactionLog {
             return Err(Error::InvalidInput);
         }
 
+        self.end_transaction(slot);
+
         // Create empty entry
         let mut entry = TransactionEntry::new();
         entry.crc32 = entry.calculate_crc32();
 
         // Write to disk
-        let sector = self.log_start_sector + slot as u32;
-        disk.seek(SeekFrom::Start(u64::from(sector) * 512)).await?;
+        disk.seek(SeekFrom::Start(self.sector_offset(slot))).await?;
         entry.serialize(disk).await?;
         disk.flush().await?;
 
@@ -536,6 +765,8 @@ impl TransactionLog {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
 
     #[test]
     fn test_transaction_entry_crc32() {
@@ -570,14 +801,102 @@ mod tests {
 
         // Use a known timestamp (2024-01-01 00:00:00 UTC = 1704067200)
         let timestamp = 1704067200u64;
-        let slot = log.begin_transaction(TransactionType::FatUpdate, &[200, 201, 202], timestamp);
+        let slot = log.begin_transaction(TransactionType::FatUpdate, timestamp);
         assert!(slot.is_some());
 
         let slot = slot.unwrap();
         assert_eq!(log.entries[slot].tx_type, TransactionType::FatUpdate);
         assert_eq!(log.entries[slot].state, TransactionState::Pending);
-        assert_eq!(log.entries[slot].sector_count, 3);
-        assert_eq!(log.entries[slot].affected_sectors[0], 200);
+        assert_eq!(log.entries[slot].sector_count, 0);
         assert_eq!(log.entries[slot].timestamp, timestamp);
+        assert_eq!(log.active_transaction(), Some(slot));
+
+        // Only one transaction can be journaled at a time
+        assert!(
+            log.begin_transaction(TransactionType::FatUpdate, timestamp)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_log_journal_and_rollback() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0u8; 64 * 512]));
+        storage.seek(SeekFrom::Start(20 * 512)).await.unwrap();
+        storage.write_all(&[0xAA; 1024]).await.unwrap();
+
+        let mut log = TransactionLog::new(2, 6);
+        log.initialize(&mut storage).await.unwrap();
+        let slot = log
+            .begin_transaction(TransactionType::DirEntryUpdate, 0)
+            .unwrap();
+        log.write_intent(&mut storage, slot).await.unwrap();
+
+        // Journaling the same sector twice keeps the first image
+        log.journal_sector(&mut storage, 20).await.unwrap();
+        log.journal_sector(&mut storage, 21).await.unwrap();
+        log.journal_sector(&mut storage, 20).await.unwrap();
+        assert_eq!(log.entries[slot].sector_count, 2);
+        assert_eq!(log.entries[slot].state, TransactionState::InProgress);
+
+        storage.seek(SeekFrom::Start(20 * 512)).await.unwrap();
+        storage.write_all(&[0x55; 1024]).await.unwrap();
+
+        // A fresh log sees the interrupted transaction like a mount after power loss
+        let mut log = TransactionLog::new(2, 6);
+        log.load(&mut storage).await.unwrap();
+        let incomplete: Vec<_> = log
+            .get_incomplete_transactions()
+            .map(|(slot, _)| slot)
+            .collect();
+        assert_eq!(incomplete, [slot]);
+        assert!(log.rollback(&mut storage, slot).await.unwrap());
+        assert_eq!(log.get_incomplete_transactions().count(), 0);
+
+        let mut buf = [0u8; 1024];
+        storage.seek(SeekFrom::Start(20 * 512)).await.unwrap();
+        storage.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0xAA; 1024]);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_log_full() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0u8; 64 * 512]));
+        let mut log = TransactionLog::new(2, 6);
+        log.initialize(&mut storage).await.unwrap();
+        log.begin_transaction(TransactionType::FatUpdate, 0)
+            .unwrap();
+
+        log.journal_sector(&mut storage, 30).await.unwrap();
+        log.journal_sector(&mut storage, 31).await.unwrap();
+        assert!(!log.overflowed());
+        assert!(matches!(
+            log.journal_sector(&mut storage, 32).await,
+            Err(Error::TransactionLogFull)
+        ));
+        assert!(log.overflowed());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_log_new_clusters() {
+        let mut storage = FromTokio::new(Cursor::new(vec![0u8; 64 * 512]));
+        let mut log = TransactionLog::new(2, 6);
+        log.initialize(&mut storage).await.unwrap();
+
+        // Nothing is tracked outside of a transaction
+        log.note_allocated_cluster(5);
+        assert!(!log.is_new_cluster(5));
+
+        let slot = log.begin_transaction(TransactionType::User, 0).unwrap();
+        log.note_allocated_cluster(5);
+        log.note_released_cluster(5);
+        assert!(log.is_new_cluster(5));
+
+        // A cluster freed by the transaction holds data a rollback needs
+        log.note_released_cluster(6);
+        log.note_allocated_cluster(6);
+        assert!(!log.is_new_cluster(6));
+
+        log.clear(&mut storage, slot).await.unwrap();
+        assert!(!log.is_new_cluster(5));
     }
 }
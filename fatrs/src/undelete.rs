@@ -0,0 +1,452 @@
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+use crate::dir::{Dir, MAX_LONG_NAME_LEN, lfn_checksum};
+use crate::dir_entry::{
+    DIR_ENTRY_DELETED_FLAG, DIR_ENTRY_SIZE, DirEntry, DirEntryData, DirFileEntryData,
+    FileAttributes, LFN_ENTRY_LAST_FLAG, LFN_PART_LEN, SFN_SIZE, ShortName,
+};
+use crate::error::Error;
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::io::Write;
+use crate::table::{FatValue, RESERVED_FAT_ENTRIES, read_fat};
+use crate::time::TimeProvider;
+
+const MAX_LONG_DIR_ENTRIES: usize = MAX_LONG_NAME_LEN.div_ceil(LFN_PART_LEN);
+
+/// A deleted file or directory found by [`Dir::deleted_entries`].
+///
+/// Deleting an entry overwrites the first character of its short name. It is recovered from the checksum stored in
+/// the long name entries when they are still present, otherwise it has to be provided with
+/// [`DeletedEntry::with_first_char`] before the entry can be recovered.
+#[derive(Clone, Debug)]
+pub struct DeletedEntry {
+    data: DirFileEntryData,
+    first_byte: Option<u8>,
+    first_char: char,
+    short_name_rest: String,
+    long_name: Option<String>,
+    first_cluster: Option<u32>,
+    entry_pos: u64,
+    lfn_positions: Vec<u64>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl DeletedEntry {
+    /// Returns the short file name. An unknown first character is shown as `?`.
+    #[must_use]
+    pub fn short_file_name(&self) -> String {
+        let mut name = String::from(self.first_char);
+        name.push_str(&self.short_name_rest);
+        name
+    }
+
+    /// Returns the reassembled long file name, if its entries are still present.
+    #[must_use]
+    pub fn long_file_name(&self) -> Option<&str> {
+        self.long_name.as_deref()
+    }
+
+    /// Returns the long file name or if it doesn't exist fallbacks to the short file name.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        self.long_name
+            .clone()
+            .unwrap_or_else(|| self.short_file_name())
+    }
+
+    /// Checks if the first character of the short name is known.
+    #[must_use]
+    pub fn is_name_complete(&self) -> bool {
+        self.first_byte.is_some()
+    }
+
+    /// Sets the first character of the short name.
+    ///
+    /// Only ASCII characters valid in a short name are accepted, lowercase letters are converted to uppercase. Other
+    /// characters leave the first character unknown.
+    #[must_use]
+    pub fn with_first_char(mut self, first_char: char) -> Self {
+        let first_char = first_char.to_ascii_uppercase();
+        self.first_byte = u8::try_from(first_char)
+            .ok()
+            .filter(|c| c.is_ascii() && is_valid_first_char(*c));
+        self.first_char = if self.first_byte.is_some() {
+            first_char
+        } else {
+            '?'
+        };
+        self
+    }
+
+    /// Returns the first cluster the entry pointed to before it was deleted.
+    #[must_use]
+    pub fn first_cluster(&self) -> Option<u32> {
+        self.first_cluster
+    }
+
+    /// Returns the file size or 0 for directory.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.data.size().map_or(0, u64::from)
+    }
+
+    /// Returns file attributes.
+    #[must_use]
+    pub fn attributes(&self) -> FileAttributes {
+        self.data.attributes()
+    }
+
+    /// Checks if entry belongs to directory.
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.data.is_dir()
+    }
+
+    fn restored_name(&self) -> Option<[u8; SFN_SIZE]> {
+        let mut name = *self.data.name();
+        name[0] = self.first_byte?;
+        Some(name)
+    }
+}
+
+fn is_valid_first_char(c: u8) -> bool {
+    c > b' ' && c != DIR_ENTRY_DELETED_FLAG && !b"\"*+,./:;<=>?[\\]|".contains(&c)
+}
+
+/// Finds the first character of a deleted short name from the checksum stored in its long name entries.
+///
+/// Every step of the checksum is a bijection, so at most one character matches.
+fn first_char_from_checksum(name: &[u8; SFN_SIZE], checksum: u8) -> Option<u8> {
+    let mut name = *name;
+    (0..=u8::MAX)
+        .find(|c| {
+            name[0] = *c;
+            lfn_checksum(&name) == checksum
+        })
+        .filter(|c| is_valid_first_char(*c))
+}
+
+/// Deleted long name entry waiting for its short name entry.
+struct DeletedLfn {
+    checksum: u8,
+    name: [u16; LFN_PART_LEN],
+    pos: u64,
+}
+
+/// Reassembles a long name from deleted entries stored right before the short name entry. The entry closest to the
+/// short name holds the first part of the name.
+fn reassemble_long_name(lfn: &[DeletedLfn], checksum: u8) -> (Option<String>, Vec<u64>) {
+    let parts: Vec<&DeletedLfn> = lfn
+        .iter()
+        .rev()
+        .take(MAX_LONG_DIR_ENTRIES)
+        .take_while(|part| part.checksum == checksum)
+        .collect();
+    if parts.is_empty() {
+        return (None, Vec::new());
+    }
+    let mut name: Vec<u16> = parts.iter().flat_map(|part| part.name).collect();
+    let len = name
+        .iter()
+        .position(|c| *c == 0 || *c == 0xFFFF)
+        .unwrap_or(name.len());
+    name.truncate(len);
+    let positions = parts.iter().rev().map(|part| part.pos).collect();
+    (Some(String::from_utf16_lossy(&name)), positions)
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, TP, OCC> {
+    /// Lists the deleted files and directories of this directory that still have a short name entry.
+    ///
+    /// Deleted long name entries are reassembled when they directly precede the short name entry and their checksum
+    /// matches it. The clusters of a deleted entry may already have been reused, which is only checked when it is
+    /// recovered.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned for exFAT volumes, which are not supported.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn deleted_entries(&self) -> Result<Vec<DeletedEntry>, Error<IO::Error>> {
+        trace!("Dir::deleted_entries");
+        if self.fs.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        self.fs.flush_dirty_dir_entries().await?;
+        let mut stream = self.stream.clone();
+        let mut entries = Vec::new();
+        let mut lfn: Vec<DeletedLfn> = Vec::new();
+        loop {
+            let raw_entry = DirEntryData::deserialize(&mut stream).await?;
+            if raw_entry.is_end() {
+                break;
+            }
+            // abs_pos() returns None only at position 0, which is not the case because an entry was just read
+            let pos = stream.abs_pos().unwrap_or_default() - u64::from(DIR_ENTRY_SIZE);
+            match raw_entry {
+                DirEntryData::Lfn(data) if data.is_deleted() => {
+                    let mut name = [0; LFN_PART_LEN];
+                    data.copy_name_to_slice(&mut name);
+                    lfn.push(DeletedLfn {
+                        checksum: data.checksum(),
+                        name,
+                        pos,
+                    });
+                }
+                DirEntryData::File(data) if data.is_deleted() && !data.is_volume() => {
+                    let first_byte = lfn
+                        .last()
+                        .and_then(|part| first_char_from_checksum(data.name(), part.checksum));
+                    let (long_name, lfn_positions) = match first_byte {
+                        Some(c) => {
+                            let mut name = *data.name();
+                            name[0] = c;
+                            reassemble_long_name(&lfn, lfn_checksum(&name))
+                        }
+                        None => (None, Vec::new()),
+                    };
+                    let occ = &self.fs.options.oem_cp_converter;
+                    let mut name = *data.name();
+                    name[0] = b'?';
+                    let mut short_name_rest = ShortName::new(&name).to_string(occ);
+                    short_name_rest.remove(0);
+                    entries.push(DeletedEntry {
+                        first_byte,
+                        first_char: first_byte.map_or('?', |c| occ.decode(c)),
+                        short_name_rest,
+                        long_name,
+                        first_cluster: data.first_cluster(self.fs.fat_type()),
+                        entry_pos: pos,
+                        lfn_positions,
+                        data,
+                    });
+                    lfn.clear();
+                }
+                _ => lfn.clear(),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Recovers a deleted file or directory returned by [`Dir::deleted_entries`].
+    ///
+    /// The directory entries are restored in place and the cluster chain is linked again assuming the data was
+    /// stored contiguously, which is how most files are written. A directory gets back only its first cluster, since
+    /// only empty directories can be removed. All clusters must still be free according to the FAT.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInput` will be returned if the first character of the short name is unknown or for exFAT
+    ///   volumes, which are not supported.
+    /// * `Error::NotFound` will be returned if the entry is no longer marked as deleted in this directory.
+    /// * `Error::AlreadyExists` will be returned if an entry with the same name exists.
+    /// * `Error::ClustersInUse` will be returned if the clusters of the entry have been reused.
+    /// * `Error::ReadOnly` will be returned if the volume is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub async fn undelete(
+        &self,
+        entry: &DeletedEntry,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::undelete {}", entry.file_name().as_str());
+        if self.fs.is_exfat() {
+            return Err(Error::InvalidInput);
+        }
+        self.fs.check_writable()?;
+        let Some(short_name) = entry.restored_name() else {
+            return Err(Error::InvalidInput);
+        };
+        self.fs.flush_dirty_dir_entries().await?;
+
+        // the entry must not have been reused since it was listed
+        match DirEntryData::deserialize(&mut self.fs.dir_entry_slice(entry.entry_pos)).await? {
+            DirEntryData::File(data) if data == entry.data => {}
+            _ => return Err(Error::NotFound),
+        }
+        let short_file_name = entry.short_file_name();
+        for name in [Some(short_file_name.as_str()), entry.long_file_name()]
+            .into_iter()
+            .flatten()
+        {
+            if self.exists(name).await? {
+                return Err(Error::AlreadyExists);
+            }
+        }
+
+        // relink the chain first, so an interrupted recovery leaves a lost chain rather than a broken entry
+        if let Some(first_cluster) = entry.first_cluster {
+            let count = if entry.is_dir() {
+                1
+            } else {
+                self.fs.clusters_from_bytes(entry.len()).max(1)
+            };
+            check_free_run(self.fs, first_cluster, count).await?;
+            self.fs.alloc_contiguous_chain(first_cluster, count).await?;
+        }
+        let parts = entry.lfn_positions.len();
+        for (i, pos) in entry.lfn_positions.iter().enumerate() {
+            let mut order = (parts - i) as u8;
+            if i == 0 {
+                order |= LFN_ENTRY_LAST_FLAG;
+            }
+            self.fs.dir_entry_slice(*pos).write_all(&[order]).await?;
+        }
+        self.fs
+            .dir_entry_slice(entry.entry_pos)
+            .write_all(&short_name[..1])
+            .await?;
+        self.fs.flush().await?;
+        let mut iter = self.iter();
+        while let Some(e) = iter.next().await {
+            let e = e?;
+            if e.entry_pos == entry.entry_pos {
+                return Ok(e);
+            }
+        }
+        Err(Error::NotFound)
+    }
+}
+
+/// Checks that a run of clusters exists and is free.
+async fn check_free_run<IO: ReadWriteSeek, TP, OCC>(
+    fs: &FileSystem<IO, TP, OCC>,
+    first_cluster: u32,
+    count: u32,
+) -> Result<(), Error<IO::Error>> {
+    let end_cluster = fs.total_clusters() + RESERVED_FAT_ENTRIES;
+    if first_cluster < RESERVED_FAT_ENTRIES || count > end_cluster - first_cluster {
+        return Err(Error::ClustersInUse);
+    }
+    let mut fat = fs.fat_slice();
+    for cluster in first_cluster..first_cluster + count {
+        if read_fat(&mut fat, fs.fat_type(), cluster).await? != FatValue::Free {
+            return Err(Error::ClustersInUse);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{FatType, FsOptions};
+    use crate::io::{Read, Seek, SeekFrom};
+    use crate::table::write_fat;
+    use crate::test_support::{MB, image};
+    use embedded_io_adapters::tokio_1::FromTokio;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_list_and_undelete() {
+        let mut image = image(4 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i % 251) as u8).collect();
+        let mut file = root.create_file("Measurements 2024.csv").await.unwrap();
+        file.write_all(&data).await.unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+        root.create_dir("logs").await.unwrap();
+        root.create_file("KEEP.TXT").await.unwrap();
+        root.remove("Measurements 2024.csv").await.unwrap();
+        root.remove("logs").await.unwrap();
+
+        let deleted = root.deleted_entries().await.unwrap();
+        assert_eq!(deleted.len(), 2);
+        let file = &deleted[0];
+        assert_eq!(file.long_file_name(), Some("Measurements 2024.csv"));
+        assert!(file.is_name_complete());
+        assert_eq!(file.short_file_name(), "MEASUR~1.CSV");
+        assert_eq!(file.len(), 3000);
+        assert!(file.first_cluster().is_some());
+        assert!(deleted[1].is_dir());
+
+        let entry = root.undelete(file).await.unwrap();
+        assert_eq!(entry.file_name(), "Measurements 2024.csv");
+        let mut read = Vec::new();
+        let mut buf = [0; 512];
+        let mut file = root.open_file("Measurements 2024.csv").await.unwrap();
+        loop {
+            let n = file.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(read, data);
+        drop(file);
+
+        let dir = root.undelete(&deleted[1]).await.unwrap();
+        assert!(dir.is_dir());
+        assert!(dir.to_dir().is_empty().await.unwrap());
+        assert!(root.deleted_entries().await.unwrap().is_empty());
+        assert!(fs.check().await.unwrap().is_clean());
+
+        // already recovered
+        assert!(matches!(
+            root.undelete(&deleted[1]).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_undelete_short_name_and_reused_clusters() {
+        let mut image = image(4 * MB, FatType::Fat16).await;
+        let fs = FileSystem::new(FromTokio::new(Cursor::new(&mut image)), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        for name in ["A.TXT", "B.TXT"] {
+            let mut file = root.create_file(name).await.unwrap();
+            file.write_all(&[b'x'; 1024]).await.unwrap();
+            file.flush().await.unwrap();
+        }
+        root.remove("A.TXT").await.unwrap();
+        root.remove("B.TXT").await.unwrap();
+        let deleted = root.deleted_entries().await.unwrap();
+        // turn the long name entries into deleted volume entries, which are ignored
+        for entry in &deleted {
+            for pos in &entry.lfn_positions {
+                let mut slice = fs.dir_entry_slice(*pos);
+                slice.seek(SeekFrom::Start(11)).await.unwrap();
+                slice
+                    .write_all(&[FileAttributes::VOLUME_ID.bits()])
+                    .await
+                    .unwrap();
+            }
+        }
+        // reuse the clusters of A.TXT
+        let mut fat = fs.fat_slice();
+        write_fat::<_, std::io::Error>(
+            &mut fat,
+            fs.fat_type(),
+            deleted[0].first_cluster().unwrap(),
+            FatValue::EndOfChain,
+        )
+        .await
+        .unwrap();
+
+        let deleted = root.deleted_entries().await.unwrap();
+        let names: Vec<String> = deleted.iter().map(DeletedEntry::short_file_name).collect();
+        assert_eq!(names, ["?.TXT", "?.TXT"]);
+        assert!(!deleted[0].is_name_complete());
+        assert_eq!(deleted[0].long_file_name(), None);
+        assert!(matches!(
+            root.undelete(&deleted[0]).await,
+            Err(Error::InvalidInput)
+        ));
+
+        let a = deleted[0].clone().with_first_char('a');
+        assert_eq!(a.short_file_name(), "A.TXT");
+        assert!(matches!(root.undelete(&a).await, Err(Error::ClustersInUse)));
+
+        let b = deleted[1].clone().with_first_char('b');
+        let entry = root.undelete(&b).await.unwrap();
+        assert_eq!(entry.short_file_name(), "B.TXT");
+        assert_eq!(entry.len(), 1024);
+        let mut data = [0; 1024];
+        entry.to_file().read_exact(&mut data).await.unwrap();
+        assert_eq!(data, [b'x'; 1024]);
+    }
+}
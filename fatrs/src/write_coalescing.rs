@@ -0,0 +1,84 @@
+//! Write coalescing buffer for small appends
+//!
+//! Small writes inside an already allocated cluster are collected in RAM and written to the device
+//! in one operation once the buffer is full, the end of the cluster is reached, or the file is flushed.
+//! Larger writes go through the regular (multi-cluster) write path, after the buffer is written.
+//!
+//! Performance impact:
+//! - Loggers appending short records: one device write per buffer instead of a sector read-modify-write per record
+//! - Memory cost: configurable with `FsOptions::write_coalescing`, allocated on the first buffered write of a file
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+/// Pending writes of a single open file
+#[derive(Clone, Default)]
+pub(crate) struct WriteBuffer {
+    /// Data not written to the device yet
+    data: Vec<u8>,
+    /// File offset of the first pending byte
+    start: u64,
+    /// Position of the first pending byte on the volume (pending data never spans clusters)
+    disk_offset: u64,
+}
+
+impl WriteBuffer {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if a write at `offset` can be appended to the pending data.
+    pub(crate) fn continues_at(&self, offset: u64) -> bool {
+        self.is_empty() || self.start + self.data.len() as u64 == offset
+    }
+
+    /// Appends data written at file offset `offset`, stored at `disk_offset` on the volume.
+    pub(crate) fn push(&mut self, offset: u64, disk_offset: u64, data: &[u8], capacity: usize) {
+        debug_assert!(self.continues_at(offset));
+        if self.is_empty() {
+            self.start = offset;
+            self.disk_offset = disk_offset;
+            self.data.reserve(capacity);
+        }
+        self.data.extend_from_slice(data);
+    }
+
+    /// Returns the position of the pending data on the volume and the data itself.
+    pub(crate) fn pending(&self) -> (u64, &[u8]) {
+        (self.disk_offset, &self.data)
+    }
+
+    /// Forgets the pending data after it has been written, keeping the allocation.
+    pub(crate) fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_clear() {
+        let mut buffer = WriteBuffer::default();
+        assert!(buffer.is_empty());
+        assert!(buffer.continues_at(123));
+        buffer.push(100, 5000, &[1, 2, 3], 64);
+        buffer.push(103, 5003, &[4, 5], 64);
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.continues_at(105));
+        assert!(!buffer.continues_at(104));
+        assert_eq!(buffer.pending(), (5000, &[1, 2, 3, 4, 5][..]));
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert!(buffer.continues_at(0));
+        buffer.push(0, 9000, &[6], 64);
+        assert_eq!(buffer.pending(), (9000, &[6][..]));
+    }
+}
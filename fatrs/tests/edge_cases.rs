@@ -63,6 +63,23 @@ impl Seek for TestBlockDevice {
     }
 }
 
+/// Discarded ranges, in the order they were issued
+#[cfg(feature = "discard")]
+static DISCARDS: Mutex<Vec<(u64, u64)>> = Mutex::new(Vec::new());
+
+/// Records the range and zeroes it, like a device returning zeros for unmapped blocks
+#[cfg(feature = "discard")]
+impl fatrs::Discard for TestBlockDevice {
+    type Error = std::io::Error;
+
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), std::io::Error> {
+        DISCARDS.lock().unwrap().push((offset, len));
+        let mut file = self.inner.lock().unwrap();
+        file.seek(std::io::SeekFrom::Start(offset))?;
+        file.write_all(&vec![0; len as usize])
+    }
+}
+
 fn create_test_image(path: &str, size_mb: u32) -> std::io::Result<()> {
     use std::process::Command;
 
@@ -479,6 +496,174 @@ async fn test_truncate_then_large_write() {
     cleanup_test_image(path);
 }
 
+/// Test growing and shrinking a file with set_len
+#[tokio::test]
+async fn test_set_len_extend_and_shrink() {
+    let path = "target/test_set_len.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+
+    let fs = FileSystem::new(device, FsOptions::new()).await.unwrap();
+    let root = fs.root_dir();
+
+    // Leave garbage in free clusters
+    let mut file = root.create_file("garbage.bin").await.unwrap();
+    file.write_all(&vec![0xAA; 20000]).await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+    root.remove("garbage.bin").await.unwrap();
+
+    let mut file = root.create_file("sized.bin").await.unwrap();
+    file.write_all(b"head").await.unwrap();
+    file.seek(SeekFrom::Start(2)).await.unwrap();
+
+    // Extend - new bytes are zeros and the position is kept
+    file.set_len(20000).await.unwrap();
+    assert_eq!(file.seek(SeekFrom::Current(0)).await.unwrap(), 2);
+    assert_eq!(file.seek(SeekFrom::End(0)).await.unwrap(), 20000);
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+    let mut buf = vec![0u8; 20000];
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], b"head");
+    assert!(buf[4..].iter().all(|&b| b == 0));
+
+    // Shrink - the position is moved to the new end
+    file.set_len(3).await.unwrap();
+    assert_eq!(file.seek(SeekFrom::Current(0)).await.unwrap(), 3);
+    file.flush().await.unwrap();
+    drop(file);
+
+    let mut file = root.open_file("sized.bin").await.unwrap();
+    let mut buf = vec![0u8; 10];
+    let n = file.read(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"hea");
+    drop(file);
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
+/// Test reserving clusters with allocate
+#[tokio::test]
+async fn test_allocate() {
+    let path = "target/test_allocate.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+
+    let fs = FileSystem::new(device, FsOptions::new()).await.unwrap();
+    let root = fs.root_dir();
+    let stats = fs.stats().await.unwrap();
+    let cluster_size = u64::from(stats.cluster_size());
+    let free = stats.free_clusters();
+    let clusters = |len: u64| len.div_ceil(cluster_size) as u32;
+
+    let mut file = root.create_file("capture.bin").await.unwrap();
+    file.allocate(100000).await.unwrap();
+    assert_eq!(file.seek(SeekFrom::End(0)).await.unwrap(), 0);
+    assert_eq!(
+        fs.stats().await.unwrap().free_clusters(),
+        free - clusters(100000)
+    );
+
+    // Writes use the reserved clusters
+    file.write_all(&vec![0x55; 50000]).await.unwrap();
+    assert_eq!(
+        fs.stats().await.unwrap().free_clusters(),
+        free - clusters(100000)
+    );
+
+    // More than the free space - nothing is reserved
+    let too_much = u64::from(free + 1) * cluster_size;
+    assert!(matches!(
+        file.allocate(too_much).await,
+        Err(fatrs::Error::NotEnoughSpace)
+    ));
+    assert_eq!(
+        fs.stats().await.unwrap().free_clusters(),
+        free - clusters(100000)
+    );
+
+    // Truncating releases what was not used
+    file.truncate().await.unwrap();
+    file.flush().await.unwrap();
+    assert_eq!(
+        fs.stats().await.unwrap().free_clusters(),
+        free - clusters(50000)
+    );
+    drop(file);
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
+/// Test that extents describe where a fragmented file is stored
+#[tokio::test]
+async fn test_extents() {
+    let path = "target/test_extents.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+    let image = device.clone();
+
+    let fs = FileSystem::new(device, FsOptions::new()).await.unwrap();
+    let root = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+    let sector_size = u64::from(fs.bytes_per_sector());
+
+    // Interleave two files so each gets two clusters, then one, then two
+    let mut a = root.create_file("a.bin").await.unwrap();
+    let mut b = root.create_file("b.bin").await.unwrap();
+    let data: Vec<u8> = (0..cluster_size * 5 - 100).map(|i| (i / 7) as u8).collect();
+    for (i, chunk) in data.chunks(cluster_size).enumerate() {
+        a.write_all(chunk).await.unwrap();
+        if i % 2 == 1 {
+            b.write_all(&vec![0; cluster_size]).await.unwrap();
+        }
+    }
+    a.flush().await.unwrap();
+    b.flush().await.unwrap();
+
+    let mut extents = Vec::new();
+    let mut iter = a.extents();
+    while let Some(extent) = iter.next().await {
+        extents.push(extent.unwrap());
+    }
+    let sizes: Vec<u64> = extents.iter().map(|e| e.size).collect();
+    let cs = cluster_size as u64;
+    assert_eq!(sizes, [2 * cs, 2 * cs, cs - 100]);
+    assert_eq!(extents[1].file_offset, 2 * cs);
+    assert_eq!(extents[2].file_offset, 4 * cs);
+
+    // The data can be read back through the device at the reported positions
+    for extent in &extents {
+        assert_eq!(extent.first_lba, extent.offset / sector_size);
+        let mut buf = vec![0; extent.size as usize];
+        {
+            let mut image = image.inner.lock().unwrap();
+            image.seek(std::io::SeekFrom::Start(extent.offset)).unwrap();
+            image.read_exact(&mut buf).unwrap();
+        }
+        let start = extent.file_offset as usize;
+        assert_eq!(buf, data[start..start + buf.len()]);
+    }
+
+    assert_eq!(
+        a.sector_at(3 * cs + cs / 2).await.unwrap(),
+        Some(extents[1].first_lba + (cs + cs / 2) / sector_size)
+    );
+    assert_eq!(a.sector_at(data.len() as u64).await.unwrap(), None);
+
+    let empty = root.create_file("empty.bin").await.unwrap();
+    assert!(empty.extents().next().await.is_none());
+
+    cleanup_test_image(path);
+}
+
 // =============================================================================
 // FILE WRITE EDGE CASES
 // =============================================================================
@@ -603,6 +788,70 @@ async fn test_many_small_writes() {
     cleanup_test_image(path);
 }
 
+/// Test appending short records through the write coalescing buffer
+#[cfg(feature = "write-coalescing")]
+#[tokio::test]
+async fn test_write_coalescing() {
+    let path = "target/test_write_coalescing.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+
+    let options = FsOptions::new().write_coalescing(256);
+    let fs = FileSystem::new(device, options).await.unwrap();
+    let root = fs.root_dir();
+    let cluster_size = fs.cluster_size() as u64;
+
+    let mut file = root.create_file("log.txt").await.unwrap();
+    let mut expected = Vec::new();
+    for i in 0..500 {
+        let record = format!("record {i:04} {}\n", "x".repeat(i % 40));
+        file.write_all(record.as_bytes()).await.unwrap();
+        expected.extend_from_slice(record.as_bytes());
+        if i == 250 {
+            // A large write goes through the regular path after the pending records
+            let block = vec![b'#'; 3 * cluster_size as usize];
+            file.write_all(&block).await.unwrap();
+            expected.extend_from_slice(&block);
+        }
+    }
+    assert_eq!(
+        file.seek(SeekFrom::Current(0)).await.unwrap(),
+        expected.len() as u64
+    );
+
+    // Pending records are written before reading
+    let mut head = vec![0u8; 100];
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+    file.read_exact(&mut head).await.unwrap();
+    assert_eq!(head, expected[..100]);
+
+    // Overwrite inside the file, then read everything back through another handle
+    file.seek(SeekFrom::Start(10)).await.unwrap();
+    file.write_all(b"OVERWRITE").await.unwrap();
+    expected[10..19].copy_from_slice(b"OVERWRITE");
+    file.flush().await.unwrap();
+
+    let mut reader = root.open_file("log.txt").await.unwrap();
+    let mut read = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let n = reader.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        read.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(read, expected);
+
+    drop(reader);
+    drop(file);
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
 // =============================================================================
 // FAT CACHE EDGE CASES (multiple files triggering cache eviction)
 // =============================================================================
@@ -886,6 +1135,240 @@ async fn test_seek_from_end() {
     cleanup_test_image(path);
 }
 
+/// Test random seeks in a long fragmented file, before and after it is truncated
+#[tokio::test]
+async fn test_seek_fragmented_file() {
+    let path = "target/test_seek_fragmented.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+
+    let fs = FileSystem::new(device, FsOptions::new()).await.unwrap();
+    let root = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+
+    // Every third cluster of the file is followed by a cluster of another file
+    let mut file = root.create_file("long.bin").await.unwrap();
+    let mut other = root.create_file("other.bin").await.unwrap();
+    let clusters = 1000;
+    let value = |pos: usize| (pos / 4 % 251) as u8;
+    let data: Vec<u8> = (0..clusters * cluster_size).map(value).collect();
+    for (i, chunk) in data.chunks(cluster_size).enumerate() {
+        file.write_all(chunk).await.unwrap();
+        if i % 3 == 2 {
+            other.write_all(&vec![0; cluster_size]).await.unwrap();
+        }
+    }
+    file.flush().await.unwrap();
+    other.flush().await.unwrap();
+
+    let mut buf = vec![0u8; 16];
+    let mut pos = 0u64;
+    for len in [data.len(), data.len() / 3] {
+        for i in 0..200u64 {
+            // mix forward and backward seeks, including cluster boundaries
+            let cluster = (i * 7919 + pos / cluster_size as u64) % (len / cluster_size) as u64;
+            let offset = match i % 3 {
+                0 => cluster * cluster_size as u64,
+                1 => cluster * cluster_size as u64 + 4,
+                _ => (cluster + 1) * cluster_size as u64 - buf.len() as u64,
+            };
+            pos = file.seek(SeekFrom::Start(offset)).await.unwrap();
+            assert_eq!(pos, offset);
+            file.read_exact(&mut buf).await.unwrap();
+            let expected: Vec<u8> = (offset as usize..offset as usize + buf.len())
+                .map(value)
+                .collect();
+            assert_eq!(buf, expected, "data at {offset}");
+        }
+        file.seek(SeekFrom::Start(data.len() as u64 / 3))
+            .await
+            .unwrap();
+        file.truncate().await.unwrap();
+    }
+    file.flush().await.unwrap();
+    drop(file);
+    drop(other);
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
+/// Test small sequential reads through the read-ahead buffer, with writes and seeks in between
+#[cfg(feature = "read-ahead")]
+#[tokio::test]
+async fn test_read_ahead() {
+    let path = "target/test_read_ahead.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+
+    let options = FsOptions::new().read_ahead(8192);
+    let fs = FileSystem::new(device, options).await.unwrap();
+    let root = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+
+    // Fragment the file every 5 clusters
+    let mut file = root.create_file("stream.bin").await.unwrap();
+    let mut other = root.create_file("other.bin").await.unwrap();
+    let mut data: Vec<u8> = (0..cluster_size * 40 + 123)
+        .map(|i| (i % 239) as u8)
+        .collect();
+    for (i, chunk) in data.chunks(cluster_size).enumerate() {
+        file.write_all(chunk).await.unwrap();
+        if i % 5 == 4 {
+            other.write_all(&vec![0; cluster_size]).await.unwrap();
+        }
+    }
+    file.flush().await.unwrap();
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+
+    let mut read = Vec::new();
+    let mut buf = [0u8; 300];
+    loop {
+        let n = file.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        read.extend_from_slice(&buf[..n]);
+        if read.len() == 20 * cluster_size + 300 {
+            // A write after a prefetch must be visible to the next reads
+            let pos = file.seek(SeekFrom::Current(0)).await.unwrap();
+            file.write_all(&[0xEE; 100]).await.unwrap();
+            file.seek(SeekFrom::Start(pos)).await.unwrap();
+            data[pos as usize..pos as usize + 100].fill(0xEE);
+        }
+    }
+    assert_eq!(read.len(), data.len());
+    assert_eq!(read, data);
+
+    // Seeking backwards drops the buffer
+    file.seek(SeekFrom::Start(100)).await.unwrap();
+    file.read_exact(&mut buf).await.unwrap();
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf[..], data[400..700]);
+    file.seek(SeekFrom::End(-200)).await.unwrap();
+    file.read_exact(&mut buf[..200]).await.unwrap();
+    assert_eq!(buf[..200], data[data.len() - 200..]);
+
+    drop(file);
+    drop(other);
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
+#[cfg(feature = "discard")]
+#[tokio::test]
+async fn test_discard_released_clusters() {
+    let path = "target/test_discard.img";
+    create_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let device = TestBlockDevice::new(file);
+    let image = device.clone();
+
+    let mut fs = FileSystem::new(device, FsOptions::new()).await.unwrap();
+    fs.enable_discard();
+    let root = fs.root_dir();
+    let cs = u64::from(fs.cluster_size());
+
+    let mut file = root.create_file("trim.bin").await.unwrap();
+    file.write_all(&vec![0xAB; 10 * cs as usize]).await.unwrap();
+    file.flush().await.unwrap();
+    let extent = file.extents().next().await.unwrap().unwrap();
+    assert_eq!(extent.size, 10 * cs);
+    let read_image = |offset: u64, len: u64| {
+        let mut buf = vec![0; len as usize];
+        let mut image = image.inner.lock().unwrap();
+        image.seek(std::io::SeekFrom::Start(offset)).unwrap();
+        image.read_exact(&mut buf).unwrap();
+        buf
+    };
+
+    // Truncating releases the tail as a single run
+    DISCARDS.lock().unwrap().clear();
+    file.seek(SeekFrom::Start(4 * cs)).await.unwrap();
+    file.truncate().await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+    assert_eq!(
+        *DISCARDS.lock().unwrap(),
+        [(extent.offset + 4 * cs, 6 * cs)]
+    );
+    assert!(read_image(extent.offset, 4 * cs).iter().all(|&b| b == 0xAB));
+    assert!(
+        read_image(extent.offset + 4 * cs, 6 * cs)
+            .iter()
+            .all(|&b| b == 0)
+    );
+
+    // Removing the file releases the rest
+    DISCARDS.lock().unwrap().clear();
+    root.remove("trim.bin").await.unwrap();
+    assert_eq!(*DISCARDS.lock().unwrap(), [(extent.offset, 4 * cs)]);
+    assert!(read_image(extent.offset, 4 * cs).iter().all(|&b| b == 0));
+
+    fs.flush().await.unwrap();
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
+#[cfg(feature = "tiny-mode")]
+#[tokio::test]
+async fn test_tiny_mode_interleaved_files() {
+    let path = "target/test_tiny_mode.img";
+    create_test_image(path, 10).unwrap();
+
+    {
+        let file = File::options().read(true).write(true).open(path).unwrap();
+        let fs = FileSystem::new(TestBlockDevice::new(file), FsOptions::new())
+            .await
+            .unwrap();
+        let root = fs.root_dir();
+        let cs = fs.cluster_size() as usize;
+
+        // All files walk and extend their cluster chains through the single FAT window, 300 clusters
+        // cover more than one FAT sector
+        let mut files = Vec::new();
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            files.push(root.create_file(name).await.unwrap());
+        }
+        for round in 0..100 {
+            for (i, file) in files.iter_mut().enumerate() {
+                let fill = ((round * 3 + i) % 251) as u8;
+                file.write_all(&vec![fill; cs]).await.unwrap();
+            }
+        }
+        for mut file in files {
+            file.flush().await.unwrap();
+        }
+        fs.flush().await.unwrap();
+    }
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let fs = FileSystem::new(TestBlockDevice::new(file), FsOptions::new())
+        .await
+        .unwrap();
+    let root = fs.root_dir();
+    let cs = fs.cluster_size() as usize;
+    for (i, name) in ["a.bin", "b.bin", "c.bin"].into_iter().enumerate() {
+        let mut file = root.open_file(name).await.unwrap();
+        let mut data = vec![0; 100 * cs];
+        file.read_exact(&mut data).await.unwrap();
+        for (round, chunk) in data.chunks(cs).enumerate() {
+            let fill = (round * 3 + i) % 251;
+            assert!(chunk.iter().all(|&b| usize::from(b) == fill));
+        }
+    }
+    assert!(fs.check().await.unwrap().is_clean());
+
+    cleanup_test_image(path);
+}
+
 // =============================================================================
 // DELETE EDGE CASES
 // =============================================================================
@@ -956,3 +1439,90 @@ async fn test_delete_long_filename() {
 
     cleanup_test_image(path);
 }
+
+// =============================================================================
+// BOOT SECTOR RECOVERY
+// =============================================================================
+
+/// Test mounting a FAT32 volume with a damaged boot sector using the backup boot sector
+#[tokio::test]
+async fn test_backup_boot_sector_fallback() {
+    let path = "target/test_backup_boot_sector.img";
+    // Smaller images are formatted as FAT16 which has no backup boot sector
+    create_test_image(path, 40).unwrap();
+
+    {
+        let file = File::options().read(true).write(true).open(path).unwrap();
+        let fs = FileSystem::new(TestBlockDevice::new(file), FsOptions::new())
+            .await
+            .unwrap();
+        let mut file = fs.root_dir().create_file("data.txt").await.unwrap();
+        file.write_all(b"survived").await.unwrap();
+        file.flush().await.unwrap();
+        drop(file);
+        fs.unmount().await.unwrap();
+    }
+
+    // Brown-out while the boot sector was written
+    {
+        let mut file = File::options().read(true).write(true).open(path).unwrap();
+        file.write_all(&[0xFF; 512]).unwrap();
+    }
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    assert!(matches!(
+        FileSystem::new(TestBlockDevice::new(file), FsOptions::new()).await,
+        Err(fatrs::Error::CorruptedFileSystem)
+    ));
+
+    {
+        let file = File::options().read(true).write(true).open(path).unwrap();
+        let options = FsOptions::new().backup_boot_sector_fallback(true);
+        let fs = FileSystem::new(TestBlockDevice::new(file), options)
+            .await
+            .unwrap();
+        assert!(fs.used_backup_boot_sector());
+        let mut file = fs.root_dir().open_file("data.txt").await.unwrap();
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"survived");
+        drop(file);
+        fs.restore_boot_sector().await.unwrap();
+        assert!(!fs.used_backup_boot_sector());
+        fs.unmount().await.unwrap();
+    }
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let fs = FileSystem::new(TestBlockDevice::new(file), FsOptions::new())
+        .await
+        .unwrap();
+    assert!(!fs.used_backup_boot_sector());
+    assert!(!fs.read_status_flags().await.unwrap().dirty());
+    assert!(fs.root_dir().open_file("data.txt").await.is_ok());
+    drop(fs);
+
+    cleanup_test_image(path);
+}
+
+/// Test that the backup boot sector is not used unless requested and only exists on FAT32
+#[tokio::test]
+async fn test_restore_boot_sector_fat16() {
+    let path = "target/test_restore_boot_sector_fat16.img";
+    create_fat16_test_image(path, 10).unwrap();
+
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let fs = FileSystem::new(
+        TestBlockDevice::new(file),
+        FsOptions::new().backup_boot_sector_fallback(true),
+    )
+    .await
+    .unwrap();
+    assert!(!fs.used_backup_boot_sector());
+    assert!(matches!(
+        fs.restore_boot_sector().await,
+        Err(fatrs::Error::InvalidInput)
+    ));
+    drop(fs);
+
+    cleanup_test_image(path);
+}
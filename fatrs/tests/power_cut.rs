@@ -0,0 +1,509 @@
+//! Power-loss tests for the transaction-safe feature
+//!
+//! Transactions updating the FAT, file data and several directory entries are interrupted after every
+//! possible number of written sectors, or a sample of them on FAT32. After each cut the volume is mounted
+//! again, which must recover it to either the complete state before or the complete state after the
+//! transaction.
+#![cfg(feature = "transaction-safe")]
+
+use std::sync::{Arc, Mutex};
+
+use embedded_io_async::{ErrorType, Read, Seek, SeekFrom, Write};
+use fatrs::{Error, FatType, FileSystem, FormatVolumeOptions, FsOptions, TransactionType};
+
+const SECTOR_SIZE: u64 = 512;
+
+struct DeviceState {
+    image: Vec<u8>,
+    /// Number of sector writes left before power is lost, `None` for no limit
+    budget: Option<usize>,
+    /// Number of sector writes so far
+    written: usize,
+    /// Byte ranges discarded so far
+    #[cfg(feature = "discard")]
+    discarded: Vec<(u64, u64)>,
+}
+
+/// In-memory block device that loses power after a configurable number of sector writes
+///
+/// Every write is split at sector boundaries and each part counts as one sector write. A write crossing the
+/// cut persists only the parts before it, like a torn multi-sector write.
+#[derive(Clone)]
+struct PowerCutDevice {
+    state: Arc<Mutex<DeviceState>>,
+    pos: u64,
+}
+
+impl PowerCutDevice {
+    fn new(image: Vec<u8>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(DeviceState {
+                image,
+                budget: None,
+                written: 0,
+                #[cfg(feature = "discard")]
+                discarded: Vec::new(),
+            })),
+            pos: 0,
+        }
+    }
+
+    fn cut_power_after(&self, sectors: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.budget = Some(sectors);
+        state.written = 0;
+    }
+
+    fn written_sectors(&self) -> usize {
+        self.state.lock().unwrap().written
+    }
+
+    fn image(&self) -> Vec<u8> {
+        self.state.lock().unwrap().image.clone()
+    }
+}
+
+fn power_lost() -> std::io::Error {
+    std::io::Error::other("power lost")
+}
+
+impl ErrorType for PowerCutDevice {
+    type Error = std::io::Error;
+}
+
+impl Read for PowerCutDevice {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let state = self.state.lock().unwrap();
+        let start = (self.pos as usize).min(state.image.len());
+        let len = buf.len().min(state.image.len() - start);
+        buf[..len].copy_from_slice(&state.image[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for PowerCutDevice {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut done = 0;
+        while done < buf.len() {
+            if state.budget == Some(0) {
+                return Err(power_lost());
+            }
+            // Persist up to the end of the current sector
+            let pos = self.pos as usize;
+            let len = (buf.len() - done).min((SECTOR_SIZE - self.pos % SECTOR_SIZE) as usize);
+            state.image[pos..pos + len].copy_from_slice(&buf[done..done + len]);
+            state.budget = state.budget.map(|budget| budget - 1);
+            state.written += 1;
+            self.pos += len as u64;
+            done += len;
+        }
+        Ok(done)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Seek for PowerCutDevice {
+    async fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let len = self.state.lock().unwrap().image.len() as i64;
+        self.pos = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::End(n) => (len + n) as u64,
+            SeekFrom::Current(n) => (self.pos as i64 + n) as u64,
+        };
+        Ok(self.pos)
+    }
+}
+
+/// Discarded ranges read back as zeros, so a discard before the commit record shows up as lost data
+#[cfg(feature = "discard")]
+impl fatrs::Discard for PowerCutDevice {
+    type Error = std::io::Error;
+
+    async fn discard(&mut self, offset: u64, len: u64) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.budget == Some(0) {
+            return Err(power_lost());
+        }
+        state.image[offset as usize..(offset + len) as usize].fill(0);
+        state.discarded.push((offset, len));
+        Ok(())
+    }
+}
+
+type TestFs = FileSystem<PowerCutDevice, fatrs::DefaultTimeProvider, fatrs::LossyOemCpConverter>;
+
+const OLD_DATA_LEN: usize = 1500;
+const APPENDED_LEN: usize = 2000;
+/// Larger than the before-image area of the default transaction log
+const NEW_CONFIG_LEN: usize = 40 * 1024;
+
+#[derive(Clone, Copy)]
+enum Scenario {
+    /// Append to a file, delete a file and create a file
+    Update,
+    /// Write a new config, replace the old one with it and delete the old one
+    ReplaceConfig,
+}
+
+/// Creates a volume with reserved sectors for the transaction log and the files changed by [`update`].
+async fn create_volume(size: usize, fat_type: FatType) -> Vec<u8> {
+    let device = PowerCutDevice::new(vec![0; size]);
+    let options = FormatVolumeOptions::new()
+        .fat_type(fat_type)
+        .bytes_per_cluster(512)
+        .with_transaction_log();
+    fatrs::format_volume(&mut device.clone(), options)
+        .await
+        .unwrap();
+
+    let fs = FileSystem::new(device.clone(), FsOptions::new())
+        .await
+        .unwrap();
+    {
+        let root = fs.root_dir();
+        let mut file = root.create_file("data.bin").await.unwrap();
+        file.write_all(&[0x11; OLD_DATA_LEN]).await.unwrap();
+        file.flush().await.unwrap();
+        let mut file = root.create_file("old config.txt").await.unwrap();
+        file.write_all(b"old contents").await.unwrap();
+        file.flush().await.unwrap();
+        let mut file = root.create_file("config.txt").await.unwrap();
+        file.write_all(b"old contents").await.unwrap();
+        file.flush().await.unwrap();
+    }
+    fs.unmount().await.unwrap();
+    device.image()
+}
+
+/// Mounts the volume with the transaction log, discarding released clusters if `discard` is set.
+async fn mount(device: PowerCutDevice, discard: bool) -> TestFs {
+    let fs = FileSystem::new(device, FsOptions::new().with_transaction_log())
+        .await
+        .unwrap();
+    #[cfg(feature = "discard")]
+    let fs = {
+        let mut fs = fs;
+        if discard {
+            fs.enable_discard();
+        }
+        fs
+    };
+    #[cfg(not(feature = "discard"))]
+    assert!(!discard, "discard requires the discard feature");
+    fs
+}
+
+/// Runs the operations of a scenario in a single transaction.
+async fn update(fs: &TestFs, scenario: Scenario) -> Result<(), Error<std::io::Error>> {
+    match scenario {
+        Scenario::Update => append_and_replace(fs).await,
+        Scenario::ReplaceConfig => replace_config(fs).await,
+    }
+}
+
+async fn append_and_replace(fs: &TestFs) -> Result<(), Error<std::io::Error>> {
+    fs.with_transaction(TransactionType::ClusterChainUpdate, &[], || async {
+        let root = fs.root_dir();
+        let mut file = root.open_file("data.bin").await?;
+        file.seek(SeekFrom::End(0)).await?;
+        file.write_all(&[0x22; APPENDED_LEN]).await?;
+        file.flush().await?;
+        root.remove("old config.txt").await?;
+        let mut file = root.create_file("new config.txt").await?;
+        file.write_all(b"new contents").await?;
+        file.flush().await
+    })
+    .await
+}
+
+async fn replace_config(fs: &TestFs) -> Result<(), Error<std::io::Error>> {
+    fs.atomic(|| async {
+        let root = fs.root_dir();
+        let mut file = root.create_file("config.new").await?;
+        file.write_all(&[0x33; NEW_CONFIG_LEN]).await?;
+        file.flush().await?;
+        drop(file);
+        root.rename("config.txt", &root, "config.bak").await?;
+        root.rename("config.new", &root, "config.txt").await?;
+        root.remove("config.bak").await
+    })
+    .await
+}
+
+async fn read_file(fs: &TestFs, name: &str) -> Option<Vec<u8>> {
+    let mut file = fs.root_dir().open_file(name).await.ok()?;
+    let mut data = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        let n = file.read(&mut buf).await.unwrap();
+        if n == 0 {
+            return Some(data);
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Returns true for the state after the transaction and false for the state before it, panics on any mix.
+async fn is_updated(fs: &TestFs, scenario: Scenario) -> bool {
+    let report = fs.check().await.unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems());
+    assert_eq!(fs.transaction_statistics().await.used_slots, 0);
+
+    if let Scenario::ReplaceConfig = scenario {
+        let config = read_file(fs, "config.txt").await.unwrap();
+        assert_eq!(read_file(fs, "config.new").await, None);
+        assert_eq!(read_file(fs, "config.bak").await, None);
+        if config == b"old contents" {
+            return false;
+        }
+        assert_eq!(config.len(), NEW_CONFIG_LEN);
+        assert!(config.iter().all(|&b| b == 0x33));
+        return true;
+    }
+
+    let data = read_file(fs, "data.bin").await.unwrap();
+    let old_config = read_file(fs, "old config.txt").await;
+    let new_config = read_file(fs, "new config.txt").await;
+    assert!(data[..OLD_DATA_LEN].iter().all(|&b| b == 0x11));
+    if data.len() == OLD_DATA_LEN {
+        assert_eq!(old_config.as_deref(), Some(&b"old contents"[..]));
+        assert_eq!(new_config, None);
+        false
+    } else {
+        assert_eq!(data.len(), OLD_DATA_LEN + APPENDED_LEN);
+        assert!(data[OLD_DATA_LEN..].iter().all(|&b| b == 0x22));
+        assert_eq!(old_config, None);
+        assert_eq!(new_config.as_deref(), Some(&b"new contents"[..]));
+        true
+    }
+}
+
+/// Cuts power after every `stride`-th sector write of the transaction and after its last sector write.
+async fn run_power_cut_test(
+    size: usize,
+    fat_type: FatType,
+    scenario: Scenario,
+    stride: usize,
+    discard: bool,
+) {
+    let image = create_volume(size, fat_type).await;
+
+    // Count the sectors written by an uninterrupted transaction
+    let device = PowerCutDevice::new(image.clone());
+    let fs = mount(device.clone(), discard).await;
+    device.cut_power_after(usize::MAX);
+    update(&fs, scenario).await.unwrap();
+    let total = device.written_sectors();
+    drop(fs);
+
+    // The last two sector writes follow the commit record, always cut after them
+    let cuts: Vec<_> = (0..total - 1)
+        .step_by(stride)
+        .chain([total - 1, total])
+        .collect();
+    let mut rolled_back = 0;
+    for &cut in &cuts {
+        let device = PowerCutDevice::new(image.clone());
+        let fs = mount(device.clone(), discard).await;
+        device.cut_power_after(cut);
+        let result = update(&fs, scenario).await;
+        assert_eq!(
+            result.is_ok(),
+            cut == total,
+            "cut after {cut} of {total} sectors"
+        );
+        drop(fs);
+
+        let fs = FileSystem::new(
+            PowerCutDevice::new(device.image()),
+            FsOptions::new().with_transaction_log(),
+        )
+        .await
+        .unwrap();
+        if !is_updated(&fs, scenario).await {
+            rolled_back += 1;
+        }
+        fs.unmount().await.unwrap();
+    }
+
+    // Early cuts roll back, cuts after the commit record keep the update
+    assert!(rolled_back > 0 && rolled_back < cuts.len() - 1);
+}
+
+#[tokio::test]
+async fn test_power_cut_during_transaction_fat12() {
+    run_power_cut_test(1024 * 1024, FatType::Fat12, Scenario::Update, 1, false).await;
+}
+
+#[tokio::test]
+async fn test_power_cut_during_transaction_fat16() {
+    run_power_cut_test(8 * 1024 * 1024, FatType::Fat16, Scenario::Update, 1, false).await;
+}
+
+#[tokio::test]
+async fn test_power_cut_during_transaction_fat32() {
+    // The FSInfo sector is journaled too, every third cut keeps the test fast on the 32 MB volume
+    run_power_cut_test(34 * 1024 * 1024, FatType::Fat32, Scenario::Update, 3, false).await;
+}
+
+#[tokio::test]
+async fn test_power_cut_during_config_replace() {
+    run_power_cut_test(
+        1024 * 1024,
+        FatType::Fat12,
+        Scenario::ReplaceConfig,
+        1,
+        false,
+    )
+    .await;
+}
+
+#[cfg(feature = "discard")]
+#[tokio::test]
+async fn test_power_cut_during_transaction_with_discard() {
+    // The clusters of the removed file keep their data until the commit record is written
+    run_power_cut_test(1024 * 1024, FatType::Fat12, Scenario::Update, 1, true).await;
+}
+
+#[cfg(feature = "discard")]
+#[tokio::test]
+async fn test_rolled_back_transaction_discards_nothing() {
+    let image = create_volume(1024 * 1024, FatType::Fat12).await;
+    let device = PowerCutDevice::new(image);
+    let fs = mount(device.clone(), true).await;
+
+    let result = fs
+        .atomic(|| async {
+            fs.root_dir().remove("old config.txt").await?;
+            Err(Error::InvalidInput)
+        })
+        .await;
+    assert!(matches!(result, Err(Error::InvalidInput)));
+    assert!(device.state.lock().unwrap().discarded.is_empty());
+    assert!(!is_updated(&fs, Scenario::Update).await);
+
+    // Committed, the released clusters are discarded
+    update(&fs, Scenario::Update).await.unwrap();
+    assert!(!device.state.lock().unwrap().discarded.is_empty());
+    assert!(is_updated(&fs, Scenario::Update).await);
+}
+
+#[tokio::test]
+async fn test_transaction_log_full_rolls_back() {
+    let image = create_volume(1024 * 1024, FatType::Fat12).await;
+    let device = PowerCutDevice::new(image);
+    // Room for two before-images only
+    let fs = FileSystem::new(
+        device.clone(),
+        FsOptions::new().with_transaction_log_at(1, 6),
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        update(&fs, Scenario::Update).await,
+        Err(Error::TransactionLogFull)
+    ));
+    assert!(!is_updated(&fs, Scenario::Update).await);
+    fs.unmount().await.unwrap();
+
+    let fs = FileSystem::new(PowerCutDevice::new(device.image()), FsOptions::new())
+        .await
+        .unwrap();
+    assert!(!is_updated(&fs, Scenario::Update).await);
+}
+
+#[tokio::test]
+async fn test_transaction_log_full_during_fat_write() {
+    let image = create_volume(1024 * 1024, FatType::Fat12).await;
+    let device = PowerCutDevice::new(image);
+    // Room for one before-image, the second FAT copy does not fit
+    let fs = FileSystem::new(
+        device.clone(),
+        FsOptions::new().with_transaction_log_at(1, 5),
+    )
+    .await
+    .unwrap();
+
+    let result = fs
+        .with_transaction(TransactionType::FatUpdate, &[], || async {
+            let mut file = fs.root_dir().open_file("data.bin").await?;
+            file.truncate().await?;
+            file.flush().await
+        })
+        .await;
+    assert!(matches!(result, Err(Error::TransactionLogFull)));
+    fs.unmount().await.unwrap();
+
+    let fs = FileSystem::new(PowerCutDevice::new(device.image()), FsOptions::new())
+        .await
+        .unwrap();
+    assert!(!is_updated(&fs, Scenario::Update).await);
+}
+
+#[tokio::test]
+async fn test_transaction_log_full_during_dir_write() {
+    let image = create_volume(1024 * 1024, FatType::Fat12).await;
+    let device = PowerCutDevice::new(image);
+    // Room for one before-image, the entries do not fit in one root directory sector
+    let fs = FileSystem::new(
+        device.clone(),
+        FsOptions::new().with_transaction_log_at(1, 5),
+    )
+    .await
+    .unwrap();
+
+    let result = fs
+        .atomic(|| async {
+            let root = fs.root_dir();
+            for i in 0..20 {
+                root.create_file(&format!("F{i}.TXT")).await?;
+            }
+            Ok(())
+        })
+        .await;
+    assert!(matches!(result, Err(Error::TransactionLogFull)));
+    fs.unmount().await.unwrap();
+
+    let fs = FileSystem::new(PowerCutDevice::new(device.image()), FsOptions::new())
+        .await
+        .unwrap();
+    assert!(!is_updated(&fs, Scenario::Update).await);
+    assert_eq!(read_file(&fs, "F0.TXT").await, None);
+}
+
+#[tokio::test]
+async fn test_new_clusters_are_not_journaled() {
+    let image = create_volume(1024 * 1024, FatType::Fat12).await;
+    let fs = FileSystem::new(
+        PowerCutDevice::new(image),
+        FsOptions::new().with_transaction_log(),
+    )
+    .await
+    .unwrap();
+    assert!(fs.transaction_statistics().await.sector_capacity * 512 < NEW_CONFIG_LEN);
+
+    update(&fs, Scenario::ReplaceConfig).await.unwrap();
+    assert!(is_updated(&fs, Scenario::ReplaceConfig).await);
+}
+
+#[tokio::test]
+async fn test_transaction_without_log_area() {
+    let device = PowerCutDevice::new(vec![0; 1024 * 1024]);
+    fatrs::format_volume(&mut device.clone(), FormatVolumeOptions::new())
+        .await
+        .unwrap();
+    let fs = FileSystem::new(device, FsOptions::new().with_transaction_log())
+        .await
+        .unwrap();
+
+    let result = fs
+        .with_transaction(TransactionType::DirEntryUpdate, &[], || async { Ok(()) })
+        .await;
+    assert!(matches!(result, Err(Error::InvalidInput)));
+}